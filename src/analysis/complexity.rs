@@ -0,0 +1,219 @@
+//! Structural complexity limits.
+//!
+//! Runtimes bound the size of the expressions they accept: the nesting depth
+//! of the AST, its number of nodes and how deeply macros such as `all()` and
+//! `map()` nest. Nodes generated by a macro expansion span the whole macro
+//! call; they are not counted, but the expressions written inside them are.
+
+use cel_core::types::Expr;
+use cel_core::SpannedExpr;
+
+use super::{children, Problem};
+
+/// Limits on the structure of expressions; `None` leaves a measure unlimited.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ComplexityLimits {
+    /// Maximum nesting depth of the AST.
+    pub max_depth: Option<usize>,
+    /// Maximum number of AST nodes.
+    pub max_nodes: Option<usize>,
+    /// Maximum number of nested comprehensions.
+    pub max_comprehension_nesting: Option<usize>,
+}
+
+/// Report the limits `ast` exceeds: `max-depth`, `max-nodes` and
+/// `max-comprehension-nesting`.
+///
+/// Each problem points at the deepest subexpression that exceeds its limit:
+/// the deepest node, the smallest subexpression with too many nodes and the
+/// innermost comprehension.
+pub fn complexity_problems(ast: &SpannedExpr, limits: &ComplexityLimits) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    if let Some(max_depth) = limits.max_depth {
+        let (depth, deepest) = deepest_node(ast, |_| true);
+        if depth > max_depth {
+            problems.push(Problem {
+                code: "max-depth",
+                message: format!(
+                    "expression is nested {} levels deep, more than the limit of {}",
+                    depth, max_depth
+                ),
+                span: deepest.span.clone(),
+            });
+        }
+    }
+
+    if let Some(max_nodes) = limits.max_nodes {
+        if let Some((nodes, expr)) = smallest_over(ast, max_nodes) {
+            problems.push(Problem {
+                code: "max-nodes",
+                message: format!(
+                    "expression has {} nodes, more than the limit of {}",
+                    nodes, max_nodes
+                ),
+                span: expr.span.clone(),
+            });
+        }
+    }
+
+    if let Some(max_nesting) = limits.max_comprehension_nesting {
+        let (nesting, innermost) =
+            deepest_node(ast, |expr| matches!(expr.node, Expr::Comprehension(_)));
+        if nesting > max_nesting {
+            problems.push(Problem {
+                code: "max-comprehension-nesting",
+                message: format!(
+                    "comprehensions are nested {} deep, more than the limit of {}",
+                    nesting, max_nesting
+                ),
+                span: innermost.span.clone(),
+            });
+        }
+    }
+
+    problems
+}
+
+/// The sub-expressions of `expr` written in the source, looking through the
+/// nodes a macro expansion generates. The function name of a call is part of
+/// the call, but the receiver of a method call is a node of its own.
+fn written_children(expr: &SpannedExpr) -> Vec<&SpannedExpr> {
+    if let Expr::Call { expr: callee, args } = &expr.node {
+        let receiver = match &callee.node {
+            Expr::Member { expr: receiver, .. } => Some(receiver.as_ref()),
+            _ => None,
+        };
+        return receiver.into_iter().chain(args).collect();
+    }
+    let mut written = Vec::new();
+    let mut pending = children(expr);
+    pending.reverse();
+    while let Some(child) = pending.pop() {
+        if matches!(expr.node, Expr::Comprehension(_)) && child.span == expr.span {
+            let mut generated = children(child);
+            generated.reverse();
+            pending.extend(generated);
+        } else {
+            written.push(child);
+        }
+    }
+    written
+}
+
+/// The deepest node of `ast` counting only nodes for which `counts` is true,
+/// with its depth. The first of equally deep nodes wins.
+fn deepest_node(
+    ast: &SpannedExpr,
+    counts: impl Fn(&SpannedExpr) -> bool + Copy,
+) -> (usize, &SpannedExpr) {
+    let own = usize::from(counts(ast));
+    written_children(ast)
+        .into_iter()
+        .map(|child| deepest_node(child, counts))
+        .filter(|(depth, _)| *depth > 0)
+        .fold(
+            None,
+            |deepest: Option<(usize, &SpannedExpr)>, found| match deepest {
+                Some(current) if current.0 >= found.0 => Some(current),
+                _ => Some(found),
+            },
+        )
+        .map_or((own, ast), |(depth, node)| (depth + own, node))
+}
+
+/// The number of written nodes in `expr`.
+fn node_count(expr: &SpannedExpr) -> usize {
+    1 + written_children(expr)
+        .into_iter()
+        .map(node_count)
+        .sum::<usize>()
+}
+
+/// The smallest subexpression of `ast` with more than `max_nodes` nodes,
+/// with its node count.
+fn smallest_over(ast: &SpannedExpr, max_nodes: usize) -> Option<(usize, &SpannedExpr)> {
+    let nodes = node_count(ast);
+    if nodes <= max_nodes {
+        return None;
+    }
+    written_children(ast)
+        .into_iter()
+        .find_map(|child| smallest_over(child, max_nodes))
+        .or(Some((nodes, ast)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cel_core::parse;
+
+    fn problems(source: &str, limits: ComplexityLimits) -> Vec<(&'static str, String, &str)> {
+        let ast = parse(source).ast.unwrap();
+        complexity_problems(&ast, &limits)
+            .into_iter()
+            .map(|p| (p.code, p.message, &source[p.span]))
+            .collect()
+    }
+
+    #[test]
+    fn reports_the_deepest_node() {
+        let limits = ComplexityLimits {
+            max_depth: Some(3),
+            ..Default::default()
+        };
+        assert!(problems("a + b * c", limits.clone()).is_empty());
+        assert_eq!(
+            problems("a + b * (c - d)", limits),
+            vec![(
+                "max-depth",
+                "expression is nested 4 levels deep, more than the limit of 3".to_string(),
+                "c"
+            )]
+        );
+    }
+
+    #[test]
+    fn reports_the_smallest_expression_over_the_node_limit() {
+        let limits = ComplexityLimits {
+            max_nodes: Some(5),
+            ..Default::default()
+        };
+        assert!(problems("a + b + c", limits.clone()).is_empty());
+        assert_eq!(
+            problems("x || s.f(a, b, c, d)", limits),
+            vec![(
+                "max-nodes",
+                "expression has 6 nodes, more than the limit of 5".to_string(),
+                "s.f(a, b, c, d)"
+            )]
+        );
+    }
+
+    #[test]
+    fn counts_written_nodes_of_macros() {
+        let ast = parse("items.all(i, i > 0)").ast.unwrap();
+        // the comprehension, its range and `i > 0`
+        assert_eq!(node_count(&ast), 5);
+    }
+
+    #[test]
+    fn reports_the_innermost_nested_comprehension() {
+        let limits = ComplexityLimits {
+            max_comprehension_nesting: Some(2),
+            ..Default::default()
+        };
+        assert!(problems("a.all(x, x.exists(y, y > 0))", limits.clone()).is_empty());
+        assert_eq!(
+            problems(
+                "a.all(x, x.exists(y, y.map(z, z + 1).size() > 0)) && b.all(x, x > 0)",
+                limits
+            ),
+            vec![(
+                "max-comprehension-nesting",
+                "comprehensions are nested 3 deep, more than the limit of 2".to_string(),
+                "y.map(z, z + 1)"
+            )]
+        );
+    }
+}
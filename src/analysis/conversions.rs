@@ -0,0 +1,205 @@
+//! Conversions of constant arguments.
+//!
+//! `timestamp("2023-13-45T00:00:00Z")` or `int("1e3")` type-check but always
+//! fail at runtime. Conversions of literals are evaluated ahead of time with
+//! the evaluator's parsing rules. `bytes()` of a string cannot fail, so for
+//! bytes the checked direction is `string()` of a bytes literal.
+
+use cel_core::time::{parse_duration, parse_timestamp};
+use cel_core::types::{Expr, UnaryOp};
+use cel_core::{SpannedExpr, Timestamp};
+
+use super::{walk, Problem};
+
+/// A constant number, possibly negated.
+#[derive(Debug, Clone, Copy)]
+enum Number {
+    Int(i128),
+    UInt(u64),
+    Double(f64),
+}
+
+fn number(expr: &SpannedExpr) -> Option<Number> {
+    match &expr.node {
+        Expr::Int(i) => Some(Number::Int(*i as i128)),
+        Expr::UInt(u) => Some(Number::UInt(*u)),
+        Expr::Float(d) => Some(Number::Double(*d)),
+        Expr::Unary {
+            op: UnaryOp::Neg,
+            expr,
+        } => match number(expr)? {
+            Number::Int(i) => Some(Number::Int(-i)),
+            Number::Double(d) => Some(Number::Double(-d)),
+            Number::UInt(_) => None,
+        },
+        _ => None,
+    }
+}
+
+/// Evaluate conversions of literal arguments and report the failing ones as
+/// `invalid-conversion` on the argument.
+pub fn conversion_problems(ast: &SpannedExpr) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    walk(ast, &mut |expr| {
+        let Expr::Call { expr: callee, args } = &expr.node else {
+            return;
+        };
+        let (Expr::Ident(function), [arg]) = (&callee.node, args.as_slice()) else {
+            return;
+        };
+        if let Some(message) = conversion_error(function, arg) {
+            problems.push(Problem {
+                code: "invalid-conversion",
+                message,
+                span: arg.span.clone(),
+            });
+        }
+    });
+    problems
+}
+
+/// The error converting the constant `arg` with `function`, if it fails.
+fn conversion_error(function: &str, arg: &SpannedExpr) -> Option<String> {
+    if let Expr::String(s) = &arg.node {
+        return string_conversion_error(function, s);
+    }
+    if let (Expr::Bytes(bytes), "string") = (&arg.node, function) {
+        return std::str::from_utf8(bytes)
+            .err()
+            .map(|e| format!("bytes are not valid UTF-8: {}", e));
+    }
+
+    let out_of_range = |target: &str| Some(format!("value is out of range for {}", target));
+    match (function, number(arg)?) {
+        ("int", Number::UInt(u)) if u > i64::MAX as u64 => out_of_range("int"),
+        ("int", Number::Double(d))
+            if !d.is_finite() || d >= i64::MAX as f64 || d <= i64::MIN as f64 =>
+        {
+            out_of_range("int")
+        }
+        ("uint", Number::Int(i)) if i < 0 => out_of_range("uint"),
+        ("uint", Number::Double(d)) if !d.is_finite() || d < 0.0 || d >= u64::MAX as f64 => {
+            out_of_range("uint")
+        }
+        ("timestamp", Number::Int(seconds))
+            if seconds < Timestamp::MIN_SECONDS as i128
+                || seconds > Timestamp::MAX_SECONDS as i128 =>
+        {
+            Some("timestamp out of range: must be between year 0001 and 9999".to_string())
+        }
+        _ => None,
+    }
+}
+
+fn string_conversion_error(function: &str, s: &str) -> Option<String> {
+    let integer = |result: Result<(), std::num::ParseIntError>, target: &str| {
+        use std::num::IntErrorKind::{NegOverflow, PosOverflow};
+        match result {
+            Ok(()) => None,
+            Err(e) if matches!(e.kind(), PosOverflow | NegOverflow) => {
+                Some(format!("'{}' is out of range for {}", s, target))
+            }
+            Err(_) if target == "uint" && s.starts_with('-') && s[1..].parse::<u64>().is_ok() => {
+                Some(format!("'{}' is out of range for uint", s))
+            }
+            Err(_) => Some(format!("'{}' is not a valid {}", s, target)),
+        }
+    };
+
+    match function {
+        "timestamp" => parse_timestamp(s).err().map(|e| {
+            let reason = e.strip_prefix("invalid timestamp format: ").unwrap_or(&e);
+            format!("'{}' is not a valid RFC 3339 timestamp: {}", s, reason)
+        }),
+        "duration" => parse_duration(s)
+            .err()
+            .map(|e| format!("'{}' is not a valid duration: {}", s, e)),
+        "int" => integer(s.parse::<i64>().map(drop), "int"),
+        "uint" => integer(s.parse::<u64>().map(drop), "uint"),
+        "double" => s
+            .parse::<f64>()
+            .err()
+            .map(|_| format!("'{}' is not a valid double", s)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cel_core::parse;
+
+    fn problems(source: &str) -> Vec<(String, &str)> {
+        let ast = parse(source).ast.unwrap();
+        conversion_problems(&ast)
+            .into_iter()
+            .map(|p| (p.message, &source[p.span]))
+            .collect()
+    }
+
+    #[test]
+    fn valid_constants_have_no_problems() {
+        let source = "timestamp('2023-01-02T03:04:05Z') + duration('1h30m') \
+             == timestamp(0) && int('-12') < uint('7') && double('1.5e3') > 0.0 \
+             && int(1.9) == int(-2.5) && uint(3.0) == uint(3) && string(b'abc') == 'abc' \
+             && int(x) == 0";
+        assert!(problems(source).is_empty(), "{:?}", problems(source));
+    }
+
+    #[test]
+    fn reports_malformed_timestamps_and_durations() {
+        let found = problems("timestamp('2023-13-45T00:00:00Z') < now && duration('1x') > d");
+        assert_eq!(
+            found,
+            vec![
+                (
+                    "'2023-13-45T00:00:00Z' is not a valid RFC 3339 timestamp: input is out of range".to_string(),
+                    "'2023-13-45T00:00:00Z'"
+                ),
+                (
+                    "'1x' is not a valid duration: invalid duration unit: 'x'".to_string(),
+                    "'1x'"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_out_of_range_numbers() {
+        let messages =
+            |source| -> Vec<String> { problems(source).into_iter().map(|(m, _)| m).collect() };
+        assert_eq!(
+            messages("int('99999999999999999999') + int('abc')"),
+            vec![
+                "'99999999999999999999' is out of range for int",
+                "'abc' is not a valid int",
+            ]
+        );
+        assert_eq!(
+            messages("uint('-1') + uint(-1) + uint(-0.5)"),
+            vec![
+                "'-1' is out of range for uint",
+                "value is out of range for uint",
+                "value is out of range for uint",
+            ]
+        );
+        assert_eq!(
+            messages("int(1e19) + int(18446744073709551615u)"),
+            vec![
+                "value is out of range for int",
+                "value is out of range for int"
+            ]
+        );
+        assert_eq!(
+            messages("timestamp(253402300800)"),
+            vec!["timestamp out of range: must be between year 0001 and 9999"]
+        );
+        assert_eq!(
+            messages("double('1.2.3') + size(string(b'\\xff'))"),
+            vec![
+                "'1.2.3' is not a valid double",
+                "bytes are not valid UTF-8: invalid utf-8 sequence of 1 bytes from index 0",
+            ]
+        );
+    }
+}
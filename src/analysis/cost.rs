@@ -0,0 +1,385 @@
+//! Static cost estimation.
+//!
+//! Estimates the worst-case cost of evaluating an expression, in the spirit of
+//! the estimator Kubernetes uses to enforce its cost budgets:
+//! - literals are free; variables, field selections, operators and function
+//!   calls cost 1
+//! - list, map and message literals cost 10, 30 and 40 plus their elements
+//! - macros cost their loop body once per element of their range
+//! - `in` on a list and string functions that scan their receiver, such as
+//!   `contains()` or `matches()`, cost in proportion to its size
+//!
+//! Sizes come from literals and from `max_sizes`, keyed by the path of a
+//! variable or field (`user.roles`), where `[]` stands for the elements of a
+//! list (`user.roles[]`). A cost that depends on a size nothing bounds is
+//! unbounded.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use cel_core::types::{BinaryOp, Expr};
+use cel_core::{CelType, CheckResult, SpannedExpr};
+
+use super::Problem;
+
+/// String functions whose cost grows with the size of the string they scan.
+const SCANNING_FUNCTIONS: &[&str] = &[
+    "contains",
+    "endsWith",
+    "find",
+    "findAll",
+    "indexOf",
+    "lastIndexOf",
+    "lowerAscii",
+    "matches",
+    "replace",
+    "split",
+    "startsWith",
+    "trim",
+    "upperAscii",
+];
+
+/// The estimated worst-case cost of an expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cost {
+    Bounded(u64),
+    /// Depends on the size of the named expression, which nothing bounds.
+    Unbounded(String),
+}
+
+impl Cost {
+    fn plus(self, other: Cost) -> Cost {
+        match (self, other) {
+            (Cost::Bounded(a), Cost::Bounded(b)) => Cost::Bounded(a.saturating_add(b)),
+            (Cost::Unbounded(s), _) | (_, Cost::Unbounded(s)) => Cost::Unbounded(s),
+        }
+    }
+
+    fn times(self, size: Size) -> Cost {
+        match (self, size) {
+            (Cost::Bounded(0), _) => Cost::Bounded(0),
+            (Cost::Bounded(a), Ok(n)) => Cost::Bounded(a.saturating_mul(n)),
+            (Cost::Bounded(_), Err(s)) | (Cost::Unbounded(s), _) => Cost::Unbounded(s),
+        }
+    }
+
+    fn max(self, other: Cost) -> Cost {
+        match (self, other) {
+            (Cost::Bounded(a), Cost::Bounded(b)) => Cost::Bounded(a.max(b)),
+            (Cost::Unbounded(s), _) | (_, Cost::Unbounded(s)) => Cost::Unbounded(s),
+        }
+    }
+}
+
+impl fmt::Display for Cost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cost::Bounded(n) => write!(f, "{}", n),
+            Cost::Unbounded(_) => write!(f, "unbounded"),
+        }
+    }
+}
+
+/// A maximum size, or the text of the expression whose size is unknown.
+type Size = Result<u64, String>;
+
+/// The cost limit and size hints expressions are estimated with.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CostBudget {
+    /// Cost above which expressions are reported.
+    pub limit: Option<u64>,
+    /// Maximum sizes by path, e.g. `user.roles` or `user.roles[]`.
+    pub max_sizes: HashMap<String, u64>,
+}
+
+/// Estimated costs of every expression in an AST.
+#[derive(Debug, Clone, Default)]
+pub struct CostEstimate {
+    costs: HashMap<i64, Cost>,
+}
+
+impl CostEstimate {
+    /// The estimated cost of the expression with `id`.
+    ///
+    /// Function names of calls carry the cost of their call.
+    pub fn cost(&self, id: i64) -> Option<&Cost> {
+        self.costs.get(&id)
+    }
+}
+
+/// Estimate the cost of `ast` and each expression in it.
+pub fn estimate_cost(
+    ast: &SpannedExpr,
+    source: &str,
+    check_result: Option<&CheckResult>,
+    max_sizes: &HashMap<String, u64>,
+) -> CostEstimate {
+    let mut estimator = Estimator {
+        source,
+        check_result,
+        max_sizes,
+        scopes: Vec::new(),
+        costs: HashMap::new(),
+    };
+    estimator.cost(ast);
+    CostEstimate {
+        costs: estimator.costs,
+    }
+}
+
+/// A `cost-limit` problem on `ast` when its estimated cost exceeds `limit`.
+pub fn cost_limit_problem(
+    ast: &SpannedExpr,
+    estimate: &CostEstimate,
+    limit: u64,
+) -> Option<Problem> {
+    let message = match estimate.cost(ast.id)? {
+        Cost::Bounded(cost) if *cost > limit => {
+            format!("estimated cost {} exceeds the limit of {}", cost, limit)
+        }
+        Cost::Bounded(_) => return None,
+        Cost::Unbounded(size_of) => format!(
+            "estimated cost is unbounded: the size of '{}' is unknown",
+            size_of
+        ),
+    };
+    Some(Problem {
+        code: "cost-limit",
+        message,
+        span: ast.span.clone(),
+    })
+}
+
+struct Estimator<'a> {
+    source: &'a str,
+    check_result: Option<&'a CheckResult>,
+    max_sizes: &'a HashMap<String, u64>,
+    /// Variables bound by enclosing macros, with the path of their values.
+    scopes: Vec<(&'a str, Option<String>)>,
+    costs: HashMap<i64, Cost>,
+}
+
+impl<'a> Estimator<'a> {
+    fn cost(&mut self, expr: &'a SpannedExpr) -> Cost {
+        let cost = match &expr.node {
+            Expr::Ident(_) | Expr::RootIdent(_) => Cost::Bounded(1),
+            Expr::Member { expr: operand, .. } | Expr::MemberTestOnly { expr: operand, .. } => {
+                self.cost(operand).plus(Cost::Bounded(1))
+            }
+            Expr::Index { expr, index, .. } => self
+                .cost(expr)
+                .plus(self.cost(index))
+                .plus(Cost::Bounded(1)),
+            Expr::List(items) => self.sum(items.iter().map(|item| &item.expr), 10),
+            Expr::Map(entries) => self.sum(entries.iter().flat_map(|e| [&e.key, &e.value]), 30),
+            Expr::Struct { fields, .. } => self.sum(fields.iter().map(|f| &f.value), 40),
+            Expr::Unary { expr, .. } => self.cost(expr).plus(Cost::Bounded(1)),
+            Expr::Binary { op, left, right } => {
+                let operands = self.cost(left).plus(self.cost(right));
+                match op {
+                    BinaryOp::And | BinaryOp::Or => operands,
+                    BinaryOp::In if matches!(self.type_of(right), Some(CelType::List(_))) => {
+                        operands.plus(Cost::Bounded(1).times(self.size(right)))
+                    }
+                    _ => operands.plus(Cost::Bounded(1)),
+                }
+            }
+            Expr::Ternary {
+                cond,
+                then_expr,
+                else_expr,
+            } => {
+                let branches = self.cost(then_expr).max(self.cost(else_expr));
+                self.cost(cond).plus(branches)
+            }
+            Expr::Call { expr: callee, args } => self.call_cost(callee, args),
+            Expr::Comprehension(comp) => {
+                let range = self.cost(&comp.iter_range);
+                let size = self.size(&comp.iter_range);
+                let elements = self.path(&comp.iter_range).map(|p| format!("{}[]", p));
+                let scopes = self.scopes.len();
+                self.scopes.push((&comp.iter_var, elements));
+                if !comp.iter_var2.is_empty() {
+                    self.scopes.push((&comp.iter_var2, None));
+                }
+                self.scopes.push((&comp.accu_var, None));
+
+                let init = self.cost(&comp.accu_init);
+                let iteration = self
+                    .cost(&comp.loop_condition)
+                    .plus(self.cost(&comp.loop_step));
+                let result = self.cost(&comp.result);
+                self.scopes.truncate(scopes);
+                range.plus(init).plus(iteration.times(size)).plus(result)
+            }
+            Expr::Bind {
+                var_name,
+                init,
+                body,
+            } => {
+                let init_cost = self.cost(init);
+                let path = self.path(init);
+                self.scopes.push((var_name, path));
+                let body_cost = self.cost(body);
+                self.scopes.pop();
+                init_cost.plus(body_cost)
+            }
+            _ => Cost::Bounded(0),
+        };
+        self.costs.insert(expr.id, cost.clone());
+        cost
+    }
+
+    fn sum(&mut self, exprs: impl Iterator<Item = &'a SpannedExpr>, base: u64) -> Cost {
+        exprs.fold(Cost::Bounded(base), |cost, e| cost.plus(self.cost(e)))
+    }
+
+    fn call_cost(&mut self, callee: &'a SpannedExpr, args: &'a [SpannedExpr]) -> Cost {
+        let (receiver, function) = match &callee.node {
+            Expr::Member { expr, field, .. } => (Some(expr.as_ref()), field.as_str()),
+            Expr::Ident(name) => (None, name.as_str()),
+            _ => (None, ""),
+        };
+        let mut cost = Cost::Bounded(1);
+        if let Some(receiver) = receiver {
+            cost = cost.plus(self.cost(receiver));
+        }
+        for arg in args {
+            cost = cost.plus(self.cost(arg));
+        }
+        if SCANNING_FUNCTIONS.contains(&function) {
+            if let Some(scanned) = receiver.or(args.first()) {
+                cost =
+                    cost.plus(Cost::Bounded(1).times(self.size(scanned).map(|n| n.div_ceil(10))));
+            }
+        }
+        self.costs.insert(callee.id, cost.clone());
+        cost
+    }
+
+    /// The maximum size of `expr`.
+    fn size(&self, expr: &SpannedExpr) -> Size {
+        match &expr.node {
+            Expr::List(items) => Ok(items.len() as u64),
+            Expr::Map(entries) => Ok(entries.len() as u64),
+            Expr::String(s) => Ok(s.chars().count() as u64),
+            Expr::Bytes(b) => Ok(b.len() as u64),
+            Expr::Binary {
+                op: BinaryOp::Add,
+                left,
+                right,
+            } => Ok(self.size(left)?.saturating_add(self.size(right)?)),
+            // `map()` and `filter()` produce at most one element per element
+            Expr::Comprehension(comp) => self.size(&comp.iter_range),
+            _ => {
+                let path = self.path(expr).unwrap_or_else(|| {
+                    self.source
+                        .get(expr.span.clone())
+                        .unwrap_or("?")
+                        .to_string()
+                });
+                self.max_sizes.get(&path).copied().ok_or(path)
+            }
+        }
+    }
+
+    /// The path of a variable or field selection, e.g. `user.roles`.
+    fn path(&self, expr: &SpannedExpr) -> Option<String> {
+        match &expr.node {
+            Expr::Ident(name) => match self.scopes.iter().rev().find(|(var, _)| var == name) {
+                Some((_, path)) => path.clone(),
+                None => Some(name.clone()),
+            },
+            Expr::RootIdent(name) => Some(name.clone()),
+            Expr::Member {
+                expr,
+                field,
+                optional: false,
+            } => Some(format!("{}.{}", self.path(expr)?, field)),
+            _ => None,
+        }
+    }
+
+    fn type_of(&self, expr: &SpannedExpr) -> Option<&'a CelType> {
+        self.check_result?.type_map.get(&expr.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cel_core::{parse, Env};
+
+    fn estimate(source: &str, max_sizes: &[(&str, u64)]) -> Cost {
+        let env = Env::with_standard_library()
+            .with_variable("name", CelType::String)
+            .with_variable("items", CelType::list(CelType::String))
+            .with_variable("tags", CelType::map(CelType::String, CelType::String));
+        let ast = parse(source).ast.unwrap();
+        let result = env.check(&ast);
+        let max_sizes = max_sizes
+            .iter()
+            .map(|(path, size)| (path.to_string(), *size))
+            .collect();
+        let estimate = estimate_cost(&ast, source, Some(&result), &max_sizes);
+        estimate.cost(ast.id).unwrap().clone()
+    }
+
+    #[test]
+    fn simple_expressions_are_cheap() {
+        assert_eq!(estimate("1 + 2", &[]), Cost::Bounded(1));
+        assert_eq!(
+            estimate("name == 'a' && size(name) > 3", &[]),
+            Cost::Bounded(5)
+        );
+        assert_eq!(estimate("[1, 2].size()", &[]), Cost::Bounded(11));
+        assert_eq!(estimate("'abc' in tags", &[]), Cost::Bounded(2));
+    }
+
+    #[test]
+    fn sizes_scale_scans_and_macros() {
+        assert_eq!(
+            estimate("name.contains('x')", &[]),
+            Cost::Unbounded("name".to_string())
+        );
+        assert_eq!(
+            estimate("name.contains('x')", &[("name", 95)]),
+            Cost::Bounded(12)
+        );
+        assert_eq!(
+            estimate("items.all(i, i.startsWith('a'))", &[("items", 10)]),
+            Cost::Unbounded("items[]".to_string())
+        );
+        // range 1 + result 1 + 10 * (condition 2 + step: accumulator 1 + call 1 + i 1 + scan 2)
+        assert_eq!(
+            estimate(
+                "items.all(i, i.startsWith('a'))",
+                &[("items", 10), ("items[]", 20)]
+            ),
+            Cost::Bounded(62)
+        );
+        assert_eq!(
+            estimate("'a' in items", &[]),
+            Cost::Unbounded("items".to_string())
+        );
+        assert_eq!(estimate("'a' in ['a', 'b']", &[]), Cost::Bounded(12));
+    }
+
+    #[test]
+    fn reports_costs_over_the_limit() {
+        let ast = parse("name.contains('x')").ast.unwrap();
+        let sizes = HashMap::from([("name".to_string(), 95)]);
+        let estimate = estimate_cost(&ast, "name.contains('x')", None, &sizes);
+        assert_eq!(cost_limit_problem(&ast, &estimate, 12), None);
+        assert_eq!(
+            cost_limit_problem(&ast, &estimate, 10).unwrap().message,
+            "estimated cost 12 exceeds the limit of 10"
+        );
+
+        let estimate = estimate_cost(&ast, "name.contains('x')", None, &HashMap::new());
+        assert_eq!(
+            cost_limit_problem(&ast, &estimate, 10).unwrap().message,
+            "estimated cost is unbounded: the size of 'name' is unknown"
+        );
+    }
+}
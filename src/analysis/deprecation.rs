@@ -0,0 +1,178 @@
+//! References to deprecated protobuf fields and enum values.
+//!
+//! Fields and enum values declared with `[deprecated = true]` still work, but
+//! are scheduled for removal. Selecting or setting such a field, or naming
+//! such an enum value, is reported as `deprecated` at the field or value name.
+
+use std::ops::Range;
+
+use cel_core::types::Expr;
+use cel_core::{CelType, CheckResult, ProtoRegistry, SpannedExpr};
+
+use super::{walk, Problem};
+use crate::types::{is_deprecated_enum_value, is_deprecated_field};
+
+/// Report the deprecated fields and enum values `ast` refers to.
+pub fn deprecation_problems(
+    ast: &SpannedExpr,
+    source: &str,
+    check_result: &CheckResult,
+    registry: &dyn ProtoRegistry,
+) -> Vec<Problem> {
+    let message_of = |expr: &SpannedExpr| match check_result.type_map.get(&expr.id) {
+        Some(CelType::Message(name)) => Some(name.to_string()),
+        _ => None,
+    };
+
+    let mut problems: Vec<Problem> = Vec::new();
+    let mut report = |message: String, span: Option<Range<usize>>| {
+        if let Some(span) = span {
+            if !problems.iter().any(|p| p.span == span) {
+                problems.push(Problem {
+                    code: "deprecated",
+                    message,
+                    span,
+                });
+            }
+        }
+    };
+
+    walk(ast, &mut |expr| {
+        // Qualified enum values are member selections resolved by the checker
+        if let Some(reference) = check_result.reference_map.get(&expr.id) {
+            if let (Some(enum_name), Some(_)) = (&reference.enum_type, &reference.value) {
+                let value = reference.name.rsplit('.').next().unwrap_or(&reference.name);
+                if is_deprecated_enum_value(registry, enum_name, value) {
+                    report(
+                        format!("enum value '{}.{}' is deprecated", enum_name, value),
+                        last_occurrence(source, expr.span.clone(), value),
+                    );
+                }
+                return;
+            }
+        }
+
+        match &expr.node {
+            Expr::Member {
+                expr: inner, field, ..
+            }
+            | Expr::MemberTestOnly { expr: inner, field } => {
+                if let Some(message) = message_of(inner) {
+                    if is_deprecated_field(registry, &message, field) {
+                        report(
+                            format!("field '{}.{}' is deprecated", message, field),
+                            last_occurrence(source, expr.span.clone(), field),
+                        );
+                    }
+                }
+            }
+            Expr::Struct { fields, .. } => {
+                let Some(message) = message_of(expr) else {
+                    return;
+                };
+                let mut start = expr.span.start;
+                for field in fields {
+                    if is_deprecated_field(registry, &message, &field.name) {
+                        report(
+                            format!("field '{}.{}' is deprecated", message, field.name),
+                            last_occurrence(source, start..field.value.span.start, &field.name),
+                        );
+                    }
+                    start = field.value.span.end;
+                }
+            }
+            _ => {}
+        }
+    });
+    problems
+}
+
+/// The span of the last occurrence of `name` within `range` of `source`.
+fn last_occurrence(source: &str, range: Range<usize>, name: &str) -> Option<Range<usize>> {
+    let start = range.start + source.get(range)?.rfind(name)?;
+    Some(start..start + name.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{Buffers, ProtoInputs};
+    use cel_core::Env;
+    use cel_core_proto::ProstProtoRegistry;
+    use std::sync::Arc;
+
+    const USER_PROTO: &str = r#"syntax = "proto3";
+package acme;
+
+message User {
+    string name = 1;
+    string nickname = 2 [deprecated = true];
+    Role role = 3;
+
+    enum Role {
+        ROLE_UNSPECIFIED = 0;
+        ROLE_GUEST = 1 [deprecated = true];
+    }
+}
+"#;
+
+    fn problems(source: &str) -> Vec<(String, &str)> {
+        let inputs = ProtoInputs {
+            bundled: vec![("user.proto".to_string(), USER_PROTO.to_string())],
+            ..Default::default()
+        };
+        let (pool, errors) = inputs.build(&Buffers::new());
+        assert!(errors.is_empty(), "{:?}", errors);
+        let env = Env::with_standard_library()
+            .with_container("acme")
+            .with_proto_registry(Arc::new(ProstProtoRegistry::from_pool(pool)))
+            .with_variable("user", CelType::message("acme.User"));
+        let ast = env.compile(source).unwrap();
+        deprecation_problems(
+            ast.expr(),
+            source,
+            ast.type_info().unwrap(),
+            env.proto_registry().unwrap(),
+        )
+        .into_iter()
+        .map(|p| (p.message, &source[p.span]))
+        .collect()
+    }
+
+    #[test]
+    fn reports_deprecated_fields() {
+        assert!(problems("user.name == ''").is_empty());
+        assert_eq!(
+            problems("has(user.nickname) && user.nickname != ''"),
+            vec![
+                (
+                    "field 'acme.User.nickname' is deprecated".to_string(),
+                    "nickname"
+                ),
+                (
+                    "field 'acme.User.nickname' is deprecated".to_string(),
+                    "nickname"
+                ),
+            ]
+        );
+        assert_eq!(
+            problems("User{name: 'a', nickname: 'b'}.name"),
+            vec![(
+                "field 'acme.User.nickname' is deprecated".to_string(),
+                "nickname"
+            )]
+        );
+    }
+
+    #[test]
+    fn reports_deprecated_enum_values() {
+        assert!(problems("user.role == User.Role.ROLE_UNSPECIFIED").is_empty());
+        assert_eq!(
+            problems("user.role == User.Role.ROLE_GUEST"),
+            vec![(
+                "enum value 'acme.User.Role.ROLE_GUEST' is deprecated".to_string(),
+                "ROLE_GUEST"
+            )]
+        );
+    }
+}
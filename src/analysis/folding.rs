@@ -0,0 +1,527 @@
+//! Constant folding.
+//!
+//! Sub-expressions are folded to constants where their operands allow it.
+//! This finds conditions with only one possible outcome (`x == x`,
+//! `size(s) >= 0`, `true || c`), branches that are never taken and arithmetic
+//! on constants that always fails (`1 / 0`, integer overflow).
+//!
+//! Macro expansions refer to their accumulator; operations on it are
+//! generated code and are folded but never reported.
+
+use std::cmp::Ordering;
+use std::ops::Range;
+
+use cel_core::types::{BinaryOp, Expr, UnaryOp};
+use cel_core::{CelType, CheckResult, SpannedExpr};
+
+use super::{children, Problem};
+
+/// A constant value.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Double(f64),
+    String(String),
+    Bytes(Vec<u8>),
+}
+
+struct Folder<'a> {
+    source: &'a str,
+    check_result: Option<&'a CheckResult>,
+    /// Accumulators of the enclosing comprehensions.
+    accumulators: Vec<&'a str>,
+    /// Spans of non-literal expressions with a constant boolean value.
+    conditions: Vec<(Range<usize>, bool)>,
+    problems: Vec<Problem>,
+}
+
+/// Fold constants in `ast` and report constant conditions
+/// (`constant-condition`), branches and operands that never affect the result
+/// (`unreachable-branch`) and arithmetic that always fails
+/// (`arithmetic-error`).
+///
+/// Types from `check_result` decide which comparisons of an expression with
+/// itself are constant; without it only literals are folded.
+pub fn constant_problems(
+    ast: &SpannedExpr,
+    source: &str,
+    check_result: Option<&CheckResult>,
+) -> Vec<Problem> {
+    let mut folder = Folder {
+        source,
+        check_result,
+        accumulators: Vec::new(),
+        conditions: Vec::new(),
+        problems: Vec::new(),
+    };
+    folder.fold(ast);
+
+    // Only the outermost constant condition is reported
+    let conditions = &folder.conditions;
+    let mut problems: Vec<_> = conditions
+        .iter()
+        .filter(|(span, _)| {
+            !conditions.iter().any(|(outer, _)| {
+                outer != span && outer.start <= span.start && span.end <= outer.end
+            })
+        })
+        .map(|(span, value)| Problem {
+            code: "constant-condition",
+            message: format!("condition is always {}", value),
+            span: with_parentheses(source, span.clone()),
+        })
+        .collect();
+    problems.dedup();
+    problems.extend(folder.problems.into_iter().map(|p| Problem {
+        span: with_parentheses(source, p.span),
+        ..p
+    }));
+    problems.sort_by_key(|p| p.span.start);
+    problems
+}
+
+impl<'a> Folder<'a> {
+    fn fold(&mut self, expr: &'a SpannedExpr) -> Option<Value> {
+        let value = match &expr.node {
+            Expr::Null => Some(Value::Null),
+            // Literal conditions are deliberate
+            Expr::Bool(b) => return Some(Value::Bool(*b)),
+            Expr::Int(i) => Some(Value::Int(*i)),
+            Expr::UInt(u) => Some(Value::UInt(*u)),
+            Expr::Float(d) => Some(Value::Double(*d)),
+            Expr::String(s) => Some(Value::String(s.clone())),
+            Expr::Bytes(b) => Some(Value::Bytes(b.clone())),
+            Expr::Unary { op, expr: operand } => match (op, self.fold(operand)?) {
+                (UnaryOp::Not, Value::Bool(b)) => Some(Value::Bool(!b)),
+                (UnaryOp::Neg, Value::Int(i)) => self.checked(
+                    expr,
+                    i.checked_neg().map(Value::Int),
+                    "integer negation overflow",
+                ),
+                (UnaryOp::Neg, Value::Double(d)) => Some(Value::Double(-d)),
+                _ => None,
+            },
+            Expr::Binary { op, left, right } => self.fold_binary(expr, *op, left, right),
+            Expr::Ternary {
+                cond,
+                then_expr,
+                else_expr,
+            } => {
+                let condition = self.fold(cond);
+                let then_value = self.fold(then_expr);
+                let else_value = self.fold(else_expr);
+                match condition {
+                    Some(Value::Bool(c)) if !self.generated(&[then_expr, else_expr]) => {
+                        let (taken, untaken) = if c {
+                            (then_value, else_expr)
+                        } else {
+                            (else_value, then_expr)
+                        };
+                        self.problems.push(Problem {
+                            code: "unreachable-branch",
+                            message: format!(
+                                "branch is never taken: the condition is always {}",
+                                c
+                            ),
+                            span: untaken.span.clone(),
+                        });
+                        taken
+                    }
+                    _ => None,
+                }
+            }
+            Expr::Comprehension(comp) => {
+                self.accumulators.push(&comp.accu_var);
+                for child in children(expr) {
+                    self.fold(child);
+                }
+                self.accumulators.pop();
+                None
+            }
+            _ => {
+                for child in children(expr) {
+                    self.fold(child);
+                }
+                None
+            }
+        };
+        if let Some(Value::Bool(b)) = value {
+            self.conditions.push((expr.span.clone(), b));
+        }
+        value
+    }
+
+    fn fold_binary(
+        &mut self,
+        expr: &'a SpannedExpr,
+        op: BinaryOp,
+        left: &'a SpannedExpr,
+        right: &'a SpannedExpr,
+    ) -> Option<Value> {
+        let l = self.fold(left);
+        let r = self.fold(right);
+        if self.generated(&[left, right]) {
+            return None;
+        }
+
+        match op {
+            BinaryOp::And | BinaryOp::Or => {
+                // `true` decides `||` and `false` decides `&&`, whichever side it is on
+                let decisive = op == BinaryOp::Or;
+                let decides = |v: &Option<Value>| *v == Some(Value::Bool(decisive));
+                let unused = match (decides(&l), decides(&r)) {
+                    (false, false) => {
+                        return match (l?, r?) {
+                            (Value::Bool(_), Value::Bool(_)) => Some(Value::Bool(!decisive)),
+                            _ => None,
+                        };
+                    }
+                    (true, false) => r.is_none().then_some(right),
+                    (false, true) => l.is_none().then_some(left),
+                    (true, true) => None,
+                };
+                if let Some(operand) = unused {
+                    self.problems.push(Problem {
+                        code: "unreachable-branch",
+                        message: format!(
+                            "operand never affects the result: the other operand is always {}",
+                            decisive
+                        ),
+                        span: operand.span.clone(),
+                    });
+                }
+                Some(Value::Bool(decisive))
+            }
+            BinaryOp::Eq
+            | BinaryOp::Ne
+            | BinaryOp::Lt
+            | BinaryOp::Le
+            | BinaryOp::Gt
+            | BinaryOp::Ge => {
+                if let (Some(l), Some(r)) = (&l, &r) {
+                    return compare(op, l, r).map(Value::Bool);
+                }
+                if self.same_operands(left, right) {
+                    return Some(Value::Bool(matches!(
+                        op,
+                        BinaryOp::Eq | BinaryOp::Le | BinaryOp::Ge
+                    )));
+                }
+                let zero = |v: &Option<Value>| matches!(v, Some(Value::Int(0) | Value::UInt(0)));
+                match op {
+                    BinaryOp::Ge if self.non_negative(left) && zero(&r) => Some(Value::Bool(true)),
+                    BinaryOp::Lt if self.non_negative(left) && zero(&r) => Some(Value::Bool(false)),
+                    BinaryOp::Le if zero(&l) && self.non_negative(right) => Some(Value::Bool(true)),
+                    BinaryOp::Gt if zero(&l) && self.non_negative(right) => {
+                        Some(Value::Bool(false))
+                    }
+                    _ => None,
+                }
+            }
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => {
+                // Integer division by zero fails whatever the dividend
+                if matches!(op, BinaryOp::Div | BinaryOp::Mod)
+                    && matches!(r, Some(Value::Int(0) | Value::UInt(0)))
+                {
+                    let message = if op == BinaryOp::Div {
+                        "division by zero"
+                    } else {
+                        "modulo by zero"
+                    };
+                    return self.checked(expr, None, message);
+                }
+                self.fold_arithmetic(expr, op, l?, r?)
+            }
+            _ => None,
+        }
+    }
+
+    fn fold_arithmetic(
+        &mut self,
+        expr: &SpannedExpr,
+        op: BinaryOp,
+        l: Value,
+        r: Value,
+    ) -> Option<Value> {
+        use Value::{Bytes, Double, Int, UInt};
+        let (value, overflow) = match (op, l, r) {
+            (BinaryOp::Add, Int(a), Int(b)) => (a.checked_add(b).map(Int), "integer addition"),
+            (BinaryOp::Sub, Int(a), Int(b)) => (a.checked_sub(b).map(Int), "integer subtraction"),
+            (BinaryOp::Mul, Int(a), Int(b)) => {
+                (a.checked_mul(b).map(Int), "integer multiplication")
+            }
+            (BinaryOp::Div, Int(a), Int(b)) => (a.checked_div(b).map(Int), "integer division"),
+            (BinaryOp::Mod, Int(a), Int(b)) => (a.checked_rem(b).map(Int), "integer modulo"),
+            (BinaryOp::Add, UInt(a), UInt(b)) => (a.checked_add(b).map(UInt), "unsigned addition"),
+            (BinaryOp::Sub, UInt(a), UInt(b)) => {
+                (a.checked_sub(b).map(UInt), "unsigned subtraction")
+            }
+            (BinaryOp::Mul, UInt(a), UInt(b)) => {
+                (a.checked_mul(b).map(UInt), "unsigned multiplication")
+            }
+            (BinaryOp::Div, UInt(a), UInt(b)) => (a.checked_div(b).map(UInt), "unsigned division"),
+            (BinaryOp::Mod, UInt(a), UInt(b)) => (a.checked_rem(b).map(UInt), "unsigned modulo"),
+            (BinaryOp::Add, Double(a), Double(b)) => return Some(Double(a + b)),
+            (BinaryOp::Sub, Double(a), Double(b)) => return Some(Double(a - b)),
+            (BinaryOp::Mul, Double(a), Double(b)) => return Some(Double(a * b)),
+            (BinaryOp::Div, Double(a), Double(b)) => return Some(Double(a / b)),
+            (BinaryOp::Add, Value::String(a), Value::String(b)) => {
+                return Some(Value::String(a + &b))
+            }
+            (BinaryOp::Add, Bytes(mut a), Bytes(b)) => {
+                a.extend(b);
+                return Some(Bytes(a));
+            }
+            _ => return None,
+        };
+        self.checked(expr, value, &format!("{} overflow", overflow))
+    }
+
+    /// `value`, or an `arithmetic-error` on `expr` when it is `None`.
+    fn checked(
+        &mut self,
+        expr: &SpannedExpr,
+        value: Option<Value>,
+        message: &str,
+    ) -> Option<Value> {
+        if value.is_none() {
+            self.problems.push(Problem {
+                code: "arithmetic-error",
+                message: message.to_string(),
+                span: expr.span.clone(),
+            });
+        }
+        value
+    }
+
+    /// Whether any of `operands` is the accumulator of an enclosing macro.
+    fn generated(&self, operands: &[&SpannedExpr]) -> bool {
+        operands.iter().any(|operand| {
+            matches!(&operand.node, Expr::Ident(name) if self.accumulators.contains(&name.as_str()))
+        })
+    }
+
+    /// Whether `left` and `right` are the same expression of a type that
+    /// equals itself. Doubles are excluded since NaN does not.
+    fn same_operands(&self, left: &SpannedExpr, right: &SpannedExpr) -> bool {
+        let reflexive = matches!(
+            self.type_of(left),
+            Some(
+                CelType::Bool
+                    | CelType::Int
+                    | CelType::UInt
+                    | CelType::String
+                    | CelType::Bytes
+                    | CelType::Timestamp
+                    | CelType::Duration
+                    | CelType::Null
+                    | CelType::Enum(_)
+            )
+        );
+        let text = |e: &SpannedExpr| self.source.get(e.span.clone());
+        reflexive
+            && left.span.end <= right.span.start
+            && text(left).is_some()
+            && text(left) == text(right)
+    }
+
+    /// Whether `expr` can never be negative: a `size()` or a `uint`.
+    fn non_negative(&self, expr: &SpannedExpr) -> bool {
+        let is_size = match &expr.node {
+            Expr::Call { expr: callee, args } => match (&callee.node, args.len()) {
+                (Expr::Ident(name), 1) | (Expr::Member { field: name, .. }, 0) => name == "size",
+                _ => false,
+            },
+            _ => false,
+        };
+        is_size || self.type_of(expr) == Some(&CelType::UInt)
+    }
+
+    fn type_of(&self, expr: &SpannedExpr) -> Option<&'a CelType> {
+        self.check_result?.type_map.get(&expr.id)
+    }
+}
+
+/// Widen `span` over the parentheses it leaves unbalanced.
+///
+/// Spans of expressions exclude their own parentheses, so an operation whose
+/// operands are parenthesized starts or ends inside a group.
+fn with_parentheses(source: &str, span: Range<usize>) -> Range<usize> {
+    let Some(text) = source.get(span.clone()) else {
+        return span;
+    };
+    let (mut depth, mut lowest) = (0i32, 0i32);
+    let mut quote = None;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(_), '\\') => {
+                chars.next();
+            }
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => {
+                depth -= 1;
+                lowest = lowest.min(depth);
+            }
+            _ => {}
+        }
+    }
+
+    let (mut start, mut end) = (span.start, span.end);
+    for _ in 0..-lowest {
+        match source[..start].trim_end().strip_suffix('(') {
+            Some(before) => start = before.len(),
+            None => return span,
+        }
+    }
+    for _ in 0..depth - lowest {
+        match source[end..].trim_start().strip_prefix(')') {
+            Some(after) => end = source.len() - after.len(),
+            None => return span,
+        }
+    }
+    start..end
+}
+
+/// Compare two constants, if they are comparable.
+fn compare(op: BinaryOp, l: &Value, r: &Value) -> Option<bool> {
+    let ordering = match (l, r) {
+        (Value::Null, Value::Null) if matches!(op, BinaryOp::Eq | BinaryOp::Ne) => {
+            Some(Ordering::Equal)
+        }
+        (Value::Bool(a), Value::Bool(b)) => a.partial_cmp(b),
+        (Value::Int(a), Value::Int(b)) => a.partial_cmp(b),
+        (Value::UInt(a), Value::UInt(b)) => a.partial_cmp(b),
+        (Value::Double(a), Value::Double(b)) => a.partial_cmp(b),
+        (Value::String(a), Value::String(b)) => a.partial_cmp(b),
+        (Value::Bytes(a), Value::Bytes(b)) => a.partial_cmp(b),
+        _ => return None,
+    };
+    // NaN is unordered: only `!=` holds
+    Some(match (op, ordering) {
+        (BinaryOp::Ne, ordering) => ordering != Some(Ordering::Equal),
+        (_, None) => false,
+        (BinaryOp::Eq, Some(o)) => o == Ordering::Equal,
+        (BinaryOp::Lt, Some(o)) => o == Ordering::Less,
+        (BinaryOp::Le, Some(o)) => o != Ordering::Greater,
+        (BinaryOp::Gt, Some(o)) => o == Ordering::Greater,
+        (BinaryOp::Ge, Some(o)) => o != Ordering::Less,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cel_core::{parse, Env};
+
+    fn problems(source: &str) -> Vec<(&'static str, String, &str)> {
+        let env = Env::with_standard_library()
+            .with_variable("x", CelType::Int)
+            .with_variable("d", CelType::Double)
+            .with_variable("s", CelType::String)
+            .with_variable("u", CelType::UInt)
+            .with_variable("items", CelType::list(CelType::Int));
+        let ast = parse(source).ast.unwrap();
+        let result = env.check(&ast);
+        constant_problems(&ast, source, Some(&result))
+            .into_iter()
+            .map(|p| (p.code, p.message, &source[p.span]))
+            .collect()
+    }
+
+    #[test]
+    fn ordinary_expressions_have_no_problems() {
+        let source = "x > 1 && size(s) > 0 && d == d && u + 1u > 2u && x / 2 == 1 \
+             && items.all(i, i > 0) && items.filter(i, true).size() > 0 \
+             && items.exists_one(i, true) && (x > 0 ? s : 'none') != ''";
+        assert!(problems(source).is_empty(), "{:?}", problems(source));
+    }
+
+    #[test]
+    fn reports_tautologies_and_contradictions() {
+        let condition = |source| {
+            let found = problems(source);
+            assert_eq!(found.len(), 1, "{:?}", found);
+            (found[0].1.clone(), found[0].2)
+        };
+        let always = |value: &str| format!("condition is always {}", value);
+        assert_eq!(condition("x == x && x > 1"), (always("true"), "x == x"));
+        assert_eq!(
+            condition("s.size() >= 0"),
+            (always("true"), "s.size() >= 0")
+        );
+        assert_eq!(
+            condition("0 > size(items)"),
+            (always("false"), "0 > size(items)")
+        );
+        assert_eq!(condition("u < 0u"), (always("false"), "u < 0u"));
+        assert_eq!(
+            condition("(1 + 1 != 2) || x > 1"),
+            (always("false"), "1 + 1 != 2")
+        );
+    }
+
+    #[test]
+    fn reports_unused_operands_and_branches() {
+        assert_eq!(
+            problems("(true || x > 1) && (x < 2 && false) ? 'a' : 'b'"),
+            vec![
+                (
+                    "constant-condition",
+                    "condition is always false".to_string(),
+                    "(true || x > 1) && (x < 2 && false)"
+                ),
+                (
+                    "unreachable-branch",
+                    "operand never affects the result: the other operand is always true"
+                        .to_string(),
+                    "x > 1"
+                ),
+                (
+                    "unreachable-branch",
+                    "operand never affects the result: the other operand is always false"
+                        .to_string(),
+                    "x < 2"
+                ),
+                (
+                    "unreachable-branch",
+                    "branch is never taken: the condition is always false".to_string(),
+                    "'a'"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_arithmetic_that_always_fails() {
+        assert_eq!(
+            problems("1 / 0 + x % 0 + (9223372036854775807 + 1) + -(-9223372036854775808) > 0 || 0u - 1u > 0u"),
+            vec![
+                ("arithmetic-error", "division by zero".to_string(), "1 / 0"),
+                ("arithmetic-error", "modulo by zero".to_string(), "x % 0"),
+                (
+                    "arithmetic-error",
+                    "integer addition overflow".to_string(),
+                    "9223372036854775807 + 1"
+                ),
+                (
+                    "arithmetic-error",
+                    "integer negation overflow".to_string(),
+                    "-(-9223372036854775808)"
+                ),
+                (
+                    "arithmetic-error",
+                    "unsigned subtraction overflow".to_string(),
+                    "0u - 1u"
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,79 @@
+//! Static analysis of expressions beyond type checking.
+//!
+//! Finds problems the checker accepts but evaluation would reject:
+//! - `re2` compiles regular expression literals
+//! - `conversions` evaluates conversions of constant arguments
+//! - `folding` folds constants to find conditions with a single outcome
+//! - `cost` estimates the worst-case cost of evaluation
+//! - `complexity` enforces limits on the structure of expressions
+//! - `deprecation` finds references to deprecated fields and enum values
+
+mod complexity;
+mod conversions;
+mod cost;
+mod deprecation;
+mod folding;
+mod re2;
+
+use std::ops::Range;
+
+use cel_core::{types::Expr, SpannedExpr};
+
+pub use complexity::{complexity_problems, ComplexityLimits};
+pub use conversions::conversion_problems;
+pub use cost::{cost_limit_problem, estimate_cost, Cost, CostBudget, CostEstimate};
+pub use deprecation::deprecation_problems;
+pub use folding::constant_problems;
+pub use re2::{regex_literals, regex_problems, structure_spans, RegexLiteral};
+
+/// A problem found by analysis, with a span in the CEL source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    /// Diagnostic code, e.g. `invalid-regex`.
+    pub code: &'static str,
+    /// Human-readable description of the problem.
+    pub message: String,
+    /// Span in the CEL source.
+    pub span: Range<usize>,
+}
+
+/// The direct sub-expressions of `expr`.
+fn children(expr: &SpannedExpr) -> Vec<&SpannedExpr> {
+    match &expr.node {
+        Expr::List(items) => items.iter().map(|item| &item.expr).collect(),
+        Expr::Map(entries) => entries
+            .iter()
+            .flat_map(|entry| [&entry.key, &entry.value])
+            .collect(),
+        Expr::Unary { expr, .. } | Expr::Member { expr, .. } => vec![expr],
+        Expr::MemberTestOnly { expr, .. } => vec![expr],
+        Expr::Binary { left, right, .. } => vec![left, right],
+        Expr::Ternary {
+            cond,
+            then_expr,
+            else_expr,
+        } => vec![cond, then_expr, else_expr],
+        Expr::Index { expr, index, .. } => vec![expr, index],
+        Expr::Call { expr, args } => std::iter::once(expr.as_ref()).chain(args).collect(),
+        Expr::Struct { type_name, fields } => std::iter::once(type_name.as_ref())
+            .chain(fields.iter().map(|field| &field.value))
+            .collect(),
+        Expr::Comprehension(comp) => vec![
+            &comp.iter_range,
+            &comp.accu_init,
+            &comp.loop_condition,
+            &comp.loop_step,
+            &comp.result,
+        ],
+        Expr::Bind { init, body, .. } => vec![init, body],
+        _ => Vec::new(),
+    }
+}
+
+/// Call `f` on `expr` and every expression below it.
+pub(crate) fn walk<'a>(expr: &'a SpannedExpr, f: &mut impl FnMut(&'a SpannedExpr)) {
+    f(expr);
+    for child in children(expr) {
+        walk(child, f);
+    }
+}
@@ -0,0 +1,384 @@
+//! Static analysis of regular expression literals.
+//!
+//! String literals passed as the pattern of `matches()` or of the `find` and
+//! `findAll` extensions are compiled at analysis time. CEL evaluates them with
+//! RE2, so besides syntax errors, features RE2 lacks (look-around,
+//! backreferences, class set operations, ...) are reported. Spans are mapped
+//! back from the decoded pattern to the literal in the CEL source.
+
+use std::ops::Range;
+
+use cel_core::{types::Expr, SpannedExpr};
+use regex_syntax::ast::{self, parse::Parser, Ast, ClassSet, ClassSetItem};
+use regex_syntax::hir::translate::Translator;
+
+use super::{walk, Problem};
+
+/// Functions whose pattern argument is a regular expression.
+const REGEX_FUNCTIONS: &[&str] = &["matches", "find", "findAll"];
+
+/// A string literal used as a regular expression.
+#[derive(Debug, Clone)]
+pub struct RegexLiteral {
+    /// The decoded pattern.
+    pub pattern: String,
+    /// Span of the whole literal in the CEL source.
+    pub span: Range<usize>,
+    /// Source offset of each pattern byte, plus one for the end.
+    offsets: Vec<usize>,
+}
+
+impl RegexLiteral {
+    /// Map a span of the pattern to the CEL source.
+    pub fn to_source(&self, span: &Range<usize>) -> Range<usize> {
+        self.offsets[span.start]..self.offsets[span.end]
+    }
+}
+
+/// Find the string literals of `ast` used as regular expressions.
+///
+/// Literals whose decoding does not match the parser's (which should not
+/// happen) are skipped rather than reported at the wrong place.
+pub fn regex_literals(ast: &SpannedExpr, source: &str) -> Vec<RegexLiteral> {
+    let mut literals = Vec::new();
+    walk(ast, &mut |expr| {
+        let Expr::Call { expr: callee, args } = &expr.node else {
+            return;
+        };
+        // `s.matches(re)` or `matches(s, re)`
+        let pattern = match &callee.node {
+            Expr::Member { field, .. } if REGEX_FUNCTIONS.contains(&field.as_str()) => args.first(),
+            Expr::Ident(name) if name == "matches" => args.get(1),
+            _ => None,
+        };
+        if let Some(literal) = pattern.and_then(|arg| decode_literal(arg, source)) {
+            literals.push(literal);
+        }
+    });
+    literals
+}
+
+/// Decode a string literal expression, recording where each byte came from.
+fn decode_literal(expr: &SpannedExpr, source: &str) -> Option<RegexLiteral> {
+    let Expr::String(value) = &expr.node else {
+        return None;
+    };
+    let text = source.get(expr.span.clone())?;
+    let (raw, text_start) = match text.strip_prefix(['r', 'R']) {
+        Some(rest) => (true, text.len() - rest.len()),
+        None => (false, 0),
+    };
+    let quoted = &text[text_start..];
+    let quote_len = if quoted.starts_with("'''") || quoted.starts_with("\"\"\"") {
+        3
+    } else {
+        1
+    };
+    let body_start = text_start + quote_len;
+    let body_end = text.len().checked_sub(quote_len)?;
+    let body = text.get(body_start..body_end)?;
+    let base = expr.span.start + body_start;
+
+    let mut pattern = String::new();
+    let mut offsets = Vec::new();
+    let mut chars = body.char_indices();
+    while let Some((start, c)) = chars.next() {
+        let decoded = if raw || c != '\\' {
+            c
+        } else {
+            let (_, escape) = chars.next()?;
+            let mut take = |n: usize| -> Option<String> {
+                (0..n).map(|_| chars.next().map(|(_, c)| c)).collect()
+            };
+            match escape {
+                '\\' | '/' | '"' | '\'' | '`' | '?' => escape,
+                'a' => '\x07',
+                'b' => '\x08',
+                'f' => '\x0C',
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                'v' => '\x0B',
+                'x' | 'X' => u8::from_str_radix(&take(2)?, 16).ok()? as char,
+                'u' => char::from_u32(u32::from_str_radix(&take(4)?, 16).ok()?)?,
+                'U' => char::from_u32(u32::from_str_radix(&take(8)?, 16).ok()?)?,
+                '0'..='3' => {
+                    let octal = format!("{}{}", escape, take(2)?);
+                    u8::from_str_radix(&octal, 8).ok()? as char
+                }
+                _ => return None,
+            }
+        };
+        pattern.push(decoded);
+        offsets.extend(std::iter::repeat_n(base + start, decoded.len_utf8()));
+    }
+    offsets.push(base + body.len());
+
+    (pattern == *value).then(|| RegexLiteral {
+        pattern,
+        span: expr.span.clone(),
+        offsets,
+    })
+}
+
+/// Compile every regular expression literal of `ast` and report its problems
+/// as `invalid-regex` or `unsupported-regex`.
+pub fn regex_problems(ast: &SpannedExpr, source: &str) -> Vec<Problem> {
+    regex_literals(ast, source)
+        .iter()
+        .flat_map(|literal| {
+            pattern_problems(&literal.pattern)
+                .into_iter()
+                .map(move |(code, message, span)| Problem {
+                    code,
+                    message,
+                    span: literal.to_source(&span),
+                })
+        })
+        .collect()
+}
+
+/// Problems of a pattern as `(code, message, pattern span)`.
+fn pattern_problems(pattern: &str) -> Vec<(&'static str, String, Range<usize>)> {
+    let parsed = match Parser::new().parse(pattern) {
+        Ok(parsed) => parsed,
+        Err(error) => {
+            let code = match error.kind() {
+                ast::ErrorKind::UnsupportedLookAround
+                | ast::ErrorKind::UnsupportedBackreference => "unsupported-regex",
+                _ => "invalid-regex",
+            };
+            return vec![(code, error.kind().to_string(), span(error.span()))];
+        }
+    };
+
+    let mut problems: Vec<_> = re2_unsupported(&parsed)
+        .into_iter()
+        .map(|(what, span)| {
+            (
+                "unsupported-regex",
+                format!("RE2 does not support {}", what),
+                span,
+            )
+        })
+        .collect();
+    if let Err(error) = Translator::new().translate(pattern, &parsed) {
+        problems.push((
+            "invalid-regex",
+            error.kind().to_string(),
+            span(error.span()),
+        ));
+    }
+    problems
+}
+
+fn span(span: &ast::Span) -> Range<usize> {
+    span.start.offset..span.end.offset
+}
+
+/// Syntax accepted by the Rust regex parser that RE2 rejects.
+fn re2_unsupported(parsed: &Ast) -> Vec<(&'static str, Range<usize>)> {
+    let mut found = Vec::new();
+    visit(parsed, &mut |node| match node {
+        Ast::Flags(set) => flag_items(&set.flags, &mut found),
+        Ast::Group(group) => {
+            if let ast::GroupKind::NonCapturing(flags) = &group.kind {
+                flag_items(flags, &mut found);
+            }
+        }
+        Ast::Assertion(assertion) => {
+            use ast::AssertionKind::*;
+            if matches!(
+                assertion.kind,
+                WordBoundaryStart
+                    | WordBoundaryEnd
+                    | WordBoundaryStartAngle
+                    | WordBoundaryEndAngle
+                    | WordBoundaryStartHalf
+                    | WordBoundaryEndHalf
+            ) {
+                found.push(("this word boundary assertion", span(&assertion.span)));
+            }
+        }
+        Ast::Literal(literal) => {
+            if let ast::LiteralKind::HexFixed(
+                ast::HexLiteralKind::UnicodeShort | ast::HexLiteralKind::UnicodeLong,
+            ) = literal.kind
+            {
+                found.push(("'\\u' escapes; use '\\x{...}'", span(&literal.span)));
+            }
+        }
+        Ast::ClassBracketed(class) => class_set(&class.kind, &mut found),
+        _ => {}
+    });
+    found
+}
+
+fn flag_items(flags: &ast::Flags, found: &mut Vec<(&'static str, Range<usize>)>) {
+    for item in &flags.items {
+        let what = match item.kind {
+            ast::FlagsItemKind::Flag(ast::Flag::Unicode) => "the 'u' flag",
+            ast::FlagsItemKind::Flag(ast::Flag::CRLF) => "the 'R' flag",
+            ast::FlagsItemKind::Flag(ast::Flag::IgnoreWhitespace) => "the 'x' flag",
+            _ => continue,
+        };
+        found.push((what, span(&item.span)));
+    }
+}
+
+fn class_set(set: &ClassSet, found: &mut Vec<(&'static str, Range<usize>)>) {
+    match set {
+        ClassSet::BinaryOp(op) => {
+            found.push(("character class set operations", span(&op.span)));
+            class_set(&op.lhs, found);
+            class_set(&op.rhs, found);
+        }
+        ClassSet::Item(item) => class_item(item, found),
+    }
+}
+
+fn class_item(item: &ClassSetItem, found: &mut Vec<(&'static str, Range<usize>)>) {
+    match item {
+        ClassSetItem::Bracketed(class) => {
+            found.push(("nested character classes", span(&class.span)));
+            class_set(&class.kind, found);
+        }
+        ClassSetItem::Union(union) => {
+            for item in &union.items {
+                class_item(item, found);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Call `f` on `node` and every node below it, outside character classes.
+fn visit(node: &Ast, f: &mut impl FnMut(&Ast)) {
+    f(node);
+    match node {
+        Ast::Repetition(repetition) => visit(&repetition.ast, f),
+        Ast::Group(group) => visit(&group.ast, f),
+        Ast::Alternation(alternation) => alternation.asts.iter().for_each(|a| visit(a, f)),
+        Ast::Concat(concat) => concat.asts.iter().for_each(|a| visit(a, f)),
+        _ => {}
+    }
+}
+
+/// Spans of a pattern's structure (escapes, classes, groups, operators and
+/// assertions) for highlighting. Invalid patterns have none.
+pub fn structure_spans(pattern: &str) -> Vec<Range<usize>> {
+    let Ok(parsed) = Parser::new().parse(pattern) else {
+        return Vec::new();
+    };
+    let mut spans = Vec::new();
+    visit(&parsed, &mut |node| match node {
+        Ast::Literal(literal) if literal.kind != ast::LiteralKind::Verbatim => {
+            spans.push(span(&literal.span))
+        }
+        Ast::Flags(set) => spans.push(span(&set.span)),
+        Ast::Dot(dot) => spans.push(span(dot)),
+        Ast::Assertion(assertion) => spans.push(span(&assertion.span)),
+        Ast::ClassUnicode(class) => spans.push(span(&class.span)),
+        Ast::ClassPerl(class) => spans.push(span(&class.span)),
+        Ast::ClassBracketed(class) => spans.push(span(&class.span)),
+        Ast::Repetition(repetition) => spans.push(span(&repetition.op.span)),
+        Ast::Group(group) => {
+            let open = group.span.start.offset..group.ast.span().start.offset;
+            let close = group.span.end.offset;
+            spans.push(open);
+            spans.push(close - 1..close);
+        }
+        Ast::Alternation(alternation) => {
+            for alternative in &alternation.asts[..alternation.asts.len().saturating_sub(1)] {
+                let end = alternative.span().end.offset;
+                if pattern[end..].starts_with('|') {
+                    spans.push(end..end + 1);
+                }
+            }
+        }
+        _ => {}
+    });
+    spans.sort_by_key(|s| s.start);
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cel_core::parse;
+
+    fn problems(source: &str) -> Vec<(&'static str, String, &str)> {
+        let ast = parse(source).ast.unwrap();
+        regex_problems(&ast, source)
+            .into_iter()
+            .map(|p| (p.code, p.message, &source[p.span]))
+            .collect()
+    }
+
+    #[test]
+    fn finds_pattern_arguments() {
+        let source = r#"a.matches('x') && matches(a, "y") && a.find(r'z') == a.findAll('w', 1)[0] && a.contains('v')"#;
+        let ast = parse(source).ast.unwrap();
+        let patterns: Vec<_> = regex_literals(&ast, source)
+            .into_iter()
+            .map(|l| l.pattern)
+            .collect();
+        assert_eq!(patterns, vec!["x", "y", "z", "w"]);
+    }
+
+    #[test]
+    fn valid_patterns_have_no_problems() {
+        assert!(problems(r"name.matches('^[a-z]+\\d*$')").is_empty());
+        assert!(problems(r"name.matches(r'^\w+@(?i:example)\.com$')").is_empty());
+        assert!(problems("name.matches(x)").is_empty());
+    }
+
+    #[test]
+    fn reports_syntax_errors_inside_the_literal() {
+        let found = problems("name.matches('a(b')");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, "invalid-regex");
+        assert_eq!(found[0].1, "unclosed group");
+        assert_eq!(found[0].2, "(");
+
+        // Escapes in the CEL string are mapped back to their source text
+        let found = problems(r"name.matches('\\d{2,1}')");
+        assert_eq!(found[0].2, "{2,1}");
+        let found = problems(r"name.matches('\\q')");
+        assert_eq!(found[0].2, r"\\q");
+    }
+
+    #[test]
+    fn reports_features_missing_from_re2() {
+        let found = problems(r"name.matches('a(?=b)') || name.matches(r'(a)\1')");
+        assert_eq!(found[0].0, "unsupported-regex");
+        assert!(found[0].1.contains("look-around"), "{}", found[0].1);
+        assert_eq!(found[1].0, "unsupported-regex");
+        assert_eq!(found[1].2, r"\1");
+
+        let found = problems(r"name.matches(r'[a-z&&[^aeiou]]')");
+        let messages: Vec<_> = found.iter().map(|p| p.1.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "RE2 does not support character class set operations",
+                "RE2 does not support nested character classes",
+            ]
+        );
+        assert_eq!(found[1].2, "[^aeiou]");
+
+        let found = problems(r"name.matches('(?x) a b')");
+        assert_eq!(found[0].1, "RE2 does not support the 'x' flag");
+        assert_eq!(found[0].2, "x");
+    }
+
+    #[test]
+    fn structure_spans_cover_operators() {
+        let pattern = r"^(a|b)+\d[xy]$";
+        let spans: Vec<_> = structure_spans(pattern)
+            .into_iter()
+            .map(|s| &pattern[s])
+            .collect();
+        assert_eq!(spans, vec!["^", "(", "|", ")", "+", r"\d", "[xy]", "$"]);
+    }
+}
@@ -0,0 +1,208 @@
+//! Command-line argument parsing for the celsp binary.
+
+use std::path::PathBuf;
+
+use crate::logging::LogFilter;
+
+/// Help text printed for `--help` and on argument errors.
+pub const HELP: &str = "\
+celsp - Language Server Protocol implementation for CEL
+
+usage:
+    celsp [options]                 Run the language server
+    celsp check [options] <paths>   Check .cel, .proto and YAML files and print diagnostics
+
+server options:
+    --stdio                 Communicate over stdin/stdout (default)
+    --socket <port>         Connect to the client on 127.0.0.1:<port>
+    --pipe <path>           Connect to the client's named pipe or Unix socket at <path>
+    --settings <path>       Use this settings.toml instead of discovering one
+    --log-file <path>       Append logs to <path> instead of stderr
+    --log-level <filter>    One of error, warn, info, debug, trace (default: warn),
+                            optionally with per-module overrides such as
+                            info,completion=trace
+    --version               Print the version and exit
+    --help                  Print this help and exit
+
+check options:
+    --format <format>       One of human, json, sarif (default: human)
+    --settings <path>       Use this settings.toml instead of discovering one
+";
+
+/// How the language server talks to the client.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Transport {
+    /// Standard input and output.
+    #[default]
+    Stdio,
+    /// A TCP connection to the client on the given local port.
+    Socket(u16),
+    /// A Unix domain socket (or named pipe) created by the client.
+    Pipe(PathBuf),
+}
+
+/// Options for running the language server.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ServerOptions {
+    /// Transport used to talk to the client.
+    pub transport: Transport,
+    /// Explicit settings.toml path, overriding discovery.
+    pub settings: Option<PathBuf>,
+    /// File to append logs to instead of stderr.
+    pub log_file: Option<PathBuf>,
+    /// Log level filter, e.g. `info,completion=trace`.
+    pub log_level: Option<LogFilter>,
+}
+
+/// A parsed command line.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Run the language server.
+    Serve(ServerOptions),
+    /// Run `celsp check` with the remaining arguments.
+    Check(Vec<String>),
+    /// Print the version.
+    Version,
+    /// Print the help text.
+    Help,
+}
+
+/// Parse the process arguments (without the program name).
+///
+/// Options accept their value either as the next argument or after `=`,
+/// since editors commonly pass `--socket=<port>` and `--pipe=<path>`.
+pub fn parse_command(args: &[String]) -> Result<Command, String> {
+    if args.first().map(String::as_str) == Some("check") {
+        return Ok(Command::Check(args[1..].to_vec()));
+    }
+
+    let mut options = ServerOptions::default();
+    let mut transport_set = false;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = |name: &str| {
+            inline_value
+                .clone()
+                .or_else(|| iter.next().cloned())
+                .ok_or_else(|| format!("{} requires a value", name))
+        };
+
+        let transport = match name {
+            "--help" | "-h" => return Ok(Command::Help),
+            "--version" | "-V" => return Ok(Command::Version),
+            "--stdio" => Some(Transport::Stdio),
+            "--socket" | "--port" => {
+                let port = value(name)?;
+                let port = port
+                    .parse()
+                    .map_err(|_| format!("invalid port '{}'", port))?;
+                Some(Transport::Socket(port))
+            }
+            "--pipe" => Some(Transport::Pipe(PathBuf::from(value(name)?))),
+            "--settings" => {
+                options.settings = Some(PathBuf::from(value(name)?));
+                None
+            }
+            "--log-file" => {
+                options.log_file = Some(PathBuf::from(value(name)?));
+                None
+            }
+            "--log-level" => {
+                options.log_level = Some(value(name)?.parse()?);
+                None
+            }
+            // Passed by some clients; the server id is not needed.
+            "--clientProcessId" => {
+                value(name)?;
+                None
+            }
+            other => return Err(format!("unknown argument '{}'", other)),
+        };
+
+        if let Some(transport) = transport {
+            if transport_set && transport != options.transport {
+                return Err("only one of --stdio, --socket and --pipe may be given".to_string());
+            }
+            options.transport = transport;
+            transport_set = true;
+        }
+    }
+
+    Ok(Command::Serve(options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::LogLevel;
+
+    fn parse(list: &[&str]) -> Result<Command, String> {
+        let args: Vec<String> = list.iter().map(|s| s.to_string()).collect();
+        parse_command(&args)
+    }
+
+    #[test]
+    fn no_arguments_serves_over_stdio() {
+        assert_eq!(parse(&[]), Ok(Command::Serve(ServerOptions::default())));
+        assert_eq!(
+            parse(&["--stdio"]),
+            Ok(Command::Serve(ServerOptions::default()))
+        );
+    }
+
+    #[test]
+    fn parses_socket_and_pipe() {
+        let Ok(Command::Serve(options)) = parse(&["--socket", "9257"]) else {
+            panic!("expected serve command");
+        };
+        assert_eq!(options.transport, Transport::Socket(9257));
+
+        let Ok(Command::Serve(options)) = parse(&["--pipe=/tmp/celsp.sock"]) else {
+            panic!("expected serve command");
+        };
+        assert_eq!(
+            options.transport,
+            Transport::Pipe(PathBuf::from("/tmp/celsp.sock"))
+        );
+    }
+
+    #[test]
+    fn parses_settings_and_logging() {
+        let Ok(Command::Serve(options)) = parse(&[
+            "--settings",
+            "conf/settings.toml",
+            "--log-file=celsp.log",
+            "--log-level",
+            "debug",
+        ]) else {
+            panic!("expected serve command");
+        };
+        assert_eq!(options.settings, Some(PathBuf::from("conf/settings.toml")));
+        assert_eq!(options.log_file, Some(PathBuf::from("celsp.log")));
+        assert_eq!(options.log_level, Some(LogFilter::new(LogLevel::Debug)));
+    }
+
+    #[test]
+    fn parses_version_help_and_check() {
+        assert_eq!(parse(&["--version"]), Ok(Command::Version));
+        assert_eq!(parse(&["--help"]), Ok(Command::Help));
+        assert_eq!(
+            parse(&["check", "a.cel"]),
+            Ok(Command::Check(vec!["a.cel".to_string()]))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        assert!(parse(&["--socket", "not-a-port"]).is_err());
+        assert!(parse(&["--socket"]).is_err());
+        assert!(parse(&["--log-level", "loud"]).is_err());
+        assert!(parse(&["--socket", "1", "--pipe", "p"]).is_err());
+        assert!(parse(&["--bogus"]).is_err());
+    }
+}
@@ -0,0 +1,614 @@
+//! Headless `celsp check` command for CI linting.
+//!
+//! Runs the same `DocumentState`/`EmbeddedDocumentState` analysis as the language
+//! server over files on disk and prints the resulting diagnostics in a human,
+//! JSON or SARIF format, so CI enforces exactly what the editor shows.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use cel_core::Env;
+use cel_core_proto::ProstProtoRegistry;
+use serde_json::{json, Value};
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Url};
+
+use crate::analysis::{ComplexityLimits, CostBudget};
+use crate::document::{DocumentKind, HostVersions};
+use crate::lsp::{compile_diagnostics, document_diagnostics, unknown_lints};
+use crate::proto::CompileErrors;
+use crate::settings::{self, DiagnosticsSettings, ExpectedTypes, Settings};
+use crate::workspace;
+
+/// Usage text printed on argument errors.
+const USAGE: &str = "usage: celsp check [--format human|json|sarif] [--settings <path>] <paths...>";
+
+/// Exit code when no error diagnostics were reported.
+const EXIT_OK: i32 = 0;
+/// Exit code when at least one error diagnostic was reported or a file could not be read.
+const EXIT_ERRORS: i32 = 1;
+/// Exit code for invalid command-line usage or an unusable `--settings` file.
+const EXIT_USAGE: i32 = 2;
+
+/// Output format for reported diagnostics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    /// One `path:line:col: severity[code]: message` line per diagnostic.
+    Human,
+    /// A JSON document listing every diagnostic.
+    Json,
+    /// A SARIF 2.1.0 log for code scanning integrations.
+    Sarif,
+}
+
+impl OutputFormat {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "human" | "text" => Some(OutputFormat::Human),
+            "json" => Some(OutputFormat::Json),
+            "sarif" => Some(OutputFormat::Sarif),
+            _ => None,
+        }
+    }
+}
+
+/// Parsed options for `celsp check`.
+#[derive(Debug)]
+struct CheckOptions {
+    format: OutputFormat,
+    /// Explicit settings.toml path, overriding discovery.
+    settings: Option<PathBuf>,
+    paths: Vec<PathBuf>,
+}
+
+/// Diagnostics reported for a single file.
+#[derive(Debug)]
+struct FileReport {
+    /// The path as discovered from the command-line arguments.
+    path: PathBuf,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// Environment and registry built from one discovered settings.toml.
+struct Analysis {
+    env: Arc<Env>,
+    registry: Option<Arc<ProstProtoRegistry>>,
+    proto_errors: CompileErrors,
+    versions: HostVersions,
+    expected_types: ExpectedTypes,
+    cost_budget: CostBudget,
+    complexity_limits: ComplexityLimits,
+    diagnostics: DiagnosticsSettings,
+}
+
+/// Run `celsp check` with the arguments following the subcommand.
+///
+/// Returns the process exit code: 0 when no errors were found, 1 when any
+/// error diagnostic was reported, and 2 for invalid usage.
+pub fn run_check(args: &[String]) -> i32 {
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("error: {}\n{}", e, USAGE);
+            return EXIT_USAGE;
+        }
+    };
+
+    let files = match collect_files(&options.paths) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return EXIT_USAGE;
+        }
+    };
+
+    let explicit = match options
+        .settings
+        .as_deref()
+        .map(settings::load_settings_file)
+    {
+        Some(Ok(loaded)) => Some(loaded),
+        Some(Err(e)) => {
+            eprintln!("error: {}", e);
+            return EXIT_USAGE;
+        }
+        None => None,
+    };
+
+    let (reports, read_failed) = check_files(&files, explicit.as_ref());
+
+    let output = match options.format {
+        OutputFormat::Human => format_human(&reports),
+        OutputFormat::Json => format_json(&reports),
+        OutputFormat::Sarif => format_sarif(&reports),
+    };
+    print!("{}", output);
+
+    if read_failed || has_errors(&reports) {
+        EXIT_ERRORS
+    } else {
+        EXIT_OK
+    }
+}
+
+/// Parse the arguments following `check`.
+fn parse_args(args: &[String]) -> Result<CheckOptions, String> {
+    let mut format = OutputFormat::Human;
+    let mut settings = None;
+    let mut paths = Vec::new();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if let Some(value) = arg.strip_prefix("--format=") {
+            format = parse_format(value)?;
+        } else if arg == "--format" {
+            let value = iter.next().ok_or("--format requires a value")?;
+            format = parse_format(value)?;
+        } else if let Some(value) = arg.strip_prefix("--settings=") {
+            settings = Some(PathBuf::from(value));
+        } else if arg == "--settings" {
+            let value = iter.next().ok_or("--settings requires a value")?;
+            settings = Some(PathBuf::from(value));
+        } else if arg.starts_with("--") {
+            return Err(format!("unknown option '{}'", arg));
+        } else {
+            paths.push(PathBuf::from(arg));
+        }
+    }
+
+    if paths.is_empty() {
+        return Err("no paths given".to_string());
+    }
+
+    Ok(CheckOptions {
+        format,
+        settings,
+        paths,
+    })
+}
+
+fn parse_format(value: &str) -> Result<OutputFormat, String> {
+    OutputFormat::parse(value).ok_or_else(|| format!("unknown format '{}'", value))
+}
+
+/// Expand the given paths into the list of files to check.
+///
+/// Files are taken as given; directories are walked recursively for `.cel`,
+/// `.proto` and YAML files, skipping hidden and build output directories.
+fn collect_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_file() {
+            files.push(path.clone());
+        } else if path.is_dir() {
+            files.extend(workspace::source_files(path));
+        } else {
+            return Err(format!("path not found: '{}'", path.display()));
+        }
+    }
+    Ok(files)
+}
+
+/// Analyze every file, discovering settings.toml from each file's directory
+/// unless explicit settings are given.
+///
+/// Returns the per-file reports and whether any file could not be read.
+fn check_files(
+    files: &[PathBuf],
+    explicit: Option<&(Settings, PathBuf)>,
+) -> (Vec<FileReport>, bool) {
+    let mut analyses: HashMap<PathBuf, Arc<Analysis>> = HashMap::new();
+    let mut reports = Vec::new();
+    let mut read_failed = false;
+
+    for path in files {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("error: failed to read '{}': {}", path.display(), e);
+                read_failed = true;
+                continue;
+            }
+        };
+
+        let absolute = std::fs::canonicalize(path).unwrap_or_else(|_| path.clone());
+        let dir = absolute.parent().unwrap_or(Path::new(".")).to_path_buf();
+        let (settings, settings_dir) = match explicit {
+            Some(loaded) => loaded.clone(),
+            None => settings::discover_settings(&dir),
+        };
+        let analysis = analyses
+            .entry(settings_dir.clone())
+            .or_insert_with(|| {
+                for name in unknown_lints(&settings.diagnostics()) {
+                    eprintln!("warning: unknown lint rule '{}'", name);
+                }
+                let protos = settings::load_protos(&settings, &settings_dir);
+                Arc::new(Analysis {
+                    env: Arc::new(settings::build_env_with_registry(
+                        &settings,
+                        &settings_dir,
+                        protos.registry.clone(),
+                    )),
+                    registry: protos.registry,
+                    proto_errors: protos.errors,
+                    versions: settings::host_versions(&settings),
+                    expected_types: settings::expected_types(&settings),
+                    cost_budget: settings::cost_budget(&settings),
+                    complexity_limits: settings::complexity_limits(&settings),
+                    diagnostics: settings.diagnostics(),
+                })
+            })
+            .clone();
+
+        let Ok(uri) = Url::from_file_path(&absolute) else {
+            eprintln!("error: invalid path '{}'", path.display());
+            read_failed = true;
+            continue;
+        };
+
+        let state = DocumentKind::new(
+            &uri,
+            source,
+            0,
+            analysis.registry.as_ref(),
+            Some(&analysis.env),
+            analysis.versions,
+            analysis.expected_types.for_path(Some(&absolute)),
+        )
+        .with_budget(&analysis.cost_budget)
+        .with_limits(&analysis.complexity_limits);
+        let mut diagnostics = document_diagnostics(&state, &analysis.diagnostics);
+        diagnostics.extend(compile_diagnostics(
+            &analysis.proto_errors,
+            &absolute,
+            &state,
+            &analysis.diagnostics,
+        ));
+        reports.push(FileReport {
+            path: path.clone(),
+            diagnostics,
+        });
+    }
+
+    (reports, read_failed)
+}
+
+/// Check if any report contains an error-severity diagnostic.
+fn has_errors(reports: &[FileReport]) -> bool {
+    reports
+        .iter()
+        .flat_map(|r| &r.diagnostics)
+        .any(|d| d.severity == Some(DiagnosticSeverity::ERROR))
+}
+
+/// Lowercase name for a diagnostic severity.
+fn severity_name(severity: Option<DiagnosticSeverity>) -> &'static str {
+    match severity {
+        Some(DiagnosticSeverity::WARNING) => "warning",
+        Some(DiagnosticSeverity::INFORMATION) => "info",
+        Some(DiagnosticSeverity::HINT) => "hint",
+        _ => "error",
+    }
+}
+
+/// The diagnostic code as a string, if any.
+fn code_string(diagnostic: &Diagnostic) -> Option<String> {
+    match &diagnostic.code {
+        Some(NumberOrString::String(s)) => Some(s.clone()),
+        Some(NumberOrString::Number(n)) => Some(n.to_string()),
+        None => None,
+    }
+}
+
+/// Format reports as `path:line:col: severity[code]: message` lines with a summary.
+fn format_human(reports: &[FileReport]) -> String {
+    let mut out = String::new();
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+
+    for report in reports {
+        for d in &report.diagnostics {
+            let severity = severity_name(d.severity);
+            *counts.entry(severity).or_default() += 1;
+            let code = code_string(d)
+                .map(|c| format!("[{}]", c))
+                .unwrap_or_default();
+            out.push_str(&format!(
+                "{}:{}:{}: {}{}: {}\n",
+                report.path.display(),
+                d.range.start.line + 1,
+                d.range.start.character + 1,
+                severity,
+                code,
+                d.message
+            ));
+        }
+    }
+
+    out.push_str(&format!(
+        "checked {} file(s): {} error(s), {} warning(s)\n",
+        reports.len(),
+        counts.get("error").copied().unwrap_or(0),
+        counts.get("warning").copied().unwrap_or(0),
+    ));
+    out
+}
+
+/// Format reports as a JSON document with 1-based line and column numbers.
+fn format_json(reports: &[FileReport]) -> String {
+    let diagnostics: Vec<Value> = reports
+        .iter()
+        .flat_map(|report| {
+            report.diagnostics.iter().map(move |d| {
+                json!({
+                    "file": report.path.display().to_string(),
+                    "line": d.range.start.line + 1,
+                    "column": d.range.start.character + 1,
+                    "end_line": d.range.end.line + 1,
+                    "end_column": d.range.end.character + 1,
+                    "severity": severity_name(d.severity),
+                    "code": code_string(d),
+                    "message": d.message,
+                })
+            })
+        })
+        .collect();
+
+    let doc = json!({
+        "files_checked": reports.len(),
+        "diagnostics": diagnostics,
+    });
+    format!(
+        "{}\n",
+        serde_json::to_string_pretty(&doc).unwrap_or_default()
+    )
+}
+
+/// Format reports as a SARIF 2.1.0 log.
+///
+/// Columns are reported in UTF-16 code units, which matches both the LSP
+/// positions and the SARIF default `columnKind`.
+fn format_sarif(reports: &[FileReport]) -> String {
+    let mut rules: BTreeMap<String, Option<String>> = BTreeMap::new();
+    let mut results = Vec::new();
+
+    for report in reports {
+        for d in &report.diagnostics {
+            let rule_id = code_string(d).unwrap_or_else(|| "parse-error".to_string());
+            let help = d.code_description.as_ref().map(|c| c.href.to_string());
+            rules.entry(rule_id.clone()).or_insert(help);
+            let level = match d.severity {
+                Some(DiagnosticSeverity::WARNING) => "warning",
+                Some(DiagnosticSeverity::INFORMATION) | Some(DiagnosticSeverity::HINT) => "note",
+                _ => "error",
+            };
+            results.push(json!({
+                "ruleId": rule_id,
+                "level": level,
+                "message": { "text": d.message },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": {
+                            "uri": report.path.to_string_lossy().replace('\\', "/"),
+                        },
+                        "region": {
+                            "startLine": d.range.start.line + 1,
+                            "startColumn": d.range.start.character + 1,
+                            "endLine": d.range.end.line + 1,
+                            "endColumn": d.range.end.character + 1,
+                        },
+                    },
+                }],
+            }));
+        }
+    }
+
+    let rules: Vec<Value> = rules
+        .iter()
+        .map(|(id, help)| match help {
+            Some(help) => json!({ "id": id, "helpUri": help }),
+            None => json!({ "id": id }),
+        })
+        .collect();
+
+    let doc = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "celsp",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": env!("CARGO_PKG_REPOSITORY"),
+                    "rules": rules,
+                },
+            },
+            "results": results,
+        }],
+    });
+    format!(
+        "{}\n",
+        serde_json::to_string_pretty(&doc).unwrap_or_default()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::make_test_dir;
+    use tower_lsp::lsp_types::{Position, Range};
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn sample_report() -> FileReport {
+        FileReport {
+            path: PathBuf::from("rules/check.cel"),
+            diagnostics: vec![Diagnostic {
+                range: Range::new(Position::new(0, 4), Position::new(0, 5)),
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::String("undeclared-reference".to_string())),
+                source: Some("cel".to_string()),
+                message: "undeclared reference to 'y'".to_string(),
+                ..Default::default()
+            }],
+        }
+    }
+
+    #[test]
+    fn parse_args_defaults_to_human() {
+        let options = parse_args(&args(&["a.cel", "dir"])).unwrap();
+        assert_eq!(options.format, OutputFormat::Human);
+        assert_eq!(options.paths.len(), 2);
+    }
+
+    #[test]
+    fn parse_args_format() {
+        let options = parse_args(&args(&["--format", "sarif", "a.cel"])).unwrap();
+        assert_eq!(options.format, OutputFormat::Sarif);
+        let options = parse_args(&args(&["--format=json", "a.cel"])).unwrap();
+        assert_eq!(options.format, OutputFormat::Json);
+        let options = parse_args(&args(&["--settings", "conf/settings.toml", "a.cel"])).unwrap();
+        assert_eq!(options.settings, Some(PathBuf::from("conf/settings.toml")));
+    }
+
+    #[test]
+    fn parse_args_errors() {
+        assert!(parse_args(&args(&[])).is_err());
+        assert!(parse_args(&args(&["--format", "xml", "a.cel"])).is_err());
+        assert!(parse_args(&args(&["--bogus", "a.cel"])).is_err());
+    }
+
+    #[test]
+    fn collects_source_files_recursively() {
+        let dir = make_test_dir(
+            "check-collect",
+            &[
+                ("a.cel", "1"),
+                ("nested/b.proto", ""),
+                ("nested/readme.md", ""),
+                (".hidden/c.cel", "1"),
+            ],
+        );
+
+        let files = collect_files(std::slice::from_ref(&dir)).unwrap();
+        assert_eq!(files, vec![dir.join("a.cel"), dir.join("nested/b.proto")]);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn checks_files_with_discovered_settings() {
+        let dir = make_test_dir(
+            "check-files",
+            &[
+                ("settings.toml", "[env]\nvariables = { x = \"int\" }\n"),
+                ("ok.cel", "x > 1"),
+                ("bad.cel", "x + y"),
+            ],
+        );
+
+        let files = collect_files(std::slice::from_ref(&dir)).unwrap();
+        let (reports, read_failed) = check_files(&files, None);
+
+        assert!(!read_failed);
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].path, dir.join("bad.cel"));
+        assert_eq!(reports[0].diagnostics.len(), 1);
+        assert!(reports[1].diagnostics.is_empty());
+        assert!(has_errors(&reports));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn reports_proto_compile_errors() {
+        let dir = make_test_dir(
+            "check-proto-sources",
+            &[
+                (
+                    "settings.toml",
+                    "[env]\nvariables = { user = \"acme.User\" }\n\n[env.proto]\nsources = [\"proto\"]\n",
+                ),
+                (
+                    "proto/user.proto",
+                    "syntax = \"proto3\";\npackage acme;\nmessage User { string name = 1; }\n",
+                ),
+                (
+                    "proto/broken.proto",
+                    "syntax = \"proto3\";\nmessage Broken { Unknown field = 1; }\n",
+                ),
+                ("rule.cel", "user.name != ''"),
+            ],
+        );
+
+        let files = collect_files(std::slice::from_ref(&dir)).unwrap();
+        let (reports, _) = check_files(&files, None);
+
+        let broken = &reports[0];
+        assert_eq!(broken.path, dir.join("proto/broken.proto"));
+        assert_eq!(broken.diagnostics.len(), 1);
+        assert_eq!(
+            code_string(&broken.diagnostics[0]).unwrap(),
+            "proto-compile"
+        );
+        assert_eq!(broken.diagnostics[0].range.start, Position::new(1, 17));
+        assert!(reports[1..].iter().all(|r| r.diagnostics.is_empty()));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn missing_settings_file_is_a_usage_error() {
+        let dir = make_test_dir("check-missing-settings", &[("a.cel", "1")]);
+        let missing = dir.join("settigns.toml");
+        let code = run_check(&args(&[
+            "--settings",
+            missing.to_str().unwrap(),
+            dir.to_str().unwrap(),
+        ]));
+        assert_eq!(code, EXIT_USAGE);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn human_output() {
+        let output = format_human(&[sample_report()]);
+        assert_eq!(
+            output,
+            "rules/check.cel:1:5: error[undeclared-reference]: undeclared reference to 'y'\n\
+             checked 1 file(s): 1 error(s), 0 warning(s)\n"
+        );
+    }
+
+    #[test]
+    fn json_output() {
+        let doc: Value = serde_json::from_str(&format_json(&[sample_report()])).unwrap();
+        assert_eq!(doc["files_checked"], 1);
+        let diag = &doc["diagnostics"][0];
+        assert_eq!(diag["file"], "rules/check.cel");
+        assert_eq!(diag["line"], 1);
+        assert_eq!(diag["column"], 5);
+        assert_eq!(diag["severity"], "error");
+        assert_eq!(diag["code"], "undeclared-reference");
+    }
+
+    #[test]
+    fn sarif_output() {
+        let doc: Value = serde_json::from_str(&format_sarif(&[sample_report()])).unwrap();
+        assert_eq!(doc["version"], "2.1.0");
+        let run = &doc["runs"][0];
+        assert_eq!(run["tool"]["driver"]["name"], "celsp");
+        assert_eq!(
+            run["tool"]["driver"]["rules"][0]["id"],
+            "undeclared-reference"
+        );
+        let result = &run["results"][0];
+        assert_eq!(result["level"], "error");
+        let region = &result["locations"][0]["physicalLocation"]["region"];
+        assert_eq!(region["startLine"], 1);
+        assert_eq!(region["startColumn"], 5);
+    }
+}
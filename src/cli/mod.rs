@@ -0,0 +1,14 @@
+//! Command-line interface for the celsp binary.
+//!
+//! This module provides:
+//! - Argument parsing for server options (transport, settings, logging)
+//! - Transport setup for stdio, TCP socket and pipe connections
+//! - `celsp check` for headless linting of `.cel` and `.proto` files in CI
+
+mod args;
+mod check;
+mod serve;
+
+pub use args::{parse_command, Command, ServerOptions, Transport, HELP};
+pub use check::run_check;
+pub use serve::serve;
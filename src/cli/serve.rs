@@ -0,0 +1,60 @@
+//! Transport setup for running the language server.
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tower_lsp::Server;
+use tracing::info;
+
+use crate::create_service_with_settings;
+
+use super::args::{ServerOptions, Transport};
+
+/// Run the language server over the transport selected in `options`.
+///
+/// For `--socket` and `--pipe` the client owns the listening end, following the
+/// convention used by vscode-languageclient: the server connects to it and
+/// serves a single session.
+pub async fn serve(options: ServerOptions) -> std::io::Result<()> {
+    match &options.transport {
+        Transport::Stdio => {
+            info!("serving on stdio");
+            serve_on(tokio::io::stdin(), tokio::io::stdout(), &options).await;
+        }
+        Transport::Socket(port) => {
+            info!("connecting to client on 127.0.0.1:{}", port);
+            let stream = tokio::net::TcpStream::connect(("127.0.0.1", *port)).await?;
+            let (read, write) = tokio::io::split(stream);
+            serve_on(read, write, &options).await;
+        }
+        Transport::Pipe(path) => {
+            info!("connecting to client pipe {}", path.display());
+            serve_on_pipe(path, &options).await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn serve_on_pipe(path: &std::path::Path, options: &ServerOptions) -> std::io::Result<()> {
+    let stream = tokio::net::UnixStream::connect(path).await?;
+    let (read, write) = tokio::io::split(stream);
+    serve_on(read, write, options).await;
+    Ok(())
+}
+
+#[cfg(windows)]
+async fn serve_on_pipe(path: &std::path::Path, options: &ServerOptions) -> std::io::Result<()> {
+    let pipe = tokio::net::windows::named_pipe::ClientOptions::new().open(path)?;
+    let (read, write) = tokio::io::split(pipe);
+    serve_on(read, write, options).await;
+    Ok(())
+}
+
+/// Serve a single LSP session over the given streams.
+async fn serve_on<I, O>(input: I, output: O, options: &ServerOptions)
+where
+    I: AsyncRead + Unpin,
+    O: AsyncWrite,
+{
+    let (service, socket) = create_service_with_settings(options.settings.clone());
+    Server::new(input, output, socket).serve(service).await;
+}
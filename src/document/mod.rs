@@ -3,12 +3,17 @@
 //! This module provides:
 //! - `LineIndex` for efficient byte offset <-> LSP position conversion
 //! - `CelRegion` and `OffsetMapper` for embedded CEL in host documents
+//! - result type checks for hosts that only accept some result types
 //! - `DocumentState` and `DocumentStore` for document lifecycle management
 
 mod region;
+mod result_type;
 mod state;
 mod text;
 
-pub use region::{CelRegion, OffsetMapper};
-pub use state::{DocumentKind, DocumentState, DocumentStore, ProtoDocumentState};
+pub use region::{CelRegion, CelRegionState, OffsetMapper};
+pub use result_type::ResultTypeMismatch;
+pub use state::{
+    is_proto_file, DocumentKind, DocumentState, DocumentStore, EmbeddedDocumentState, HostVersions,
+};
 pub use text::LineIndex;
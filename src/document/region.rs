@@ -4,14 +4,20 @@
 //! host documents (like .proto files) and mapping between CEL-local coordinates
 //! and host document coordinates.
 
+use std::collections::HashMap;
 use std::ops::Range;
 use std::sync::Arc;
 
 use cel_core::{parse, CelType, CheckError, CheckResult, Env, ParseError, SpannedExpr};
 use cel_core_proto::ProstProtoRegistry;
 
-use crate::protovalidate::ProtovalidateContext;
-use crate::settings::protovalidate_extension;
+use crate::analysis::{
+    complexity_problems, cost_limit_problem, deprecation_problems, estimate_cost, ComplexityLimits,
+    CostBudget, CostEstimate, Problem,
+};
+use crate::protovalidate::{protovalidate_library, ProtovalidateContext, ProtovalidateVersion};
+
+use super::result_type::{result_type_mismatch, ResultTypeMismatch};
 
 /// Represents a single CEL expression region within a host document.
 #[derive(Debug, Clone)]
@@ -118,18 +124,50 @@ pub struct CelRegionState {
 
     /// The environment used for type checking (needed for completion).
     pub env: Arc<Env>,
+
+    /// Result types the host accepts; empty accepts any type.
+    pub expected: Vec<CelType>,
+
+    /// Host range of the enclosing rule block, if the host has one.
+    pub block: Option<Range<usize>>,
+
+    /// Cost limit and size hints the expression is estimated with.
+    pub budget: CostBudget,
+
+    /// Limits on the structure of the expression.
+    pub limits: ComplexityLimits,
+
+    /// The protovalidate release the region is checked against, if it is a
+    /// protovalidate rule.
+    pub protovalidate: Option<ProtovalidateVersion>,
 }
 
 impl CelRegionState {
-    /// Create a new CEL region state with a specific protovalidate context.
+    /// Create a new CEL region state with a specific protovalidate context,
+    /// checked with the library of the `protovalidate` release.
+    ///
+    /// Rules must evaluate to `bool` or to a `string` violation message.
     pub fn with_context(
         region: CelRegion,
         mapper: OffsetMapper,
         context: ProtovalidateContext,
         proto_registry: Option<&Arc<ProstProtoRegistry>>,
+        protovalidate: ProtovalidateVersion,
     ) -> Self {
+        let env = Arc::new(build_protovalidate_env_typed(
+            &context,
+            proto_registry,
+            protovalidate,
+        ));
+        let mut state =
+            Self::with_env(region, mapper, env).expecting(vec![CelType::Bool, CelType::String]);
+        state.protovalidate = Some(protovalidate);
+        state
+    }
+
+    /// Create a new CEL region state checked against the given environment.
+    pub fn with_env(region: CelRegion, mapper: OffsetMapper, env: Arc<Env>) -> Self {
         let result = parse(&region.source);
-        let env = Arc::new(build_protovalidate_env_typed(&context, proto_registry));
 
         // Run type checking if we have an AST
         let check_result = result.ast.as_ref().map(|ast| env.check(ast));
@@ -141,6 +179,74 @@ impl CelRegionState {
             parse_errors: result.errors,
             check_result,
             env,
+            expected: Vec::new(),
+            block: None,
+            budget: CostBudget::default(),
+            limits: ComplexityLimits::default(),
+            protovalidate: None,
+        }
+    }
+
+    /// Require the expression to evaluate to one of `expected`.
+    pub fn expecting(mut self, expected: Vec<CelType>) -> Self {
+        self.expected = expected;
+        self
+    }
+
+    /// Record the host range of the rule block the expression belongs to.
+    pub fn in_block(mut self, block: Range<usize>) -> Self {
+        self.block = Some(block);
+        self
+    }
+
+    /// Estimate costs with the maximum sizes in `size_hints`.
+    pub fn with_size_hints(mut self, size_hints: HashMap<String, u64>) -> Self {
+        self.budget.max_sizes = size_hints;
+        self
+    }
+
+    /// The mismatch between the checked result type and the expected types, if any.
+    pub fn result_type_mismatch(&self) -> Option<ResultTypeMismatch> {
+        result_type_mismatch(
+            self.ast.as_ref(),
+            self.check_result.as_ref(),
+            &self.expected,
+        )
+    }
+
+    /// The estimated costs of the expression, if it parsed.
+    pub fn cost(&self) -> Option<CostEstimate> {
+        let ast = self.ast.as_ref()?;
+        Some(estimate_cost(
+            ast,
+            &self.region.source,
+            self.check_result.as_ref(),
+            &self.budget.max_sizes,
+        ))
+    }
+
+    /// A `cost-limit` problem when the estimated cost exceeds the limit.
+    pub fn cost_overrun(&self) -> Option<Problem> {
+        let limit = self.budget.limit?;
+        cost_limit_problem(self.ast.as_ref()?, &self.cost()?, limit)
+    }
+
+    /// Problems with the cost limit and the structural limits.
+    pub fn limit_problems(&self) -> Vec<Problem> {
+        let mut problems: Vec<_> = self.cost_overrun().into_iter().collect();
+        if let Some(ast) = &self.ast {
+            problems.extend(complexity_problems(ast, &self.limits));
+        }
+        problems
+    }
+
+    /// References to deprecated proto fields and enum values.
+    pub fn deprecations(&self) -> Vec<Problem> {
+        match (&self.ast, &self.check_result, self.env.proto_registry()) {
+            (Some(ast), Some(check_result), Some(registry)) => {
+                deprecation_problems(ast, &self.region.source, check_result, registry)
+            }
+            _ => Vec::new(),
         }
     }
 
@@ -253,12 +359,13 @@ fn resolve_this_type(
 fn build_protovalidate_env_typed(
     context: &ProtovalidateContext,
     proto_registry: Option<&Arc<ProstProtoRegistry>>,
+    protovalidate: ProtovalidateVersion,
 ) -> Env {
     let this_type = resolve_this_type(context, proto_registry);
 
     let mut env = Env::with_standard_library()
         .with_all_extensions()
-        .with_extension(protovalidate_extension())
+        .with_extension(protovalidate_library(protovalidate))
         .with_variable("this", this_type)
         .with_variable("rules", CelType::Dyn)
         .with_variable("now", CelType::Timestamp);
@@ -323,6 +430,11 @@ mod tests {
             parse_errors: vec![],
             check_result: None,
             env: Arc::new(Env::new()),
+            expected: Vec::new(),
+            block: None,
+            budget: CostBudget::default(),
+            limits: ComplexityLimits::default(),
+            protovalidate: None,
         };
 
         assert!(state.contains_host_offset(100));
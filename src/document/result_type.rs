@@ -0,0 +1,89 @@
+//! Checking the result type of an expression against the types its host accepts.
+//!
+//! Hosts only accept certain results: protovalidate rules evaluate to `bool`
+//! or `string`, Kubernetes `messageExpression`s to `string`. An expression
+//! whose root type is none of them is reported on the root span.
+
+use std::ops::Range;
+
+use cel_core::{CelType, CheckResult, SpannedExpr};
+
+/// An expression whose checked result type is not one of the expected types.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResultTypeMismatch {
+    /// Span of the root expression.
+    pub span: Range<usize>,
+    /// Human-readable description of the mismatch.
+    pub message: String,
+}
+
+/// Compare the checked type of the root of `ast` with `expected`.
+///
+/// An empty `expected` accepts any type. Types the checker could not determine
+/// (`dyn`, errors) are accepted, as are wrappers of an expected type.
+pub fn result_type_mismatch(
+    ast: Option<&SpannedExpr>,
+    check_result: Option<&CheckResult>,
+    expected: &[CelType],
+) -> Option<ResultTypeMismatch> {
+    if expected.is_empty() {
+        return None;
+    }
+    let ast = ast?;
+    let actual = check_result?.type_map.get(&ast.id)?;
+    let unwrapped = match actual {
+        CelType::Wrapper(inner) => inner.as_ref(),
+        other => other,
+    };
+    if expected.iter().any(|t| t.is_assignable_from(unwrapped)) {
+        return None;
+    }
+
+    let names: Vec<_> = expected
+        .iter()
+        .map(|t| format!("'{}'", t.display_name()))
+        .collect();
+    Some(ResultTypeMismatch {
+        span: ast.span.clone(),
+        message: format!(
+            "expected result type {} but found '{}'",
+            names.join(" or "),
+            actual.display_name()
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cel_core::{parse, Env};
+
+    fn mismatch(source: &str, expected: &[CelType]) -> Option<String> {
+        let env = Env::with_standard_library().with_variable("x", CelType::Dyn);
+        let ast = parse(source).ast.unwrap();
+        let result = env.check(&ast);
+        result_type_mismatch(Some(&ast), Some(&result), expected).map(|m| m.message)
+    }
+
+    #[test]
+    fn accepts_expected_and_undetermined_types() {
+        let expected = [CelType::Bool, CelType::String];
+        assert_eq!(mismatch("1 < 2", &expected), None);
+        assert_eq!(mismatch("'too small'", &expected), None);
+        assert_eq!(mismatch("x", &expected), None);
+        assert_eq!(mismatch("missing", &expected), None);
+        assert_eq!(mismatch("1 + 2", &[]), None);
+    }
+
+    #[test]
+    fn reports_other_types() {
+        assert_eq!(
+            mismatch("1 + 2", &[CelType::Bool, CelType::String]).unwrap(),
+            "expected result type 'bool' or 'string' but found 'int'"
+        );
+        assert_eq!(
+            mismatch("[true]", &[CelType::Bool]).unwrap(),
+            "expected result type 'bool' but found 'list<bool>'"
+        );
+    }
+}
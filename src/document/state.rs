@@ -2,14 +2,20 @@
 
 use std::sync::Arc;
 
-use cel_core::{parse, CheckError, CheckResult, Env, ParseError, SpannedExpr};
+use cel_core::{parse, CelType, CheckError, CheckResult, Env, ParseError, SpannedExpr};
 use cel_core_proto::ProstProtoRegistry;
 use dashmap::DashMap;
 use tower_lsp::lsp_types::Url;
 
-use crate::protovalidate::extract_cel_regions;
+use crate::analysis::{
+    complexity_problems, cost_limit_problem, deprecation_problems, estimate_cost, ComplexityLimits,
+    CostBudget, CostEstimate, Problem,
+};
+use crate::kubernetes::{extract_kubernetes_regions, KubernetesVersion};
+use crate::protovalidate::{extract_cel_regions, ProtovalidateVersion};
 
 use super::region::CelRegionState;
+use super::result_type::{result_type_mismatch, ResultTypeMismatch};
 use super::text::LineIndex;
 
 /// State for a single document.
@@ -29,6 +35,12 @@ pub struct DocumentState {
     pub source: String,
     /// The environment used for type checking (needed for completion).
     pub env: Arc<Env>,
+    /// Result types the expression must evaluate to; empty accepts any type.
+    pub expected: Vec<CelType>,
+    /// Cost limit and size hints the expression is estimated with.
+    pub budget: CostBudget,
+    /// Limits on the structure of the expression.
+    pub limits: ComplexityLimits,
 }
 
 impl DocumentState {
@@ -54,9 +66,18 @@ impl DocumentState {
             version,
             source,
             env,
+            expected: Vec::new(),
+            budget: CostBudget::default(),
+            limits: ComplexityLimits::default(),
         }
     }
 
+    /// Require the expression to evaluate to one of `expected`.
+    pub fn expecting(mut self, expected: Vec<CelType>) -> Self {
+        self.expected = expected;
+        self
+    }
+
     /// Get the AST if available.
     /// Note: The AST may contain Expr::Error nodes if there were parse errors.
     pub fn ast(&self) -> Option<&SpannedExpr> {
@@ -70,27 +91,89 @@ impl DocumentState {
             .map(|r| r.errors.as_slice())
             .unwrap_or(&[])
     }
+
+    /// The mismatch between the checked result type and the expected types, if any.
+    pub fn result_type_mismatch(&self) -> Option<ResultTypeMismatch> {
+        result_type_mismatch(
+            self.ast.as_ref(),
+            self.check_result.as_ref(),
+            &self.expected,
+        )
+    }
+
+    /// The estimated costs of the expression, if it parsed.
+    pub fn cost(&self) -> Option<CostEstimate> {
+        let ast = self.ast.as_ref()?;
+        Some(estimate_cost(
+            ast,
+            &self.source,
+            self.check_result.as_ref(),
+            &self.budget.max_sizes,
+        ))
+    }
+
+    /// A `cost-limit` problem when the estimated cost exceeds the limit.
+    pub fn cost_overrun(&self) -> Option<Problem> {
+        let limit = self.budget.limit?;
+        cost_limit_problem(self.ast.as_ref()?, &self.cost()?, limit)
+    }
+
+    /// Problems with the cost limit and the structural limits.
+    pub fn limit_problems(&self) -> Vec<Problem> {
+        let mut problems: Vec<_> = self.cost_overrun().into_iter().collect();
+        if let Some(ast) = &self.ast {
+            problems.extend(complexity_problems(ast, &self.limits));
+        }
+        problems
+    }
+
+    /// References to deprecated proto fields and enum values.
+    pub fn deprecations(&self) -> Vec<Problem> {
+        match (&self.ast, &self.check_result, self.env.proto_registry()) {
+            (Some(ast), Some(check_result), Some(registry)) => {
+                deprecation_problems(ast, &self.source, check_result, registry)
+            }
+            _ => Vec::new(),
+        }
+    }
 }
 
-/// State for a .proto file containing embedded CEL expressions.
+/// Releases of the hosts that embedded expressions are checked for.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HostVersions {
+    /// Protovalidate release for rules in `.proto` files.
+    pub protovalidate: ProtovalidateVersion,
+    /// Kubernetes version for expressions in YAML manifests.
+    pub kubernetes: KubernetesVersion,
+}
+
+/// State for a host document containing embedded CEL expressions.
+///
+/// Used for .proto files (protovalidate options) and Kubernetes YAML manifests.
 #[derive(Debug, Clone)]
-pub struct ProtoDocumentState {
-    /// Pre-computed line index for the full proto file.
+pub struct EmbeddedDocumentState {
+    /// Pre-computed line index for the full host document.
     pub line_index: LineIndex,
 
-    /// All CEL regions extracted from this proto file.
+    /// All CEL regions extracted from this document.
     pub regions: Vec<CelRegionState>,
 
     /// Document version from the client.
     pub version: i32,
+
+    /// The token starting a line comment in the host document.
+    pub comment: &'static str,
 }
 
-impl ProtoDocumentState {
+impl EmbeddedDocumentState {
     /// Create a new proto document state by extracting and parsing CEL regions.
+    ///
+    /// Regions are checked with the library of the `protovalidate` release.
     pub fn new(
         source: String,
         version: i32,
         proto_registry: Option<&Arc<ProstProtoRegistry>>,
+        protovalidate: ProtovalidateVersion,
     ) -> Self {
         let line_index = LineIndex::new(source.clone());
 
@@ -100,10 +183,14 @@ impl ProtoDocumentState {
         // Parse and validate each region with its context
         let regions = extracted
             .into_iter()
-            .map(|ext| {
+            .map(|mut ext| {
                 let context = ext.context.clone();
+                let block = ext.block.clone();
+                let size_hints = std::mem::take(&mut ext.size_hints);
                 let (region, mapper) = ext.into_region_and_mapper();
-                CelRegionState::with_context(region, mapper, context, proto_registry)
+                CelRegionState::with_context(region, mapper, context, proto_registry, protovalidate)
+                    .in_block(block)
+                    .with_size_hints(size_hints)
             })
             .collect();
 
@@ -111,6 +198,25 @@ impl ProtoDocumentState {
             line_index,
             regions,
             version,
+            comment: "//",
+        }
+    }
+
+    /// Create a document state for a Kubernetes YAML manifest.
+    ///
+    /// Each CEL expression gets the environment the API server of `kubernetes`
+    /// would provide for it.
+    pub fn from_kubernetes_yaml(
+        source: String,
+        version: i32,
+        kubernetes: KubernetesVersion,
+    ) -> Self {
+        let regions = extract_kubernetes_regions(&source, kubernetes);
+        Self {
+            line_index: LineIndex::new(source),
+            regions,
+            version,
+            comment: "#",
         }
     }
 
@@ -128,7 +234,101 @@ pub enum DocumentKind {
     /// A .cel file containing a single CEL expression.
     Cel(Box<DocumentState>),
     /// A .proto file containing embedded CEL expressions.
-    Proto(ProtoDocumentState),
+    Proto(EmbeddedDocumentState),
+    /// A Kubernetes YAML manifest containing embedded CEL expressions.
+    Yaml(EmbeddedDocumentState),
+}
+
+impl DocumentKind {
+    /// Analyze a document, auto-detecting its type based on file extension.
+    ///
+    /// If `env` is provided, `.cel` files will use it instead of the default
+    /// environment; they must evaluate to one of `expected`, or anything when
+    /// it is empty. Embedded expressions are checked for the host releases in
+    /// `versions`.
+    pub fn new(
+        uri: &Url,
+        source: String,
+        version: i32,
+        proto_registry: Option<&Arc<ProstProtoRegistry>>,
+        env: Option<&Arc<Env>>,
+        versions: HostVersions,
+        expected: &[CelType],
+    ) -> Self {
+        if is_proto_file(uri) {
+            DocumentKind::Proto(EmbeddedDocumentState::new(
+                source,
+                version,
+                proto_registry,
+                versions.protovalidate,
+            ))
+        } else if is_yaml_file(uri) {
+            DocumentKind::Yaml(EmbeddedDocumentState::from_kubernetes_yaml(
+                source,
+                version,
+                versions.kubernetes,
+            ))
+        } else if let Some(env) = env {
+            DocumentKind::Cel(Box::new(
+                DocumentState::with_env(source, version, Arc::clone(env))
+                    .expecting(expected.to_vec()),
+            ))
+        } else {
+            DocumentKind::Cel(Box::new(
+                DocumentState::new(source, version).expecting(expected.to_vec()),
+            ))
+        }
+    }
+
+    /// Estimate costs against `budget`.
+    ///
+    /// Sizes derived from the document itself, such as protovalidate
+    /// `max_len` rules, take precedence over the sizes in `budget`.
+    pub fn with_budget(mut self, budget: &CostBudget) -> Self {
+        match &mut self {
+            DocumentKind::Cel(state) => state.budget = budget.clone(),
+            DocumentKind::Proto(state) | DocumentKind::Yaml(state) => {
+                for region in &mut state.regions {
+                    let mut max_sizes = budget.max_sizes.clone();
+                    max_sizes.extend(std::mem::take(&mut region.budget.max_sizes));
+                    region.budget = CostBudget {
+                        limit: budget.limit,
+                        max_sizes,
+                    };
+                }
+            }
+        }
+        self
+    }
+
+    /// Enforce `limits` on the structure of every expression.
+    pub fn with_limits(mut self, limits: &ComplexityLimits) -> Self {
+        match &mut self {
+            DocumentKind::Cel(state) => state.limits = limits.clone(),
+            DocumentKind::Proto(state) | DocumentKind::Yaml(state) => {
+                for region in &mut state.regions {
+                    region.limits = limits.clone();
+                }
+            }
+        }
+        self
+    }
+
+    /// Get the document source text.
+    pub fn source(&self) -> &str {
+        match self {
+            DocumentKind::Cel(state) => &state.source,
+            DocumentKind::Proto(state) | DocumentKind::Yaml(state) => state.line_index.source(),
+        }
+    }
+
+    /// Get the document version from the client.
+    pub fn version(&self) -> i32 {
+        match self {
+            DocumentKind::Cel(state) => state.version,
+            DocumentKind::Proto(state) | DocumentKind::Yaml(state) => state.version,
+        }
+    }
 }
 
 /// Thread-safe storage for open documents.
@@ -145,34 +345,21 @@ impl DocumentStore {
         }
     }
 
-    /// Open or update a document with the given source text.
-    /// Auto-detects document type based on file extension.
-    ///
-    /// If `env` is provided, `.cel` files will use it instead of the default environment.
-    pub fn open(
-        &self,
-        uri: Url,
-        source: String,
-        version: i32,
-        proto_registry: Option<&Arc<ProstProtoRegistry>>,
-        env: Option<&Arc<Env>>,
-    ) -> Arc<DocumentKind> {
-        let kind = if is_proto_file(&uri) {
-            DocumentKind::Proto(ProtoDocumentState::new(source, version, proto_registry))
-        } else if let Some(env) = env {
-            DocumentKind::Cel(Box::new(DocumentState::with_env(
-                source,
-                version,
-                Arc::clone(env),
-            )))
-        } else {
-            DocumentKind::Cel(Box::new(DocumentState::new(source, version)))
-        };
-        let state = Arc::new(kind);
+    /// Open or update a document with its analyzed state.
+    pub fn insert(&self, uri: Url, state: DocumentKind) -> Arc<DocumentKind> {
+        let state = Arc::new(state);
         self.documents.insert(uri, Arc::clone(&state));
         state
     }
 
+    /// Get every open document.
+    pub fn all(&self) -> Vec<(Url, Arc<DocumentKind>)> {
+        self.documents
+            .iter()
+            .map(|entry| (entry.key().clone(), Arc::clone(entry.value())))
+            .collect()
+    }
+
     /// Close a document.
     pub fn close(&self, uri: &Url) {
         self.documents.remove(uri);
@@ -185,6 +372,11 @@ impl DocumentStore {
 }
 
 /// Check if a URI refers to a .proto file.
-fn is_proto_file(uri: &Url) -> bool {
+pub fn is_proto_file(uri: &Url) -> bool {
     uri.path().ends_with(".proto")
 }
+
+/// Check if a URI refers to a YAML file.
+fn is_yaml_file(uri: &Url) -> bool {
+    uri.path().ends_with(".yaml") || uri.path().ends_with(".yml")
+}
@@ -0,0 +1,392 @@
+//! Envoy attribute vocabulary.
+//!
+//! Envoy exposes request, response, connection and upstream properties to CEL
+//! expressions in RBAC policies, access log filters and other extensions.
+//! Attribute groups are declared as synthetic message types so that members
+//! are type checked and offered in completion.
+//!
+//! See <https://www.envoyproxy.io/docs/envoy/latest/intro/arch_overview/advanced/attributes>.
+
+use cel_core::{CelType, Env};
+
+use crate::types::SyntheticTypes;
+
+/// Message type of `metadata` and the `*_metadata` attributes.
+const METADATA_TYPE: &str = "envoy.config.core.v3.Metadata";
+/// Message type of `upstream.locality`.
+const LOCALITY_TYPE: &str = "envoy.config.core.v3.Locality";
+/// Message type of `xds.node`.
+const NODE_TYPE: &str = "envoy.config.core.v3.Node";
+
+/// An attribute group: a top-level variable with typed members.
+struct AttributeGroup {
+    variable: &'static str,
+    type_name: &'static str,
+    attributes: &'static [Attribute],
+    /// Whether the group also has the TLS certificate attributes.
+    certificates: bool,
+}
+
+/// A member of an attribute group.
+struct Attribute {
+    name: &'static str,
+    cel_type: fn() -> CelType,
+    description: &'static str,
+}
+
+const fn attr(
+    name: &'static str,
+    cel_type: fn() -> CelType,
+    description: &'static str,
+) -> Attribute {
+    Attribute {
+        name,
+        cel_type,
+        description,
+    }
+}
+
+fn string() -> CelType {
+    CelType::String
+}
+
+fn int() -> CelType {
+    CelType::Int
+}
+
+fn uint() -> CelType {
+    CelType::UInt
+}
+
+fn boolean() -> CelType {
+    CelType::Bool
+}
+
+fn timestamp() -> CelType {
+    CelType::Timestamp
+}
+
+fn duration() -> CelType {
+    CelType::Duration
+}
+
+fn headers() -> CelType {
+    CelType::map(CelType::String, CelType::String)
+}
+
+fn metadata() -> CelType {
+    CelType::message(METADATA_TYPE)
+}
+
+fn locality() -> CelType {
+    CelType::message(LOCALITY_TYPE)
+}
+
+fn node() -> CelType {
+    CelType::message(NODE_TYPE)
+}
+
+const ADDRESS_ATTRIBUTES: &[Attribute] = &[
+    attr("address", string, "Peer address as `host:port`."),
+    attr("port", int, "Peer port."),
+];
+
+const CERTIFICATE_ATTRIBUTES: &[Attribute] = &[
+    attr("tls_version", string, "TLS version of the connection."),
+    attr(
+        "subject_local_certificate",
+        string,
+        "Subject field of the local certificate.",
+    ),
+    attr(
+        "subject_peer_certificate",
+        string,
+        "Subject field of the peer certificate.",
+    ),
+    attr(
+        "dns_san_local_certificate",
+        string,
+        "First DNS entry in the SAN field of the local certificate.",
+    ),
+    attr(
+        "dns_san_peer_certificate",
+        string,
+        "First DNS entry in the SAN field of the peer certificate.",
+    ),
+    attr(
+        "uri_san_local_certificate",
+        string,
+        "First URI entry in the SAN field of the local certificate.",
+    ),
+    attr(
+        "uri_san_peer_certificate",
+        string,
+        "First URI entry in the SAN field of the peer certificate.",
+    ),
+    attr(
+        "sha256_peer_certificate_digest",
+        string,
+        "SHA256 digest of the peer certificate.",
+    ),
+];
+
+const GROUPS: &[AttributeGroup] = &[
+    AttributeGroup {
+        variable: "request",
+        type_name: "envoy.attributes.Request",
+        attributes: &[
+            attr("path", string, "The path portion of the URL."),
+            attr(
+                "url_path",
+                string,
+                "The path portion of the URL without the query string.",
+            ),
+            attr("host", string, "The host portion of the URL."),
+            attr("scheme", string, "The scheme portion of the URL, e.g. `http`."),
+            attr("method", string, "Request method, e.g. `GET`."),
+            attr(
+                "headers",
+                headers,
+                "All request headers indexed by the lower-cased header name.",
+            ),
+            attr("referer", string, "Referer request header."),
+            attr("useragent", string, "User agent request header."),
+            attr("time", timestamp, "Time of the first byte received."),
+            attr("id", string, "Request ID corresponding to `x-request-id`."),
+            attr(
+                "protocol",
+                string,
+                "Request protocol, e.g. `HTTP/1.1` or `HTTP/2`.",
+            ),
+            attr("query", string, "The query portion of the URL."),
+            attr("duration", duration, "Total duration of the request."),
+            attr("size", int, "Size of the request body."),
+            attr(
+                "total_size",
+                int,
+                "Total size of the request including the headers.",
+            ),
+        ],
+        certificates: false,
+    },
+    AttributeGroup {
+        variable: "response",
+        type_name: "envoy.attributes.Response",
+        attributes: &[
+            attr("code", int, "Response HTTP status code."),
+            attr("code_details", string, "Internal response code details."),
+            attr("flags", int, "Additional details about the response."),
+            attr("grpc_status", int, "Response gRPC status code."),
+            attr(
+                "headers",
+                headers,
+                "All response headers indexed by the lower-cased header name.",
+            ),
+            attr(
+                "trailers",
+                headers,
+                "All response trailers indexed by the lower-cased trailer name.",
+            ),
+            attr("size", int, "Size of the response body."),
+            attr(
+                "total_size",
+                int,
+                "Total size of the response including the headers.",
+            ),
+            attr(
+                "backend_latency",
+                duration,
+                "Duration between the first byte sent upstream and the first byte received.",
+            ),
+        ],
+        certificates: false,
+    },
+    AttributeGroup {
+        variable: "source",
+        type_name: "envoy.attributes.Source",
+        attributes: ADDRESS_ATTRIBUTES,
+        certificates: false,
+    },
+    AttributeGroup {
+        variable: "destination",
+        type_name: "envoy.attributes.Destination",
+        attributes: ADDRESS_ATTRIBUTES,
+        certificates: false,
+    },
+    AttributeGroup {
+        variable: "connection",
+        type_name: "envoy.attributes.Connection",
+        attributes: &[
+            attr("id", uint, "Downstream connection ID."),
+            attr(
+                "mtls",
+                boolean,
+                "Whether TLS is applied to the downstream connection and the peer presented a certificate.",
+            ),
+            attr(
+                "requested_server_name",
+                string,
+                "Requested server name in the downstream TLS connection.",
+            ),
+            attr(
+                "transport_failure_reason",
+                string,
+                "Transport failure reason, e.g. certificate validation failed.",
+            ),
+            attr(
+                "termination_details",
+                string,
+                "Internal termination details of the connection.",
+            ),
+        ],
+        certificates: true,
+    },
+    AttributeGroup {
+        variable: "upstream",
+        type_name: "envoy.attributes.Upstream",
+        attributes: &[
+            attr("address", string, "Upstream connection remote address."),
+            attr("port", int, "Upstream connection remote port."),
+            attr(
+                "local_address",
+                string,
+                "Local address of the upstream connection.",
+            ),
+            attr(
+                "transport_failure_reason",
+                string,
+                "Upstream transport failure reason.",
+            ),
+            attr(
+                "request_attempt_count",
+                uint,
+                "Number of attempts made to send the request upstream.",
+            ),
+            attr(
+                "cx_pool_ready_duration",
+                duration,
+                "Duration from the request until the connection pool was ready.",
+            ),
+            attr(
+                "locality",
+                locality,
+                "Locality of the upstream host.",
+            ),
+        ],
+        certificates: true,
+    },
+    AttributeGroup {
+        variable: "xds",
+        type_name: "envoy.attributes.Xds",
+        attributes: &[
+            attr("node", node, "Local node description."),
+            attr("cluster_name", string, "Upstream cluster name."),
+            attr("cluster_metadata", metadata, "Upstream cluster metadata."),
+            attr("listener_direction", int, "Enumeration value of the listener traffic direction."),
+            attr("listener_metadata", metadata, "Listener metadata."),
+            attr("route_name", string, "Route name."),
+            attr("route_metadata", metadata, "Route metadata."),
+            attr("virtual_host_name", string, "Virtual host name."),
+            attr("virtual_host_metadata", metadata, "Virtual host metadata."),
+            attr("upstream_host_metadata", metadata, "Upstream host metadata."),
+            attr("filter_chain_name", string, "Listener filter chain name."),
+        ],
+        certificates: false,
+    },
+];
+
+/// Declare Envoy's attributes as variables, adding their types to `types`.
+pub fn with_envoy_attributes(mut env: Env, types: &mut SyntheticTypes) -> Env {
+    for group in GROUPS {
+        let certificates = if group.certificates {
+            CERTIFICATE_ATTRIBUTES
+        } else {
+            &[]
+        };
+        for attribute in group.attributes.iter().chain(certificates) {
+            types.add_field(
+                group.type_name,
+                attribute.name,
+                (attribute.cel_type)(),
+                Some(attribute.description.to_string()),
+            );
+        }
+        env.add_variable(group.variable, CelType::message(group.type_name));
+    }
+
+    declare_core_types(types);
+    env.add_variable("metadata", metadata());
+    env.add_variable(
+        "filter_state",
+        CelType::map(CelType::String, CelType::Bytes),
+    );
+    env
+}
+
+/// Declare the subset of `envoy.config.core.v3` messages reachable from attributes.
+fn declare_core_types(types: &mut SyntheticTypes) {
+    let structs = CelType::map(CelType::String, CelType::map(CelType::String, CelType::Dyn));
+    types.add_field(
+        METADATA_TYPE,
+        "filter_metadata",
+        structs,
+        Some("Metadata structs keyed by filter name.".to_string()),
+    );
+    types.add_field(
+        METADATA_TYPE,
+        "typed_filter_metadata",
+        CelType::map(CelType::String, CelType::Dyn),
+        Some("Typed metadata keyed by filter name.".to_string()),
+    );
+
+    for field in ["region", "zone", "sub_zone"] {
+        types.add_field(LOCALITY_TYPE, field, CelType::String, None);
+    }
+
+    for field in ["id", "cluster", "user_agent_name"] {
+        types.add_field(NODE_TYPE, field, CelType::String, None);
+    }
+    types.add_field(
+        NODE_TYPE,
+        "metadata",
+        CelType::map(CelType::String, CelType::Dyn),
+        Some("Opaque node metadata.".to_string()),
+    );
+    types.add_field(NODE_TYPE, "locality", locality(), None);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cel_core::parse;
+    use std::sync::Arc;
+
+    fn check(source: &str) -> cel_core::CheckResult {
+        let mut types = SyntheticTypes::new();
+        let env = with_envoy_attributes(Env::with_standard_library(), &mut types);
+        let env = env.with_proto_registry(Arc::new(types));
+        env.check(&parse(source).ast.unwrap())
+    }
+
+    #[test]
+    fn attributes_are_typed() {
+        let result = check(
+            "request.headers['x-user'] == 'admin' && source.port < 1024 \
+             && request.time > timestamp('2024-01-01T00:00:00Z') \
+             && connection.mtls && response.code == 200 \
+             && upstream.request_attempt_count > 1u \
+             && metadata.filter_metadata['envoy.filters.http.rbac']['shadow'] == 'x' \
+             && xds.node.locality.zone == 'us-east1-b' && 'k' in filter_state",
+        );
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+    }
+
+    #[test]
+    fn wrong_types_are_reported() {
+        let result = check("request.size + 'large'");
+        assert_eq!(result.errors.len(), 1);
+
+        let result = check("source.hostname == 'a'");
+        assert_eq!(result.errors.len(), 1);
+    }
+}
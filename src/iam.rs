@@ -0,0 +1,269 @@
+//! Google Cloud IAM Conditions profile.
+//!
+//! IAM Conditions accept a subset of CEL: a fixed set of request, resource
+//! and API attributes, and a limited set of functions. The profile declares
+//! the attributes and removes every other function from the environment, so
+//! calls outside the subset are reported when checking.
+//!
+//! See <https://cloud.google.com/iam/docs/conditions-overview>.
+
+use std::sync::LazyLock;
+
+use cel_core::types::{FunctionDecl, OverloadDecl};
+use cel_core::{CelType, Env};
+
+use crate::types::SyntheticTypes;
+
+const REQUEST_TYPE: &str = "iam.Request";
+const AUTH_TYPE: &str = "iam.Request.Auth";
+const RESOURCE_TYPE: &str = "iam.Resource";
+const DESTINATION_TYPE: &str = "iam.Destination";
+/// Opaque type of `api`, which only offers `getAttribute`.
+const API_TYPE: &str = "iam.Api";
+
+/// Functions and operators available in IAM Conditions.
+const ALLOWED_FUNCTIONS: &[&str] = &[
+    // Logical operators
+    "_&&_",
+    "_||_",
+    "!_",
+    "_?_:_",
+    // Comparison and membership
+    "_==_",
+    "_!=_",
+    "_<_",
+    "_<=_",
+    "_>_",
+    "_>=_",
+    "@in",
+    "_[_]",
+    // Date/time arithmetic
+    "_+_",
+    "_-_",
+    "timestamp",
+    "duration",
+    "getDate",
+    "getDayOfMonth",
+    "getDayOfWeek",
+    "getDayOfYear",
+    "getFullYear",
+    "getHours",
+    "getMilliseconds",
+    "getMinutes",
+    "getMonth",
+    "getSeconds",
+    // Strings
+    "startsWith",
+    "endsWith",
+    "extract",
+    // Lists
+    "hasOnly",
+    // Attributes
+    "getAttribute",
+    "matchTag",
+    "matchTagId",
+    "hasTagKey",
+    "hasTagKeyId",
+];
+
+/// Declare the IAM Conditions attributes and restrict the environment to the
+/// functions IAM supports.
+pub fn with_iam_conditions(env: Env, types: &mut SyntheticTypes) -> Env {
+    declare_types(types);
+
+    let env = env
+        .with_extension(iam_library())
+        .with_variable("request", CelType::message(REQUEST_TYPE))
+        .with_variable("resource", CelType::message(RESOURCE_TYPE))
+        .with_variable("destination", CelType::message(DESTINATION_TYPE))
+        .with_variable("api", CelType::abstract_type(API_TYPE, &[]));
+
+    restrict_functions(&env, ALLOWED_FUNCTIONS)
+}
+
+/// Functions of the standard library, all of which any other environment has.
+static STANDARD_LIBRARY: LazyLock<Vec<String>> = LazyLock::new(|| {
+    Env::with_standard_library()
+        .functions()
+        .keys()
+        .cloned()
+        .collect()
+});
+
+/// Whether `env` was restricted to the IAM Conditions functions.
+pub fn is_restricted(env: &Env) -> bool {
+    let functions = env.functions();
+    STANDARD_LIBRARY
+        .iter()
+        .any(|name| !functions.contains_key(name))
+}
+
+/// Rebuild `env` keeping only the named functions.
+///
+/// Must run before a proto registry, container or abbreviations are set, as
+/// only variables and functions are carried over.
+fn restrict_functions(env: &Env, allowed: &[&str]) -> Env {
+    let mut restricted = Env::new();
+    for (name, cel_type) in env.variables() {
+        restricted.add_variable(name.clone(), cel_type.clone());
+    }
+    for (name, decl) in env.functions() {
+        if allowed.contains(&name.as_str()) {
+            restricted.add_function(decl.clone());
+        }
+    }
+    restricted
+}
+
+fn declare_types(types: &mut SyntheticTypes) {
+    let doc = |text: &str| Some(text.to_string());
+
+    types.add_field(
+        REQUEST_TYPE,
+        "time",
+        CelType::Timestamp,
+        doc("Time the request was received."),
+    );
+    types.add_field(
+        REQUEST_TYPE,
+        "host",
+        CelType::String,
+        doc("Host of the request (Identity-Aware Proxy)."),
+    );
+    types.add_field(
+        REQUEST_TYPE,
+        "path",
+        CelType::String,
+        doc("Path of the request (Identity-Aware Proxy)."),
+    );
+    types.add_field(
+        REQUEST_TYPE,
+        "auth",
+        CelType::message(AUTH_TYPE),
+        doc("Authentication attributes of the request."),
+    );
+    types.add_field(
+        AUTH_TYPE,
+        "access_levels",
+        CelType::list(CelType::String),
+        doc("Access Context Manager access levels satisfied by the request."),
+    );
+
+    types.add_field(
+        RESOURCE_TYPE,
+        "name",
+        CelType::String,
+        doc("Full resource name, e.g. `projects/_/buckets/b/objects/o`."),
+    );
+    types.add_field(
+        RESOURCE_TYPE,
+        "type",
+        CelType::String,
+        doc("Resource type, e.g. `storage.googleapis.com/Object`."),
+    );
+    types.add_field(
+        RESOURCE_TYPE,
+        "service",
+        CelType::String,
+        doc("Service name, e.g. `storage.googleapis.com`."),
+    );
+
+    types.add_field(
+        DESTINATION_TYPE,
+        "ip",
+        CelType::String,
+        doc("Destination IP address (Identity-Aware Proxy TCP forwarding)."),
+    );
+    types.add_field(
+        DESTINATION_TYPE,
+        "port",
+        CelType::Int,
+        doc("Destination port (Identity-Aware Proxy TCP forwarding)."),
+    );
+}
+
+fn iam_library() -> Vec<FunctionDecl> {
+    let api = || CelType::abstract_type(API_TYPE, &[]);
+    let resource = || CelType::message(RESOURCE_TYPE);
+    let t = || CelType::type_param("T");
+
+    vec![
+        FunctionDecl::new("getAttribute").with_overloads(vec![OverloadDecl::method(
+            "api_get_attribute",
+            vec![api(), CelType::String, t()],
+            t(),
+        )
+        .with_type_params(vec!["T".to_string()])]),
+        FunctionDecl::new("extract").with_overloads(vec![OverloadDecl::method(
+            "string_extract",
+            vec![CelType::String, CelType::String],
+            CelType::String,
+        )]),
+        FunctionDecl::new("hasOnly").with_overloads(vec![OverloadDecl::method(
+            "list_has_only",
+            vec![CelType::list(t()), CelType::list(t())],
+            CelType::Bool,
+        )
+        .with_type_params(vec!["T".to_string()])]),
+        FunctionDecl::new("matchTag").with_overloads(vec![OverloadDecl::method(
+            "resource_match_tag",
+            vec![resource(), CelType::String, CelType::String],
+            CelType::Bool,
+        )]),
+        FunctionDecl::new("matchTagId").with_overloads(vec![OverloadDecl::method(
+            "resource_match_tag_id",
+            vec![resource(), CelType::String, CelType::String],
+            CelType::Bool,
+        )]),
+        FunctionDecl::new("hasTagKey").with_overloads(vec![OverloadDecl::method(
+            "resource_has_tag_key",
+            vec![resource(), CelType::String],
+            CelType::Bool,
+        )]),
+        FunctionDecl::new("hasTagKeyId").with_overloads(vec![OverloadDecl::method(
+            "resource_has_tag_key_id",
+            vec![resource(), CelType::String],
+            CelType::Bool,
+        )]),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cel_core::parse;
+    use std::sync::Arc;
+
+    fn check(source: &str) -> cel_core::CheckResult {
+        let mut types = SyntheticTypes::new();
+        let env = with_iam_conditions(Env::with_standard_library(), &mut types);
+        let env = env.with_proto_registry(Arc::new(types));
+        env.check(&parse(source).ast.unwrap())
+    }
+
+    #[test]
+    fn supported_conditions_check() {
+        for source in [
+            "request.time < timestamp('2025-01-01T00:00:00Z')",
+            "request.time.getHours('Europe/Berlin') >= 9",
+            "resource.name.startsWith('projects/_/buckets/b/objects/tmp/')",
+            "resource.name.extract('/objects/{name}') != ''",
+            "resource.type == 'storage.googleapis.com/Object' && resource.service == 'storage.googleapis.com'",
+            "'accessPolicies/1/accessLevels/corp' in request.auth.access_levels",
+            "api.getAttribute('iam.googleapis.com/modifiedGrantsByRole', []).hasOnly(['roles/viewer'])",
+            "resource.matchTag('123/env', 'prod') || !resource.hasTagKey('123/env')",
+        ] {
+            let result = check(source);
+            assert!(result.errors.is_empty(), "{}: {:?}", source, result.errors);
+        }
+    }
+
+    #[test]
+    fn other_functions_are_removed() {
+        let result = check("resource.name.matches('^projects/.*')");
+        assert_eq!(result.errors.len(), 1);
+
+        let result = check("size(resource.name) > 3");
+        assert_eq!(result.errors.len(), 1);
+    }
+}
@@ -0,0 +1,501 @@
+//! Extraction of CEL expressions from Kubernetes manifests.
+//!
+//! Each expression becomes a `CelRegionState` with an environment matching
+//! what the API server provides, including the Kubernetes CEL libraries of the
+//! configured version:
+//! - CRD `x-kubernetes-validations` rules get `self` and `oldSelf` typed from
+//!   the surrounding OpenAPI schema
+//! - ValidatingAdmissionPolicy expressions get `object`, `oldObject`,
+//!   `request`, `params`, `namespaceObject`, `authorizer` and `variables`,
+//!   where each variable is typed by checking its expression
+//! - webhook `matchConditions` get `object`, `oldObject`, `request` and
+//!   `authorizer`
+//!
+//! Rules and conditions must evaluate to `bool`, messages and audit
+//! annotations to `string`.
+
+use std::sync::Arc;
+
+use cel_core::{CelType, Env};
+
+use crate::document::{CelRegion, CelRegionState, OffsetMapper};
+use crate::types::SyntheticTypes;
+
+use super::library::{with_kubernetes_libraries, KubernetesVersion, AUTHORIZER_TYPE};
+use super::schema::SchemaConverter;
+use super::yaml::{parse_documents, YamlNode, YamlScalar};
+
+/// Message type of the admission `request` variable.
+const ADMISSION_REQUEST: &str = "AdmissionRequest";
+
+/// Message type of the admission policy `variables` variable.
+const VARIABLES: &str = "Variables";
+
+/// Extract and check every CEL expression in a Kubernetes YAML document.
+/// Expressions get the libraries of the `kubernetes` API server.
+pub fn extract_kubernetes_regions(
+    source: &str,
+    kubernetes: KubernetesVersion,
+) -> Vec<CelRegionState> {
+    let mut regions = Vec::new();
+
+    for document in parse_documents(source) {
+        match document.get("kind").and_then(YamlNode::as_str) {
+            Some("CustomResourceDefinition") => crd_regions(&document, kubernetes, &mut regions),
+            Some("ValidatingAdmissionPolicy") => {
+                policy_regions(&document, kubernetes, &mut regions)
+            }
+            Some("ValidatingWebhookConfiguration" | "MutatingWebhookConfiguration") => {
+                webhook_regions(&document, kubernetes, &mut regions)
+            }
+            _ => {}
+        }
+    }
+
+    regions.sort_by_key(|r| r.mapper.host_offset());
+    regions
+}
+
+/// Build a region state for an expression scalar.
+fn region_state(scalar: &YamlScalar, env: &Arc<Env>) -> CelRegionState {
+    CelRegionState::with_env(
+        CelRegion {
+            source: scalar.value.clone(),
+        },
+        OffsetMapper::new(scalar.host_offset, scalar.escape_adjustments.clone()),
+        Arc::clone(env),
+    )
+}
+
+/// Push a region for `item[key]` if it is a scalar, expecting one of `expected`.
+fn push_expression(
+    item: &YamlNode,
+    key: &str,
+    env: &Arc<Env>,
+    expected: &[CelType],
+    regions: &mut Vec<CelRegionState>,
+) -> Option<CelType> {
+    let scalar = item.get(key)?.as_scalar()?;
+    let state = region_state(scalar, env).expecting(expected.to_vec());
+    let result_type = state
+        .ast
+        .as_ref()
+        .zip(state.check_result.as_ref())
+        .and_then(|(ast, result)| result.type_map.get(&ast.id).cloned());
+    regions.push(state);
+    result_type
+}
+
+fn base_env(types: &SyntheticTypes, kubernetes: KubernetesVersion) -> Env {
+    with_kubernetes_libraries(Env::with_standard_library(), kubernetes)
+        .with_proto_registry(Arc::new(types.clone()))
+}
+
+/// Get a value by following a path of mapping keys.
+fn path<'a>(node: &'a YamlNode, keys: &[&str]) -> Option<&'a YamlNode> {
+    keys.iter().try_fold(node, |node, key| node.get(key))
+}
+
+fn crd_regions(
+    document: &YamlNode,
+    kubernetes: KubernetesVersion,
+    regions: &mut Vec<CelRegionState>,
+) {
+    let kind = path(document, &["spec", "names", "kind"])
+        .and_then(YamlNode::as_str)
+        .unwrap_or("Object");
+
+    let mut schemas = Vec::new();
+    let versions = path(document, &["spec", "versions"])
+        .map(YamlNode::items)
+        .unwrap_or_default();
+    for version in versions {
+        if let Some(schema) = path(version, &["schema", "openAPIV3Schema"]) {
+            // Keep the types of each version apart when they differ
+            let name = match version.get("name").and_then(YamlNode::as_str) {
+                Some(version) if versions.len() > 1 => format!("{}.{}", version, kind),
+                _ => kind.to_string(),
+            };
+            schemas.push((schema, name));
+        }
+    }
+    // apiextensions.k8s.io/v1beta1 top-level schema
+    if let Some(schema) = path(document, &["spec", "validation", "openAPIV3Schema"]) {
+        schemas.push((schema, kind.to_string()));
+    }
+
+    for (schema, name) in schemas {
+        let mut converter = SchemaConverter::new();
+        converter.convert_root(schema, &name);
+        let SchemaConverter { types, sites } = converter;
+        let env = base_env(&types, kubernetes);
+
+        for site in sites {
+            for rule in site.validations.items() {
+                let optional =
+                    rule.get("optionalOldSelf").and_then(YamlNode::as_str) == Some("true");
+                let old_self_type = if optional {
+                    CelType::optional(site.self_type.clone())
+                } else {
+                    site.self_type.clone()
+                };
+                let rule_env = Arc::new(
+                    env.clone()
+                        .with_variable("self", site.self_type.clone())
+                        .with_variable("oldSelf", old_self_type),
+                );
+                push_expression(rule, "rule", &rule_env, &[CelType::Bool], regions);
+                push_expression(
+                    rule,
+                    "messageExpression",
+                    &rule_env,
+                    &[CelType::String],
+                    regions,
+                );
+            }
+        }
+    }
+}
+
+/// Declare the `AdmissionRequest` message and its nested types.
+fn admission_types() -> SyntheticTypes {
+    let mut types = SyntheticTypes::new();
+    let string = || CelType::String;
+
+    for (message, fields) in [
+        (
+            "AdmissionRequest.GroupVersionKind",
+            &["group", "version", "kind"],
+        ),
+        (
+            "AdmissionRequest.GroupVersionResource",
+            &["group", "version", "resource"],
+        ),
+    ] {
+        for field in fields {
+            types.add_field(message, field, string(), None);
+        }
+    }
+
+    let gvk = CelType::message("AdmissionRequest.GroupVersionKind");
+    let gvr = CelType::message("AdmissionRequest.GroupVersionResource");
+    let user_info = "AdmissionRequest.UserInfo";
+    types.add_field(user_info, "username", string(), None);
+    types.add_field(user_info, "uid", string(), None);
+    types.add_field(user_info, "groups", CelType::list(string()), None);
+    types.add_field(
+        user_info,
+        "extra",
+        CelType::map(string(), CelType::list(string())),
+        None,
+    );
+
+    let fields = [
+        ("uid", string(), "Identifier of this admission call."),
+        ("kind", gvk.clone(), "Kind of the object being submitted."),
+        ("resource", gvr.clone(), "Resource being requested."),
+        (
+            "subResource",
+            string(),
+            "Subresource being requested, if any.",
+        ),
+        ("requestKind", gvk, "Kind of the original API request."),
+        (
+            "requestResource",
+            gvr,
+            "Resource of the original API request.",
+        ),
+        (
+            "requestSubResource",
+            string(),
+            "Subresource of the original API request.",
+        ),
+        ("name", string(), "Name of the object."),
+        ("namespace", string(), "Namespace of the object."),
+        (
+            "operation",
+            string(),
+            "Operation: CREATE, UPDATE, DELETE or CONNECT.",
+        ),
+        (
+            "userInfo",
+            CelType::message(user_info),
+            "Information about the requesting user.",
+        ),
+        (
+            "dryRun",
+            CelType::Bool,
+            "Whether this is a dry run request.",
+        ),
+        ("options", CelType::Dyn, "Operation options."),
+    ];
+    for (field, cel_type, description) in fields {
+        types.add_field(
+            ADMISSION_REQUEST,
+            field,
+            cel_type,
+            Some(description.to_string()),
+        );
+    }
+
+    types
+}
+
+/// Build the admission environment, optionally with `variables`.
+fn admission_env(
+    types: &SyntheticTypes,
+    kubernetes: KubernetesVersion,
+    with_variables: bool,
+) -> Arc<Env> {
+    let mut env = base_env(types, kubernetes)
+        .with_variable("object", CelType::Dyn)
+        .with_variable("oldObject", CelType::Dyn)
+        .with_variable("request", CelType::message(ADMISSION_REQUEST))
+        .with_variable("authorizer", CelType::abstract_type(AUTHORIZER_TYPE, &[]));
+    if with_variables {
+        env = env
+            .with_variable("params", CelType::Dyn)
+            .with_variable("namespaceObject", CelType::Dyn)
+            .with_variable("variables", CelType::message(VARIABLES));
+    }
+    Arc::new(env)
+}
+
+fn policy_regions(
+    document: &YamlNode,
+    kubernetes: KubernetesVersion,
+    regions: &mut Vec<CelRegionState>,
+) {
+    let Some(spec) = document.get("spec") else {
+        return;
+    };
+    let items = |key: &str| spec.get(key).map(YamlNode::items).unwrap_or_default();
+    let mut types = admission_types();
+    types.add_message(VARIABLES);
+
+    // Variables are typed in order; each may refer to the ones before it
+    for variable in items("variables") {
+        let env = admission_env(&types, kubernetes, true);
+        let result_type = push_expression(variable, "expression", &env, &[], regions)
+            .filter(|t| !matches!(t, CelType::Error))
+            .unwrap_or(CelType::Dyn);
+        if let Some(name) = variable.get("name").and_then(YamlNode::as_str) {
+            types.add_field(VARIABLES, name, result_type, None);
+        }
+    }
+
+    let env = admission_env(&types, kubernetes, true);
+    for condition in items("matchConditions") {
+        push_expression(condition, "expression", &env, &[CelType::Bool], regions);
+    }
+    for validation in items("validations") {
+        push_expression(validation, "expression", &env, &[CelType::Bool], regions);
+        push_expression(
+            validation,
+            "messageExpression",
+            &env,
+            &[CelType::String],
+            regions,
+        );
+    }
+    for annotation in items("auditAnnotations") {
+        // A null value omits the annotation
+        push_expression(
+            annotation,
+            "valueExpression",
+            &env,
+            &[CelType::String, CelType::Null],
+            regions,
+        );
+    }
+}
+
+fn webhook_regions(
+    document: &YamlNode,
+    kubernetes: KubernetesVersion,
+    regions: &mut Vec<CelRegionState>,
+) {
+    let env = admission_env(&admission_types(), kubernetes, false);
+    let webhooks = document
+        .get("webhooks")
+        .map(YamlNode::items)
+        .unwrap_or_default();
+    for webhook in webhooks {
+        let conditions = webhook
+            .get("matchConditions")
+            .map(YamlNode::items)
+            .unwrap_or_default();
+        for condition in conditions {
+            push_expression(condition, "expression", &env, &[CelType::Bool], regions);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(region: &CelRegionState) -> Vec<String> {
+        region
+            .parse_errors
+            .iter()
+            .map(|e| e.message.clone())
+            .chain(region.check_errors().iter().map(|e| e.message()))
+            .collect()
+    }
+
+    const CRD: &str = r#"apiVersion: apiextensions.k8s.io/v1
+kind: CustomResourceDefinition
+metadata:
+  name: widgets.acme.io
+spec:
+  names:
+    kind: Widget
+  versions:
+  - name: v1
+    schema:
+      openAPIV3Schema:
+        type: object
+        x-kubernetes-validations:
+        - rule: self.metadata.name.startsWith('w-')
+        properties:
+          spec:
+            type: object
+            x-kubernetes-validations:
+            - rule: "self.minReplicas <= self.maxReplicas"
+              messageExpression: "'min ' + string(self.minReplicas)"
+            - rule: |
+                self.mode == oldSelf.mode
+            - rule: oldSelf.hasValue()
+              optionalOldSelf: true
+            - rule: self.colour > 0
+            properties:
+              minReplicas:
+                type: integer
+              maxReplicas:
+                type: integer
+              mode:
+                type: string
+"#;
+
+    #[test]
+    fn crd_rules_are_typed_by_schema() {
+        let regions = extract_kubernetes_regions(CRD, KubernetesVersion::LATEST);
+        let sources: Vec<_> = regions.iter().map(|r| r.region.source.trim()).collect();
+        assert_eq!(
+            sources,
+            vec![
+                "self.metadata.name.startsWith('w-')",
+                "self.minReplicas <= self.maxReplicas",
+                "'min ' + string(self.minReplicas)",
+                "self.mode == oldSelf.mode",
+                "oldSelf.hasValue()",
+                "self.colour > 0",
+            ]
+        );
+        for region in &regions[..5] {
+            assert!(errors(region).is_empty(), "{:?}", errors(region));
+        }
+        assert_eq!(errors(&regions[5]).len(), 1);
+    }
+
+    #[test]
+    fn crd_regions_map_to_host() {
+        let regions = extract_kubernetes_regions(CRD, KubernetesVersion::LATEST);
+        for region in &regions {
+            let start = region.mapper.to_host(0);
+            let source = region.region.source.trim_end();
+            assert_eq!(&CRD[start..start + source.len()], source);
+        }
+    }
+
+    #[test]
+    fn admission_policy_variables_are_typed() {
+        let source = r#"apiVersion: admissionregistration.k8s.io/v1
+kind: ValidatingAdmissionPolicy
+spec:
+  matchConditions:
+  - name: not-system
+    expression: "!request.userInfo.username.startsWith('system:')"
+  variables:
+  - name: replicas
+    expression: object.spec.replicas
+  - name: isCreate
+    expression: request.operation == 'CREATE'
+  validations:
+  - expression: variables.isCreate || variables.replicas <= params.maxReplicas
+    messageExpression: "'too many: ' + string(variables.replicas)"
+  - expression: variables.missing
+  auditAnnotations:
+  - key: replicas
+    valueExpression: string(variables.isCreate)
+"#;
+        let regions = extract_kubernetes_regions(source, KubernetesVersion::LATEST);
+        assert_eq!(regions.len(), 7);
+        for region in regions
+            .iter()
+            .filter(|r| r.region.source != "variables.missing")
+        {
+            assert!(errors(region).is_empty(), "{:?}", errors(region));
+        }
+        let missing = regions
+            .iter()
+            .find(|r| r.region.source == "variables.missing")
+            .unwrap();
+        assert_eq!(errors(missing).len(), 1);
+    }
+
+    #[test]
+    fn expressions_have_expected_result_types() {
+        let source = r#"apiVersion: admissionregistration.k8s.io/v1
+kind: ValidatingAdmissionPolicy
+spec:
+  variables:
+  - name: replicas
+    expression: object.spec.replicas
+  validations:
+  - expression: request.name
+    messageExpression: "size(request.name)"
+  auditAnnotations:
+  - key: name
+    valueExpression: request.name
+"#;
+        let mismatches: Vec<_> = extract_kubernetes_regions(source, KubernetesVersion::LATEST)
+            .iter()
+            .map(|r| r.result_type_mismatch().map(|m| m.message))
+            .collect();
+        assert_eq!(
+            mismatches,
+            vec![
+                None,
+                Some("expected result type 'bool' but found 'string'".to_string()),
+                Some("expected result type 'string' but found 'int'".to_string()),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn other_documents_are_ignored() {
+        let source = "apiVersion: v1\nkind: ConfigMap\ndata:\n  rule: self > 0\n";
+        assert!(extract_kubernetes_regions(source, KubernetesVersion::LATEST).is_empty());
+    }
+
+    #[test]
+    fn expressions_use_the_configured_version() {
+        let source = r#"apiVersion: admissionregistration.k8s.io/v1
+kind: ValidatingWebhookConfiguration
+webhooks:
+- name: limits.acme.io
+  matchConditions:
+  - name: small
+    expression: quantity('1Gi').isLessThan(quantity('2Gi'))
+"#;
+        let errors_for = |version: &str| {
+            let regions = extract_kubernetes_regions(source, version.parse().unwrap());
+            errors(&regions[0])
+        };
+        assert!(errors_for("1.28").is_empty());
+        assert!(!errors_for("1.27").is_empty());
+    }
+}
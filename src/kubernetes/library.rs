@@ -0,0 +1,1102 @@
+//! Kubernetes CEL libraries.
+//!
+//! Declarations for the libraries the Kubernetes API server adds to CEL, gated
+//! by the Kubernetes version that introduced them:
+//! - 1.25: lists (`isSorted`, `sum`, `min`, `max`, `indexOf`, `lastIndexOf`),
+//!   regex (`find`, `findAll`) and URLs (`url`, `isURL`)
+//! - 1.27: authorizer
+//! - 1.28: quantity
+//! - 1.30: IP addresses and CIDRs
+//! - 1.31: named formats
+//! - 1.32: authorizer field and label selectors
+//!
+//! [`LIBRARY`] is the single list of functions: declarations for the checker
+//! and docs for hover and completion are both derived from it. Only
+//! declarations are provided; they are used for type checking, hover and
+//! completion, not evaluation.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+use cel_core::types::{FunctionDecl, OverloadDecl};
+use cel_core::{ext, CelType, Env};
+
+use crate::types::FunctionDef;
+
+/// A Kubernetes minor version selecting which libraries are available.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct KubernetesVersion {
+    minor: u32,
+}
+
+impl KubernetesVersion {
+    /// The oldest supported version, which introduced CRD validation rules.
+    pub const OLDEST: Self = Self { minor: 25 };
+    /// The newest supported version, used when no version is configured.
+    pub const LATEST: Self = Self { minor: 33 };
+
+    fn at_least(self, minor: u32) -> bool {
+        self.minor >= minor
+    }
+}
+
+impl Default for KubernetesVersion {
+    fn default() -> Self {
+        Self::LATEST
+    }
+}
+
+impl FromStr for KubernetesVersion {
+    type Err = String;
+
+    /// Parse `1.30`, `v1.30` or `1.30.2`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed = s.trim();
+        let trimmed = trimmed.strip_prefix('v').unwrap_or(trimmed);
+        let mut parts = trimmed.split('.');
+        let major = parts.next().and_then(|p| p.parse::<u32>().ok());
+        let minor = parts.next().and_then(|p| p.parse::<u32>().ok());
+        let (Some(1), Some(minor)) = (major, minor) else {
+            return Err(format!("invalid Kubernetes version '{}'", s));
+        };
+
+        let version = Self { minor };
+        if version < Self::OLDEST || version > Self::LATEST {
+            return Err(format!(
+                "unsupported Kubernetes version '{}' (supported: {} to {})",
+                s,
+                Self::OLDEST,
+                Self::LATEST
+            ));
+        }
+        Ok(version)
+    }
+}
+
+impl fmt::Display for KubernetesVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "1.{}", self.minor)
+    }
+}
+
+/// `kubernetes.URL`, returned by `url()`.
+pub const URL_TYPE: &str = "kubernetes.URL";
+/// `kubernetes.Quantity`, returned by `quantity()`.
+pub const QUANTITY_TYPE: &str = "kubernetes.Quantity";
+/// `net.IP`, returned by `ip()`.
+pub const IP_TYPE: &str = "net.IP";
+/// `net.CIDR`, returned by `cidr()`.
+pub const CIDR_TYPE: &str = "net.CIDR";
+/// `kubernetes.NamedFormat`, returned by the `format` functions.
+pub const FORMAT_TYPE: &str = "kubernetes.NamedFormat";
+/// `kubernetes.authorization.Authorizer`, the type of `authorizer`.
+pub const AUTHORIZER_TYPE: &str = "kubernetes.authorization.Authorizer";
+const PATH_CHECK_TYPE: &str = "kubernetes.authorization.PathCheck";
+const GROUP_CHECK_TYPE: &str = "kubernetes.authorization.GroupCheck";
+const RESOURCE_CHECK_TYPE: &str = "kubernetes.authorization.ResourceCheck";
+const DECISION_TYPE: &str = "kubernetes.authorization.Decision";
+
+/// Opaque types declared by the Kubernetes libraries.
+pub const KUBERNETES_TYPES: &[&str] = &[
+    URL_TYPE,
+    QUANTITY_TYPE,
+    IP_TYPE,
+    CIDR_TYPE,
+    FORMAT_TYPE,
+    AUTHORIZER_TYPE,
+    PATH_CHECK_TYPE,
+    GROUP_CHECK_TYPE,
+    RESOURCE_CHECK_TYPE,
+    DECISION_TYPE,
+];
+
+/// Named formats available through `format.<name>()` and `format.named()`.
+const NAMED_FORMATS: &[&str] = &[
+    "dns1123Label",
+    "dns1123Subdomain",
+    "dns1035Label",
+    "qualifiedName",
+    "dns1123LabelPrefix",
+    "dns1123SubdomainPrefix",
+    "dns1035LabelPrefix",
+    "labelValue",
+    "uri",
+    "uuid",
+    "byte",
+    "date",
+    "datetime",
+];
+
+fn opaque(name: &str) -> CelType {
+    CelType::abstract_type(name, &[])
+}
+
+/// A function of the Kubernetes CEL libraries.
+pub struct LibraryFunction {
+    /// Documentation for hover and completion.
+    pub def: FunctionDef,
+    /// The Kubernetes version that added the function.
+    pub since: KubernetesVersion,
+    /// Declared name; `def.name` is its last segment, e.g. `isCanonical`
+    /// for `ip.isCanonical`.
+    name: String,
+    overloads: Vec<OverloadDecl>,
+}
+
+impl LibraryFunction {
+    /// The checker declaration of the function.
+    pub fn decl(&self) -> FunctionDecl {
+        FunctionDecl::new(&self.name).with_overloads(self.overloads.clone())
+    }
+}
+
+fn function(def: FunctionDef, since: u32, overloads: Vec<OverloadDecl>) -> LibraryFunction {
+    LibraryFunction {
+        name: def.name.to_string(),
+        def,
+        since: KubernetesVersion { minor: since },
+        overloads,
+    }
+}
+
+fn namespaced(
+    namespace: &str,
+    def: FunctionDef,
+    since: u32,
+    overloads: Vec<OverloadDecl>,
+) -> LibraryFunction {
+    LibraryFunction {
+        name: format!("{}.{}", namespace, def.name),
+        ..function(def, since, overloads)
+    }
+}
+
+/// Add the extensions and libraries the API server provides in `version`.
+///
+/// This is the strings extension, optionals from 1.28, and the Kubernetes
+/// libraries.
+pub fn with_kubernetes_libraries(mut env: Env, version: KubernetesVersion) -> Env {
+    env = env.with_extension(ext::string_extension());
+    if version.at_least(28) {
+        env = env.with_extension(ext::optionals_extension());
+    }
+    env.with_extension(kubernetes_library(version))
+}
+
+/// Declarations of the Kubernetes library functions available in `version`.
+fn kubernetes_library(version: KubernetesVersion) -> Vec<FunctionDecl> {
+    LIBRARY
+        .iter()
+        .filter(|f| f.since <= version)
+        .map(LibraryFunction::decl)
+        .collect()
+}
+
+/// All Kubernetes library functions, lazily initialized.
+pub static LIBRARY: LazyLock<Vec<LibraryFunction>> = LazyLock::new(|| {
+    use CelType::{Bool, Double, Duration, Int, String, UInt};
+
+    let t = || CelType::type_param("T");
+    let list_t = || CelType::list(t());
+    let generic = |id: &str, params: Vec<CelType>, result: CelType| {
+        OverloadDecl::method(id, params, result).with_type_params(vec!["T".to_string()])
+    };
+    let url = || opaque(URL_TYPE);
+    let authorizer = || opaque(AUTHORIZER_TYPE);
+    let path_check = || opaque(PATH_CHECK_TYPE);
+    let group_check = || opaque(GROUP_CHECK_TYPE);
+    let resource_check = || opaque(RESOURCE_CHECK_TYPE);
+    let decision = || opaque(DECISION_TYPE);
+    let quantity = || opaque(QUANTITY_TYPE);
+    let ip = || opaque(IP_TYPE);
+    let cidr = || opaque(CIDR_TYPE);
+    let format = || opaque(FORMAT_TYPE);
+
+    let mut library = vec![
+        // ==================== Lists ====================
+        function(
+            FunctionDef {
+                name: "isSorted",
+                signature: "(list<T>) -> bool",
+                description: "Returns true if the list elements are in ascending order. Elements must be comparable.",
+                example: Some("[1, 2, 3].isSorted()"),
+            },
+            25,
+            vec![generic("list_is_sorted", vec![list_t()], Bool)],
+        ),
+        function(
+            FunctionDef {
+                name: "sum",
+                signature: "(list<T>) -> T",
+                description: "Returns the sum of a list of int, uint, double or duration values.",
+                example: Some("[1, 2, 3].sum() == 6"),
+            },
+            25,
+            [
+                ("list_int_sum", Int),
+                ("list_uint_sum", UInt),
+                ("list_double_sum", Double),
+                ("list_duration_sum", Duration),
+            ]
+            .into_iter()
+            .map(|(id, elem)| OverloadDecl::method(id, vec![CelType::list(elem.clone())], elem))
+            .collect(),
+        ),
+        function(
+            FunctionDef {
+                name: "min",
+                signature: "(list<T>) -> T",
+                description: "Returns the smallest element of a non-empty list of comparable values.",
+                example: Some("[3, 1, 2].min() == 1"),
+            },
+            25,
+            vec![generic("list_min", vec![list_t()], t())],
+        ),
+        function(
+            FunctionDef {
+                name: "max",
+                signature: "(list<T>) -> T",
+                description: "Returns the largest element of a non-empty list of comparable values.",
+                example: Some("[3, 1, 2].max() == 3"),
+            },
+            25,
+            vec![generic("list_max", vec![list_t()], t())],
+        ),
+        function(
+            FunctionDef {
+                name: "indexOf",
+                signature: "(list<T>, T) -> int",
+                description: "Returns the index of the first occurrence of a value in a list, or -1 if absent. On strings, returns the index of a substring.",
+                example: Some("[1, 2, 2].indexOf(2) == 1"),
+            },
+            25,
+            vec![generic("list_index_of", vec![list_t(), t()], Int)],
+        ),
+        function(
+            FunctionDef {
+                name: "lastIndexOf",
+                signature: "(list<T>, T) -> int",
+                description: "Returns the index of the last occurrence of a value in a list, or -1 if absent. On strings, returns the index of a substring.",
+                example: Some("[1, 2, 2].lastIndexOf(2) == 2"),
+            },
+            25,
+            vec![generic("list_last_index_of", vec![list_t(), t()], Int)],
+        ),
+        // ==================== Regex ====================
+        function(
+            FunctionDef {
+                name: "find",
+                signature: "(string, regex: string) -> string",
+                description: "Returns the first match of a regular expression in the string, or an empty string if there is none.",
+                example: Some("'abc 123'.find('[0-9]+') == '123'"),
+            },
+            25,
+            vec![OverloadDecl::method(
+                "string_find_string",
+                vec![String, String],
+                String,
+            )],
+        ),
+        function(
+            FunctionDef {
+                name: "findAll",
+                signature: "(string, regex: string, limit?: int) -> list<string>",
+                description: "Returns all matches of a regular expression in the string, up to an optional limit.",
+                example: Some("'123 abc 456'.findAll('[0-9]+') == ['123', '456']"),
+            },
+            25,
+            vec![
+                OverloadDecl::method(
+                    "string_find_all_string",
+                    vec![String, String],
+                    CelType::list(String),
+                ),
+                OverloadDecl::method(
+                    "string_find_all_string_int",
+                    vec![String, String, Int],
+                    CelType::list(String),
+                ),
+            ],
+        ),
+        // ==================== URLs ====================
+        function(
+            FunctionDef {
+                name: "url",
+                signature: "(string) -> kubernetes.URL",
+                description: "Parses an absolute URI or absolute path into a URL. Errors if the string is not a valid URL.",
+                example: Some("url('https://example.com:80/path').getHost() == 'example.com:80'"),
+            },
+            25,
+            vec![OverloadDecl::function("string_to_url", vec![String], url())],
+        ),
+        function(
+            FunctionDef {
+                name: "isURL",
+                signature: "(string) -> bool",
+                description: "Returns true if the string is a valid URL that `url()` accepts.",
+                example: Some("isURL('https://example.com')"),
+            },
+            25,
+            vec![OverloadDecl::function("is_url_string", vec![String], Bool)],
+        ),
+        function(
+            FunctionDef {
+                name: "getScheme",
+                signature: "(kubernetes.URL) -> string",
+                description: "Returns the URL scheme, or an empty string for paths.",
+                example: Some("url('https://example.com').getScheme() == 'https'"),
+            },
+            25,
+            vec![OverloadDecl::method("url_get_scheme", vec![url()], String)],
+        ),
+        function(
+            FunctionDef {
+                name: "getHost",
+                signature: "(kubernetes.URL) -> string",
+                description: "Returns the URL host including the port, if any.",
+                example: Some("url('https://example.com:80').getHost() == 'example.com:80'"),
+            },
+            25,
+            vec![OverloadDecl::method("url_get_host", vec![url()], String)],
+        ),
+        function(
+            FunctionDef {
+                name: "getHostname",
+                signature: "(kubernetes.URL) -> string",
+                description: "Returns the URL host without the port. IPv6 addresses are returned without brackets.",
+                example: Some("url('https://example.com:80').getHostname() == 'example.com'"),
+            },
+            25,
+            vec![OverloadDecl::method("url_get_hostname", vec![url()], String)],
+        ),
+        function(
+            FunctionDef {
+                name: "getPort",
+                signature: "(kubernetes.URL) -> string",
+                description: "Returns the URL port, or an empty string if none is set.",
+                example: Some("url('https://example.com:80').getPort() == '80'"),
+            },
+            25,
+            vec![OverloadDecl::method("url_get_port", vec![url()], String)],
+        ),
+        function(
+            FunctionDef {
+                name: "getEscapedPath",
+                signature: "(kubernetes.URL) -> string",
+                description: "Returns the escaped URL path.",
+                example: Some("url('https://example.com/a%20b').getEscapedPath() == '/a%20b'"),
+            },
+            25,
+            vec![OverloadDecl::method(
+                "url_get_escaped_path",
+                vec![url()],
+                String,
+            )],
+        ),
+        function(
+            FunctionDef {
+                name: "getQuery",
+                signature: "(kubernetes.URL) -> map<string, list<string>>",
+                description: "Returns the URL query parameters, mapping each key to all of its values.",
+                example: Some("url('https://example.com?k=a&k=b').getQuery()['k'] == ['a', 'b']"),
+            },
+            25,
+            vec![OverloadDecl::method(
+                "url_get_query",
+                vec![url()],
+                CelType::map(String, CelType::list(String)),
+            )],
+        ),
+        // ==================== Authorizer ====================
+        function(
+            FunctionDef {
+                name: "path",
+                signature: "(kubernetes.authorization.Authorizer, string) -> kubernetes.authorization.PathCheck",
+                description: "Starts an authorization check for a non-resource request path.",
+                example: Some("authorizer.path('/healthz').check('get').allowed()"),
+            },
+            27,
+            vec![OverloadDecl::method(
+                "authorizer_path",
+                vec![authorizer(), String],
+                path_check(),
+            )],
+        ),
+        function(
+            FunctionDef {
+                name: "group",
+                signature: "(kubernetes.authorization.Authorizer, string) -> kubernetes.authorization.GroupCheck",
+                description: "Starts an authorization check for an API group. Use an empty string for the core group.",
+                example: Some("authorizer.group('apps').resource('deployments').check('create').allowed()"),
+            },
+            27,
+            vec![OverloadDecl::method(
+                "authorizer_group",
+                vec![authorizer(), String],
+                group_check(),
+            )],
+        ),
+        function(
+            FunctionDef {
+                name: "serviceAccount",
+                signature: "(kubernetes.authorization.Authorizer, namespace: string, name: string) -> kubernetes.authorization.Authorizer",
+                description: "Returns an authorizer that checks permissions of the given service account.",
+                example: Some("authorizer.serviceAccount('default', 'builder').group('').resource('pods').check('get').allowed()"),
+            },
+            27,
+            vec![OverloadDecl::method(
+                "authorizer_serviceaccount",
+                vec![authorizer(), String, String],
+                authorizer(),
+            )],
+        ),
+        function(
+            FunctionDef {
+                name: "resource",
+                signature: "(kubernetes.authorization.GroupCheck, string) -> kubernetes.authorization.ResourceCheck",
+                description: "Selects the resource to check within an API group.",
+                example: Some("authorizer.group('').resource('pods')"),
+            },
+            27,
+            vec![OverloadDecl::method(
+                "groupcheck_resource",
+                vec![group_check(), String],
+                resource_check(),
+            )],
+        ),
+        function(
+            FunctionDef {
+                name: "subresource",
+                signature: "(kubernetes.authorization.ResourceCheck, string) -> kubernetes.authorization.ResourceCheck",
+                description: "Selects a subresource of the resource being checked.",
+                example: Some("authorizer.group('').resource('pods').subresource('status')"),
+            },
+            27,
+            vec![OverloadDecl::method(
+                "resourcecheck_subresource",
+                vec![resource_check(), String],
+                resource_check(),
+            )],
+        ),
+        function(
+            FunctionDef {
+                name: "namespace",
+                signature: "(kubernetes.authorization.ResourceCheck, string) -> kubernetes.authorization.ResourceCheck",
+                description: "Selects the namespace of the resource being checked.",
+                example: Some("authorizer.group('').resource('pods').namespace('default')"),
+            },
+            27,
+            vec![OverloadDecl::method(
+                "resourcecheck_namespace",
+                vec![resource_check(), String],
+                resource_check(),
+            )],
+        ),
+        function(
+            FunctionDef {
+                name: "name",
+                signature: "(kubernetes.authorization.ResourceCheck, string) -> kubernetes.authorization.ResourceCheck",
+                description: "Selects the name of the resource being checked.",
+                example: Some("authorizer.group('').resource('pods').name('web')"),
+            },
+            27,
+            vec![OverloadDecl::method(
+                "resourcecheck_name",
+                vec![resource_check(), String],
+                resource_check(),
+            )],
+        ),
+        function(
+            FunctionDef {
+                name: "fieldSelector",
+                signature: "(kubernetes.authorization.ResourceCheck, string) -> kubernetes.authorization.ResourceCheck",
+                description: "Restricts the check to objects matching a field selector.",
+                example: Some("authorizer.group('').resource('pods').fieldSelector('spec.nodeName=node1')"),
+            },
+            32,
+            vec![OverloadDecl::method(
+                "resourcecheck_fieldselector",
+                vec![resource_check(), String],
+                resource_check(),
+            )],
+        ),
+        function(
+            FunctionDef {
+                name: "labelSelector",
+                signature: "(kubernetes.authorization.ResourceCheck, string) -> kubernetes.authorization.ResourceCheck",
+                description: "Restricts the check to objects matching a label selector.",
+                example: Some("authorizer.group('').resource('pods').labelSelector('app=web')"),
+            },
+            32,
+            vec![OverloadDecl::method(
+                "resourcecheck_labelselector",
+                vec![resource_check(), String],
+                resource_check(),
+            )],
+        ),
+        function(
+            FunctionDef {
+                name: "check",
+                signature: "(PathCheck | ResourceCheck, verb: string) -> kubernetes.authorization.Decision",
+                description: "Performs the authorization check for a verb and returns the decision.",
+                example: Some("authorizer.group('').resource('pods').check('delete').allowed()"),
+            },
+            27,
+            vec![
+                OverloadDecl::method("pathcheck_check", vec![path_check(), String], decision()),
+                OverloadDecl::method(
+                    "resourcecheck_check",
+                    vec![resource_check(), String],
+                    decision(),
+                ),
+            ],
+        ),
+        function(
+            FunctionDef {
+                name: "allowed",
+                signature: "(kubernetes.authorization.Decision) -> bool",
+                description: "Returns true if the authorization decision allows the request.",
+                example: Some("authorizer.path('/metrics').check('get').allowed()"),
+            },
+            27,
+            vec![OverloadDecl::method(
+                "decision_allowed",
+                vec![decision()],
+                Bool,
+            )],
+        ),
+        function(
+            FunctionDef {
+                name: "reason",
+                signature: "(kubernetes.authorization.Decision) -> string",
+                description: "Returns the reason given by the authorizer for its decision.",
+                example: None,
+            },
+            27,
+            vec![OverloadDecl::method(
+                "decision_reason",
+                vec![decision()],
+                String,
+            )],
+        ),
+        function(
+            FunctionDef {
+                name: "errored",
+                signature: "(kubernetes.authorization.Decision) -> bool",
+                description: "Returns true if the authorization check failed with an error.",
+                example: None,
+            },
+            27,
+            vec![OverloadDecl::method(
+                "decision_errored",
+                vec![decision()],
+                Bool,
+            )],
+        ),
+        function(
+            FunctionDef {
+                name: "error",
+                signature: "(kubernetes.authorization.Decision) -> string",
+                description: "Returns the error of a failed authorization check.",
+                example: None,
+            },
+            27,
+            vec![OverloadDecl::method(
+                "decision_error",
+                vec![decision()],
+                String,
+            )],
+        ),
+        // ==================== Quantity ====================
+        function(
+            FunctionDef {
+                name: "quantity",
+                signature: "(string) -> kubernetes.Quantity",
+                description: "Parses a Kubernetes resource quantity such as `1.5Gi` or `250m`.",
+                example: Some("quantity('1Gi').isGreaterThan(quantity('500Mi'))"),
+            },
+            28,
+            vec![OverloadDecl::function(
+                "string_to_quantity",
+                vec![String],
+                quantity(),
+            )],
+        ),
+        function(
+            FunctionDef {
+                name: "isQuantity",
+                signature: "(string) -> bool",
+                description: "Returns true if the string is a valid resource quantity.",
+                example: Some("isQuantity('250m')"),
+            },
+            28,
+            vec![OverloadDecl::function(
+                "is_quantity_string",
+                vec![String],
+                Bool,
+            )],
+        ),
+        function(
+            FunctionDef {
+                name: "sign",
+                signature: "(kubernetes.Quantity) -> int",
+                description: "Returns -1, 0 or 1 for negative, zero and positive quantities.",
+                example: Some("quantity('-1').sign() == -1"),
+            },
+            28,
+            vec![OverloadDecl::method("quantity_sign", vec![quantity()], Int)],
+        ),
+        function(
+            FunctionDef {
+                name: "isInteger",
+                signature: "(kubernetes.Quantity) -> bool",
+                description: "Returns true if the quantity can be represented as an int without loss.",
+                example: Some("quantity('2k').isInteger()"),
+            },
+            28,
+            vec![OverloadDecl::method(
+                "quantity_is_integer",
+                vec![quantity()],
+                Bool,
+            )],
+        ),
+        function(
+            FunctionDef {
+                name: "asInteger",
+                signature: "(kubernetes.Quantity) -> int",
+                description: "Converts the quantity to an int. Errors if `isInteger()` is false.",
+                example: Some("quantity('2k').asInteger() == 2000"),
+            },
+            28,
+            vec![OverloadDecl::method(
+                "quantity_as_integer",
+                vec![quantity()],
+                Int,
+            )],
+        ),
+        function(
+            FunctionDef {
+                name: "asApproximateFloat",
+                signature: "(kubernetes.Quantity) -> double",
+                description: "Converts the quantity to a double, possibly losing precision.",
+                example: Some("quantity('250m').asApproximateFloat() == 0.25"),
+            },
+            28,
+            vec![OverloadDecl::method(
+                "quantity_as_float",
+                vec![quantity()],
+                Double,
+            )],
+        ),
+        function(
+            FunctionDef {
+                name: "add",
+                signature: "(kubernetes.Quantity, kubernetes.Quantity | int) -> kubernetes.Quantity",
+                description: "Returns the sum of two quantities, or of a quantity and an int.",
+                example: Some("quantity('1Gi').add(quantity('512Mi'))"),
+            },
+            28,
+            vec![
+                OverloadDecl::method("quantity_add", vec![quantity(), quantity()], quantity()),
+                OverloadDecl::method("quantity_add_int", vec![quantity(), Int], quantity()),
+            ],
+        ),
+        function(
+            FunctionDef {
+                name: "sub",
+                signature: "(kubernetes.Quantity, kubernetes.Quantity | int) -> kubernetes.Quantity",
+                description: "Returns the difference of two quantities, or of a quantity and an int.",
+                example: Some("quantity('1Gi').sub(quantity('512Mi'))"),
+            },
+            28,
+            vec![
+                OverloadDecl::method("quantity_sub", vec![quantity(), quantity()], quantity()),
+                OverloadDecl::method("quantity_sub_int", vec![quantity(), Int], quantity()),
+            ],
+        ),
+        function(
+            FunctionDef {
+                name: "isLessThan",
+                signature: "(kubernetes.Quantity, kubernetes.Quantity) -> bool",
+                description: "Returns true if the quantity is less than the other.",
+                example: Some("quantity('500Mi').isLessThan(quantity('1Gi'))"),
+            },
+            28,
+            vec![OverloadDecl::method(
+                "quantity_less",
+                vec![quantity(), quantity()],
+                Bool,
+            )],
+        ),
+        function(
+            FunctionDef {
+                name: "isGreaterThan",
+                signature: "(kubernetes.Quantity, kubernetes.Quantity) -> bool",
+                description: "Returns true if the quantity is greater than the other.",
+                example: Some("quantity('1Gi').isGreaterThan(quantity('500Mi'))"),
+            },
+            28,
+            vec![OverloadDecl::method(
+                "quantity_greater",
+                vec![quantity(), quantity()],
+                Bool,
+            )],
+        ),
+        function(
+            FunctionDef {
+                name: "compareTo",
+                signature: "(kubernetes.Quantity, kubernetes.Quantity) -> int",
+                description: "Returns -1, 0 or 1 as the quantity is less than, equal to or greater than the other.",
+                example: Some("quantity('1Gi').compareTo(quantity('1024Mi')) == 0"),
+            },
+            28,
+            vec![OverloadDecl::method(
+                "quantity_compare_to",
+                vec![quantity(), quantity()],
+                Int,
+            )],
+        ),
+        // ==================== IP and CIDR ====================
+        function(
+            FunctionDef {
+                name: "ip",
+                signature: "(string) -> net.IP",
+                description: "Parses an IPv4 or IPv6 address. On a CIDR, returns its IP address.",
+                example: Some("ip('10.0.0.1').family() == 4"),
+            },
+            30,
+            vec![
+                OverloadDecl::function("string_to_ip", vec![String], ip()),
+                OverloadDecl::method("cidr_ip", vec![cidr()], ip()),
+            ],
+        ),
+        function(
+            FunctionDef {
+                name: "isIP",
+                signature: "(string) -> bool",
+                description: "Returns true if the string is a valid IPv4 or IPv6 address.",
+                example: Some("isIP('::1')"),
+            },
+            30,
+            vec![OverloadDecl::function("is_ip", vec![String], Bool)],
+        ),
+        namespaced(
+            "ip",
+            FunctionDef {
+                name: "isCanonical",
+                signature: "ip.isCanonical(string) -> bool",
+                description: "Returns true if the IP address string is in canonical form.",
+                example: Some("ip.isCanonical('2001:db8::abcd')"),
+            },
+            30,
+            vec![OverloadDecl::function(
+                "ip_is_canonical",
+                vec![String],
+                Bool,
+            )],
+        ),
+        function(
+            FunctionDef {
+                name: "string",
+                signature: "(net.IP | net.CIDR) -> string",
+                description: "Converts an IP address or CIDR subnet to its string form.",
+                example: Some("string(ip('10.0.0.1')) == '10.0.0.1'"),
+            },
+            30,
+            vec![
+                OverloadDecl::function("ip_to_string", vec![ip()], String),
+                OverloadDecl::function("cidr_to_string", vec![cidr()], String),
+            ],
+        ),
+        function(
+            FunctionDef {
+                name: "family",
+                signature: "(net.IP) -> int",
+                description: "Returns 4 for IPv4 addresses and 6 for IPv6 addresses.",
+                example: Some("ip('::1').family() == 6"),
+            },
+            30,
+            vec![OverloadDecl::method("ip_family", vec![ip()], Int)],
+        ),
+        function(
+            FunctionDef {
+                name: "isUnspecified",
+                signature: "(net.IP) -> bool",
+                description: "Returns true if the address is the unspecified address (`0.0.0.0` or `::`).",
+                example: None,
+            },
+            30,
+            vec![OverloadDecl::method("ip_is_unspecified", vec![ip()], Bool)],
+        ),
+        function(
+            FunctionDef {
+                name: "isLoopback",
+                signature: "(net.IP) -> bool",
+                description: "Returns true if the address is a loopback address.",
+                example: Some("ip('127.0.0.1').isLoopback()"),
+            },
+            30,
+            vec![OverloadDecl::method("ip_is_loopback", vec![ip()], Bool)],
+        ),
+        function(
+            FunctionDef {
+                name: "isLinkLocalMulticast",
+                signature: "(net.IP) -> bool",
+                description: "Returns true if the address is a link-local multicast address.",
+                example: None,
+            },
+            30,
+            vec![OverloadDecl::method(
+                "ip_is_link_local_multicast",
+                vec![ip()],
+                Bool,
+            )],
+        ),
+        function(
+            FunctionDef {
+                name: "isLinkLocalUnicast",
+                signature: "(net.IP) -> bool",
+                description: "Returns true if the address is a link-local unicast address.",
+                example: None,
+            },
+            30,
+            vec![OverloadDecl::method(
+                "ip_is_link_local_unicast",
+                vec![ip()],
+                Bool,
+            )],
+        ),
+        function(
+            FunctionDef {
+                name: "isGlobalUnicast",
+                signature: "(net.IP) -> bool",
+                description: "Returns true if the address is a global unicast address.",
+                example: None,
+            },
+            30,
+            vec![OverloadDecl::method(
+                "ip_is_global_unicast",
+                vec![ip()],
+                Bool,
+            )],
+        ),
+        function(
+            FunctionDef {
+                name: "cidr",
+                signature: "(string) -> net.CIDR",
+                description: "Parses an IPv4 or IPv6 CIDR subnet.",
+                example: Some("cidr('10.0.0.0/8').containsIP('10.1.2.3')"),
+            },
+            30,
+            vec![OverloadDecl::function("string_to_cidr", vec![String], cidr())],
+        ),
+        function(
+            FunctionDef {
+                name: "isCIDR",
+                signature: "(string) -> bool",
+                description: "Returns true if the string is a valid CIDR subnet.",
+                example: Some("isCIDR('10.0.0.0/8')"),
+            },
+            30,
+            vec![OverloadDecl::function("is_cidr", vec![String], Bool)],
+        ),
+        function(
+            FunctionDef {
+                name: "containsIP",
+                signature: "(net.CIDR, net.IP | string) -> bool",
+                description: "Returns true if the subnet contains the IP address.",
+                example: Some("cidr('10.0.0.0/8').containsIP(ip('10.1.2.3'))"),
+            },
+            30,
+            vec![
+                OverloadDecl::method("cidr_contains_ip_string", vec![cidr(), String], Bool),
+                OverloadDecl::method("cidr_contains_ip_ip", vec![cidr(), ip()], Bool),
+            ],
+        ),
+        function(
+            FunctionDef {
+                name: "containsCIDR",
+                signature: "(net.CIDR, net.CIDR | string) -> bool",
+                description: "Returns true if the subnet contains the other subnet.",
+                example: Some("cidr('10.0.0.0/8').containsCIDR('10.1.0.0/16')"),
+            },
+            30,
+            vec![
+                OverloadDecl::method("cidr_contains_cidr_string", vec![cidr(), String], Bool),
+                OverloadDecl::method("cidr_contains_cidr", vec![cidr(), cidr()], Bool),
+            ],
+        ),
+        function(
+            FunctionDef {
+                name: "masked",
+                signature: "(net.CIDR) -> net.CIDR",
+                description: "Returns the subnet with the host bits of its address cleared.",
+                example: Some("cidr('10.1.2.3/8').masked() == cidr('10.0.0.0/8')"),
+            },
+            30,
+            vec![OverloadDecl::method("cidr_masked", vec![cidr()], cidr())],
+        ),
+        function(
+            FunctionDef {
+                name: "prefixLength",
+                signature: "(net.CIDR) -> int",
+                description: "Returns the length of the subnet prefix in bits.",
+                example: Some("cidr('10.0.0.0/8').prefixLength() == 8"),
+            },
+            30,
+            vec![OverloadDecl::method(
+                "cidr_prefix_length",
+                vec![cidr()],
+                Int,
+            )],
+        ),
+        // ==================== Named formats ====================
+        namespaced(
+            "format",
+            FunctionDef {
+                name: "named",
+                signature: "format.named(string) -> optional<kubernetes.NamedFormat>",
+                description: "Looks up a named format such as `dns1123Label`, `uuid` or `datetime`.",
+                example: Some("format.named('uuid').hasValue()"),
+            },
+            31,
+            vec![OverloadDecl::function(
+                "format_named",
+                vec![String],
+                CelType::optional(format()),
+            )],
+        ),
+        function(
+            FunctionDef {
+                name: "validate",
+                signature: "(kubernetes.NamedFormat, string) -> optional<list<string>>",
+                description: "Validates a string against the format, returning the validation errors if there are any.",
+                example: Some("!format.dns1123Label().validate(self.name).hasValue()"),
+            },
+            31,
+            vec![OverloadDecl::method(
+                "format_validate",
+                vec![format(), String],
+                CelType::optional(CelType::list(String)),
+            )],
+        ),
+    ];
+    for name in NAMED_FORMATS {
+        library.push(namespaced(
+            "format",
+            FunctionDef {
+                name,
+                signature: "() -> kubernetes.NamedFormat",
+                description:
+                    "Returns the named format of the same name, for use with `validate()`.",
+                example: Some("format.dns1123Label().validate('my-name')"),
+            },
+            31,
+            vec![OverloadDecl::function(
+                format!("format_{}", name),
+                vec![],
+                format(),
+            )],
+        ));
+    }
+    library
+});
+
+/// Get documentation for the Kubernetes library function `name` declared with
+/// the overload `overload_id`.
+///
+/// Keying by the declared overload keeps a user or protovalidate function that
+/// shares a name with a Kubernetes function from picking up its docs.
+pub fn get_kubernetes_builtin(name: &str, overload_id: &str) -> Option<&'static FunctionDef> {
+    LIBRARY
+        .iter()
+        .find(|f| f.name == name && f.overloads.iter().any(|o| o.id == overload_id))
+        .map(|f| &f.def)
+}
+
+/// Check whether a type name is one of the Kubernetes opaque types.
+pub fn is_kubernetes_type(name: &str) -> bool {
+    KUBERNETES_TYPES.contains(&name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cel_core::parse;
+
+    fn check(source: &str, version: KubernetesVersion) -> Vec<String> {
+        let env = with_kubernetes_libraries(Env::with_standard_library(), version)
+            .with_variable("authorizer", opaque(AUTHORIZER_TYPE))
+            .with_variable("names", CelType::list(CelType::String));
+        let ast = parse(source).ast.unwrap();
+        env.check(&ast).errors.iter().map(|e| e.message()).collect()
+    }
+
+    #[test]
+    fn parses_versions() {
+        assert_eq!("1.30".parse(), Ok(KubernetesVersion { minor: 30 }));
+        assert_eq!("v1.28.3".parse(), Ok(KubernetesVersion { minor: 28 }));
+        assert!("2.0".parse::<KubernetesVersion>().is_err());
+        assert!("1.20".parse::<KubernetesVersion>().is_err());
+        assert!("latest".parse::<KubernetesVersion>().is_err());
+        assert_eq!(KubernetesVersion::LATEST.to_string(), "1.33");
+    }
+
+    #[test]
+    fn library_functions_type_check() {
+        let latest = KubernetesVersion::LATEST;
+        for source in [
+            "names.isSorted() && names.indexOf('a') >= 0 && [1, 2].sum() == 3",
+            "names.max() == 'z'",
+            "'abc'.find('[a-z]') == 'a' && 'abc'.findAll('b', 1).size() == 1",
+            "url('https://example.com').getHost() == 'example.com' && isURL('/x')",
+            "quantity('1Gi').add(1).isGreaterThan(quantity('1'))",
+            "ip('10.0.0.1').family() == 4 && cidr('10.0.0.0/8').containsIP('10.0.0.1')",
+            "string(cidr('10.0.0.0/8').ip()) == '10.0.0.0' && ip.isCanonical('::1')",
+            "authorizer.group('').resource('pods').namespace('ns').check('get').allowed()",
+            "authorizer.group('').resource('pods').labelSelector('a=b').check('list').allowed()",
+            "!format.dns1123Label().validate('x').hasValue() && format.named('uuid').hasValue()",
+        ] {
+            assert_eq!(check(source, latest), Vec::<String>::new(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn functions_are_gated_by_version() {
+        let old = KubernetesVersion::OLDEST;
+        assert!(check("names.isSorted()", old).is_empty());
+        assert!(!check("quantity('1') == quantity('1')", old).is_empty());
+        assert!(!check("optional.of(1).hasValue()", old).is_empty());
+        assert!(!check("isCIDR('10.0.0.0/8')", "1.29".parse().unwrap()).is_empty());
+        assert!(check("isCIDR('10.0.0.0/8')", "1.30".parse().unwrap()).is_empty());
+    }
+
+    #[test]
+    fn docs_are_found_by_declared_overload() {
+        let sorted = get_kubernetes_builtin("isSorted", "list_is_sorted").unwrap();
+        assert_eq!(sorted.name, "isSorted");
+        let named = get_kubernetes_builtin("format.named", "format_named").unwrap();
+        assert_eq!(named.name, "named");
+        assert!(get_kubernetes_builtin("format.uuid", "format_uuid").is_some());
+        // Same short name, but not the declared Kubernetes function
+        assert!(get_kubernetes_builtin("named", "format_named").is_none());
+        assert!(get_kubernetes_builtin("isSorted", "my_is_sorted").is_none());
+    }
+
+    #[test]
+    fn every_declared_function_is_documented() {
+        let decls = kubernetes_library(KubernetesVersion::LATEST);
+        assert_eq!(decls.len(), LIBRARY.len());
+        for decl in &decls {
+            for overload in &decl.overloads {
+                assert!(
+                    get_kubernetes_builtin(&decl.name, &overload.id).is_some(),
+                    "{}",
+                    overload.id
+                );
+            }
+        }
+        assert!(kubernetes_library(KubernetesVersion::OLDEST).len() < decls.len());
+    }
+}
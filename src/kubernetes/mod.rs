@@ -0,0 +1,19 @@
+//! CEL embedded in Kubernetes YAML manifests.
+//!
+//! This module provides:
+//! - a span-preserving YAML parser (`yaml`)
+//! - conversion of CRD OpenAPI schemas to CEL types (`schema`)
+//! - extraction of CEL expressions from CRDs, ValidatingAdmissionPolicies and
+//!   webhook configurations, each with its own typed environment (`extract`)
+//! - declarations and docs for the Kubernetes CEL libraries (`library`)
+
+mod extract;
+mod library;
+mod schema;
+mod yaml;
+
+pub use extract::extract_kubernetes_regions;
+pub use library::{
+    get_kubernetes_builtin, is_kubernetes_type, with_kubernetes_libraries, KubernetesVersion,
+};
+pub use yaml::{parse_documents, YamlNode};
@@ -0,0 +1,324 @@
+//! Conversion of OpenAPI v3 schemas to CEL types.
+//!
+//! Follows the Kubernetes mapping for CRD validation rules: objects with
+//! `properties` become message types, objects with `additionalProperties`
+//! become maps, and string formats select timestamp, duration or bytes.
+//! Property names are escaped the way the API server escapes them.
+
+use cel_core::CelType;
+
+use crate::types::SyntheticTypes;
+
+use super::yaml::YamlNode;
+
+/// A schema node carrying `x-kubernetes-validations` rules.
+#[derive(Debug, Clone)]
+pub struct ValidationSite<'a> {
+    /// The type of `self` at this node.
+    pub self_type: CelType,
+    /// The `x-kubernetes-validations` sequence.
+    pub validations: &'a YamlNode,
+}
+
+/// Converts schemas into synthetic message types, collecting validation sites.
+#[derive(Debug, Default)]
+pub struct SchemaConverter<'a> {
+    /// The message types declared so far.
+    pub types: SyntheticTypes,
+    /// Validation rules found while converting, in document order.
+    pub sites: Vec<ValidationSite<'a>>,
+}
+
+impl<'a> SchemaConverter<'a> {
+    /// Create a converter with an empty type registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Convert the root schema of a resource.
+    ///
+    /// The root is always a message with `apiVersion`, `kind` and `metadata`
+    /// (exposing only `name` and `generateName`) in addition to its properties.
+    pub fn convert_root(&mut self, schema: &'a YamlNode, name: &str) -> CelType {
+        let site = self.sites.len();
+        self.convert_object(schema, name);
+        self.types.add_message(name);
+        self.add_type_meta(name, &["name", "generateName"]);
+        let self_type = CelType::message(name);
+        if let Some(validations) = schema.get("x-kubernetes-validations") {
+            self.sites.insert(
+                site,
+                ValidationSite {
+                    self_type: self_type.clone(),
+                    validations,
+                },
+            );
+        }
+        self_type
+    }
+
+    /// Convert a schema node, naming any message types after `name`.
+    pub fn convert(&mut self, schema: &'a YamlNode, name: &str) -> CelType {
+        // Record the site before descending so rules stay in document order
+        let site = self.sites.len();
+        let cel_type = self.schema_type(schema, name);
+        if let Some(validations) = schema.get("x-kubernetes-validations") {
+            self.sites.insert(
+                site,
+                ValidationSite {
+                    self_type: cel_type.clone(),
+                    validations,
+                },
+            );
+        }
+        cel_type
+    }
+
+    fn schema_type(&mut self, schema: &'a YamlNode, name: &str) -> CelType {
+        if flag(schema, "x-kubernetes-int-or-string") {
+            return CelType::Dyn;
+        }
+
+        match schema.get("type").and_then(YamlNode::as_str) {
+            Some("string") => match schema.get("format").and_then(YamlNode::as_str) {
+                Some("date-time") => CelType::Timestamp,
+                Some("duration") => CelType::Duration,
+                Some("byte") => CelType::Bytes,
+                _ => CelType::String,
+            },
+            Some("integer") => CelType::Int,
+            Some("number") => CelType::Double,
+            Some("boolean") => CelType::Bool,
+            Some("array") => match schema.get("items") {
+                Some(items) => CelType::list(self.convert(items, &format!("{}.@items", name))),
+                None => CelType::list(CelType::Dyn),
+            },
+            Some("object") | None => self.convert_object(schema, name),
+            Some(_) => CelType::Dyn,
+        }
+    }
+
+    fn convert_object(&mut self, schema: &'a YamlNode, name: &str) -> CelType {
+        let embedded = flag(schema, "x-kubernetes-embedded-resource");
+        let properties = schema.get("properties").filter(|p| !p.entries().is_empty());
+
+        if let Some(properties) = properties {
+            self.types.add_message(name);
+            for (key, property) in properties.entries() {
+                let Some(field) = escape_property_name(&key.value) else {
+                    continue;
+                };
+                let field_type = self.convert(property, &format!("{}.{}", name, field));
+                self.types
+                    .add_field(name, &field, field_type, description(property));
+            }
+            if embedded {
+                self.add_embedded_meta(name);
+            }
+            return CelType::message(name);
+        }
+
+        if embedded {
+            self.types.add_message(name);
+            self.add_embedded_meta(name);
+            return CelType::message(name);
+        }
+
+        match schema.get("additionalProperties") {
+            Some(additional @ YamlNode::Mapping(_)) => CelType::map(
+                CelType::String,
+                self.convert(additional, &format!("{}.@values", name)),
+            ),
+            Some(YamlNode::Scalar(s)) if s.value == "true" => {
+                CelType::map(CelType::String, CelType::Dyn)
+            }
+            _ => CelType::Dyn,
+        }
+    }
+
+    /// Add `apiVersion`, `kind` and a `metadata` message with the given fields.
+    fn add_type_meta(&mut self, name: &str, metadata_fields: &[&str]) {
+        for field in ["apiVersion", "kind"] {
+            if self.types.field(name, field).is_none() {
+                self.types.add_field(name, field, CelType::String, None);
+            }
+        }
+        let metadata = format!("{}.metadata", name);
+        for field in metadata_fields {
+            let field_type = match *field {
+                "labels" | "annotations" => CelType::map(CelType::String, CelType::String),
+                _ => CelType::String,
+            };
+            self.types.add_field(&metadata, field, field_type, None);
+        }
+        self.types
+            .add_field(name, "metadata", CelType::message(&metadata), None);
+    }
+
+    fn add_embedded_meta(&mut self, name: &str) {
+        self.add_type_meta(
+            name,
+            &["name", "generateName", "namespace", "labels", "annotations"],
+        );
+    }
+}
+
+/// Check whether a boolean schema extension is set.
+fn flag(schema: &YamlNode, key: &str) -> bool {
+    schema.get(key).and_then(YamlNode::as_str) == Some("true")
+}
+
+/// Get the `description` of a schema node.
+fn description(schema: &YamlNode) -> Option<String> {
+    schema
+        .get("description")
+        .and_then(YamlNode::as_str)
+        .map(|d| d.trim().to_string())
+        .filter(|d| !d.is_empty())
+}
+
+/// CEL reserved words that must be escaped as `__{word}__` in field names.
+const RESERVED: &[&str] = &[
+    "true",
+    "false",
+    "null",
+    "in",
+    "as",
+    "break",
+    "const",
+    "continue",
+    "else",
+    "for",
+    "function",
+    "if",
+    "import",
+    "let",
+    "loop",
+    "package",
+    "namespace",
+    "return",
+    "var",
+    "void",
+    "while",
+];
+
+/// Escape a property name the way Kubernetes exposes it to CEL.
+///
+/// Returns `None` for names that cannot be accessed from CEL.
+pub fn escape_property_name(name: &str) -> Option<String> {
+    if RESERVED.contains(&name) {
+        return Some(format!("__{}__", name));
+    }
+
+    let escaped = name
+        .replace("__", "__underscores__")
+        .replace('.', "__dot__")
+        .replace('-', "__dash__")
+        .replace('/', "__slash__");
+
+    let mut chars = escaped.chars();
+    let valid_start = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+    let valid_rest = chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    (valid_start && valid_rest).then_some(escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kubernetes::yaml::parse_documents;
+
+    #[test]
+    fn escapes_property_names() {
+        assert_eq!(
+            escape_property_name("replicas").as_deref(),
+            Some("replicas")
+        );
+        assert_eq!(
+            escape_property_name("namespace").as_deref(),
+            Some("__namespace__")
+        );
+        assert_eq!(
+            escape_property_name("a.b-c").as_deref(),
+            Some("a__dot__b__dash__c")
+        );
+        assert_eq!(escape_property_name("x/y").as_deref(), Some("x__slash__y"));
+        assert_eq!(
+            escape_property_name("a__b").as_deref(),
+            Some("a__underscores__b")
+        );
+        assert_eq!(escape_property_name("1st"), None);
+        assert_eq!(escape_property_name("a b"), None);
+    }
+
+    #[test]
+    fn converts_schema_types() {
+        let source = r#"
+type: object
+properties:
+  spec:
+    type: object
+    x-kubernetes-validations:
+    - rule: self.replicas >= 0
+    properties:
+      replicas:
+        type: integer
+      ratio:
+        type: number
+      started:
+        type: string
+        format: date-time
+      timeout:
+        type: string
+        format: duration
+      port:
+        x-kubernetes-int-or-string: true
+      tags:
+        type: array
+        items:
+          type: string
+      labels:
+        type: object
+        additionalProperties:
+          type: string
+"#;
+        let docs = parse_documents(source);
+        let mut converter = SchemaConverter::new();
+        let root = converter.convert_root(&docs[0], "Widget");
+        assert_eq!(root, CelType::message("Widget"));
+
+        let types = &converter.types;
+        let field = |m: &str, f: &str| types.field(m, f).map(|f| f.cel_type.clone());
+        assert_eq!(
+            field("Widget", "spec"),
+            Some(CelType::message("Widget.spec"))
+        );
+        assert_eq!(field("Widget", "kind"), Some(CelType::String));
+        assert_eq!(
+            field("Widget", "metadata"),
+            Some(CelType::message("Widget.metadata"))
+        );
+        assert_eq!(field("Widget.metadata", "name"), Some(CelType::String));
+        assert_eq!(field("Widget.metadata", "labels"), None);
+        assert_eq!(field("Widget.spec", "replicas"), Some(CelType::Int));
+        assert_eq!(field("Widget.spec", "ratio"), Some(CelType::Double));
+        assert_eq!(field("Widget.spec", "started"), Some(CelType::Timestamp));
+        assert_eq!(field("Widget.spec", "timeout"), Some(CelType::Duration));
+        assert_eq!(field("Widget.spec", "port"), Some(CelType::Dyn));
+        assert_eq!(
+            field("Widget.spec", "tags"),
+            Some(CelType::list(CelType::String))
+        );
+        assert_eq!(
+            field("Widget.spec", "labels"),
+            Some(CelType::map(CelType::String, CelType::String))
+        );
+
+        assert_eq!(converter.sites.len(), 1);
+        assert_eq!(
+            converter.sites[0].self_type,
+            CelType::message("Widget.spec")
+        );
+    }
+}
@@ -0,0 +1,1046 @@
+//! A small YAML parser that keeps host offsets for every scalar.
+//!
+//! Only the subset of YAML used by Kubernetes manifests is supported: block
+//! mappings and sequences, flow collections, plain, single-quoted and
+//! double-quoted scalars, literal (`|`) and folded (`>`) block scalars, comments
+//! and `---` document separators. Tags and anchors are skipped and aliases
+//! read as null.
+//!
+//! Each scalar records where its content starts in the host document and how
+//! decoding (escapes, line folding, block indentation) shifted offsets, in the
+//! form used by `OffsetMapper`, so CEL spans map back to the exact host text.
+//! Malformed input never fails: the parser recovers at the next line.
+
+/// A parsed YAML node.
+#[derive(Debug, Clone, PartialEq)]
+pub enum YamlNode {
+    /// An empty value, alias, or unparseable content.
+    Null,
+    /// A scalar value.
+    Scalar(YamlScalar),
+    /// A mapping, in document order.
+    Mapping(Vec<(YamlScalar, YamlNode)>),
+    /// A sequence.
+    Sequence(Vec<YamlNode>),
+}
+
+/// A decoded scalar with its position in the host document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct YamlScalar {
+    /// The decoded value.
+    pub value: String,
+    /// Byte offset in the host document where the value's first character is.
+    pub host_offset: usize,
+    /// Offset adjustments as `(value_offset, cumulative_extra_host_bytes)`
+    /// pairs, matching `OffsetMapper::new`.
+    pub escape_adjustments: Vec<(usize, usize)>,
+}
+
+impl YamlNode {
+    /// Look up a mapping value by key.
+    pub fn get(&self, key: &str) -> Option<&YamlNode> {
+        self.entries()
+            .iter()
+            .find(|(k, _)| k.value == key)
+            .map(|(_, v)| v)
+    }
+
+    /// Get the scalar, if this node is one.
+    pub fn as_scalar(&self) -> Option<&YamlScalar> {
+        match self {
+            YamlNode::Scalar(scalar) => Some(scalar),
+            _ => None,
+        }
+    }
+
+    /// Get the scalar value, if this node is a scalar.
+    pub fn as_str(&self) -> Option<&str> {
+        self.as_scalar().map(|s| s.value.as_str())
+    }
+
+    /// Get the mapping entries, or an empty slice for other nodes.
+    pub fn entries(&self) -> &[(YamlScalar, YamlNode)] {
+        match self {
+            YamlNode::Mapping(entries) => entries,
+            _ => &[],
+        }
+    }
+
+    /// Get the sequence items, or an empty slice for other nodes.
+    pub fn items(&self) -> &[YamlNode] {
+        match self {
+            YamlNode::Sequence(items) => items,
+            _ => &[],
+        }
+    }
+}
+
+/// Parse every document in a YAML stream.
+pub fn parse_documents(source: &str) -> Vec<YamlNode> {
+    let mut parser = Parser {
+        src: source,
+        bytes: source.as_bytes(),
+        pos: 0,
+    };
+    let mut documents = Vec::new();
+    let mut current = YamlNode::Null;
+
+    loop {
+        parser.skip_blank();
+        if parser.at_eof() {
+            break;
+        }
+        if parser.at_document_marker() {
+            if current != YamlNode::Null {
+                documents.push(std::mem::replace(&mut current, YamlNode::Null));
+            }
+            parser.skip_line();
+            continue;
+        }
+
+        let start = parser.pos;
+        let node = parser.parse_node_at(-1);
+        if current == YamlNode::Null {
+            current = node;
+        }
+        if parser.pos == start {
+            parser.skip_line();
+        }
+    }
+
+    if current != YamlNode::Null {
+        documents.push(current);
+    }
+    documents
+}
+
+/// Builds a decoded scalar while tracking host offset adjustments.
+struct ScalarBuilder {
+    value: String,
+    host_offset: Option<usize>,
+    adjustments: Vec<(usize, usize)>,
+}
+
+impl ScalarBuilder {
+    fn new() -> Self {
+        Self {
+            value: String::new(),
+            host_offset: None,
+            adjustments: Vec::new(),
+        }
+    }
+
+    /// Append a decoded character that came from `host_pos`.
+    fn push(&mut self, c: char, host_pos: usize) {
+        self.record(host_pos);
+        self.value.push(c);
+    }
+
+    /// Record the adjustment for the next value offset mapping to `host_pos`.
+    fn record(&mut self, host_pos: usize) {
+        let start = *self.host_offset.get_or_insert(host_pos);
+        let adjustment = host_pos.saturating_sub(start + self.value.len());
+        let last = self.adjustments.last().map(|(_, adj)| *adj).unwrap_or(0);
+        if adjustment != last {
+            self.adjustments.push((self.value.len(), adjustment));
+        }
+    }
+
+    /// Remove trailing spaces and tabs, e.g. before a folded line break.
+    fn trim_end(&mut self) {
+        let len = self.value.trim_end_matches([' ', '\t']).len();
+        self.value.truncate(len);
+        self.adjustments.retain(|(offset, _)| *offset < len);
+    }
+
+    /// Finish the scalar whose content ends at `host_end`.
+    fn finish(mut self, host_end: usize) -> YamlScalar {
+        if self.host_offset.is_none() {
+            self.host_offset = Some(host_end);
+        }
+        self.record(host_end);
+        YamlScalar {
+            value: self.value,
+            host_offset: self.host_offset.unwrap_or(host_end),
+            escape_adjustments: self.adjustments,
+        }
+    }
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn at_eof(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, pos: usize) -> Option<u8> {
+        self.bytes.get(pos).copied()
+    }
+
+    fn char_at(&self, pos: usize) -> Option<char> {
+        self.src.get(pos..)?.chars().next()
+    }
+
+    /// Byte offset of the start of the line containing `pos`.
+    fn line_start(&self, pos: usize) -> usize {
+        self.bytes[..pos.min(self.bytes.len())]
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map(|i| i + 1)
+            .unwrap_or(0)
+    }
+
+    fn column(&self, pos: usize) -> isize {
+        (pos - self.line_start(pos)) as isize
+    }
+
+    /// Byte offset of the newline ending the line containing `pos` (or EOF).
+    fn line_end(&self, pos: usize) -> usize {
+        self.bytes[pos.min(self.bytes.len())..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map(|i| pos + i)
+            .unwrap_or(self.bytes.len())
+    }
+
+    fn is_space(b: Option<u8>) -> bool {
+        matches!(b, Some(b' ') | Some(b'\t') | Some(b'\r'))
+    }
+
+    fn is_break_or_eof(b: Option<u8>) -> bool {
+        matches!(b, None | Some(b'\n'))
+    }
+
+    /// Whether `pos` is at a comment (`#` at line start or after whitespace).
+    fn is_comment_start(&self, pos: usize) -> bool {
+        self.peek_at(pos) == Some(b'#')
+            && (pos == 0 || matches!(self.peek_at(pos - 1), Some(b' ' | b'\t' | b'\n')))
+    }
+
+    fn skip_spaces(&mut self) {
+        while Self::is_space(self.peek()) {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_line(&mut self) {
+        self.pos = (self.line_end(self.pos) + 1).min(self.bytes.len());
+    }
+
+    /// Whether only spaces and an optional comment remain on the current line.
+    fn at_line_end(&self) -> bool {
+        let mut pos = self.pos;
+        while Self::is_space(self.peek_at(pos)) {
+            pos += 1;
+        }
+        Self::is_break_or_eof(self.peek_at(pos)) || self.is_comment_start(pos)
+    }
+
+    /// Skip whitespace, comments and line breaks up to the next content.
+    fn skip_blank(&mut self) {
+        loop {
+            self.skip_spaces();
+            if self.is_comment_start(self.pos) {
+                self.pos = self.line_end(self.pos);
+            }
+            if self.peek() == Some(b'\n') {
+                self.pos += 1;
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn at_document_marker(&self) -> bool {
+        self.column(self.pos) == 0
+            && (self.src[self.pos..].starts_with("---") || self.src[self.pos..].starts_with("..."))
+            && (Self::is_space(self.peek_at(self.pos + 3))
+                || Self::is_break_or_eof(self.peek_at(self.pos + 3)))
+    }
+
+    fn at_sequence_dash(&self, pos: usize) -> bool {
+        self.peek_at(pos) == Some(b'-')
+            && (Self::is_space(self.peek_at(pos + 1))
+                || Self::is_break_or_eof(self.peek_at(pos + 1)))
+    }
+
+    /// Skip tags (`!tag`) and anchors (`&anchor`) before a node.
+    fn skip_properties(&mut self) {
+        while matches!(self.peek(), Some(b'!') | Some(b'&')) {
+            while !Self::is_space(self.peek()) && !Self::is_break_or_eof(self.peek()) {
+                self.pos += 1;
+            }
+            self.skip_spaces();
+        }
+    }
+
+    /// Whether the content at `pos` is a mapping key followed by `:`.
+    fn is_mapping_key(&self, pos: usize) -> bool {
+        let end = self.line_end(pos);
+        let mut i = pos;
+        match self.peek_at(i) {
+            Some(quote @ (b'"' | b'\'')) => {
+                i += 1;
+                while i < end {
+                    let b = self.bytes[i];
+                    if b == b'\\' && quote == b'"' {
+                        i += 2;
+                        continue;
+                    }
+                    if b == quote {
+                        if quote == b'\'' && self.peek_at(i + 1) == Some(b'\'') {
+                            i += 2;
+                            continue;
+                        }
+                        break;
+                    }
+                    i += 1;
+                }
+                i += 1;
+                while Self::is_space(self.peek_at(i)) {
+                    i += 1;
+                }
+                self.is_value_indicator(i)
+            }
+            Some(b'[' | b'{' | b'|' | b'>' | b'#' | b'*') | None => false,
+            _ => {
+                while i < end {
+                    if self.is_value_indicator(i) {
+                        return true;
+                    }
+                    if self.is_comment_start(i) {
+                        return false;
+                    }
+                    i += 1;
+                }
+                false
+            }
+        }
+    }
+
+    /// Whether `pos` is a `:` followed by whitespace or a line break.
+    fn is_value_indicator(&self, pos: usize) -> bool {
+        self.peek_at(pos) == Some(b':')
+            && (Self::is_space(self.peek_at(pos + 1))
+                || Self::is_break_or_eof(self.peek_at(pos + 1)))
+    }
+
+    /// Parse the node starting at the current position.
+    ///
+    /// `parent_indent` is the indentation of the enclosing collection, which
+    /// bounds block scalars and multi-line plain scalars.
+    fn parse_node_at(&mut self, parent_indent: isize) -> YamlNode {
+        self.skip_properties();
+        if self.at_line_end() {
+            // Properties alone on a line; the node follows on the next lines
+            self.skip_blank();
+            if self.at_eof() || self.column(self.pos) <= parent_indent {
+                return YamlNode::Null;
+            }
+        }
+
+        let column = self.column(self.pos);
+        if self.at_sequence_dash(self.pos) {
+            return self.parse_sequence(column);
+        }
+        if self.is_mapping_key(self.pos) {
+            return self.parse_mapping(column);
+        }
+        self.parse_inline_value(parent_indent)
+    }
+
+    /// Parse a scalar or flow collection starting on the current line.
+    fn parse_inline_value(&mut self, parent_indent: isize) -> YamlNode {
+        match self.peek() {
+            Some(b'|' | b'>') => YamlNode::Scalar(self.parse_block_scalar(parent_indent)),
+            Some(b'"') => YamlNode::Scalar(self.parse_double_quoted()),
+            Some(b'\'') => YamlNode::Scalar(self.parse_single_quoted()),
+            Some(b'[' | b'{') => self.parse_flow(),
+            Some(b'*') => {
+                while !Self::is_space(self.peek()) && !Self::is_break_or_eof(self.peek()) {
+                    self.pos += 1;
+                }
+                YamlNode::Null
+            }
+            _ => YamlNode::Scalar(self.parse_plain(parent_indent)),
+        }
+    }
+
+    fn parse_mapping(&mut self, indent: isize) -> YamlNode {
+        let mut entries = Vec::new();
+
+        loop {
+            let key = match self.peek() {
+                Some(b'"') => self.parse_double_quoted(),
+                Some(b'\'') => self.parse_single_quoted(),
+                _ => self.parse_key(),
+            };
+            self.skip_spaces();
+            if self.peek() == Some(b':') {
+                self.pos += 1;
+            }
+            let value = self.parse_mapping_value(indent);
+            entries.push((key, value));
+
+            self.skip_blank();
+            if self.at_eof()
+                || self.column(self.pos) != indent
+                || self.at_document_marker()
+                || !self.is_mapping_key(self.pos)
+            {
+                break;
+            }
+        }
+
+        YamlNode::Mapping(entries)
+    }
+
+    /// Parse a plain mapping key up to its `:` indicator.
+    fn parse_key(&mut self) -> YamlScalar {
+        let mut builder = ScalarBuilder::new();
+        while !self.at_eof() && !self.is_value_indicator(self.pos) && self.peek() != Some(b'\n') {
+            let c = self.char_at(self.pos).unwrap_or(' ');
+            builder.push(c, self.pos);
+            self.pos += c.len_utf8();
+        }
+        builder.trim_end();
+        let end = builder
+            .host_offset
+            .map(|start| start + builder.value.len())
+            .unwrap_or(self.pos);
+        builder.finish(end)
+    }
+
+    /// Parse the value after a mapping key's `:` at column `indent`.
+    fn parse_mapping_value(&mut self, indent: isize) -> YamlNode {
+        self.skip_spaces();
+        self.skip_properties();
+        if !self.at_line_end() {
+            return self.parse_inline_value(indent);
+        }
+
+        self.skip_blank();
+        if self.at_eof() || self.at_document_marker() {
+            return YamlNode::Null;
+        }
+        let column = self.column(self.pos);
+        if column > indent {
+            self.parse_node_at(indent)
+        } else if column == indent && self.at_sequence_dash(self.pos) {
+            // Sequences may sit at the same indentation as their key
+            self.parse_sequence(column)
+        } else {
+            YamlNode::Null
+        }
+    }
+
+    fn parse_sequence(&mut self, indent: isize) -> YamlNode {
+        let mut items = Vec::new();
+
+        loop {
+            self.pos += 1;
+            self.skip_spaces();
+            let item = if self.at_line_end() {
+                self.skip_blank();
+                if !self.at_eof() && self.column(self.pos) > indent && !self.at_document_marker() {
+                    self.parse_node_at(indent)
+                } else {
+                    YamlNode::Null
+                }
+            } else {
+                self.parse_node_at(indent)
+            };
+            items.push(item);
+
+            self.skip_blank();
+            if self.at_eof() || self.column(self.pos) != indent || !self.at_sequence_dash(self.pos)
+            {
+                break;
+            }
+        }
+
+        YamlNode::Sequence(items)
+    }
+
+    /// Parse a plain scalar, folding continuation lines indented past `parent_indent`.
+    fn parse_plain(&mut self, parent_indent: isize) -> YamlScalar {
+        let mut builder = ScalarBuilder::new();
+        let mut end = self.pos;
+
+        loop {
+            // Read the rest of the current line
+            while !self.at_eof() && self.peek() != Some(b'\n') {
+                if self.is_value_indicator(self.pos) || self.is_comment_start(self.pos) {
+                    break;
+                }
+                let c = self.char_at(self.pos).unwrap_or(' ');
+                if !Self::is_space(self.peek()) {
+                    end = self.pos + c.len_utf8();
+                }
+                builder.push(c, self.pos);
+                self.pos += c.len_utf8();
+            }
+            builder.trim_end();
+            if self.peek() != Some(b'\n') {
+                break;
+            }
+
+            // Continue onto the next line if it is indented past the parent
+            let line_break = self.pos;
+            let mut next = self.pos + 1;
+            let mut empty_lines = Vec::new();
+            loop {
+                let content = self.skip_spaces_from(next);
+                if self.peek_at(content) == Some(b'\n') {
+                    empty_lines.push(content);
+                    next = content + 1;
+                } else {
+                    next = content;
+                    break;
+                }
+            }
+            let ends = next >= self.bytes.len()
+                || self.column(next) <= parent_indent
+                || self.is_comment_start(next)
+                || self.is_mapping_key(next)
+                || self.at_sequence_dash(next)
+                || (self.column(next) == 0 && self.starts_document_marker(next));
+            if ends {
+                break;
+            }
+
+            if empty_lines.is_empty() {
+                builder.push(' ', line_break);
+            } else {
+                for empty in empty_lines {
+                    builder.push('\n', empty);
+                }
+            }
+            self.pos = next;
+        }
+
+        self.pos = self.pos.max(end);
+        builder.finish(end)
+    }
+
+    fn skip_spaces_from(&self, mut pos: usize) -> usize {
+        while Self::is_space(self.peek_at(pos)) {
+            pos += 1;
+        }
+        pos
+    }
+
+    fn starts_document_marker(&self, pos: usize) -> bool {
+        self.src[pos..].starts_with("---") || self.src[pos..].starts_with("...")
+    }
+
+    /// Handle a line break inside a quoted scalar at the current position.
+    ///
+    /// A single break folds to a space; each following empty line becomes `\n`.
+    fn fold_quoted_line_break(&mut self, builder: &mut ScalarBuilder) {
+        builder.trim_end();
+        let line_break = self.pos;
+        self.pos += 1;
+        let mut empty_lines = 0;
+        loop {
+            let content = self.skip_spaces_from(self.pos);
+            if self.peek_at(content) == Some(b'\n') {
+                builder.push('\n', content);
+                empty_lines += 1;
+                self.pos = content + 1;
+            } else {
+                self.pos = content;
+                break;
+            }
+        }
+        if empty_lines == 0 {
+            builder.push(' ', line_break);
+        }
+    }
+
+    fn parse_double_quoted(&mut self) -> YamlScalar {
+        let mut builder = ScalarBuilder::new();
+        self.pos += 1;
+        builder.record(self.pos);
+
+        while let Some(b) = self.peek() {
+            match b {
+                b'"' => {
+                    let scalar = builder.finish(self.pos);
+                    self.pos += 1;
+                    return scalar;
+                }
+                b'\\' => {
+                    let start = self.pos;
+                    let Some(escape) = self.char_at(self.pos + 1) else {
+                        self.pos += 1;
+                        break;
+                    };
+                    self.pos += 1 + escape.len_utf8();
+                    let decoded = match escape {
+                        'n' => Some('\n'),
+                        't' | '\t' => Some('\t'),
+                        'r' => Some('\r'),
+                        '0' => Some('\0'),
+                        'a' => Some('\x07'),
+                        'b' => Some('\x08'),
+                        'e' => Some('\x1b'),
+                        'f' => Some('\x0c'),
+                        'v' => Some('\x0b'),
+                        'N' => Some('\u{85}'),
+                        '_' => Some('\u{a0}'),
+                        'L' => Some('\u{2028}'),
+                        'P' => Some('\u{2029}'),
+                        'x' => self.parse_hex_escape(2),
+                        'u' => self.parse_hex_escape(4),
+                        'U' => self.parse_hex_escape(8),
+                        '\n' => {
+                            // Escaped line break: join lines without a space
+                            self.pos = self.skip_spaces_from(self.pos);
+                            None
+                        }
+                        other => Some(other),
+                    };
+                    if let Some(c) = decoded {
+                        builder.push(c, start);
+                    }
+                }
+                b'\n' => self.fold_quoted_line_break(&mut builder),
+                b'\r' if self.peek_at(self.pos + 1) == Some(b'\n') => self.pos += 1,
+                _ => {
+                    let c = self.char_at(self.pos).unwrap_or(' ');
+                    builder.push(c, self.pos);
+                    self.pos += c.len_utf8();
+                }
+            }
+        }
+
+        // Unterminated string
+        builder.finish(self.pos)
+    }
+
+    fn parse_hex_escape(&mut self, digits: usize) -> Option<char> {
+        let hex = self.src.get(self.pos..self.pos + digits)?;
+        let code = u32::from_str_radix(hex, 16).ok()?;
+        self.pos += digits;
+        char::from_u32(code)
+    }
+
+    fn parse_single_quoted(&mut self) -> YamlScalar {
+        let mut builder = ScalarBuilder::new();
+        self.pos += 1;
+        builder.record(self.pos);
+
+        while let Some(b) = self.peek() {
+            match b {
+                b'\'' if self.peek_at(self.pos + 1) == Some(b'\'') => {
+                    builder.push('\'', self.pos);
+                    self.pos += 2;
+                }
+                b'\'' => {
+                    let scalar = builder.finish(self.pos);
+                    self.pos += 1;
+                    return scalar;
+                }
+                b'\n' => self.fold_quoted_line_break(&mut builder),
+                b'\r' if self.peek_at(self.pos + 1) == Some(b'\n') => self.pos += 1,
+                _ => {
+                    let c = self.char_at(self.pos).unwrap_or(' ');
+                    builder.push(c, self.pos);
+                    self.pos += c.len_utf8();
+                }
+            }
+        }
+
+        builder.finish(self.pos)
+    }
+
+    /// Parse a literal (`|`) or folded (`>`) block scalar.
+    fn parse_block_scalar(&mut self, parent_indent: isize) -> YamlScalar {
+        let folded = self.peek() == Some(b'>');
+        self.pos += 1;
+
+        // Header: chomping indicator and explicit indentation in either order
+        let mut chomping = b' ';
+        let mut explicit_indent = None;
+        for _ in 0..2 {
+            match self.peek() {
+                Some(c @ (b'+' | b'-')) => {
+                    chomping = c;
+                    self.pos += 1;
+                }
+                Some(c @ b'1'..=b'9') => {
+                    explicit_indent = Some((c - b'0') as isize);
+                    self.pos += 1;
+                }
+                _ => {}
+            }
+        }
+        self.pos = (self.line_end(self.pos) + 1).min(self.bytes.len());
+
+        // Collect the content lines as (line_start, line_end) pairs
+        let base = parent_indent.max(0);
+        let mut content_indent = explicit_indent.map(|i| base + i);
+        let mut lines = Vec::new();
+        let mut pos = self.pos;
+        while pos < self.bytes.len() {
+            let end = self.line_end(pos);
+            let first = self.skip_spaces_from(pos);
+            let blank = first >= end || self.bytes[first..end].iter().all(|b| *b == b'\r');
+            if blank {
+                lines.push((pos, end));
+            } else {
+                let indent = (first - pos) as isize;
+                let required = *content_indent.get_or_insert(indent.max(parent_indent + 1));
+                if indent < required || indent <= parent_indent {
+                    break;
+                }
+                lines.push((pos, end));
+            }
+            pos = (end + 1).min(self.bytes.len());
+            if end >= self.bytes.len() {
+                break;
+            }
+        }
+
+        // Trailing blank lines belong to the scalar only for chomping purposes
+        let last_content = lines.iter().rposition(|&(start, end)| {
+            let first = self.skip_spaces_from(start);
+            first < end && !self.bytes[first..end].iter().all(|b| *b == b'\r')
+        });
+        let Some(last_content) = last_content else {
+            self.pos = pos;
+            return ScalarBuilder::new().finish(self.pos);
+        };
+        let content_indent = content_indent.unwrap_or(0) as usize;
+
+        let mut builder = ScalarBuilder::new();
+        let mut previous_more_indented = false;
+        let mut pending_breaks: Vec<usize> = Vec::new();
+        let mut wrote_line = false;
+
+        for &(start, end) in &lines[..=last_content] {
+            let text_start = (start + content_indent).min(end);
+            let mut text_end = end;
+            if text_end > text_start && self.bytes[text_end - 1] == b'\r' {
+                text_end -= 1;
+            }
+            let blank = self.bytes[start..text_end].iter().all(|b| *b == b' ');
+
+            if blank {
+                pending_breaks.push(end);
+                continue;
+            }
+
+            let more_indented = matches!(self.bytes.get(text_start), Some(b' ' | b'\t'));
+            if wrote_line {
+                // pending_breaks[0] is the break ending the previous line
+                let fold = folded && !more_indented && !previous_more_indented;
+                if fold && pending_breaks.len() == 1 {
+                    builder.push(' ', pending_breaks[0]);
+                } else {
+                    let skip = usize::from(fold);
+                    for &line_break in &pending_breaks[skip..] {
+                        builder.push('\n', line_break);
+                    }
+                }
+            } else {
+                // Leading blank lines are kept as line breaks
+                for &line_break in &pending_breaks {
+                    builder.push('\n', line_break);
+                }
+            }
+            pending_breaks.clear();
+
+            for (offset, c) in self.src[text_start..text_end].char_indices() {
+                builder.push(c, text_start + offset);
+            }
+            pending_breaks.push(end);
+            previous_more_indented = more_indented;
+            wrote_line = true;
+        }
+
+        // Trailing blank lines count towards "keep" chomping
+        for &(_, end) in &lines[last_content + 1..] {
+            pending_breaks.push(end);
+        }
+        let content_end = lines[last_content].1;
+        let scalar_end = match chomping {
+            b'-' => content_end,
+            b'+' => {
+                for &line_break in &pending_breaks {
+                    if line_break < self.bytes.len() {
+                        builder.push('\n', line_break);
+                    }
+                }
+                pending_breaks
+                    .last()
+                    .map(|e| (*e + 1).min(self.bytes.len()))
+                    .unwrap_or(content_end)
+            }
+            _ => {
+                if content_end < self.bytes.len() {
+                    builder.push('\n', content_end);
+                    content_end + 1
+                } else {
+                    content_end
+                }
+            }
+        };
+
+        self.pos = pos;
+        builder.finish(scalar_end)
+    }
+
+    /// Parse a flow sequence (`[...]`) or flow mapping (`{...}`).
+    fn parse_flow(&mut self) -> YamlNode {
+        let is_mapping = self.peek() == Some(b'{');
+        let close = if is_mapping { b'}' } else { b']' };
+        self.pos += 1;
+
+        let mut items = Vec::new();
+        let mut entries = Vec::new();
+
+        loop {
+            self.skip_blank();
+            match self.peek() {
+                None => break,
+                Some(b) if b == close => {
+                    self.pos += 1;
+                    break;
+                }
+                Some(b',') => {
+                    self.pos += 1;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let start = self.pos;
+            let node = self.parse_flow_item();
+            self.skip_blank();
+            if self.peek() == Some(b':') {
+                self.pos += 1;
+                self.skip_blank();
+                let value =
+                    if matches!(self.peek(), Some(b',') | None) || self.peek() == Some(close) {
+                        YamlNode::Null
+                    } else {
+                        self.parse_flow_item()
+                    };
+                let key = node.as_scalar().cloned().unwrap_or_else(|| YamlScalar {
+                    value: String::new(),
+                    host_offset: start,
+                    escape_adjustments: Vec::new(),
+                });
+                if is_mapping {
+                    entries.push((key, value));
+                } else {
+                    items.push(YamlNode::Mapping(vec![(key, value)]));
+                }
+            } else if is_mapping {
+                if let Some(key) = node.as_scalar() {
+                    entries.push((key.clone(), YamlNode::Null));
+                }
+            } else {
+                items.push(node);
+            }
+
+            if self.pos == start {
+                // Unexpected character; skip it to guarantee progress
+                self.pos += self.char_at(self.pos).map(char::len_utf8).unwrap_or(1);
+            }
+        }
+
+        if is_mapping {
+            YamlNode::Mapping(entries)
+        } else {
+            YamlNode::Sequence(items)
+        }
+    }
+
+    fn parse_flow_item(&mut self) -> YamlNode {
+        self.skip_properties();
+        match self.peek() {
+            Some(b'"') => YamlNode::Scalar(self.parse_double_quoted()),
+            Some(b'\'') => YamlNode::Scalar(self.parse_single_quoted()),
+            Some(b'[' | b'{') => self.parse_flow(),
+            _ => {
+                let mut builder = ScalarBuilder::new();
+                let mut end = self.pos;
+                while let Some(b) = self.peek() {
+                    if matches!(b, b',' | b'[' | b']' | b'{' | b'}' | b'\n')
+                        || self.is_value_indicator(self.pos)
+                        || self.is_comment_start(self.pos)
+                    {
+                        break;
+                    }
+                    let c = self.char_at(self.pos).unwrap_or(' ');
+                    if !Self::is_space(Some(b)) {
+                        end = self.pos + c.len_utf8();
+                    }
+                    builder.push(c, self.pos);
+                    self.pos += c.len_utf8();
+                }
+                builder.trim_end();
+                YamlNode::Scalar(builder.finish(end))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check that every character of the scalar maps back to the same
+    /// character in the host, using the same arithmetic as `OffsetMapper`.
+    fn host_char(source: &str, scalar: &YamlScalar, offset: usize) -> char {
+        let mut adjustment = 0;
+        for (threshold, adj) in &scalar.escape_adjustments {
+            if offset >= *threshold {
+                adjustment = *adj;
+            }
+        }
+        source[scalar.host_offset + offset + adjustment..]
+            .chars()
+            .next()
+            .unwrap()
+    }
+
+    fn scalar<'a>(doc: &'a YamlNode, path: &[&str]) -> &'a YamlScalar {
+        let mut node = doc;
+        for key in path {
+            node = node
+                .get(key)
+                .unwrap_or_else(|| panic!("missing key {}", key));
+        }
+        node.as_scalar().expect("scalar")
+    }
+
+    #[test]
+    fn parses_block_mappings_and_sequences() {
+        let source = "a: 1\nb:\n  c: two\n  d:\n  - x\n  - y: z\n    w: v\ne: [1, 'two', {k: v}]\n";
+        let docs = parse_documents(source);
+        assert_eq!(docs.len(), 1);
+        let doc = &docs[0];
+        assert_eq!(doc.get("a").unwrap().as_str(), Some("1"));
+        assert_eq!(scalar(doc, &["b", "c"]).value, "two");
+        let d = doc.get("b").unwrap().get("d").unwrap().items();
+        assert_eq!(d.len(), 2);
+        assert_eq!(d[0].as_str(), Some("x"));
+        assert_eq!(d[1].get("y").unwrap().as_str(), Some("z"));
+        assert_eq!(d[1].get("w").unwrap().as_str(), Some("v"));
+        let e = doc.get("e").unwrap().items();
+        assert_eq!(e[1].as_str(), Some("two"));
+        assert_eq!(e[2].get("k").unwrap().as_str(), Some("v"));
+    }
+
+    #[test]
+    fn splits_documents_and_skips_comments() {
+        let source = "# leading\nkind: A # trailing\n---\nkind: B\n...\n";
+        let docs = parse_documents(source);
+        assert_eq!(docs.len(), 2);
+        assert_eq!(docs[0].get("kind").unwrap().as_str(), Some("A"));
+        assert_eq!(docs[1].get("kind").unwrap().as_str(), Some("B"));
+    }
+
+    #[test]
+    fn plain_scalar_offsets() {
+        let source = "rule: self.a > 0\n";
+        let docs = parse_documents(source);
+        let rule = scalar(&docs[0], &["rule"]);
+        assert_eq!(rule.value, "self.a > 0");
+        assert_eq!(rule.host_offset, 6);
+        assert!(rule.escape_adjustments.is_empty());
+    }
+
+    #[test]
+    fn multiline_plain_scalar_folds() {
+        let source = "rule: self.a > 0 &&\n  self.b < 1\nnext: x\n";
+        let docs = parse_documents(source);
+        let rule = scalar(&docs[0], &["rule"]);
+        assert_eq!(rule.value, "self.a > 0 && self.b < 1");
+        for (i, c) in rule.value.char_indices().filter(|(_, c)| *c != ' ') {
+            assert_eq!(host_char(source, rule, i), c);
+        }
+        assert_eq!(docs[0].get("next").unwrap().as_str(), Some("x"));
+    }
+
+    #[test]
+    fn double_quoted_escapes_map_to_host() {
+        let source = "rule: \"self.name == \\\"a\\\" &&\n  true\"\n";
+        let docs = parse_documents(source);
+        let rule = scalar(&docs[0], &["rule"]);
+        assert_eq!(rule.value, "self.name == \"a\" && true");
+        let t = rule.value.find("true").unwrap();
+        assert_eq!(host_char(source, rule, t), 't');
+        let a = rule.value.find('a').unwrap();
+        assert_eq!(host_char(source, rule, a), 'a');
+    }
+
+    #[test]
+    fn single_quoted_escapes_map_to_host() {
+        let source = "rule: 'self.name == ''a'' && true'\n";
+        let docs = parse_documents(source);
+        let rule = scalar(&docs[0], &["rule"]);
+        assert_eq!(rule.value, "self.name == 'a' && true");
+        let t = rule.value.find("true").unwrap();
+        assert_eq!(host_char(source, rule, t), 't');
+    }
+
+    #[test]
+    fn literal_block_scalar() {
+        let source = "rules:\n- rule: |\n    self.a > 0 &&\n      self.b < 1\n  message: m\n";
+        let docs = parse_documents(source);
+        let item = &docs[0].get("rules").unwrap().items()[0];
+        let rule = item.get("rule").unwrap().as_scalar().unwrap();
+        assert_eq!(rule.value, "self.a > 0 &&\n  self.b < 1\n");
+        for (i, c) in rule
+            .value
+            .char_indices()
+            .filter(|(_, c)| !c.is_whitespace())
+        {
+            assert_eq!(host_char(source, rule, i), c);
+        }
+        assert_eq!(item.get("message").unwrap().as_str(), Some("m"));
+    }
+
+    #[test]
+    fn folded_block_scalar() {
+        let source = "rule: >-\n  self.a > 0 &&\n  self.b < 1\n\n  || false\nnext: x\n";
+        let docs = parse_documents(source);
+        let rule = scalar(&docs[0], &["rule"]);
+        assert_eq!(rule.value, "self.a > 0 && self.b < 1\n|| false");
+        for (i, c) in rule
+            .value
+            .char_indices()
+            .filter(|(_, c)| !c.is_whitespace())
+        {
+            assert_eq!(host_char(source, rule, i), c);
+        }
+        assert_eq!(docs[0].get("next").unwrap().as_str(), Some("x"));
+    }
+
+    #[test]
+    fn malformed_input_does_not_hang() {
+        for source in [
+            "]: [\n  - {",
+            "a: \"unterminated\n",
+            "- - -\n:\n",
+            "key: |\n",
+            "\t\t- x",
+        ] {
+            let _ = parse_documents(source);
+        }
+    }
+}
@@ -1,81 +1,486 @@
 //! CEL Language Server implementation.
 
-use std::path::PathBuf;
-use std::sync::{Arc, OnceLock};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::Duration;
 
 use cel_core::Env;
 use cel_core_proto::ProstProtoRegistry;
+use serde_json::Value;
 use tower_lsp::jsonrpc::Result;
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer, LspService};
+use tracing::{debug, trace, warn};
 
+pub(crate) mod analysis;
+mod cli;
 mod document;
+pub(crate) mod envoy;
+pub(crate) mod iam;
+pub(crate) mod kubernetes;
+pub mod logging;
 mod lsp;
+pub(crate) mod proto;
 pub(crate) mod protovalidate;
+pub(crate) mod schemas;
 pub(crate) mod settings;
 pub(crate) mod types;
+pub(crate) mod workspace;
 
-pub use document::{DocumentState, LineIndex, ProtoDocumentState};
+#[cfg(test)]
+mod test_util;
+
+pub use cli::{parse_command, run_check, serve, Command, ServerOptions, Transport, HELP};
+pub use document::{DocumentState, EmbeddedDocumentState, LineIndex};
+pub use kubernetes::KubernetesVersion;
 pub use lsp::{completion_at_position_proto, proto_to_diagnostics, to_diagnostics};
-pub use settings::{build_env_with_protos, discover_settings, load_proto_registry, load_settings};
+pub use protovalidate::ProtovalidateVersion;
+pub use settings::{
+    build_env_with_protos, discover_settings, load_proto_registry, load_settings,
+    load_settings_file, Settings,
+};
+
+use analysis::{ComplexityLimits, CostBudget};
+use document::{is_proto_file, DocumentKind, DocumentStore, HostVersions};
 
-use document::{DocumentKind, DocumentStore};
+/// The language server, shared with the tasks it spawns.
+pub struct Backend(Arc<Server>);
 
-pub struct Backend {
+/// State of the language server.
+struct Server {
     client: Client,
     documents: DocumentStore,
     workspace_root: OnceLock<PathBuf>,
-    proto_registry: OnceLock<Option<Arc<ProstProtoRegistry>>>,
-    env: OnceLock<Arc<Env>>,
+    /// Settings from settings.toml merged with `initializationOptions`, and the
+    /// directory relative paths are resolved against.
+    base_settings: OnceLock<(Settings, PathBuf)>,
+    /// Analysis configuration built from the fully merged settings.
+    config: RwLock<Config>,
+    /// Whether the client supports `workspace/configuration` requests.
+    supports_configuration: OnceLock<bool>,
+    /// Whether the client pulls diagnostics, in which case none are pushed.
+    pull_diagnostics: OnceLock<bool>,
+    /// Explicit settings.toml path from `--settings`, overriding discovery.
+    settings_path: Option<PathBuf>,
+    /// Counts `.proto` buffer edits, so only the last of a burst rebuilds.
+    proto_edits: AtomicU64,
+    /// Held while the configuration is rebuilt, so that rebuilds do not
+    /// overwrite each other's results.
+    rebuilding: Mutex<()>,
+}
+
+/// How long `.proto` buffers must stay unchanged before the registry is rebuilt.
+const PROTO_REBUILD_DELAY: Duration = Duration::from_millis(300);
+
+/// Analysis configuration derived from the merged settings.
+#[derive(Default, Clone)]
+struct Config {
+    settings: Settings,
+    /// Descriptor sets and sources the registry is built from.
+    proto_inputs: Arc<proto::ProtoInputs>,
+    proto_registry: Option<Arc<ProstProtoRegistry>>,
+    /// Compile errors of `.proto` sources and open `.proto` buffers.
+    proto_errors: proto::CompileErrors,
+    env: Option<Arc<Env>>,
+    /// Host releases that embedded expressions are checked for.
+    versions: HostVersions,
+    /// Result types `.cel` files must evaluate to.
+    expected_types: settings::ExpectedTypes,
+    /// Cost limit and size hints expressions are estimated with.
+    cost_budget: CostBudget,
+    /// Limits on the structure of expressions.
+    complexity_limits: ComplexityLimits,
+    /// Incremented on every rebuild so pull diagnostic result IDs change.
+    generation: u64,
 }
 
 impl Backend {
-    pub(crate) fn new(client: Client) -> Self {
+    pub(crate) fn new(client: Client, settings_path: Option<PathBuf>) -> Self {
+        Self(Arc::new(Server::new(client, settings_path)))
+    }
+
+    /// Handle `$/setTrace`, which controls how much is logged to the client.
+    async fn set_trace(&self, params: SetTraceParams) {
+        logging::set_client_trace(params.value);
+    }
+}
+
+impl Server {
+    fn new(client: Client, settings_path: Option<PathBuf>) -> Self {
         Self {
             client,
             documents: DocumentStore::new(),
             workspace_root: OnceLock::new(),
-            proto_registry: OnceLock::new(),
-            env: OnceLock::new(),
+            base_settings: OnceLock::new(),
+            config: RwLock::new(Config::default()),
+            supports_configuration: OnceLock::new(),
+            pull_diagnostics: OnceLock::new(),
+            settings_path,
+            proto_edits: AtomicU64::new(0),
+            rebuilding: Mutex::new(()),
+        }
+    }
+
+    /// Rebuild the analysis configuration off the async runtime, since it
+    /// compiles every proto source.
+    async fn reload_settings(self: &Arc<Self>, workspace_settings: Option<Settings>) {
+        let server = Arc::clone(self);
+        let applied =
+            tokio::task::spawn_blocking(move || server.apply_settings(workspace_settings)).await;
+        if let Err(e) = applied {
+            warn!("applying settings failed: {}", e);
+        }
+    }
+
+    /// Rebuild the analysis configuration from the base settings with the
+    /// client's workspace settings layered on top.
+    fn apply_settings(&self, workspace_settings: Option<Settings>) {
+        let Some((base, settings_dir)) = self.base_settings.get() else {
+            return;
+        };
+        let _rebuilding = self.rebuilding.lock();
+
+        let mut settings = base.clone();
+        if let Some(overrides) = workspace_settings {
+            settings.merge(overrides);
+        }
+
+        let proto_inputs = Arc::new(settings::proto_inputs(&settings, settings_dir));
+        let protos = settings::build_protos(&proto_inputs, &self.proto_buffers());
+        let env = Arc::new(settings::build_env_with_registry(
+            &settings,
+            settings_dir,
+            protos.registry.clone(),
+        ));
+        let versions = settings::host_versions(&settings);
+        let expected_types = settings::expected_types(&settings);
+        let cost_budget = settings::cost_budget(&settings);
+        let complexity_limits = settings::complexity_limits(&settings);
+        for name in lsp::unknown_lints(&settings.diagnostics()) {
+            warn!("unknown lint rule: '{}'", name);
+        }
+        if let Ok(mut config) = self.config.write() {
+            *config = Config {
+                settings,
+                proto_inputs,
+                proto_registry: protos.registry,
+                proto_errors: protos.errors,
+                env: Some(env),
+                versions,
+                expected_types,
+                cost_budget,
+                complexity_limits,
+                generation: config.generation + 1,
+            };
+        }
+    }
+
+    /// Contents of the open `.proto` documents by canonical path.
+    fn proto_buffers(&self) -> proto::Buffers {
+        self.documents
+            .all()
+            .into_iter()
+            .filter(|(_, state)| matches!(state.as_ref(), DocumentKind::Proto(_)))
+            .filter_map(|(uri, state)| {
+                let path = uri.to_file_path().ok()?;
+                let path = std::fs::canonicalize(&path).unwrap_or(path);
+                Some((path, state.source().to_string()))
+            })
+            .collect()
+    }
+
+    /// Rebuild the proto registry with the current `.proto` buffers.
+    ///
+    /// Returns whether the definitions or compile errors changed, in which
+    /// case the environment is rebuilt as well. Runs while holding
+    /// `rebuilding`, so the configuration it reads is the one it replaces.
+    fn rebuild_protos(&self) -> bool {
+        let Some((_, settings_dir)) = self.base_settings.get() else {
+            return false;
+        };
+        let _rebuilding = self.rebuilding.lock();
+        let (settings, inputs, previous) = match self.config.read() {
+            Ok(config) => (
+                config.settings.clone(),
+                config.proto_inputs.clone(),
+                config.proto_registry.clone(),
+            ),
+            Err(_) => return false,
+        };
+
+        let protos = settings::build_protos(&inputs, &self.proto_buffers());
+        let unchanged = match (&protos.registry, &previous) {
+            (Some(new), Some(old)) => proto::same_definitions(new.pool(), old.pool()),
+            (None, None) => true,
+            _ => false,
+        };
+        if unchanged
+            && self
+                .config
+                .read()
+                .is_ok_and(|config| config.proto_errors == protos.errors)
+        {
+            return false;
+        }
+
+        let env = Arc::new(settings::build_env_with_registry(
+            &settings,
+            settings_dir,
+            protos.registry.clone(),
+        ));
+        if let Ok(mut config) = self.config.write() {
+            config.proto_registry = protos.registry;
+            config.proto_errors = protos.errors;
+            config.env = Some(env);
+            config.generation += 1;
+        }
+        true
+    }
+
+    /// Request the `celsp` section through `workspace/configuration`.
+    async fn fetch_workspace_settings(&self) -> Option<Settings> {
+        if !self.supports_configuration.get().copied().unwrap_or(false) {
+            return None;
+        }
+
+        let item = ConfigurationItem {
+            scope_uri: None,
+            section: Some("celsp".to_string()),
+        };
+        match self.client.configuration(vec![item]).await {
+            Ok(values) => values.first().and_then(settings::parse_client_settings),
+            Err(e) => {
+                warn!("workspace/configuration request failed: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Re-analyze every open document after a configuration change.
+    async fn reanalyze_documents(&self) {
+        for (uri, state) in self.documents.all() {
+            self.on_document_change(uri, state.source().to_string(), state.version())
+                .await;
+        }
+        if self.pulls_diagnostics() {
+            let _ = self.client.workspace_diagnostic_refresh().await;
+        }
+        let _ = self.client.inlay_hint_refresh().await;
+    }
+
+    fn pulls_diagnostics(&self) -> bool {
+        self.pull_diagnostics.get().copied().unwrap_or(false)
+    }
+
+    /// Analyze a document with the current configuration.
+    fn analyze(&self, uri: &Url, source: String, version: i32) -> DocumentKind {
+        match self.config.read() {
+            Ok(config) => config.analyze(uri, source, version),
+            Err(_) => Config::default().analyze(uri, source, version),
+        }
+    }
+
+    /// Compute all diagnostics of an analyzed document.
+    fn diagnostics(&self, uri: &Url, state: &DocumentKind) -> Vec<Diagnostic> {
+        match self.config.read() {
+            Ok(config) => config.diagnostics(uri, state),
+            Err(_) => Vec::new(),
         }
     }
 
     /// Parse document and publish diagnostics.
     async fn on_document_change(&self, uri: Url, text: String, version: i32) {
-        let registry = self.proto_registry.get().and_then(|r| r.clone());
-        let env = self.env.get();
-        let state = self
-            .documents
-            .open(uri.clone(), text, version, registry.as_ref(), env);
+        let state = self.analyze(&uri, text, version);
+        let state = self.documents.insert(uri.clone(), state);
         self.publish_diagnostics_for(&uri, &state).await;
     }
 
-    /// Publish diagnostics for a document.
-    async fn publish_diagnostics_for(&self, uri: &Url, state: &DocumentKind) {
-        let (diagnostics, version) = match state {
-            DocumentKind::Cel(cel_state) => {
-                let diags = lsp::to_diagnostics(
-                    &cel_state.errors,
-                    cel_state.check_errors(),
-                    &cel_state.line_index,
-                );
-                (diags, cel_state.version)
+    /// Analyze an edited document.
+    ///
+    /// Edits to `.proto` buffers rebuild the proto registry once they pause,
+    /// and when their definitions change every open document is checked again.
+    async fn on_buffer_change(self: &Arc<Self>, uri: Url, text: String, version: i32) {
+        let is_proto = is_proto_file(&uri);
+        self.on_document_change(uri, text, version).await;
+        if is_proto {
+            self.schedule_proto_rebuild();
+        }
+    }
+
+    /// Rebuild the proto registry after [`PROTO_REBUILD_DELAY`], unless
+    /// another `.proto` buffer changes in the meantime.
+    ///
+    /// The wait runs in its own task so that the notification that triggered
+    /// it does not hold one of the server's concurrent request slots, and the
+    /// compile runs off the async runtime.
+    fn schedule_proto_rebuild(self: &Arc<Self>) {
+        let edit = self.proto_edits.fetch_add(1, Ordering::SeqCst) + 1;
+        let server = Arc::clone(self);
+        tokio::spawn(async move {
+            tokio::time::sleep(PROTO_REBUILD_DELAY).await;
+            if server.proto_edits.load(Ordering::SeqCst) != edit {
+                return;
             }
-            DocumentKind::Proto(proto_state) => {
-                let diags = lsp::proto_to_diagnostics(proto_state);
-                (diags, proto_state.version)
+            let rebuilder = Arc::clone(&server);
+            match tokio::task::spawn_blocking(move || rebuilder.rebuild_protos()).await {
+                Ok(true) => server.reanalyze_documents().await,
+                Ok(false) => {}
+                Err(e) => warn!("proto rebuild failed: {}", e),
             }
-        };
+        });
+    }
+
+    /// Forward log messages to the client as `window/logMessage`.
+    fn start_client_logging(&self) {
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        logging::set_client_sink(sender);
+
+        let client = self.client.clone();
+        tokio::spawn(async move {
+            while let Some((level, message)) = receiver.recv().await {
+                client.log_message(level.message_type(), message).await;
+            }
+        });
+    }
+
+    /// Publish diagnostics for a document.
+    ///
+    /// Skipped when the client pulls diagnostics instead.
+    async fn publish_diagnostics_for(&self, uri: &Url, state: &DocumentKind) {
+        if self.pulls_diagnostics() {
+            return;
+        }
+        let diagnostics = self.diagnostics(uri, state);
 
         self.client
-            .publish_diagnostics(uri.clone(), diagnostics, Some(version))
+            .publish_diagnostics(uri.clone(), diagnostics, Some(state.version()))
             .await;
     }
 }
 
+impl Config {
+    /// Analyze a document.
+    fn analyze(&self, uri: &Url, source: String, version: i32) -> DocumentKind {
+        let path = uri.to_file_path().ok();
+        DocumentKind::new(
+            uri,
+            source,
+            version,
+            self.proto_registry.as_ref(),
+            self.env.as_ref(),
+            self.versions,
+            self.expected_types.for_path(path.as_deref()),
+        )
+        .with_budget(&self.cost_budget)
+        .with_limits(&self.complexity_limits)
+    }
+
+    /// Compute all diagnostics of an analyzed document.
+    fn diagnostics(&self, uri: &Url, state: &DocumentKind) -> Vec<Diagnostic> {
+        let options = self.settings.diagnostics();
+        let mut diagnostics = lsp::document_diagnostics(state, &options);
+        if let Ok(path) = uri.to_file_path() {
+            diagnostics.extend(lsp::compile_diagnostics(
+                &self.proto_errors,
+                &path,
+                state,
+                &options,
+            ));
+        }
+        diagnostics
+    }
+
+    /// Build a pull diagnostic report for a document.
+    ///
+    /// Open documents are reported from their in-memory state; other files are
+    /// read from disk. Returns `None` when the file cannot be read, and an
+    /// unchanged report when `previous_result_id` still matches.
+    fn diagnostic_report(
+        &self,
+        uri: &Url,
+        open: Option<Arc<DocumentKind>>,
+        previous_result_id: Option<&str>,
+    ) -> Option<DocumentDiagnosticReportKind> {
+        let source = match &open {
+            Some(state) => state.source().to_string(),
+            None => std::fs::read_to_string(uri.to_file_path().ok()?).ok()?,
+        };
+
+        let result_id = lsp::diagnostic_result_id(&source, self.generation);
+        if previous_result_id == Some(result_id.as_str()) {
+            return Some(DocumentDiagnosticReportKind::Unchanged(
+                UnchangedDocumentDiagnosticReport { result_id },
+            ));
+        }
+
+        let state = match open {
+            Some(state) => state,
+            None => Arc::new(self.analyze(uri, source, 0)),
+        };
+        Some(DocumentDiagnosticReportKind::Full(
+            FullDocumentDiagnosticReport {
+                result_id: Some(result_id),
+                items: self.diagnostics(uri, &state),
+            },
+        ))
+    }
+
+    /// Build the pull diagnostic reports of every source file under `root` and
+    /// of the open documents, skipping files whose result ID is unchanged.
+    fn workspace_reports(
+        &self,
+        root: Option<&Path>,
+        open: HashMap<Url, Arc<DocumentKind>>,
+        previous: HashMap<Url, String>,
+    ) -> Vec<WorkspaceDocumentDiagnosticReport> {
+        // Every source file under the workspace root, plus open documents outside it
+        let mut uris: Vec<Url> = root
+            .map(workspace::source_files)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|path| Url::from_file_path(path).ok())
+            .collect();
+        let on_disk: HashSet<Url> = uris.iter().cloned().collect();
+        uris.extend(open.keys().filter(|uri| !on_disk.contains(*uri)).cloned());
+
+        uris.into_iter()
+            .filter_map(|uri| {
+                let state = open.get(&uri).cloned();
+                let version = state.as_ref().map(|s| s.version() as i64);
+                let previous = previous.get(&uri).map(String::as_str);
+                Some(match self.diagnostic_report(&uri, state, previous)? {
+                    DocumentDiagnosticReportKind::Full(full) => {
+                        WorkspaceDocumentDiagnosticReport::Full(
+                            WorkspaceFullDocumentDiagnosticReport {
+                                uri,
+                                version,
+                                full_document_diagnostic_report: full,
+                            },
+                        )
+                    }
+                    DocumentDiagnosticReportKind::Unchanged(unchanged) => {
+                        WorkspaceDocumentDiagnosticReport::Unchanged(
+                            WorkspaceUnchangedDocumentDiagnosticReport {
+                                uri,
+                                version,
+                                unchanged_document_diagnostic_report: unchanged,
+                            },
+                        )
+                    }
+                })
+            })
+            .collect()
+    }
+}
+
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
     async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        logging::set_client_trace(params.trace.unwrap_or(TraceValue::Off));
+        self.0.start_client_logging();
+
         // Extract workspace root from params
         let workspace_root = params
             .workspace_folders
@@ -87,17 +492,47 @@ impl LanguageServer for Backend {
                 params.root_uri.as_ref()?.to_file_path().ok()
             });
 
-        if let Some(root) = workspace_root {
-            let _ = self.workspace_root.set(root.clone());
-
-            // Discover settings by walking up the directory tree
-            let (settings, settings_dir) = settings::discover_settings(&root);
-            let registry = settings::load_proto_registry(&settings, &settings_dir);
-            let env = Arc::new(settings::build_env_with_protos(&settings, &settings_dir));
-            let _ = self.proto_registry.set(registry);
-            let _ = self.env.set(env);
-        } else {
-            let _ = self.proto_registry.set(None);
+        if let Some(root) = &workspace_root {
+            let _ = self.0.workspace_root.set(root.clone());
+        }
+
+        let init_settings = params
+            .initialization_options
+            .as_ref()
+            .and_then(settings::parse_client_settings);
+        let supports_configuration = params
+            .capabilities
+            .workspace
+            .as_ref()
+            .and_then(|w| w.configuration)
+            .unwrap_or(false);
+        let _ = self.0.supports_configuration.set(supports_configuration);
+        let pull_diagnostics = params
+            .capabilities
+            .text_document
+            .as_ref()
+            .is_some_and(|t| t.diagnostic.is_some());
+        let _ = self.0.pull_diagnostics.set(pull_diagnostics);
+
+        // An explicit --settings path wins; otherwise discover settings by
+        // walking up the directory tree from the workspace root
+        let discovered = match (&self.0.settings_path, &workspace_root) {
+            (Some(path), _) => match settings::load_settings_file(path) {
+                Ok(loaded) => Some(loaded),
+                Err(e) => return Err(tower_lsp::jsonrpc::Error::invalid_params(e)),
+            },
+            (None, Some(root)) => Some(settings::discover_settings(root)),
+            (None, None) => init_settings
+                .is_some()
+                .then(|| (Settings::default(), PathBuf::from("."))),
+        };
+
+        if let Some((mut settings, settings_dir)) = discovered {
+            if let Some(overrides) = init_settings {
+                settings.merge(overrides);
+            }
+            let _ = self.0.base_settings.set((settings, settings_dir));
+            self.0.reload_settings(None).await;
         }
 
         Ok(InitializeResult {
@@ -111,6 +546,18 @@ impl LanguageServer for Backend {
                     resolve_provider: Some(false),
                     ..Default::default()
                 }),
+                inlay_hint_provider: Some(OneOf::Left(true)),
+                code_lens_provider: Some(CodeLensOptions {
+                    resolve_provider: Some(false),
+                }),
+                diagnostic_provider: Some(DiagnosticServerCapabilities::Options(
+                    DiagnosticOptions {
+                        identifier: Some("celsp".to_string()),
+                        inter_file_dependencies: false,
+                        workspace_diagnostics: true,
+                        work_done_progress_options: WorkDoneProgressOptions::default(),
+                    },
+                )),
                 semantic_tokens_provider: Some(
                     SemanticTokensServerCapabilities::SemanticTokensOptions(
                         SemanticTokensOptions {
@@ -128,9 +575,30 @@ impl LanguageServer for Backend {
     }
 
     async fn initialized(&self, _: InitializedParams) {
-        self.client
+        self.0
+            .client
             .log_message(MessageType::INFO, "CEL language server initialized")
             .await;
+
+        if let Some(workspace_settings) = self.0.fetch_workspace_settings().await {
+            self.0.reload_settings(Some(workspace_settings)).await;
+            self.0.reanalyze_documents().await;
+        }
+    }
+
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        // Prefer pushed settings; clients using the pull model send null here
+        let pushed = match params.settings {
+            Value::Null => None,
+            ref value => settings::parse_client_settings(value),
+        };
+        let workspace_settings = match pushed {
+            Some(settings) => Some(settings),
+            None => self.0.fetch_workspace_settings().await,
+        };
+
+        self.0.reload_settings(workspace_settings).await;
+        self.0.reanalyze_documents().await;
     }
 
     async fn shutdown(&self) -> Result<()> {
@@ -138,30 +606,40 @@ impl LanguageServer for Backend {
     }
 
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
-        self.on_document_change(
-            params.text_document.uri,
-            params.text_document.text,
-            params.text_document.version,
-        )
-        .await;
+        self.0
+            .on_buffer_change(
+                params.text_document.uri,
+                params.text_document.text,
+                params.text_document.version,
+            )
+            .await;
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
         // We use FULL sync, so there's exactly one change with the full text
         if let Some(change) = params.content_changes.into_iter().next() {
-            self.on_document_change(
-                params.text_document.uri,
-                change.text,
-                params.text_document.version,
-            )
-            .await;
+            self.0
+                .on_buffer_change(
+                    params.text_document.uri,
+                    change.text,
+                    params.text_document.version,
+                )
+                .await;
         }
     }
 
     async fn did_close(&self, params: DidCloseTextDocumentParams) {
-        self.documents.close(&params.text_document.uri);
+        self.0.documents.close(&params.text_document.uri);
+        // The file on disk takes over from the closed buffer
+        if is_proto_file(&params.text_document.uri) {
+            self.0.schedule_proto_rebuild();
+        }
+        if self.0.pulls_diagnostics() {
+            return;
+        }
         // Clear diagnostics
-        self.client
+        self.0
+            .client
             .publish_diagnostics(params.text_document.uri, vec![], None)
             .await;
     }
@@ -170,7 +648,7 @@ impl LanguageServer for Backend {
         let uri = &params.text_document_position_params.text_document.uri;
         let position = params.text_document_position_params.position;
 
-        let Some(doc) = self.documents.get(uri) else {
+        let Some(doc) = self.0.documents.get(uri) else {
             return Ok(None);
         };
 
@@ -183,10 +661,14 @@ impl LanguageServer for Backend {
                     &state.line_index,
                     ast,
                     state.check_result.as_ref(),
+                    state.env.proto_registry(),
+                    state.cost().as_ref(),
                     position,
                 ))
             }
-            DocumentKind::Proto(state) => Ok(lsp::hover_at_position_proto(state, position)),
+            DocumentKind::Proto(state) | DocumentKind::Yaml(state) => {
+                Ok(lsp::hover_at_position_proto(state, position))
+            }
         }
     }
 
@@ -194,8 +676,8 @@ impl LanguageServer for Backend {
         let uri = &params.text_document_position.text_document.uri;
         let position = params.text_document_position.position;
 
-        let Some(doc) = self.documents.get(uri) else {
-            eprintln!("[completion] no document found for {}", uri);
+        let Some(doc) = self.0.documents.get(uri) else {
+            debug!(target: "celsp::completion", "no document found for {}", uri);
             return Ok(None);
         };
 
@@ -206,31 +688,14 @@ impl LanguageServer for Backend {
                 &state.env,
                 position,
             )),
-            DocumentKind::Proto(state) => {
-                let host_offset = state.line_index.position_to_offset(position);
-                eprintln!(
-                    "[completion] proto position={:?} host_offset={:?} regions={}",
-                    position,
-                    host_offset,
-                    state.regions.len()
-                );
-                if let Some(offset) = host_offset {
-                    for (i, r) in state.regions.iter().enumerate() {
-                        let start = r.mapper.host_offset();
-                        let end = start + r.mapper.host_length(r.region.source.len());
-                        eprintln!(
-                            "[completion]   region[{}]: host=[{}..{}] source={:?} contains={}",
-                            i,
-                            start,
-                            end,
-                            r.region.source,
-                            r.contains_host_offset(offset)
-                        );
-                    }
+            DocumentKind::Proto(state) | DocumentKind::Yaml(state) => {
+                if tracing::enabled!(target: "celsp::completion", tracing::Level::TRACE) {
+                    log_proto_regions(state, position);
                 }
                 let result = lsp::completion_at_position_proto(state, position);
-                eprintln!(
-                    "[completion] result items={}",
+                trace!(
+                    target: "celsp::completion",
+                    "result items={}",
                     result
                         .as_ref()
                         .map(|r| match r {
@@ -244,13 +709,102 @@ impl LanguageServer for Backend {
         }
     }
 
+    async fn diagnostic(
+        &self,
+        params: DocumentDiagnosticParams,
+    ) -> Result<DocumentDiagnosticReportResult> {
+        let uri = &params.text_document.uri;
+        let report = self
+            .0
+            .config
+            .read()
+            .ok()
+            .and_then(|config| {
+                config.diagnostic_report(
+                    uri,
+                    self.0.documents.get(uri),
+                    params.previous_result_id.as_deref(),
+                )
+            })
+            .unwrap_or_else(|| DocumentDiagnosticReportKind::Full(Default::default()));
+
+        let report = match report {
+            DocumentDiagnosticReportKind::Full(full) => {
+                DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
+                    related_documents: None,
+                    full_document_diagnostic_report: full,
+                })
+            }
+            DocumentDiagnosticReportKind::Unchanged(unchanged) => {
+                DocumentDiagnosticReport::Unchanged(RelatedUnchangedDocumentDiagnosticReport {
+                    related_documents: None,
+                    unchanged_document_diagnostic_report: unchanged,
+                })
+            }
+        };
+        Ok(DocumentDiagnosticReportResult::Report(report))
+    }
+
+    async fn workspace_diagnostic(
+        &self,
+        params: WorkspaceDiagnosticParams,
+    ) -> Result<WorkspaceDiagnosticReportResult> {
+        let previous: HashMap<Url, String> = params
+            .previous_result_ids
+            .into_iter()
+            .map(|p| (p.uri, p.value))
+            .collect();
+
+        // Files are read and analyzed off the async runtime, against a
+        // snapshot of the configuration and the open documents
+        let Ok(config) = self.0.config.read().map(|config| config.clone()) else {
+            return Ok(WorkspaceDiagnosticReportResult::Report(Default::default()));
+        };
+        let root = self.0.workspace_root.get().cloned();
+        let open: HashMap<Url, Arc<DocumentKind>> = self.0.documents.all().into_iter().collect();
+        let items = tokio::task::spawn_blocking(move || {
+            config.workspace_reports(root.as_deref(), open, previous)
+        })
+        .await
+        .unwrap_or_else(|e| {
+            warn!("workspace diagnostics failed: {}", e);
+            Vec::new()
+        });
+
+        Ok(WorkspaceDiagnosticReportResult::Report(
+            WorkspaceDiagnosticReport { items },
+        ))
+    }
+
+    async fn inlay_hint(&self, params: InlayHintParams) -> Result<Option<Vec<InlayHint>>> {
+        let enabled = match self.0.config.read() {
+            Ok(config) => config.settings.inlay_hints().result_types_enabled(),
+            Err(_) => false,
+        };
+        if !enabled {
+            return Ok(None);
+        }
+
+        let Some(doc) = self.0.documents.get(&params.text_document.uri) else {
+            return Ok(None);
+        };
+        Ok(Some(lsp::inlay_hints(&doc, params.range)))
+    }
+
+    async fn code_lens(&self, params: CodeLensParams) -> Result<Option<Vec<CodeLens>>> {
+        let Some(doc) = self.0.documents.get(&params.text_document.uri) else {
+            return Ok(None);
+        };
+        Ok(Some(lsp::cost_lenses(&doc)))
+    }
+
     async fn semantic_tokens_full(
         &self,
         params: SemanticTokensParams,
     ) -> Result<Option<SemanticTokensResult>> {
         let uri = &params.text_document.uri;
 
-        let Some(doc) = self.documents.get(uri) else {
+        let Some(doc) = self.0.documents.get(uri) else {
             return Ok(None);
         };
 
@@ -259,9 +813,14 @@ impl LanguageServer for Backend {
                 let Some(ast) = state.ast() else {
                     return Ok(None);
                 };
-                lsp::tokens_for_ast(&state.line_index, ast)
+                let deprecated: Vec<_> = state
+                    .deprecations()
+                    .into_iter()
+                    .map(|problem| problem.span)
+                    .collect();
+                lsp::tokens_for_ast(&state.line_index, ast, &deprecated)
             }
-            DocumentKind::Proto(state) => lsp::tokens_for_proto(state),
+            DocumentKind::Proto(state) | DocumentKind::Yaml(state) => lsp::tokens_for_proto(state),
         };
 
         Ok(Some(SemanticTokensResult::Tokens(SemanticTokens {
@@ -272,15 +831,102 @@ impl LanguageServer for Backend {
 }
 
 pub fn create_service() -> (LspService<Backend>, tower_lsp::ClientSocket) {
-    LspService::new(Backend::new)
+    create_service_with_settings(None)
+}
+
+/// Create the service with an explicit settings.toml path that overrides discovery.
+pub fn create_service_with_settings(
+    settings_path: Option<PathBuf>,
+) -> (LspService<Backend>, tower_lsp::ClientSocket) {
+    LspService::build(move |client| Backend::new(client, settings_path.clone()))
+        .custom_method("$/setTrace", Backend::set_trace)
+        .finish()
+}
+
+/// Log the host ranges of a proto document's CEL regions around a completion request.
+fn log_proto_regions(state: &EmbeddedDocumentState, position: Position) {
+    let host_offset = state.line_index.position_to_offset(position);
+    trace!(
+        target: "celsp::completion",
+        "proto position={:?} host_offset={:?} regions={}",
+        position,
+        host_offset,
+        state.regions.len()
+    );
+    let Some(offset) = host_offset else {
+        return;
+    };
+    for (i, r) in state.regions.iter().enumerate() {
+        let start = r.mapper.host_offset();
+        let end = start + r.mapper.host_length(r.region.source.len());
+        trace!(
+            target: "celsp::completion",
+            "  region[{}]: host=[{}..{}] source={:?} contains={}",
+            i,
+            start,
+            end,
+            r.region.source,
+            r.contains_host_offset(offset)
+        );
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::make_test_dir;
 
     #[test]
     fn service_can_be_created() {
         let (_service, _socket) = create_service();
     }
+
+    #[test]
+    fn workspace_reports_cover_files_and_open_documents_once() {
+        let dir = make_test_dir(
+            "workspace-reports",
+            &[("a.cel", "1 + 1"), ("b.cel", "undeclared")],
+        );
+
+        let config = Config::default();
+        let uri = |name: &str| Url::from_file_path(dir.join(name)).unwrap();
+        let open = HashMap::from([
+            (
+                uri("b.cel"),
+                Arc::new(config.analyze(&uri("b.cel"), "2".to_string(), 3)),
+            ),
+            (
+                uri("c.cel"),
+                Arc::new(config.analyze(&uri("c.cel"), "3".to_string(), 1)),
+            ),
+        ]);
+        let a_id = lsp::diagnostic_result_id("1 + 1", config.generation);
+        let previous = HashMap::from([(uri("a.cel"), a_id)]);
+
+        let mut reports: Vec<_> = config
+            .workspace_reports(Some(&dir), open, previous)
+            .into_iter()
+            .map(|report| match report {
+                WorkspaceDocumentDiagnosticReport::Full(full) => (
+                    full.uri,
+                    full.version,
+                    Some(full.full_document_diagnostic_report.items),
+                ),
+                WorkspaceDocumentDiagnosticReport::Unchanged(unchanged) => {
+                    (unchanged.uri, unchanged.version, None)
+                }
+            })
+            .collect();
+        reports.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            reports,
+            vec![
+                (uri("a.cel"), None, None),
+                // The open buffer is reported instead of the file on disk
+                (uri("b.cel"), Some(3), Some(vec![])),
+                (uri("c.cel"), Some(1), Some(vec![])),
+            ]
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
@@ -0,0 +1,362 @@
+//! Logging for the celsp binary, built on `tracing`.
+//!
+//! Events are logged with the `tracing` macros and carry a level and a target
+//! (the module path of the call site by default, e.g. `celsp::settings`).
+//! They are written to:
+//! - stderr, or a log file configured with `--log-file`, filtered by the
+//!   `--log-level` directives (e.g. `info,completion=trace`)
+//! - the client via `window/logMessage`, filtered by the client's `$/setTrace`
+//!   value: `off` forwards warnings and errors, `messages` adds info, and
+//!   `verbose` forwards everything
+//!
+//! Stdout is never used since it carries the LSP stream for the stdio transport.
+
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Mutex, RwLock};
+
+use tokio::sync::mpsc::UnboundedSender;
+use tower_lsp::lsp_types::{MessageType, TraceValue};
+use tracing::field::{Field, Visit};
+use tracing::subscriber::Interest;
+use tracing::{Event, Level, Metadata, Subscriber};
+use tracing_subscriber::filter::{filter_fn, LevelFilter};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::layer::{Context, Filter, SubscriberExt};
+use tracing_subscriber::Layer;
+
+/// Severity level of a log message, ordered from least to most verbose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    /// The `window/logMessage` type for this level.
+    pub fn message_type(self) -> MessageType {
+        match self {
+            LogLevel::Error => MessageType::ERROR,
+            LogLevel::Warn => MessageType::WARNING,
+            LogLevel::Info => MessageType::INFO,
+            LogLevel::Debug | LogLevel::Trace => MessageType::LOG,
+        }
+    }
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => LevelFilter::ERROR,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+impl From<&Level> for LogLevel {
+    fn from(level: &Level) -> Self {
+        match *level {
+            Level::ERROR => LogLevel::Error,
+            Level::WARN => LogLevel::Warn,
+            Level::INFO => LogLevel::Info,
+            Level::DEBUG => LogLevel::Debug,
+            Level::TRACE => LogLevel::Trace,
+        }
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "error" => Ok(LogLevel::Error),
+            "warn" | "warning" => Ok(LogLevel::Warn),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            "trace" => Ok(LogLevel::Trace),
+            other => Err(format!("unknown log level '{}'", other)),
+        }
+    }
+}
+
+/// Per-target level filter parsed from `--log-level`.
+///
+/// The syntax is a comma-separated list of directives, each either a bare
+/// level (the default) or `target=level`. Targets match their own module path
+/// and every module below it; the `celsp::` prefix may be omitted, so
+/// `completion=trace` applies to `celsp::completion`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFilter {
+    default: LogLevel,
+    targets: Vec<(String, LogLevel)>,
+}
+
+impl LogFilter {
+    /// A filter that applies one level to every target.
+    pub fn new(default: LogLevel) -> Self {
+        Self {
+            default,
+            targets: Vec::new(),
+        }
+    }
+
+    /// The most verbose level enabled for a target.
+    pub fn level_for(&self, target: &str) -> LogLevel {
+        self.targets
+            .iter()
+            .filter(|(prefix, _)| {
+                target == prefix
+                    || target
+                        .strip_prefix(prefix.as_str())
+                        .is_some_and(|rest| rest.starts_with("::"))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default)
+    }
+
+    /// The most verbose level enabled for any target.
+    fn max_level(&self) -> LogLevel {
+        self.targets
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, Ord::max)
+    }
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        Self::new(DEFAULT_LEVEL)
+    }
+}
+
+impl FromStr for LogFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut filter = LogFilter::default();
+        for directive in s.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((target, level)) => {
+                    let target = target.trim();
+                    let target = if target == "celsp" || target.starts_with("celsp::") {
+                        target.to_string()
+                    } else {
+                        format!("celsp::{}", target)
+                    };
+                    filter.targets.push((target, level.trim().parse()?));
+                }
+                None => filter.default = directive.parse()?,
+            }
+        }
+        Ok(filter)
+    }
+}
+
+/// Level of the default filter.
+const DEFAULT_LEVEL: LogLevel = LogLevel::Warn;
+
+/// Install the global subscriber, writing to `log_file` or else to stderr.
+///
+/// The log file is appended to. Only the first call has an effect.
+pub fn init(filter: LogFilter, log_file: Option<&Path>) -> std::io::Result<()> {
+    let writer = match log_file {
+        Some(path) => BoxMakeWriter::new(Mutex::new(
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?,
+        )),
+        None => BoxMakeWriter::new(std::io::stderr),
+    };
+    let max_level = filter.max_level();
+    let local = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(false)
+        .with_filter(
+            filter_fn(move |metadata| {
+                LogLevel::from(metadata.level()) <= filter.level_for(metadata.target())
+            })
+            .with_max_level_hint(max_level),
+        );
+    let subscriber = tracing_subscriber::registry()
+        .with(local)
+        .with(ClientLayer.with_filter(ClientFilter));
+    let _ = tracing::subscriber::set_global_default(subscriber);
+    Ok(())
+}
+
+/// The client's log channel and the most verbose level forwarded to it.
+struct Client {
+    sink: Option<UnboundedSender<(LogLevel, String)>>,
+    level: LogLevel,
+}
+
+static CLIENT: RwLock<Client> = RwLock::new(Client {
+    sink: None,
+    level: LogLevel::Warn,
+});
+
+/// Install the channel used to forward `(level, message)` pairs to the client.
+pub fn set_client_sink(sink: UnboundedSender<(LogLevel, String)>) {
+    if let Ok(mut client) = CLIENT.write() {
+        client.sink = Some(sink);
+    }
+}
+
+/// Update the level forwarded to the client from its `$/setTrace` value.
+pub fn set_client_trace(trace: TraceValue) {
+    if let Ok(mut client) = CLIENT.write() {
+        client.level = client_level_for(trace);
+    }
+}
+
+/// Map a client trace value to the most verbose level forwarded to it.
+fn client_level_for(trace: TraceValue) -> LogLevel {
+    match trace {
+        TraceValue::Off => LogLevel::Warn,
+        TraceValue::Messages => LogLevel::Info,
+        TraceValue::Verbose => LogLevel::Trace,
+    }
+}
+
+/// Whether `target` belongs to this crate.
+fn is_own_target(target: &str) -> bool {
+    target == "celsp" || target.starts_with("celsp::")
+}
+
+/// Enables this crate's events at the level the client traces, once it has a
+/// channel.
+///
+/// Events of dependencies are never forwarded: tower-lsp traces every message
+/// it sends, including the log messages forwarded here. The level changes at
+/// runtime, so the interest of call sites is never cached.
+struct ClientFilter;
+
+impl<S> Filter<S> for ClientFilter {
+    fn enabled(&self, metadata: &Metadata<'_>, _: &Context<'_, S>) -> bool {
+        is_own_target(metadata.target())
+            && CLIENT.read().is_ok_and(|client| {
+                client.sink.is_some() && LogLevel::from(metadata.level()) <= client.level
+            })
+    }
+
+    fn callsite_enabled(&self, _: &'static Metadata<'static>) -> Interest {
+        Interest::sometimes()
+    }
+}
+
+/// Forwards events to the client as `[target] message`.
+struct ClientLayer;
+
+impl<S: Subscriber> Layer<S> for ClientLayer {
+    fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+        let mut message = MessageVisitor::default();
+        event.record(&mut message);
+        let metadata = event.metadata();
+        if let Ok(client) = CLIENT.read() {
+            if let Some(sink) = &client.sink {
+                let _ = sink.send((
+                    LogLevel::from(metadata.level()),
+                    format!("[{}] {}", metadata.target(), message.0),
+                ));
+            }
+        }
+    }
+}
+
+/// Collects the formatted `message` field of an event.
+#[derive(Default)]
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{:?}", value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_levels() {
+        assert_eq!("error".parse::<LogLevel>(), Ok(LogLevel::Error));
+        assert_eq!("WARNING".parse::<LogLevel>(), Ok(LogLevel::Warn));
+        assert_eq!("trace".parse::<LogLevel>(), Ok(LogLevel::Trace));
+        assert!("loud".parse::<LogLevel>().is_err());
+    }
+
+    #[test]
+    fn levels_are_ordered_by_verbosity() {
+        assert!(LogLevel::Error < LogLevel::Warn);
+        assert!(LogLevel::Debug < LogLevel::Trace);
+    }
+
+    #[test]
+    fn filter_with_bare_level() {
+        let filter: LogFilter = "debug".parse().unwrap();
+        assert_eq!(filter.level_for("celsp::settings"), LogLevel::Debug);
+        assert_eq!(filter.max_level(), LogLevel::Debug);
+    }
+
+    #[test]
+    fn filter_with_target_directives() {
+        let filter: LogFilter = "info,completion=trace,celsp::settings=error"
+            .parse()
+            .unwrap();
+        assert_eq!(filter.level_for("celsp"), LogLevel::Info);
+        assert_eq!(filter.level_for("celsp::completion"), LogLevel::Trace);
+        assert_eq!(
+            filter.level_for("celsp::completion::proto"),
+            LogLevel::Trace
+        );
+        assert_eq!(filter.level_for("celsp::completions"), LogLevel::Info);
+        assert_eq!(filter.level_for("celsp::settings"), LogLevel::Error);
+        assert_eq!(filter.max_level(), LogLevel::Trace);
+    }
+
+    #[test]
+    fn filter_rejects_unknown_levels() {
+        assert!("info,completion=loud".parse::<LogFilter>().is_err());
+    }
+
+    #[test]
+    fn forwards_own_events_at_the_client_trace_level() {
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        set_client_sink(sender);
+        let subscriber = tracing_subscriber::registry().with(ClientLayer.with_filter(ClientFilter));
+        tracing::subscriber::with_default(subscriber, || {
+            set_client_trace(TraceValue::Verbose);
+            tracing::trace!(target: "tower_lsp::codec", "-> {}", "window/logMessage");
+            set_client_trace(TraceValue::Messages);
+            tracing::info!(target: "celsp::settings", "loaded {}", "settings.toml");
+            tracing::debug!(target: "celsp::settings", "hidden");
+        });
+        assert_eq!(
+            receiver.try_recv().ok(),
+            Some((
+                LogLevel::Info,
+                "[celsp::settings] loaded settings.toml".to_string()
+            ))
+        );
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn client_trace_controls_forwarded_level() {
+        assert_eq!(client_level_for(TraceValue::Off), LogLevel::Warn);
+        assert_eq!(client_level_for(TraceValue::Messages), LogLevel::Info);
+        assert_eq!(client_level_for(TraceValue::Verbose), LogLevel::Trace);
+    }
+}
@@ -0,0 +1,148 @@
+//! Code lenses showing the estimated cost of CEL expressions.
+
+use tower_lsp::lsp_types::{CodeLens, Command, Range};
+
+use crate::analysis::{Cost, CostBudget, CostEstimate};
+use crate::document::{DocumentKind, LineIndex};
+
+/// Compute a cost lens for every expression of a document.
+///
+/// A lens is placed above each root expression (the whole `.cel` file, or
+/// each CEL region of a host document) once it parses.
+pub fn cost_lenses(state: &DocumentKind) -> Vec<CodeLens> {
+    match state {
+        DocumentKind::Cel(cel_state) => cel_state
+            .ast()
+            .zip(cel_state.cost())
+            .map(|(ast, cost)| {
+                cost_lens(
+                    &cost,
+                    ast.id,
+                    &cel_state.budget,
+                    &cel_state.line_index,
+                    ast.span.start,
+                )
+            })
+            .into_iter()
+            .collect(),
+        DocumentKind::Proto(proto_state) | DocumentKind::Yaml(proto_state) => proto_state
+            .regions
+            .iter()
+            .filter_map(|region_state| {
+                let ast = region_state.ast.as_ref()?;
+                Some(cost_lens(
+                    &region_state.cost()?,
+                    ast.id,
+                    &region_state.budget,
+                    &proto_state.line_index,
+                    region_state.mapper.to_host(ast.span.start),
+                ))
+            })
+            .collect(),
+    }
+}
+
+/// Build the lens for a root expression starting at host offset `start`.
+fn cost_lens(
+    estimate: &CostEstimate,
+    id: i64,
+    budget: &CostBudget,
+    line_index: &LineIndex,
+    start: usize,
+) -> CodeLens {
+    let cost = estimate.cost(id).cloned().unwrap_or(Cost::Bounded(0));
+    let mut title = format!("estimated cost: {}", cost);
+    if let Some(limit) = budget.limit {
+        title.push_str(&format!(" (limit: {})", limit));
+    }
+    let position = line_index.offset_to_position(start);
+    CodeLens {
+        range: Range::new(position, position),
+        command: Some(Command {
+            title,
+            command: String::new(),
+            arguments: None,
+        }),
+        data: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::HostVersions;
+    use std::collections::HashMap;
+    use tower_lsp::lsp_types::{Position, Url};
+
+    fn titles(lenses: &[CodeLens]) -> Vec<(Position, &str)> {
+        lenses
+            .iter()
+            .map(|lens| {
+                (
+                    lens.range.start,
+                    lens.command.as_ref().unwrap().title.as_str(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lens_per_cel_file() {
+        let uri = Url::parse("file:///test.cel").unwrap();
+        let state = DocumentKind::new(
+            &uri,
+            "\n[1, 2].exists(x, x > 1)".to_string(),
+            1,
+            None,
+            None,
+            HostVersions::default(),
+            &[],
+        )
+        .with_budget(&CostBudget {
+            limit: Some(100),
+            max_sizes: HashMap::new(),
+        });
+
+        let lenses = cost_lenses(&state);
+        assert_eq!(
+            titles(&lenses),
+            vec![(Position::new(1, 0), "estimated cost: 21 (limit: 100)")]
+        );
+    }
+
+    #[test]
+    fn lens_per_proto_region_with_size_hints() {
+        let uri = Url::parse("file:///test.proto").unwrap();
+        let source = r#"
+message User {
+    string name = 1 [(buf.validate.field).string.max_len = 100, (buf.validate.field).cel = {
+        id: "name_prefix"
+        expression: "this.startsWith('a')"
+    }];
+    repeated string tags = 2 [(buf.validate.field).cel = {
+        id: "tags_short"
+        expression: "this.all(t, size(t) < 5)"
+    }];
+}
+"#;
+        let state = DocumentKind::new(
+            &uri,
+            source.to_string(),
+            1,
+            None,
+            None,
+            HostVersions::default(),
+            &[],
+        )
+        .with_budget(&CostBudget::default());
+
+        let lenses = cost_lenses(&state);
+        assert_eq!(
+            titles(&lenses),
+            vec![
+                (Position::new(4, 21), "estimated cost: 12"),
+                (Position::new(8, 21), "estimated cost: unbounded"),
+            ]
+        );
+    }
+}
@@ -4,13 +4,14 @@
 //! cursor position, re-parsing and re-checking the expression, then generating
 //! completion items based on the inferred type context.
 
-use cel_core::types::Expr;
+use cel_core::types::{Expr, OverloadDecl};
 use cel_core::{CelType, Env, SpannedExpr};
 use tower_lsp::lsp_types::*;
 
-use crate::document::{LineIndex, ProtoDocumentState};
+use crate::document::{EmbeddedDocumentState, LineIndex};
+use crate::kubernetes::get_kubernetes_builtin;
 use crate::protovalidate::PROTOVALIDATE_BUILTINS;
-use crate::types::{get_builtin, FunctionDef};
+use crate::types::{field_documentation, get_builtin, is_deprecated_field, FunctionDef};
 
 /// Placeholder identifier inserted at the cursor for re-checking.
 const PLACEHOLDER: &str = "__cel_complete__";
@@ -146,7 +147,7 @@ fn resolve_receiver_type(
 }
 
 /// Format a function overload as a detail string.
-fn format_overload_detail(overload: &cel_core::types::OverloadDecl) -> String {
+fn format_overload_detail(overload: &OverloadDecl) -> String {
     let args: Vec<String> = overload
         .arg_types()
         .iter()
@@ -181,14 +182,25 @@ fn member_completions(
                         .get_field_type(msg_name, &field_name)
                         .map(|t| t.display_name())
                         .unwrap_or_default();
+                    let documentation =
+                        field_documentation(registry, msg_name, &field_name).map(|value| {
+                            Documentation::MarkupContent(MarkupContent {
+                                kind: MarkupKind::Markdown,
+                                value,
+                            })
+                        });
+                    let tags = is_deprecated_field(registry, msg_name, &field_name)
+                        .then(|| vec![CompletionItemTag::DEPRECATED]);
                     items.push(CompletionItem {
                         label: field_name.clone(),
                         kind: Some(CompletionItemKind::FIELD),
+                        tags,
                         detail: if field_type.is_empty() {
                             None
                         } else {
                             Some(field_type)
                         },
+                        documentation,
                         sort_text: Some(format!("0_{}", field_name)),
                         ..Default::default()
                     });
@@ -223,7 +235,7 @@ fn member_completions(
         };
 
         // Look up documentation from builtins
-        let documentation = get_function_docs(name, is_proto);
+        let documentation = get_function_docs(name, std::slice::from_ref(*overload), is_proto);
 
         items.push(CompletionItem {
             label: name.to_string(),
@@ -271,7 +283,12 @@ fn identifier_completions(env: &Env, prefix: &str, is_proto: bool) -> Vec<Comple
         }
 
         // Look up documentation
-        let documentation = get_function_docs(name, is_proto);
+        let overloads = env
+            .functions()
+            .get(name)
+            .map(|f| f.overloads.as_slice())
+            .unwrap_or_default();
+        let documentation = get_function_docs(name, overloads, is_proto);
 
         items.push(CompletionItem {
             label: name.to_string(),
@@ -287,7 +304,7 @@ fn identifier_completions(env: &Env, prefix: &str, is_proto: bool) -> Vec<Comple
         if !prefix.is_empty() && !name.to_lowercase().starts_with(&prefix.to_lowercase()) {
             continue;
         }
-        let documentation = get_function_docs(name, is_proto);
+        let documentation = get_function_docs(name, &[], is_proto);
         items.push(CompletionItem {
             label: name.to_string(),
             kind: Some(CompletionItemKind::KEYWORD),
@@ -300,15 +317,24 @@ fn identifier_completions(env: &Env, prefix: &str, is_proto: bool) -> Vec<Comple
     items
 }
 
-/// Look up documentation for a function from builtins or protovalidate builtins.
-fn get_function_docs(name: &str, is_proto: bool) -> Option<Documentation> {
-    let builtin: Option<&FunctionDef> = get_builtin(name).or_else(|| {
-        if is_proto {
-            PROTOVALIDATE_BUILTINS.get(name)
-        } else {
-            None
-        }
-    });
+/// Look up documentation for a function from builtins, protovalidate builtins
+/// or the Kubernetes library that declared one of its `overloads`.
+fn get_function_docs(
+    name: &str,
+    overloads: &[OverloadDecl],
+    is_proto: bool,
+) -> Option<Documentation> {
+    let builtin: Option<&FunctionDef> = overloads
+        .iter()
+        .find_map(|o| get_kubernetes_builtin(name, &o.id))
+        .or_else(|| get_builtin(name))
+        .or_else(|| {
+            if is_proto {
+                PROTOVALIDATE_BUILTINS.get(name)
+            } else {
+                None
+            }
+        });
     builtin.map(|b| {
         let mut doc = format!("{}\n\n{}", b.signature, b.description);
         if let Some(example) = b.example {
@@ -351,7 +377,7 @@ pub fn completion_at_position(
 
 /// Generate completions at a position in a proto document.
 pub fn completion_at_position_proto(
-    state: &ProtoDocumentState,
+    state: &EmbeddedDocumentState,
     position: Position,
 ) -> Option<CompletionResponse> {
     let host_offset = state.line_index.position_to_offset(position)?;
@@ -383,6 +409,8 @@ pub fn completion_at_position_proto(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::kubernetes::{with_kubernetes_libraries, KubernetesVersion};
+    use cel_core::types::FunctionDecl;
     use cel_core::Env;
 
     fn get_completions(source: &str, position: Position) -> Vec<CompletionItem> {
@@ -470,6 +498,37 @@ mod tests {
         );
     }
 
+    #[test]
+    fn proto_field_completions_are_documented_and_tagged() {
+        let inputs = crate::proto::ProtoInputs {
+            bundled: vec![(
+                "user.proto".to_string(),
+                "syntax = \"proto3\";\nmessage User {\n  int32 age = 1; // Age in years.\n  string nick = 2 [deprecated = true];\n}\n"
+                    .to_string(),
+            )],
+            ..Default::default()
+        };
+        let (pool, _) = inputs.build(&crate::proto::Buffers::new());
+        let env = Env::with_standard_library()
+            .with_proto_registry(std::sync::Arc::new(
+                cel_core_proto::ProstProtoRegistry::from_pool(pool),
+            ))
+            .with_variable("user", CelType::message("User"));
+
+        let items = get_completions_with_env("user.", Position::new(0, 5), &env);
+        let age = items.iter().find(|item| item.label == "age").unwrap();
+        assert_eq!(
+            age.documentation,
+            Some(Documentation::MarkupContent(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: "```proto\nint32 age = 1\n```\n\nAge in years.".to_string(),
+            }))
+        );
+        assert_eq!(age.tags, None);
+        let nick = items.iter().find(|item| item.label == "nick").unwrap();
+        assert_eq!(nick.tags, Some(vec![CompletionItemTag::DEPRECATED]));
+    }
+
     #[test]
     fn member_access_on_list() {
         let env = Env::with_standard_library()
@@ -481,14 +540,38 @@ mod tests {
         assert!(names.contains(&"size"), "should suggest size: {:?}", names);
     }
 
+    #[test]
+    fn kubernetes_docs_only_for_declared_library_functions() {
+        let is_sorted_docs = |env: &Env| {
+            get_completions_with_env("names.isS", Position::new(0, 9), env)
+                .into_iter()
+                .find(|item| item.label == "isSorted")
+                .and_then(|item| item.documentation)
+        };
+        let env =
+            Env::with_standard_library().with_variable("names", CelType::list(CelType::String));
+
+        let kubernetes = with_kubernetes_libraries(env.clone(), KubernetesVersion::LATEST);
+        assert!(is_sorted_docs(&kubernetes).is_some());
+
+        let user = env.with_function(FunctionDecl::new("isSorted").with_overloads(vec![
+            OverloadDecl::method(
+                "my_is_sorted",
+                vec![CelType::list(CelType::String)],
+                CelType::Bool,
+            ),
+        ]));
+        assert_eq!(is_sorted_docs(&user), None);
+    }
+
     #[test]
     fn member_access_mid_expression() {
         // Reproduces the bug: cursor after `this.` in `this.isEmail()`
         // The placeholder was concatenating with `isEmail` instead of replacing it
-        use crate::settings::protovalidate_extension;
+        use crate::protovalidate::{protovalidate_library, ProtovalidateVersion};
         let env = Env::with_standard_library()
             .with_all_extensions()
-            .with_extension(protovalidate_extension())
+            .with_extension(protovalidate_library(ProtovalidateVersion::LATEST))
             .with_variable("this", CelType::String);
 
         // Cursor right after the dot (offset 5), full expression still present
@@ -509,10 +592,10 @@ mod tests {
     #[test]
     fn member_access_with_partial_mid_expression() {
         // Cursor after `this.is` in `this.isEmail()` — prefix "is" should filter
-        use crate::settings::protovalidate_extension;
+        use crate::protovalidate::{protovalidate_library, ProtovalidateVersion};
         let env = Env::with_standard_library()
             .with_all_extensions()
-            .with_extension(protovalidate_extension())
+            .with_extension(protovalidate_library(ProtovalidateVersion::LATEST))
             .with_variable("this", CelType::String);
 
         let items = get_completions_with_env("this.isEmail()", Position::new(0, 7), &env);
@@ -604,10 +687,10 @@ mod tests {
     #[test]
     fn incomplete_member_access_trailing_dot() {
         // User has typed "this." — incomplete expression, cursor at end
-        use crate::settings::protovalidate_extension;
+        use crate::protovalidate::{protovalidate_library, ProtovalidateVersion};
         let env = Env::with_standard_library()
             .with_all_extensions()
-            .with_extension(protovalidate_extension())
+            .with_extension(protovalidate_library(ProtovalidateVersion::LATEST))
             .with_variable("this", CelType::String);
 
         let items = get_completions_with_env("this.", Position::new(0, 5), &env);
@@ -627,17 +710,16 @@ mod tests {
     #[test]
     fn protovalidate_string_this_completions() {
         // Simulate protovalidate env where `this` is a string field
-        use crate::settings::protovalidate_extension;
+        use crate::protovalidate::{protovalidate_library, ProtovalidateVersion};
         let env = Env::with_standard_library()
             .with_all_extensions()
-            .with_extension(protovalidate_extension())
+            .with_extension(protovalidate_library(ProtovalidateVersion::LATEST))
             .with_variable("this", CelType::String)
             .with_variable("rules", CelType::Dyn)
             .with_variable("now", CelType::Timestamp);
 
         let items = get_completions_with_env("this.", Position::new(0, 5), &env);
         let names = labels(&items);
-        eprintln!("protovalidate string completions: {:?}", names);
         assert!(
             names.contains(&"isEmail"),
             "should suggest isEmail for string this: {:?}",
@@ -663,10 +745,10 @@ mod tests {
     #[test]
     fn protovalidate_int_this_no_string_methods() {
         // Simulate protovalidate env where `this` is an int field
-        use crate::settings::protovalidate_extension;
+        use crate::protovalidate::{protovalidate_library, ProtovalidateVersion};
         let env = Env::with_standard_library()
             .with_all_extensions()
-            .with_extension(protovalidate_extension())
+            .with_extension(protovalidate_library(ProtovalidateVersion::LATEST))
             .with_variable("this", CelType::Int)
             .with_variable("rules", CelType::Dyn)
             .with_variable("now", CelType::Timestamp);
@@ -690,10 +772,10 @@ mod tests {
     #[test]
     fn protovalidate_double_this_completions() {
         // Simulate protovalidate env where `this` is a double field
-        use crate::settings::protovalidate_extension;
+        use crate::protovalidate::{protovalidate_library, ProtovalidateVersion};
         let env = Env::with_standard_library()
             .with_all_extensions()
-            .with_extension(protovalidate_extension())
+            .with_extension(protovalidate_library(ProtovalidateVersion::LATEST))
             .with_variable("this", CelType::Double)
             .with_variable("rules", CelType::Dyn)
             .with_variable("now", CelType::Timestamp);
@@ -715,10 +797,10 @@ mod tests {
     #[test]
     fn protovalidate_list_this_completions() {
         // Simulate protovalidate env where `this` is a repeated field (list)
-        use crate::settings::protovalidate_extension;
+        use crate::protovalidate::{protovalidate_library, ProtovalidateVersion};
         let env = Env::with_standard_library()
             .with_all_extensions()
-            .with_extension(protovalidate_extension())
+            .with_extension(protovalidate_library(ProtovalidateVersion::LATEST))
             .with_variable("this", CelType::list(CelType::String))
             .with_variable("rules", CelType::Dyn)
             .with_variable("now", CelType::Timestamp);
@@ -1,9 +1,21 @@
 //! Diagnostics conversion from parser and check errors to LSP diagnostics.
 
-use cel_core::{CheckError, CheckErrorKind, ParseError};
-use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::sync::LazyLock;
 
-use crate::document::{LineIndex, ProtoDocumentState};
+use cel_core::{CheckError, CheckErrorKind, CheckResult, Env, ParseError, Span, SpannedExpr};
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Range};
+
+use super::lints::{apply_lints, ignore_comments, ignored_on_line};
+use crate::analysis::{constant_problems, conversion_problems, regex_problems, Problem};
+use crate::document::{DocumentKind, EmbeddedDocumentState, LineIndex, ResultTypeMismatch};
+use crate::iam;
+use crate::proto::CompileErrors;
+use crate::protovalidate::{get_library_function, ProtovalidateVersion};
+use crate::settings::DiagnosticsSettings;
 
 /// Convert parser errors to LSP diagnostics.
 fn parse_errors_to_diagnostics(errors: &[ParseError], line_index: &LineIndex) -> Vec<Diagnostic> {
@@ -26,29 +38,94 @@ fn parse_errors_to_diagnostics(errors: &[ParseError], line_index: &LineIndex) ->
         .collect()
 }
 
+/// Functions of the standard library and the built-in extensions.
+static STANDARD_FUNCTIONS: LazyLock<HashSet<String>> = LazyLock::new(|| {
+    Env::with_standard_library()
+        .with_all_extensions()
+        .functions()
+        .keys()
+        .cloned()
+        .collect()
+});
+
+/// Get the diagnostic code and message for a check error.
+///
+/// In an environment `restricted` to the `iam-conditions` functions,
+/// references to other standard functions are reported as
+/// `disallowed-function`. In protovalidate rules
+/// checked against `protovalidate`, library functions added after that release
+/// are reported as `unavailable-function`.
+fn check_error_code(
+    error: &CheckError,
+    restricted: bool,
+    protovalidate: Option<ProtovalidateVersion>,
+) -> (&'static str, String) {
+    let code = match &error.kind {
+        CheckErrorKind::UndeclaredReference { name, .. }
+            if restricted && STANDARD_FUNCTIONS.contains(name) =>
+        {
+            return (
+                "disallowed-function",
+                format!(
+                    "'{}' is not available in this environment",
+                    display_function_name(name)
+                ),
+            );
+        }
+        CheckErrorKind::UndeclaredReference { name, .. } => {
+            match (protovalidate, get_library_function(name)) {
+                (Some(version), Some(function)) if version < function.since => {
+                    return (
+                        "unavailable-function",
+                        format!(
+                            "'{}' requires protovalidate {} or later",
+                            name, function.since
+                        ),
+                    );
+                }
+                _ => "undeclared-reference",
+            }
+        }
+        CheckErrorKind::NoMatchingOverload { .. } => "no-matching-overload",
+        CheckErrorKind::TypeMismatch { .. } => "type-mismatch",
+        CheckErrorKind::UndefinedField { .. } => "undefined-field",
+        CheckErrorKind::NotAssignable { .. } => "type-mismatch",
+        CheckErrorKind::HeterogeneousAggregate { .. } => "heterogeneous-aggregate",
+        CheckErrorKind::NotAType { .. } => "not-a-type",
+        CheckErrorKind::Other(_) => "check-error",
+    };
+    (code, error.message())
+}
+
+/// Display an operator's internal name (e.g. `_==_`, `@in`) as written in source.
+fn display_function_name(name: &str) -> &str {
+    if name.chars().any(|c| c.is_ascii_alphanumeric()) {
+        return name.strip_prefix('@').unwrap_or(name);
+    }
+    match name {
+        "_?_:_" => "?:",
+        "_[_]" => "[]",
+        _ => name.trim_matches('_'),
+    }
+}
+
 /// Convert check errors to LSP diagnostics.
-fn check_errors_to_diagnostics(errors: &[CheckError], line_index: &LineIndex) -> Vec<Diagnostic> {
+fn check_errors_to_diagnostics(
+    errors: &[CheckError],
+    line_index: &LineIndex,
+    restricted: bool,
+) -> Vec<Diagnostic> {
     errors
         .iter()
         .map(|error| {
-            let code = match &error.kind {
-                CheckErrorKind::UndeclaredReference { .. } => "undeclared-reference",
-                CheckErrorKind::NoMatchingOverload { .. } => "no-matching-overload",
-                CheckErrorKind::TypeMismatch { .. } => "type-mismatch",
-                CheckErrorKind::UndefinedField { .. } => "undefined-field",
-                CheckErrorKind::NotAssignable { .. } => "type-mismatch",
-                CheckErrorKind::HeterogeneousAggregate { .. } => "heterogeneous-aggregate",
-                CheckErrorKind::NotAType { .. } => "not-a-type",
-                CheckErrorKind::Other(_) => "check-error",
-            };
-
+            let (code, message) = check_error_code(error, restricted, None);
             Diagnostic {
                 range: line_index.span_to_range(&error.span),
                 severity: Some(DiagnosticSeverity::ERROR),
                 code: Some(NumberOrString::String(code.to_string())),
                 code_description: None,
                 source: Some("cel".to_string()),
-                message: error.message(),
+                message,
                 related_information: None,
                 tags: None,
                 data: None,
@@ -57,14 +134,66 @@ fn check_errors_to_diagnostics(errors: &[CheckError], line_index: &LineIndex) ->
         .collect()
 }
 
-/// Convert all errors (parse + check) to LSP diagnostics.
+/// Problems found beyond type checking: a result type the host does not
+/// accept, invalid regular expression literals, conversions of constants
+/// that always fail, constant folding, and the problems the document state
+/// finds: exceeded cost and structural limits and deprecated references.
+///
+/// Returned as `(code, message, span)` with spans in the expression source.
+fn analysis_problems(
+    ast: Option<&SpannedExpr>,
+    source: &str,
+    check_result: Option<&CheckResult>,
+    mismatch: Option<ResultTypeMismatch>,
+    state_problems: Vec<Problem>,
+) -> Vec<(&'static str, String, Span)> {
+    let mut problems: Vec<_> = mismatch
+        .map(|m| ("type-mismatch", m.message, m.span))
+        .into_iter()
+        .collect();
+    if let Some(ast) = ast {
+        problems.extend(
+            regex_problems(ast, source)
+                .into_iter()
+                .chain(conversion_problems(ast))
+                .chain(constant_problems(ast, source, check_result))
+                .chain(state_problems)
+                .map(|p| (p.code, p.message, p.span)),
+        );
+    }
+    problems
+}
+
+/// Build a diagnostic for an analysis problem at `range`.
+fn analysis_diagnostic(code: &str, message: String, range: Range) -> Diagnostic {
+    Diagnostic {
+        range,
+        severity: Some(DiagnosticSeverity::ERROR),
+        code: Some(NumberOrString::String(code.to_string())),
+        code_description: None,
+        source: Some("cel".to_string()),
+        message,
+        related_information: None,
+        tags: None,
+        data: None,
+    }
+}
+
+/// Convert all errors (parse + check) of an expression checked in `env` to LSP
+/// diagnostics.
 pub fn to_diagnostics(
     parse_errors: &[ParseError],
     check_errors: &[CheckError],
     line_index: &LineIndex,
+    env: &Env,
 ) -> Vec<Diagnostic> {
     let mut diagnostics = parse_errors_to_diagnostics(parse_errors, line_index);
-    diagnostics.extend(check_errors_to_diagnostics(check_errors, line_index));
+    let restricted = !check_errors.is_empty() && iam::is_restricted(env);
+    diagnostics.extend(check_errors_to_diagnostics(
+        check_errors,
+        line_index,
+        restricted,
+    ));
     diagnostics
 }
 
@@ -72,17 +201,40 @@ pub fn to_diagnostics(
 ///
 /// This processes all CEL regions in the proto document, converting their
 /// parse and check errors with proper offset mapping to host coordinates.
-pub fn proto_to_diagnostics(state: &ProtoDocumentState) -> Vec<Diagnostic> {
+pub fn proto_to_diagnostics(state: &EmbeddedDocumentState) -> Vec<Diagnostic> {
+    proto_diagnostics(state, &DiagnosticsSettings::default())
+}
+
+/// Convert the enabled categories of errors from a proto document to LSP diagnostics.
+///
+/// Lints are suppressed by `celsp:ignore` comments on or above their line,
+/// or anywhere in the rule block of their region.
+fn proto_diagnostics(
+    state: &EmbeddedDocumentState,
+    options: &DiagnosticsSettings,
+) -> Vec<Diagnostic> {
     let mut diagnostics = Vec::new();
+    let comments = ignore_comments(state.line_index.source(), state.comment);
 
     for region_state in &state.regions {
+        let mut region_diagnostics = Vec::new();
         let mapper = &region_state.mapper;
+        let parse_errors: &[ParseError] = if options.parse_enabled() {
+            &region_state.parse_errors
+        } else {
+            &[]
+        };
+        let check_errors = if options.check_enabled() {
+            region_state.check_errors()
+        } else {
+            &[]
+        };
 
         // Convert parse errors
-        for error in &region_state.parse_errors {
+        for error in parse_errors {
             let host_span = mapper.span_to_host(&error.span);
             let range = state.line_index.span_to_range(&host_span);
-            diagnostics.push(Diagnostic {
+            region_diagnostics.push(Diagnostic {
                 range,
                 severity: Some(DiagnosticSeverity::ERROR),
                 code: None,
@@ -96,41 +248,160 @@ pub fn proto_to_diagnostics(state: &ProtoDocumentState) -> Vec<Diagnostic> {
         }
 
         // Convert check errors
-        for error in region_state.check_errors() {
+        for error in check_errors {
             let host_span = mapper.span_to_host(&error.span);
             let range = state.line_index.span_to_range(&host_span);
-            let code = match &error.kind {
-                CheckErrorKind::UndeclaredReference { .. } => "undeclared-reference",
-                CheckErrorKind::NoMatchingOverload { .. } => "no-matching-overload",
-                CheckErrorKind::TypeMismatch { .. } => "type-mismatch",
-                CheckErrorKind::UndefinedField { .. } => "undefined-field",
-                CheckErrorKind::NotAssignable { .. } => "type-mismatch",
-                CheckErrorKind::HeterogeneousAggregate { .. } => "heterogeneous-aggregate",
-                CheckErrorKind::NotAType { .. } => "not-a-type",
-                CheckErrorKind::Other(_) => "check-error",
-            };
-
-            diagnostics.push(Diagnostic {
+            let (code, message) = check_error_code(error, false, region_state.protovalidate);
+            region_diagnostics.push(Diagnostic {
                 range,
                 severity: Some(DiagnosticSeverity::ERROR),
                 code: Some(NumberOrString::String(code.to_string())),
                 code_description: None,
                 source: Some("cel".to_string()),
-                message: error.message(),
+                message,
                 related_information: None,
                 tags: None,
                 data: None,
             });
         }
+
+        if options.check_enabled() {
+            let problems = analysis_problems(
+                region_state.ast.as_ref(),
+                &region_state.region.source,
+                region_state.check_result.as_ref(),
+                region_state.result_type_mismatch(),
+                [region_state.limit_problems(), region_state.deprecations()].concat(),
+            );
+            for (code, message, span) in problems {
+                let range = state.line_index.span_to_range(&mapper.span_to_host(&span));
+                region_diagnostics.push(analysis_diagnostic(code, message, range));
+            }
+        }
+
+        let block_comments: Vec<_> = comments
+            .iter()
+            .filter(|c| {
+                region_state
+                    .block
+                    .as_ref()
+                    .is_some_and(|block| block.contains(&c.offset))
+            })
+            .collect();
+        diagnostics.extend(apply_lints(region_diagnostics, options, |d, rule| {
+            ignored_on_line(&comments, d, rule) || block_comments.iter().any(|c| c.ignores(rule))
+        }));
     }
 
     diagnostics
 }
 
+/// Convert the enabled categories of errors from an analyzed document of either
+/// kind to LSP diagnostics.
+pub fn document_diagnostics(
+    state: &DocumentKind,
+    options: &DiagnosticsSettings,
+) -> Vec<Diagnostic> {
+    match state {
+        DocumentKind::Cel(cel_state) => {
+            let parse_errors: &[ParseError] = if options.parse_enabled() {
+                &cel_state.errors
+            } else {
+                &[]
+            };
+            let check_errors = if options.check_enabled() {
+                cel_state.check_errors()
+            } else {
+                &[]
+            };
+            let mut diagnostics = to_diagnostics(
+                parse_errors,
+                check_errors,
+                &cel_state.line_index,
+                &cel_state.env,
+            );
+            if options.check_enabled() {
+                let problems = analysis_problems(
+                    cel_state.ast(),
+                    &cel_state.source,
+                    cel_state.check_result.as_ref(),
+                    cel_state.result_type_mismatch(),
+                    [cel_state.limit_problems(), cel_state.deprecations()].concat(),
+                );
+                for (code, message, span) in problems {
+                    let range = cel_state.line_index.span_to_range(&span);
+                    diagnostics.push(analysis_diagnostic(code, message, range));
+                }
+            }
+            let comments = ignore_comments(&cel_state.source, "//");
+            apply_lints(diagnostics, options, |d, rule| {
+                ignored_on_line(&comments, d, rule)
+            })
+        }
+        DocumentKind::Proto(proto_state) | DocumentKind::Yaml(proto_state) => {
+            proto_diagnostics(proto_state, options)
+        }
+    }
+}
+
+/// Convert the compile errors of a `.proto` source to LSP diagnostics.
+///
+/// `path` is the document's file path; nothing is reported for other kinds of
+/// documents or files without errors.
+pub fn compile_diagnostics(
+    errors: &CompileErrors,
+    path: &Path,
+    state: &DocumentKind,
+    options: &DiagnosticsSettings,
+) -> Vec<Diagnostic> {
+    let DocumentKind::Proto(proto_state) = state else {
+        return Vec::new();
+    };
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let Some(errors) = errors.get(&path) else {
+        return Vec::new();
+    };
+
+    let diagnostics = errors
+        .iter()
+        .map(|error| Diagnostic {
+            range: proto_state.line_index.span_to_range(&error.span),
+            severity: Some(DiagnosticSeverity::ERROR),
+            code: Some(NumberOrString::String("proto-compile".to_string())),
+            code_description: None,
+            source: Some("proto".to_string()),
+            message: error.message.clone(),
+            related_information: None,
+            tags: None,
+            data: None,
+        })
+        .collect();
+    let comments = ignore_comments(proto_state.line_index.source(), proto_state.comment);
+    apply_lints(diagnostics, options, |d, rule| {
+        ignored_on_line(&comments, d, rule)
+    })
+}
+
+/// Compute the pull-diagnostics result ID for a document's source.
+///
+/// The ID changes whenever the source or the configuration generation changes,
+/// so clients can skip files whose diagnostics are unchanged.
+pub fn diagnostic_result_id(source: &str, generation: u64) -> String {
+    let mut hasher = DefaultHasher::new();
+    generation.hash(&mut hasher);
+    source.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use cel_core::parse;
+    use crate::document::{DocumentState, HostVersions};
+    use crate::settings::LintLevel;
+    use cel_core::{parse, CelType};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use tower_lsp::lsp_types::Position;
 
     #[test]
     fn creates_diagnostic_from_parse_error() {
@@ -139,7 +410,12 @@ mod tests {
         let line_index = LineIndex::new(source.to_string());
 
         assert!(!result.errors.is_empty());
-        let diagnostics = to_diagnostics(&result.errors, &[], &line_index);
+        let diagnostics = to_diagnostics(
+            &result.errors,
+            &[],
+            &line_index,
+            &Env::with_standard_library(),
+        );
 
         assert!(!diagnostics.is_empty());
         assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
@@ -151,7 +427,7 @@ mod tests {
         let error = CheckError::undeclared_reference("x", 0..1, 1);
         let line_index = LineIndex::new("x".to_string());
 
-        let diagnostics = to_diagnostics(&[], &[error], &line_index);
+        let diagnostics = to_diagnostics(&[], &[error], &line_index, &Env::with_standard_library());
 
         assert_eq!(diagnostics.len(), 1);
         assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
@@ -160,4 +436,501 @@ mod tests {
             Some(NumberOrString::String("undeclared-reference".to_string()))
         );
     }
+
+    #[test]
+    fn undeclared_standard_functions_are_disallowed_in_iam_conditions() {
+        let line_index = LineIndex::new("x.matches('a') && y in z".to_string());
+        let errors = [
+            CheckError::undeclared_reference("matches", 0..14, 1),
+            CheckError::undeclared_reference("@in", 18..24, 2),
+        ];
+        let env = iam::with_iam_conditions(Env::with_standard_library(), &mut Default::default());
+
+        let diagnostics = to_diagnostics(&[], &errors, &line_index, &env);
+
+        let code = Some(NumberOrString::String("disallowed-function".to_string()));
+        assert_eq!(diagnostics[0].code, code);
+        assert_eq!(
+            diagnostics[0].message,
+            "'matches' is not available in this environment"
+        );
+        assert_eq!(diagnostics[1].code, code);
+        assert_eq!(
+            diagnostics[1].message,
+            "'in' is not available in this environment"
+        );
+    }
+
+    #[test]
+    fn missing_extension_functions_are_undeclared() {
+        let source = "'abc'.charAt(0)";
+        let env = Env::with_standard_library();
+        let state = DocumentState::with_env(source.to_string(), 1, Arc::new(env));
+
+        let diagnostics = to_diagnostics(
+            &state.errors,
+            state.check_errors(),
+            &state.line_index,
+            &state.env,
+        );
+
+        assert_eq!(
+            diagnostics[0].code,
+            Some(NumberOrString::String("undeclared-reference".to_string()))
+        );
+    }
+
+    #[test]
+    fn protovalidate_functions_are_gated_by_version() {
+        let source = r#"syntax = "proto3";
+message Server {
+  string addr = 1 [(buf.validate.field).cel = {
+    id: "addr"
+    expression: "this.isHostAndPort(true) && this.isHostname()"
+  }];
+}
+"#;
+        let oldest =
+            EmbeddedDocumentState::new(source.to_string(), 1, None, ProtovalidateVersion::OLDEST);
+        let diagnostics = proto_to_diagnostics(&oldest);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].code,
+            Some(NumberOrString::String("unavailable-function".to_string()))
+        );
+        assert_eq!(
+            diagnostics[0].message,
+            "'isHostAndPort' requires protovalidate 0.6 or later"
+        );
+
+        let latest =
+            EmbeddedDocumentState::new(source.to_string(), 1, None, ProtovalidateVersion::LATEST);
+        assert!(proto_to_diagnostics(&latest).is_empty());
+    }
+
+    #[test]
+    fn protovalidate_functions_outside_rules_are_undeclared() {
+        let url = tower_lsp::lsp_types::Url::parse("file:///test.cel").unwrap();
+        let state = DocumentKind::new(
+            &url,
+            "x.isHostAndPort(true)".to_string(),
+            1,
+            None,
+            None,
+            HostVersions::default(),
+            &[],
+        );
+        let diagnostics = document_diagnostics(&state, &DiagnosticsSettings::default());
+        assert!(!diagnostics.is_empty());
+        assert!(diagnostics
+            .iter()
+            .all(|d| d.code == Some(NumberOrString::String("undeclared-reference".to_string()))));
+    }
+
+    #[test]
+    fn protovalidate_rules_must_be_bool_or_string() {
+        let source = r#"syntax = "proto3";
+message User {
+  string name = 1 [(buf.validate.field).cel = {
+    id: "name_len"
+    expression: "size(this)"
+  }];
+}
+"#;
+        let state =
+            EmbeddedDocumentState::new(source.to_string(), 1, None, ProtovalidateVersion::LATEST);
+        let diagnostics = proto_to_diagnostics(&state);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].code,
+            Some(NumberOrString::String("type-mismatch".to_string()))
+        );
+        assert_eq!(
+            diagnostics[0].message,
+            "expected result type 'bool' or 'string' but found 'int'"
+        );
+        assert_eq!(diagnostics[0].range.start, Position::new(4, 17));
+        assert_eq!(diagnostics[0].range.end, Position::new(4, 27));
+    }
+
+    #[test]
+    fn regex_problems_map_into_proto_strings() {
+        let source = r#"syntax = "proto3";
+message User {
+  string name = 1 [(buf.validate.field).cel = {
+    id: "name_format"
+    expression: "this.matches(\"[a-z\") && this.matches('(?<=a)b')"
+  }];
+}
+"#;
+        let state =
+            EmbeddedDocumentState::new(source.to_string(), 1, None, ProtovalidateVersion::LATEST);
+        let diagnostics = proto_to_diagnostics(&state);
+        let found: Vec<_> = diagnostics
+            .iter()
+            .map(|d| {
+                let line = source.lines().nth(d.range.start.line as usize).unwrap();
+                let text = &line[d.range.start.character as usize..d.range.end.character as usize];
+                (d.code.clone(), text)
+            })
+            .collect();
+        let code = |c: &str| Some(NumberOrString::String(c.to_string()));
+        assert_eq!(
+            found,
+            vec![
+                (code("invalid-regex"), "["),
+                (code("unsupported-regex"), "(?<="),
+            ]
+        );
+    }
+
+    #[test]
+    fn constant_conversions_are_evaluated() {
+        let url = tower_lsp::lsp_types::Url::parse("file:///test.cel").unwrap();
+        let state = DocumentKind::new(
+            &url,
+            "duration('5d') > duration('1h')".to_string(),
+            1,
+            None,
+            None,
+            HostVersions::default(),
+            &[],
+        );
+        let diagnostics = document_diagnostics(&state, &DiagnosticsSettings::default());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].code,
+            Some(NumberOrString::String("invalid-conversion".to_string()))
+        );
+        assert_eq!(
+            diagnostics[0].message,
+            "'5d' is not a valid duration: invalid duration unit: 'd'"
+        );
+        assert_eq!(diagnostics[0].range.start, Position::new(0, 9));
+        assert_eq!(diagnostics[0].range.end, Position::new(0, 13));
+    }
+
+    #[test]
+    fn cel_files_are_checked_against_expected_types() {
+        let url = tower_lsp::lsp_types::Url::parse("file:///test.cel").unwrap();
+        let analyze = |expected: &[CelType]| {
+            let state = DocumentKind::new(
+                &url,
+                "1 + 2".to_string(),
+                1,
+                None,
+                None,
+                HostVersions::default(),
+                expected,
+            );
+            document_diagnostics(&state, &DiagnosticsSettings::default())
+        };
+        assert!(analyze(&[]).is_empty());
+        assert!(analyze(&[CelType::Int]).is_empty());
+
+        let diagnostics = analyze(&[CelType::Bool]);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].message,
+            "expected result type 'bool' but found 'int'"
+        );
+        assert_eq!(diagnostics[0].range.end, Position::new(0, 5));
+    }
+
+    #[test]
+    fn disabled_categories_are_not_reported() {
+        let url = tower_lsp::lsp_types::Url::parse("file:///test.cel").unwrap();
+        let state = DocumentKind::new(
+            &url,
+            "x +".to_string(),
+            1,
+            None,
+            None,
+            HostVersions::default(),
+            &[],
+        );
+        let parse_off = DiagnosticsSettings {
+            parse: Some(false),
+            ..Default::default()
+        };
+        assert!(!document_diagnostics(&state, &DiagnosticsSettings::default()).is_empty());
+        assert!(document_diagnostics(&state, &parse_off).is_empty());
+
+        let state = DocumentKind::new(
+            &url,
+            "undeclared".to_string(),
+            1,
+            None,
+            None,
+            HostVersions::default(),
+            &[],
+        );
+        let check_off = DiagnosticsSettings {
+            check: Some(false),
+            ..Default::default()
+        };
+        assert_eq!(
+            document_diagnostics(&state, &DiagnosticsSettings::default()).len(),
+            1
+        );
+        assert!(document_diagnostics(&state, &check_off).is_empty());
+    }
+
+    #[test]
+    fn constant_folding_lints_have_severities_and_tags() {
+        let source = r#"syntax = "proto3";
+message User {
+  string name = 1 [(buf.validate.field).cel = {
+    id: "name_len"
+    expression: "this.size() >= 0 ? '' : 'empty'"
+  }];
+}
+"#;
+        let state =
+            EmbeddedDocumentState::new(source.to_string(), 1, None, ProtovalidateVersion::LATEST);
+        let found: Vec<_> = proto_to_diagnostics(&state)
+            .into_iter()
+            .map(|d| (d.code, d.severity, d.tags))
+            .collect();
+        let code = |c: &str| Some(NumberOrString::String(c.to_string()));
+        assert_eq!(
+            found,
+            vec![
+                (
+                    code("constant-condition"),
+                    Some(DiagnosticSeverity::WARNING),
+                    None
+                ),
+                (
+                    code("unreachable-branch"),
+                    Some(DiagnosticSeverity::HINT),
+                    Some(vec![tower_lsp::lsp_types::DiagnosticTag::UNNECESSARY])
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn deprecated_fields_are_tagged() {
+        let inputs = crate::proto::ProtoInputs {
+            bundled: vec![(
+                "user.proto".to_string(),
+                "syntax = \"proto3\";\nmessage User { string nick = 1 [deprecated = true]; }\n"
+                    .to_string(),
+            )],
+            ..Default::default()
+        };
+        let (pool, _) = inputs.build(&crate::proto::Buffers::new());
+        let env = cel_core::Env::with_standard_library()
+            .with_proto_registry(std::sync::Arc::new(
+                cel_core_proto::ProstProtoRegistry::from_pool(pool),
+            ))
+            .with_variable("user", cel_core::CelType::message("User"));
+        let url = tower_lsp::lsp_types::Url::parse("file:///test.cel").unwrap();
+        let state = DocumentKind::new(
+            &url,
+            "user.nick == ''".to_string(),
+            1,
+            None,
+            Some(&std::sync::Arc::new(env)),
+            HostVersions::default(),
+            &[],
+        );
+
+        let diagnostics = document_diagnostics(&state, &DiagnosticsSettings::default());
+        assert_eq!(diagnostics.len(), 1);
+        let diagnostic = &diagnostics[0];
+        assert_eq!(diagnostic.message, "field 'User.nick' is deprecated");
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(
+            diagnostic.tags,
+            Some(vec![tower_lsp::lsp_types::DiagnosticTag::DEPRECATED])
+        );
+        assert_eq!(
+            (
+                diagnostic.range.start.character,
+                diagnostic.range.end.character
+            ),
+            (5, 9)
+        );
+    }
+
+    #[test]
+    fn costs_over_the_limit_are_reported() {
+        let source = r#"syntax = "proto3";
+message User {
+  string name = 1 [(buf.validate.field).string.max_len = 100, (buf.validate.field).cel = {
+    id: "name_a"
+    expression: "this.contains('a')"
+  }];
+  string nick = 2 [(buf.validate.field).cel = {
+    id: "nick_a"
+    expression: "this.contains('a')"
+  }];
+}
+"#;
+        let url = tower_lsp::lsp_types::Url::parse("file:///test.proto").unwrap();
+        let state = DocumentKind::new(
+            &url,
+            source.to_string(),
+            1,
+            None,
+            None,
+            HostVersions::default(),
+            &[],
+        );
+        let options = DiagnosticsSettings::default();
+        assert!(document_diagnostics(&state, &options).is_empty());
+
+        let state = state.with_budget(&crate::analysis::CostBudget {
+            limit: Some(10),
+            max_sizes: Default::default(),
+        });
+        let found: Vec<_> = document_diagnostics(&state, &options)
+            .into_iter()
+            .map(|d| (d.range.start.line, d.severity, d.message))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    4,
+                    Some(DiagnosticSeverity::WARNING),
+                    "estimated cost 12 exceeds the limit of 10".to_string()
+                ),
+                (
+                    8,
+                    Some(DiagnosticSeverity::WARNING),
+                    "estimated cost is unbounded: the size of 'this' is unknown".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn structural_limits_are_enforced() {
+        let url = tower_lsp::lsp_types::Url::parse("file:///test.cel").unwrap();
+        let state = DocumentKind::new(
+            &url,
+            "a.all(x, x.all(y, y.all(z, z > 0)))".to_string(),
+            1,
+            None,
+            None,
+            HostVersions::default(),
+            &[],
+        )
+        .with_limits(&crate::analysis::ComplexityLimits {
+            max_depth: Some(8),
+            max_comprehension_nesting: Some(2),
+            ..Default::default()
+        });
+        let found: Vec<_> = document_diagnostics(&state, &DiagnosticsSettings::default())
+            .into_iter()
+            .filter(|d| d.code != Some(NumberOrString::String("undeclared-reference".into())))
+            .map(|d| {
+                (
+                    d.code.unwrap(),
+                    d.range.start.character,
+                    d.range.end.character,
+                )
+            })
+            .collect();
+        let code = |c: &str| NumberOrString::String(c.to_string());
+        assert_eq!(found, vec![(code("max-comprehension-nesting"), 18, 33)]);
+    }
+
+    #[test]
+    fn lint_levels_are_configurable() {
+        let url = tower_lsp::lsp_types::Url::parse("file:///test.cel").unwrap();
+        let state = DocumentKind::new(
+            &url,
+            "x.matches('[') && int('a') == 1".to_string(),
+            1,
+            None,
+            Some(&Arc::new(
+                Env::with_standard_library().with_variable("x", CelType::String),
+            )),
+            HostVersions::default(),
+            &[],
+        );
+        let options = DiagnosticsSettings {
+            lints: Some(HashMap::from([
+                ("invalid-regex".to_string(), LintLevel::Warning),
+                ("invalid-conversion".to_string(), LintLevel::Off),
+            ])),
+            ..Default::default()
+        };
+        let diagnostics = document_diagnostics(&state, &options);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(
+            diagnostics[0]
+                .code_description
+                .as_ref()
+                .unwrap()
+                .href
+                .as_str(),
+            "https://github.com/ponix-dev/celsp/blob/main/docs/lints.md#invalid-regex"
+        );
+    }
+
+    #[test]
+    fn ignore_comments_suppress_lints_in_cel_files() {
+        let url = tower_lsp::lsp_types::Url::parse("file:///test.cel").unwrap();
+        let source = "// celsp:ignore undeclared-reference\n\
+                      first &&\n\
+                      second && // celsp:ignore invalid-conversion\n\
+                      third && int('x') == 1";
+        let state = DocumentKind::new(
+            &url,
+            source.to_string(),
+            1,
+            None,
+            None,
+            HostVersions::default(),
+            &[],
+        );
+        let found: Vec<_> = document_diagnostics(&state, &DiagnosticsSettings::default())
+            .into_iter()
+            .map(|d| (d.range.start.line, d.code))
+            .collect();
+        let code = |c: &str| Some(NumberOrString::String(c.to_string()));
+        assert_eq!(
+            found,
+            vec![
+                (2, code("undeclared-reference")),
+                (3, code("undeclared-reference")),
+            ]
+        );
+    }
+
+    #[test]
+    fn ignore_comments_apply_to_proto_rule_blocks() {
+        let source = r#"syntax = "proto3";
+message User {
+  string name = 1 [(buf.validate.field).cel = {
+    // celsp:ignore type-mismatch -- counted elsewhere
+    id: "name_len"
+    expression: "size(this)"
+  }];
+  string nick = 2 [(buf.validate.field).cel = {
+    id: "nick_len"
+    expression: "size(this)"
+  }];
+}
+"#;
+        let state =
+            EmbeddedDocumentState::new(source.to_string(), 1, None, ProtovalidateVersion::LATEST);
+        let diagnostics = proto_to_diagnostics(&state);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].range.start.line, 9);
+    }
+
+    #[test]
+    fn result_id_tracks_source_and_generation() {
+        let id = diagnostic_result_id("a == b", 0);
+        assert_eq!(id, diagnostic_result_id("a == b", 0));
+        assert_ne!(id, diagnostic_result_id("a != b", 0));
+        assert_ne!(id, diagnostic_result_id("a == b", 1));
+    }
 }
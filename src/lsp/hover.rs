@@ -1,11 +1,16 @@
 //! Hover information for CEL expressions.
 
-use cel_core::{types::Expr, CheckError, CheckErrorKind, CheckResult, SpannedExpr};
+use cel_core::{
+    types::Expr, CelType, CheckError, CheckErrorKind, CheckResult, ProtoRegistry, ReferenceInfo,
+    SpannedExpr,
+};
 use tower_lsp::lsp_types::{Hover, HoverContents, MarkupContent, MarkupKind, Position};
 
-use crate::document::{LineIndex, ProtoDocumentState};
+use crate::analysis::{walk, Cost, CostEstimate};
+use crate::document::{EmbeddedDocumentState, LineIndex};
+use crate::kubernetes::get_kubernetes_builtin;
 use crate::protovalidate::get_protovalidate_builtin;
-use crate::types::{get_builtin, FunctionDef};
+use crate::types::{field_documentation, get_builtin, message_documentation, FunctionDef};
 
 /// Format builtin function documentation as markdown.
 fn format_builtin_docs(builtin: &FunctionDef) -> String {
@@ -19,6 +24,59 @@ fn format_builtin_docs(builtin: &FunctionDef) -> String {
     doc
 }
 
+/// Append the estimated cost of a call or macro to its hover text.
+///
+/// Macros have no documentation, so their hover shows just the cost.
+fn with_cost(
+    description: Option<String>,
+    node: &SpannedExpr,
+    cost: Option<&CostEstimate>,
+) -> Option<String> {
+    let cost = match cost.and_then(|estimate| estimate.cost(node.id)) {
+        Some(Cost::Bounded(cost)) => format!("*Estimated cost:* `{}`", cost),
+        Some(Cost::Unbounded(size_of)) => format!(
+            "*Estimated cost:* unbounded, the size of `{}` is unknown",
+            size_of
+        ),
+        None => return description,
+    };
+    match description {
+        Some(description) => Some(format!("{}\n\n{}", description, cost)),
+        None if matches!(node.node, Expr::Comprehension(_)) => Some(cost),
+        None => None,
+    }
+}
+
+/// Find the function the checker resolved for `node`, a call or the function
+/// name of a call.
+fn resolved_function<'a>(
+    ast: &SpannedExpr,
+    node: &SpannedExpr,
+    check_result: Option<&'a CheckResult>,
+) -> Option<&'a ReferenceInfo> {
+    let mut call_id = node.id;
+    walk(ast, &mut |expr| {
+        if let Expr::Call { expr: callee, .. } = &expr.node {
+            if callee.id == node.id {
+                call_id = expr.id;
+            }
+        }
+    });
+    check_result?.get_reference(call_id)
+}
+
+/// Get Kubernetes library documentation for a resolved function.
+///
+/// Only calls resolved to a Kubernetes library overload match, so the docs
+/// show just where the environment declares the Kubernetes libraries.
+fn kubernetes_docs(reference: Option<&ReferenceInfo>) -> Option<&'static FunctionDef> {
+    let reference = reference?;
+    reference
+        .overload_ids
+        .iter()
+        .find_map(|id| get_kubernetes_builtin(&reference.name, id))
+}
+
 /// Find the AST node at a given position.
 fn find_node_at_position<'a>(
     line_index: &LineIndex,
@@ -69,11 +127,13 @@ fn find_node_containing_offset(ast: &SpannedExpr, offset: usize) -> Option<&Span
                     .iter()
                     .find_map(|field| find_node_containing_offset(&field.value, offset))
             }),
+        // Nodes generated by the macro span the whole macro call
         Expr::Comprehension(comp) => find_node_containing_offset(&comp.iter_range, offset)
             .or_else(|| find_node_containing_offset(&comp.accu_init, offset))
             .or_else(|| find_node_containing_offset(&comp.loop_condition, offset))
             .or_else(|| find_node_containing_offset(&comp.loop_step, offset))
-            .or_else(|| find_node_containing_offset(&comp.result, offset)),
+            .or_else(|| find_node_containing_offset(&comp.result, offset))
+            .filter(|node| node.span != ast.span),
         Expr::MemberTestOnly { expr, .. } => find_node_containing_offset(expr, offset),
         Expr::Bind { init, body, .. } => find_node_containing_offset(init, offset)
             .or_else(|| find_node_containing_offset(body, offset)),
@@ -149,6 +209,33 @@ fn format_check_error(error: &CheckError) -> String {
     }
 }
 
+/// Describe a field selected from a message, with its type and documentation.
+fn format_field_hover(
+    node: &SpannedExpr,
+    check_result: &CheckResult,
+    registry: Option<&dyn ProtoRegistry>,
+) -> Option<String> {
+    let Expr::Member { expr, field, .. } = &node.node else {
+        return None;
+    };
+    let CelType::Message(message) = check_result.type_map.get(&expr.id)? else {
+        return None;
+    };
+    let field_type = check_result.type_map.get(&node.id)?;
+
+    let mut value = format!(
+        "(field) `{}.{}`: `{}`",
+        message,
+        field,
+        field_type.display_name()
+    );
+    if let Some(documentation) = registry.and_then(|r| field_documentation(r, message, field)) {
+        value.push_str("\n\n");
+        value.push_str(&documentation);
+    }
+    Some(value)
+}
+
 /// Find a check error that overlaps with the given node.
 fn find_check_error_at<'a>(node: &SpannedExpr, errors: &'a [CheckError]) -> Option<&'a CheckError> {
     errors.iter().find(|e| {
@@ -158,11 +245,15 @@ fn find_check_error_at<'a>(node: &SpannedExpr, errors: &'a [CheckError]) -> Opti
 }
 
 /// Generate hover information for a node.
-/// Checks check errors first, then variable types, then falls back to builtin docs.
+/// Checks check errors first, then variable and field types, then falls back to builtin docs
+/// with the estimated cost.
 fn hover_for_node(
     line_index: &LineIndex,
+    ast: &SpannedExpr,
     node: &SpannedExpr,
     check_result: Option<&CheckResult>,
+    registry: Option<&dyn ProtoRegistry>,
+    cost: Option<&CostEstimate>,
 ) -> Option<Hover> {
     let check_errors = check_result.map(|r| r.errors.as_slice()).unwrap_or(&[]);
 
@@ -186,29 +277,59 @@ fn hover_for_node(
         };
         if let Some(name) = var_name {
             if let Some(cel_type) = check_result.type_map.get(&node.id) {
+                let mut value = format!("(variable) `{}`: `{}`", name, cel_type.display_name());
+                // Message types are documented by the comments of their declaration
+                let documentation = match (cel_type, registry) {
+                    (CelType::Message(message), Some(registry)) => {
+                        message_documentation(registry, message)
+                    }
+                    _ => None,
+                };
+                if let Some(documentation) = documentation {
+                    value.push_str("\n\n");
+                    value.push_str(&documentation);
+                }
                 return Some(Hover {
                     contents: HoverContents::Markup(MarkupContent {
                         kind: MarkupKind::Markdown,
-                        value: format!("(variable) `{}`: `{}`", name, cel_type.display_name()),
+                        value,
                     }),
                     range: Some(line_index.span_to_range(&node.span)),
                 });
             }
         }
+
+        if let Some(value) = format_field_hover(node, check_result, registry) {
+            return Some(Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value,
+                }),
+                range: Some(line_index.span_to_range(&node.span)),
+            });
+        }
     }
 
-    // Fall back to builtin documentation
+    // Fall back to builtin documentation (including the Kubernetes libraries)
+    let resolved = resolved_function(ast, node, check_result);
+    let get_function_docs = |name: &str| -> Option<String> {
+        kubernetes_docs(resolved)
+            .or_else(|| get_builtin(name))
+            .map(format_builtin_docs)
+    };
+
     let description = match &node.node {
-        Expr::Ident(name) => get_builtin(name).map(format_builtin_docs),
-        Expr::Member { field, .. } => get_builtin(field).map(format_builtin_docs),
+        Expr::Ident(name) => get_function_docs(name),
+        Expr::Member { field, .. } => get_function_docs(field),
         Expr::Call { expr, .. } => match &expr.node {
-            Expr::Ident(name) => get_builtin(name).map(format_builtin_docs),
-            Expr::Member { field, .. } => get_builtin(field).map(format_builtin_docs),
+            Expr::Ident(name) => get_function_docs(name),
+            Expr::Member { field, .. } => get_function_docs(field),
             _ => None,
         },
-        Expr::MemberTestOnly { .. } => get_builtin("has").map(format_builtin_docs),
+        Expr::MemberTestOnly { .. } => get_function_docs("has"),
         _ => None,
-    }?;
+    };
+    let description = with_cost(description, node, cost)?;
 
     Some(Hover {
         contents: HoverContents::Markup(MarkupContent {
@@ -224,17 +345,19 @@ pub fn hover_at_position(
     line_index: &LineIndex,
     ast: &SpannedExpr,
     check_result: Option<&CheckResult>,
+    registry: Option<&dyn ProtoRegistry>,
+    cost: Option<&CostEstimate>,
     position: Position,
 ) -> Option<Hover> {
     let node = find_node_at_position(line_index, ast, position)?;
-    hover_for_node(line_index, node, check_result)
+    hover_for_node(line_index, ast, node, check_result, registry, cost)
 }
 
 /// Get hover information for a position in a proto document.
 ///
 /// This finds the CEL region at the given position, locates the AST node,
 /// and returns hover information with proper offset mapping.
-pub fn hover_at_position_proto(state: &ProtoDocumentState, position: Position) -> Option<Hover> {
+pub fn hover_at_position_proto(state: &EmbeddedDocumentState, position: Position) -> Option<Hover> {
     // Convert position to byte offset in host document
     let host_offset = state.line_index.position_to_offset(position)?;
 
@@ -282,11 +405,26 @@ pub fn hover_at_position_proto(state: &ProtoDocumentState, position: Position) -
                 });
             }
         }
+
+        let registry = region_state.env.proto_registry();
+        if let Some(value) = format_field_hover(node, check_result, registry) {
+            let host_span = region_state.mapper.span_to_host(&node.span);
+            return Some(Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value,
+                }),
+                range: Some(state.line_index.span_to_range(&host_span)),
+            });
+        }
     }
 
-    // Fall back to builtin documentation (including protovalidate functions for proto files)
+    // Fall back to builtin documentation (including protovalidate functions for
+    // proto files and the Kubernetes libraries for YAML manifests) with the cost
+    let resolved = resolved_function(ast, node, check_result);
     let get_function_docs = |name: &str| -> Option<String> {
-        get_builtin(name)
+        kubernetes_docs(resolved)
+            .or_else(|| get_builtin(name))
             .or_else(|| get_protovalidate_builtin(name))
             .map(format_builtin_docs)
     };
@@ -301,7 +439,8 @@ pub fn hover_at_position_proto(state: &ProtoDocumentState, position: Position) -
         },
         Expr::MemberTestOnly { .. } => get_function_docs("has"),
         _ => None,
-    }?;
+    };
+    let description = with_cost(description, node, region_state.cost().as_ref())?;
 
     let host_span = region_state.mapper.span_to_host(&node.span);
     Some(Hover {
@@ -316,6 +455,10 @@ pub fn hover_at_position_proto(state: &ProtoDocumentState, position: Position) -
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::analysis::estimate_cost;
+    use crate::kubernetes::{with_kubernetes_libraries, KubernetesVersion};
+    use crate::types::SyntheticTypes;
+    use cel_core::types::{FunctionDecl, OverloadDecl};
     use cel_core::{parse, CelType, Env};
 
     #[test]
@@ -325,7 +468,7 @@ mod tests {
         let ast = result.ast.unwrap();
         let line_index = LineIndex::new(source.to_string());
 
-        let hover = hover_at_position(&line_index, &ast, None, Position::new(0, 0));
+        let hover = hover_at_position(&line_index, &ast, None, None, None, Position::new(0, 0));
         assert!(hover.is_none());
     }
 
@@ -336,7 +479,7 @@ mod tests {
         let ast = result.ast.unwrap();
         let line_index = LineIndex::new(source.to_string());
 
-        let hover = hover_at_position(&line_index, &ast, None, Position::new(0, 0));
+        let hover = hover_at_position(&line_index, &ast, None, None, None, Position::new(0, 0));
         assert!(hover.is_some());
         let hover = hover.unwrap();
         match hover.contents {
@@ -359,7 +502,14 @@ mod tests {
             errors: vec![CheckError::undeclared_reference("x", 0..1, 1)],
         };
 
-        let hover = hover_at_position(&line_index, &ast, Some(&check_result), Position::new(0, 0));
+        let hover = hover_at_position(
+            &line_index,
+            &ast,
+            Some(&check_result),
+            None,
+            None,
+            Position::new(0, 0),
+        );
         assert!(hover.is_some());
         let hover = hover.unwrap();
         match hover.contents {
@@ -379,7 +529,7 @@ mod tests {
         let line_index = LineIndex::new(source.to_string());
 
         // Hover on "has" (position 0) should return has() builtin docs
-        let hover = hover_at_position(&line_index, &ast, None, Position::new(0, 0));
+        let hover = hover_at_position(&line_index, &ast, None, None, None, Position::new(0, 0));
         assert!(hover.is_some());
         let hover = hover.unwrap();
         match hover.contents {
@@ -403,6 +553,8 @@ mod tests {
             &line_index,
             ast.expr(),
             Some(check_result),
+            None,
+            None,
             Position::new(0, 0),
         );
         assert!(hover.is_some());
@@ -432,6 +584,8 @@ mod tests {
             &line_index,
             ast.expr(),
             Some(check_result),
+            None,
+            None,
             Position::new(0, 0),
         );
         assert!(hover.is_some());
@@ -448,4 +602,146 @@ mod tests {
             _ => panic!("Expected markup content"),
         }
     }
+
+    #[test]
+    fn hover_for_field_with_description() {
+        let mut types = SyntheticTypes::new();
+        types.add_field(
+            "User",
+            "email",
+            CelType::String,
+            Some("Primary email address.".to_string()),
+        );
+        let env = Env::with_standard_library()
+            .with_proto_registry(std::sync::Arc::new(types))
+            .with_variable("user", CelType::message("User"));
+        let source = "user.email";
+        let ast = env.compile(source).unwrap();
+        let line_index = LineIndex::new(source.to_string());
+
+        let hover = hover_at_position(
+            &line_index,
+            ast.expr(),
+            ast.type_info(),
+            env.proto_registry(),
+            None,
+            Position::new(0, 7),
+        )
+        .unwrap();
+        match hover.contents {
+            HoverContents::Markup(m) => {
+                assert_eq!(
+                    m.value,
+                    "(field) `User.email`: `string`\n\nPrimary email address."
+                );
+            }
+            _ => panic!("Expected markup content"),
+        }
+    }
+
+    #[test]
+    fn hover_for_proto_field_and_message_shows_comments() {
+        let inputs = crate::proto::ProtoInputs {
+            bundled: vec![(
+                "user.proto".to_string(),
+                "syntax = \"proto3\";\n// A user.\nmessage User {\n  // Login name.\n  repeated string names = 1;\n}\n"
+                    .to_string(),
+            )],
+            ..Default::default()
+        };
+        let (pool, _) = inputs.build(&crate::proto::Buffers::new());
+        let env = Env::with_standard_library()
+            .with_proto_registry(std::sync::Arc::new(
+                cel_core_proto::ProstProtoRegistry::from_pool(pool),
+            ))
+            .with_variable("user", CelType::message("User"));
+        let source = "user.names";
+        let ast = env.compile(source).unwrap();
+        let line_index = LineIndex::new(source.to_string());
+        let hover_value = |column| {
+            let hover = hover_at_position(
+                &line_index,
+                ast.expr(),
+                ast.type_info(),
+                env.proto_registry(),
+                None,
+                Position::new(0, column),
+            )
+            .unwrap();
+            match hover.contents {
+                HoverContents::Markup(m) => m.value,
+                _ => panic!("Expected markup content"),
+            }
+        };
+
+        assert_eq!(
+            hover_value(7),
+            "(field) `User.names`: `list<string>`\n\n```proto\nrepeated string names = 1\n```\n\nLogin name."
+        );
+        assert_eq!(hover_value(1), "(variable) `user`: `User`\n\nA user.");
+    }
+
+    #[test]
+    fn kubernetes_docs_need_the_resolved_library_function() {
+        let hover_value = |env: Env| {
+            let source = "names.isSorted()";
+            let ast = env.compile(source).unwrap();
+            let line_index = LineIndex::new(source.to_string());
+            hover_at_position(
+                &line_index,
+                ast.expr(),
+                ast.type_info(),
+                None,
+                None,
+                Position::new(0, 8),
+            )
+            .map(|hover| match hover.contents {
+                HoverContents::Markup(m) => m.value,
+                _ => panic!("Expected markup content"),
+            })
+        };
+        let env =
+            Env::with_standard_library().with_variable("names", CelType::list(CelType::String));
+
+        let kubernetes = with_kubernetes_libraries(env.clone(), KubernetesVersion::LATEST);
+        assert!(hover_value(kubernetes).unwrap().starts_with("**isSorted**"));
+
+        // A user function with the same name gets no Kubernetes docs
+        let user = env.with_function(FunctionDecl::new("isSorted").with_overloads(vec![
+            OverloadDecl::method(
+                "my_is_sorted",
+                vec![CelType::list(CelType::String)],
+                CelType::Bool,
+            ),
+        ]));
+        assert_eq!(hover_value(user), None);
+    }
+
+    #[test]
+    fn hover_shows_estimated_cost_of_calls_and_macros() {
+        let env = Env::with_standard_library().with_variable("items", CelType::list(CelType::Int));
+        let source = "items.all(i, i > 0) && size(items) < 5";
+        let ast = env.compile(source).unwrap();
+        let line_index = LineIndex::new(source.to_string());
+        let max_sizes = std::collections::HashMap::from([("items".to_string(), 10)]);
+        let cost = estimate_cost(ast.expr(), source, ast.type_info(), &max_sizes);
+        let hover_value = |character| {
+            let hover = hover_at_position(
+                &line_index,
+                ast.expr(),
+                ast.type_info(),
+                None,
+                Some(&cost),
+                Position::new(0, character),
+            )
+            .unwrap();
+            match hover.contents {
+                HoverContents::Markup(m) => m.value,
+                _ => panic!("Expected markup content"),
+            }
+        };
+
+        assert_eq!(hover_value(7), "*Estimated cost:* `42`");
+        assert!(hover_value(24).ends_with("\n\n*Estimated cost:* `2`"));
+    }
 }
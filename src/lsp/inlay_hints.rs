@@ -0,0 +1,167 @@
+//! Inlay hints showing the inferred result type of CEL expressions.
+
+use cel_core::{CelType, CheckResult, SpannedExpr};
+use tower_lsp::lsp_types::{InlayHint, InlayHintKind, InlayHintLabel, Position, Range};
+
+use crate::document::{DocumentKind, LineIndex};
+
+/// Compute result type hints for every expression of a document within `range`.
+///
+/// A hint is placed after each root expression (the whole `.cel` file, or each
+/// CEL region of a `.proto` file) once it type-checks to a concrete type.
+pub fn inlay_hints(state: &DocumentKind, range: Range) -> Vec<InlayHint> {
+    let mut hints = Vec::new();
+
+    match state {
+        DocumentKind::Cel(cel_state) => {
+            if let Some(hint) = result_type_hint(
+                cel_state.ast(),
+                cel_state.check_result.as_ref(),
+                &cel_state.line_index,
+                |offset| offset,
+            ) {
+                hints.push(hint);
+            }
+        }
+        DocumentKind::Proto(proto_state) | DocumentKind::Yaml(proto_state) => {
+            for region_state in &proto_state.regions {
+                if let Some(hint) = result_type_hint(
+                    region_state.ast.as_ref(),
+                    region_state.check_result.as_ref(),
+                    &proto_state.line_index,
+                    |offset| region_state.mapper.to_host(offset),
+                ) {
+                    hints.push(hint);
+                }
+            }
+        }
+    }
+
+    hints.retain(|hint| in_range(hint.position, range));
+    hints
+}
+
+/// Build the hint for a root expression, mapping its end offset to the host document.
+fn result_type_hint(
+    ast: Option<&SpannedExpr>,
+    check_result: Option<&CheckResult>,
+    line_index: &LineIndex,
+    to_host: impl Fn(usize) -> usize,
+) -> Option<InlayHint> {
+    let ast = ast?;
+    let cel_type = check_result?.type_map.get(&ast.id)?;
+    if matches!(cel_type, CelType::Error) {
+        return None;
+    }
+
+    Some(InlayHint {
+        position: line_index.offset_to_position(to_host(ast.span.end)),
+        label: InlayHintLabel::String(format!(": {}", cel_type.display_name())),
+        kind: Some(InlayHintKind::TYPE),
+        text_edits: None,
+        tooltip: None,
+        padding_left: Some(true),
+        padding_right: None,
+        data: None,
+    })
+}
+
+/// Check whether a position lies within a range (inclusive of both ends).
+fn in_range(position: Position, range: Range) -> bool {
+    position >= range.start && position <= range.end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::HostVersions;
+    use tower_lsp::lsp_types::Url;
+
+    fn full_range() -> Range {
+        Range::new(Position::new(0, 0), Position::new(u32::MAX, 0))
+    }
+
+    fn label(hint: &InlayHint) -> &str {
+        match &hint.label {
+            InlayHintLabel::String(s) => s,
+            InlayHintLabel::LabelParts(_) => "",
+        }
+    }
+
+    #[test]
+    fn hint_after_cel_expression() {
+        let url = Url::parse("file:///test.cel").unwrap();
+        let state = DocumentKind::new(
+            &url,
+            "1 + 2 > 2".to_string(),
+            1,
+            None,
+            None,
+            HostVersions::default(),
+            &[],
+        );
+
+        let hints = inlay_hints(&state, full_range());
+        assert_eq!(hints.len(), 1);
+        assert_eq!(label(&hints[0]), ": bool");
+        assert_eq!(hints[0].position, Position::new(0, 9));
+    }
+
+    #[test]
+    fn no_hint_for_ill_typed_expression() {
+        let url = Url::parse("file:///test.cel").unwrap();
+        let state = DocumentKind::new(
+            &url,
+            "1 + 'a'".to_string(),
+            1,
+            None,
+            None,
+            HostVersions::default(),
+            &[],
+        );
+
+        assert!(inlay_hints(&state, full_range()).is_empty());
+    }
+
+    #[test]
+    fn hints_for_proto_regions_are_mapped_to_host() {
+        let url = Url::parse("file:///test.proto").unwrap();
+        let source = r#"message M {
+  string name = 1 [(buf.validate.field).cel = {
+    id: "len"
+    expression: "size(this) > 0"
+  }];
+}"#;
+        let state = DocumentKind::new(
+            &url,
+            source.to_string(),
+            1,
+            None,
+            None,
+            HostVersions::default(),
+            &[],
+        );
+
+        let hints = inlay_hints(&state, full_range());
+        assert_eq!(hints.len(), 1);
+        assert_eq!(label(&hints[0]), ": bool");
+        assert_eq!(hints[0].position, Position::new(3, 31));
+    }
+
+    #[test]
+    fn hints_outside_range_are_dropped() {
+        let url = Url::parse("file:///test.cel").unwrap();
+        let state = DocumentKind::new(
+            &url,
+            "\n\ntrue".to_string(),
+            1,
+            None,
+            None,
+            HostVersions::default(),
+            &[],
+        );
+        let range = Range::new(Position::new(0, 0), Position::new(1, 0));
+
+        assert!(inlay_hints(&state, range).is_empty());
+    }
+}
@@ -0,0 +1,366 @@
+//! Named lint rules with configurable severities and inline suppressions.
+//!
+//! Every diagnostic with a code (except parse errors) belongs to a lint rule.
+//! `[diagnostics.lints]` in `settings.toml` sets a rule to `off`, `hint`,
+//! `warning` or `error`, and a `// celsp:ignore <rule>` comment (`#` in YAML)
+//! suppresses it on the comment's line and the line below. In `.proto` files
+//! a comment inside a rule's option block suppresses it for the whole block.
+
+use tower_lsp::lsp_types::{
+    CodeDescription, Diagnostic, DiagnosticSeverity, DiagnosticTag, NumberOrString, Url,
+};
+
+use crate::settings::{DiagnosticsSettings, LintLevel};
+
+/// A named lint rule.
+#[derive(Debug, Clone, Copy)]
+pub struct Lint {
+    /// Name used as the diagnostic code, in settings and in suppressions.
+    pub name: &'static str,
+    /// Level when the rule is not configured.
+    pub default: LintLevel,
+    /// Tags of the rule's diagnostics.
+    pub tags: &'static [DiagnosticTag],
+}
+
+/// All lint rules, documented in `docs/lints.md`.
+pub const LINTS: &[Lint] = &[
+    lint("undeclared-reference", LintLevel::Error),
+    lint("disallowed-function", LintLevel::Error),
+    lint("unavailable-function", LintLevel::Error),
+    lint("no-matching-overload", LintLevel::Error),
+    lint("type-mismatch", LintLevel::Error),
+    lint("undefined-field", LintLevel::Error),
+    lint("heterogeneous-aggregate", LintLevel::Error),
+    lint("not-a-type", LintLevel::Error),
+    lint("check-error", LintLevel::Error),
+    lint("invalid-regex", LintLevel::Error),
+    lint("unsupported-regex", LintLevel::Error),
+    lint("invalid-conversion", LintLevel::Error),
+    lint("constant-condition", LintLevel::Warning),
+    Lint {
+        name: "unreachable-branch",
+        default: LintLevel::Hint,
+        tags: &[DiagnosticTag::UNNECESSARY],
+    },
+    lint("arithmetic-error", LintLevel::Error),
+    lint("cost-limit", LintLevel::Warning),
+    lint("max-depth", LintLevel::Error),
+    lint("max-nodes", LintLevel::Error),
+    lint("max-comprehension-nesting", LintLevel::Error),
+    Lint {
+        name: "deprecated",
+        default: LintLevel::Warning,
+        tags: &[DiagnosticTag::DEPRECATED],
+    },
+    lint("proto-compile", LintLevel::Error),
+];
+
+const fn lint(name: &'static str, default: LintLevel) -> Lint {
+    Lint {
+        name,
+        default,
+        tags: &[],
+    }
+}
+
+/// Look up a lint rule by name.
+pub fn get_lint(name: &str) -> Option<&'static Lint> {
+    LINTS.iter().find(|lint| lint.name == name)
+}
+
+/// Configured rule names that are not lint rules, sorted.
+pub fn unknown_lints(options: &DiagnosticsSettings) -> Vec<&str> {
+    let mut names: Vec<_> = options
+        .lints
+        .iter()
+        .flat_map(|lints| lints.keys())
+        .map(String::as_str)
+        .filter(|name| get_lint(name).is_none())
+        .collect();
+    names.sort_unstable();
+    names
+}
+
+/// Link to the documentation of a lint rule.
+fn lint_description(name: &str) -> Option<CodeDescription> {
+    let url = format!(
+        "{}/blob/main/docs/lints.md#{}",
+        env!("CARGO_PKG_REPOSITORY"),
+        name
+    );
+    Url::parse(&url).ok().map(|href| CodeDescription { href })
+}
+
+const IGNORE_MARKER: &str = "celsp:ignore";
+
+/// A `// celsp:ignore [rule, ...]` comment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IgnoreComment {
+    /// Byte offset of the `celsp:ignore` marker.
+    pub offset: usize,
+    /// Zero-based line of the comment.
+    pub line: u32,
+    /// Rules named by the comment; empty ignores every rule.
+    pub rules: Vec<String>,
+}
+
+impl IgnoreComment {
+    /// Whether this comment ignores `rule`.
+    pub fn ignores(&self, rule: &str) -> bool {
+        self.rules.is_empty() || self.rules.iter().any(|r| r == rule)
+    }
+}
+
+/// Find the `celsp:ignore` comments in `source`.
+///
+/// The marker must directly follow `comment`, the line comment token of the
+/// file's kind (`//` in CEL and proto, `#` in YAML), outside quoted strings. Rule names are separated by spaces or commas; anything after
+/// them, such as `-- reason`, is ignored. Names that are not lint rules are
+/// dropped, and a comment naming only such rules ignores nothing.
+pub fn ignore_comments(source: &str, comment: &str) -> Vec<IgnoreComment> {
+    let mut comments = Vec::new();
+    let mut line_start = 0;
+    for (line, text) in source.split('\n').enumerate() {
+        let offset = line_start;
+        line_start += text.len() + 1;
+        let Some(start) = comment_start(text, comment) else {
+            continue;
+        };
+        let comment = text[start..].trim_start();
+        let Some(after) = comment.strip_prefix(IGNORE_MARKER) else {
+            continue;
+        };
+        if after.starts_with(|c: char| !c.is_whitespace()) {
+            continue;
+        }
+        let names: Vec<&str> = after
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|word| !word.is_empty())
+            .take_while(|word| is_rule_name(word))
+            .collect();
+        let rules: Vec<String> = names
+            .iter()
+            .filter(|name| get_lint(name).is_some())
+            .map(|name| name.to_string())
+            .collect();
+        if rules.is_empty() && !names.is_empty() {
+            continue;
+        }
+        comments.push(IgnoreComment {
+            offset: offset + text.len() - comment.len(),
+            line: line as u32,
+            rules,
+        });
+    }
+    comments
+}
+
+/// The byte offset just past the `comment` token starting a comment in
+/// `line`, skipping quoted strings.
+fn comment_start(line: &str, comment: &str) -> Option<usize> {
+    let mut quote = None;
+    let mut chars = line.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            (Some(_), '\\') => {
+                chars.next();
+            }
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, _) if line[i..].starts_with(comment) => return Some(i + comment.len()),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn is_rule_name(word: &str) -> bool {
+    word.starts_with(|c: char| c.is_ascii_lowercase())
+        && word
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+/// Whether a comment on the line of `diagnostic`, or the line above it,
+/// ignores `rule`.
+pub fn ignored_on_line(comments: &[IgnoreComment], diagnostic: &Diagnostic, rule: &str) -> bool {
+    let line = diagnostic.range.start.line;
+    comments
+        .iter()
+        .any(|c| (c.line == line || c.line + 1 == line) && c.ignores(rule))
+}
+
+/// Apply the configured lint levels to `diagnostics`.
+///
+/// Diagnostics of rules that are `off` or for which `ignored` returns true
+/// are dropped; the others get the rule's severity and tags and a link to its
+/// documentation. Diagnostics without a lint code are kept as they are.
+pub fn apply_lints(
+    diagnostics: Vec<Diagnostic>,
+    options: &DiagnosticsSettings,
+    ignored: impl Fn(&Diagnostic, &str) -> bool,
+) -> Vec<Diagnostic> {
+    diagnostics
+        .into_iter()
+        .filter_map(|mut diagnostic| {
+            let Some(NumberOrString::String(code)) = &diagnostic.code else {
+                return Some(diagnostic);
+            };
+            let Some(lint) = get_lint(code) else {
+                return Some(diagnostic);
+            };
+            let severity = match options.lint_level(lint.name).unwrap_or(lint.default) {
+                LintLevel::Off => return None,
+                LintLevel::Hint => DiagnosticSeverity::HINT,
+                LintLevel::Warning => DiagnosticSeverity::WARNING,
+                LintLevel::Error => DiagnosticSeverity::ERROR,
+            };
+            if ignored(&diagnostic, lint.name) {
+                return None;
+            }
+            diagnostic.severity = Some(severity);
+            diagnostic.code_description = lint_description(lint.name);
+            if !lint.tags.is_empty() {
+                diagnostic.tags = Some(lint.tags.to_vec());
+            }
+            Some(diagnostic)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn diagnostic(code: Option<&str>, line: u32) -> Diagnostic {
+        Diagnostic {
+            range: tower_lsp::lsp_types::Range::new(
+                tower_lsp::lsp_types::Position::new(line, 0),
+                tower_lsp::lsp_types::Position::new(line, 1),
+            ),
+            severity: Some(DiagnosticSeverity::ERROR),
+            code: code.map(|c| NumberOrString::String(c.to_string())),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parses_ignore_comments() {
+        let source = "a // celsp:ignore type-mismatch, invalid-regex -- legacy\n\
+                      // celsp:ignore\n\
+                      'x // celsp:ignored' // not celsp:ignore\n\
+                      \"b // celsp:ignore invalid-conversion\\n\"\n\
+                      'it\\'s' // celsp:ignore no-such-rule, max-depth\n\
+                      c // celsp:ignore no-such-rule";
+        let comments = ignore_comments(source, "//");
+        let found: Vec<_> = comments.iter().map(|c| (c.line, c.rules.clone())).collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    0,
+                    vec!["type-mismatch".to_string(), "invalid-regex".to_string()]
+                ),
+                (1, vec![]),
+                (4, vec!["max-depth".to_string()]),
+            ]
+        );
+        assert_eq!(&source[comments[1].offset..][..12], "celsp:ignore");
+        assert_eq!(&source[comments[2].offset..][..12], "celsp:ignore");
+        assert!(comments[1].ignores("anything"));
+        assert!(!comments[0].ignores("invalid-conversion"));
+    }
+
+    #[test]
+    fn ignore_comments_use_the_comment_syntax_of_the_file() {
+        let source = "a // celsp:ignore\nb # celsp:ignore\n'#' // c";
+        let lines = |comment| {
+            ignore_comments(source, comment)
+                .iter()
+                .map(|c| c.line)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(lines("//"), vec![0]);
+        assert_eq!(lines("#"), vec![1]);
+    }
+
+    #[test]
+    fn hash_lines_do_not_ignore_lints_in_cel_sources() {
+        let comments = ignore_comments("# celsp:ignore type-mismatch\nx", "//");
+        let diagnostic = diagnostic(Some("type-mismatch"), 1);
+        assert!(!ignored_on_line(&comments, &diagnostic, "type-mismatch"));
+    }
+
+    #[test]
+    fn applies_configured_levels() {
+        let options = DiagnosticsSettings {
+            lints: Some(HashMap::from([
+                ("invalid-regex".to_string(), LintLevel::Warning),
+                ("type-mismatch".to_string(), LintLevel::Off),
+            ])),
+            ..Default::default()
+        };
+        let diagnostics = vec![
+            diagnostic(Some("invalid-regex"), 0),
+            diagnostic(Some("type-mismatch"), 0),
+            diagnostic(Some("undeclared-reference"), 0),
+            diagnostic(None, 0),
+        ];
+        let applied = apply_lints(diagnostics, &options, |_, _| false);
+
+        let summary: Vec<_> = applied
+            .iter()
+            .map(|d| (d.code.clone(), d.severity))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    Some(NumberOrString::String("invalid-regex".to_string())),
+                    Some(DiagnosticSeverity::WARNING)
+                ),
+                (
+                    Some(NumberOrString::String("undeclared-reference".to_string())),
+                    Some(DiagnosticSeverity::ERROR)
+                ),
+                (None, Some(DiagnosticSeverity::ERROR)),
+            ]
+        );
+        assert_eq!(
+            applied[0].code_description.as_ref().unwrap().href.as_str(),
+            "https://github.com/ponix-dev/celsp/blob/main/docs/lints.md#invalid-regex"
+        );
+        assert!(applied[2].code_description.is_none());
+    }
+
+    #[test]
+    fn ignores_on_the_comment_line_and_the_next() {
+        let comments = ignore_comments("x\n// celsp:ignore type-mismatch\ny\nz", "//");
+        let ignored = |line| {
+            ignored_on_line(
+                &comments,
+                &diagnostic(Some("type-mismatch"), line),
+                "type-mismatch",
+            )
+        };
+        assert_eq!(
+            (ignored(0), ignored(1), ignored(2), ignored(3)),
+            (false, true, true, false)
+        );
+    }
+
+    #[test]
+    fn reports_unknown_rules() {
+        let options = DiagnosticsSettings {
+            lints: Some(HashMap::from([
+                ("invalid-regex".to_string(), LintLevel::Off),
+                ("no-such-rule".to_string(), LintLevel::Off),
+            ])),
+            ..Default::default()
+        };
+        assert_eq!(unknown_lints(&options), vec!["no-such-rule"]);
+    }
+}
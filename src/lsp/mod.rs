@@ -1,16 +1,28 @@
 //! LSP protocol feature implementations.
 //!
 //! This module provides implementations for LSP features:
+//! - Code lenses with the estimated cost of each expression
 //! - Diagnostics conversion from parser/validation errors
 //! - Hover information for CEL expressions
+//! - Inlay hints for inferred result types
+//! - Lint rule severities and suppressions
 //! - Semantic tokens for syntax highlighting
 
+mod code_lens;
 mod completion;
 mod diagnostics;
 mod hover;
+mod inlay_hints;
+mod lints;
 mod semantic_tokens;
 
+pub use code_lens::cost_lenses;
 pub use completion::{completion_at_position, completion_at_position_proto};
-pub use diagnostics::{proto_to_diagnostics, to_diagnostics};
+pub use diagnostics::{
+    compile_diagnostics, diagnostic_result_id, document_diagnostics, proto_to_diagnostics,
+    to_diagnostics,
+};
 pub use hover::{hover_at_position, hover_at_position_proto};
+pub use inlay_hints::inlay_hints;
+pub use lints::unknown_lints;
 pub use semantic_tokens::{legend, tokens_for_ast, tokens_for_proto};
@@ -1,5 +1,7 @@
 //! Semantic tokens for CEL syntax highlighting.
 
+use std::ops::Range;
+
 use cel_core::{
     types::{BinaryOp, Expr, UnaryOp},
     SpannedExpr,
@@ -8,7 +10,8 @@ use tower_lsp::lsp_types::{
     SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokensLegend,
 };
 
-use crate::document::{LineIndex, ProtoDocumentState};
+use crate::analysis::{regex_literals, structure_spans, RegexLiteral};
+use crate::document::{EmbeddedDocumentState, LineIndex};
 use crate::types::is_builtin;
 
 /// Token type indices (must match LEGEND order).
@@ -21,11 +24,13 @@ pub mod token_types {
     pub const FUNCTION: u32 = 5;
     pub const METHOD: u32 = 6;
     pub const PUNCTUATION: u32 = 7;
+    pub const REGEXP: u32 = 8;
 }
 
-/// Token modifier bit flags.
+/// Token modifier bit flags (must match LEGEND order).
 pub mod token_modifiers {
     pub const DEFAULT_LIBRARY: u32 = 1 << 0;
+    pub const DEPRECATED: u32 = 1 << 1;
 }
 
 /// Get the semantic tokens legend for capability declaration.
@@ -40,8 +45,12 @@ pub fn legend() -> SemanticTokensLegend {
             SemanticTokenType::FUNCTION,
             SemanticTokenType::METHOD,
             SemanticTokenType::new("punctuation"),
+            SemanticTokenType::REGEXP,
+        ],
+        token_modifiers: vec![
+            SemanticTokenModifier::DEFAULT_LIBRARY,
+            SemanticTokenModifier::DEPRECATED,
         ],
-        token_modifiers: vec![SemanticTokenModifier::DEFAULT_LIBRARY],
     }
 }
 
@@ -58,17 +67,26 @@ struct RawToken {
 struct TokenCollector<'a> {
     source: &'a str,
     tokens: Vec<RawToken>,
+    /// String literals used as regular expressions, highlighted by structure.
+    regexes: Vec<RegexLiteral>,
+    /// Spans of names referring to deprecated fields and enum values.
+    deprecated: &'a [Range<usize>],
 }
 
 impl<'a> TokenCollector<'a> {
-    fn new(source: &'a str) -> Self {
+    fn new(source: &'a str, ast: &SpannedExpr, deprecated: &'a [Range<usize>]) -> Self {
         Self {
             source,
             tokens: Vec::new(),
+            regexes: regex_literals(ast, source),
+            deprecated,
         }
     }
 
-    fn push(&mut self, start: usize, end: usize, token_type: u32, token_modifiers: u32) {
+    fn push(&mut self, start: usize, end: usize, token_type: u32, mut token_modifiers: u32) {
+        if self.deprecated.contains(&(start..end)) {
+            token_modifiers |= token_modifiers::DEPRECATED;
+        }
         if start < end && end <= self.source.len() {
             self.tokens.push(RawToken {
                 start,
@@ -83,6 +101,18 @@ impl<'a> TokenCollector<'a> {
         self.push(start, start + len, token_types::PUNCTUATION, 0);
     }
 
+    /// Push a regex literal as string pieces around its structure.
+    fn push_regex(&mut self, literal: &RegexLiteral) {
+        let mut pos = literal.span.start;
+        for span in structure_spans(&literal.pattern) {
+            let span = literal.to_source(&span);
+            self.push(pos, span.start, token_types::STRING, 0);
+            self.push(span.start, span.end, token_types::REGEXP, 0);
+            pos = span.end;
+        }
+        self.push(pos, literal.span.end, token_types::STRING, 0);
+    }
+
     /// Find a single character in the source between start and end.
     fn find_char(&self, start: usize, end: usize, c: char) -> Option<usize> {
         self.source[start..end].find(c).map(|i| start + i)
@@ -99,7 +129,14 @@ impl<'a> TokenCollector<'a> {
             Expr::Int(_) | Expr::UInt(_) | Expr::Float(_) => {
                 self.push(expr.span.start, expr.span.end, token_types::NUMBER, 0);
             }
-            Expr::String(_) | Expr::Bytes(_) => {
+            Expr::String(_) => match self.regexes.iter().position(|r| r.span == expr.span) {
+                Some(index) => {
+                    let literal = self.regexes[index].clone();
+                    self.push_regex(&literal);
+                }
+                None => self.push(expr.span.start, expr.span.end, token_types::STRING, 0),
+            },
+            Expr::Bytes(_) => {
                 self.push(expr.span.start, expr.span.end, token_types::STRING, 0);
             }
             Expr::Ident(name) | Expr::RootIdent(name) => {
@@ -463,8 +500,14 @@ impl<'a> TokenCollector<'a> {
 }
 
 /// Generate semantic tokens for a parsed expression.
-pub fn tokens_for_ast(line_index: &LineIndex, ast: &SpannedExpr) -> Vec<SemanticToken> {
-    let mut collector = TokenCollector::new(line_index.source());
+///
+/// Tokens at the `deprecated` spans get the deprecated modifier.
+pub fn tokens_for_ast(
+    line_index: &LineIndex,
+    ast: &SpannedExpr,
+    deprecated: &[Range<usize>],
+) -> Vec<SemanticToken> {
+    let mut collector = TokenCollector::new(line_index.source(), ast, deprecated);
     collector.visit_expr(ast);
     collector.into_semantic_tokens(line_index)
 }
@@ -473,13 +516,18 @@ pub fn tokens_for_ast(line_index: &LineIndex, ast: &SpannedExpr) -> Vec<Semantic
 ///
 /// This processes all CEL regions, generates tokens for each, and maps
 /// them to host document coordinates.
-pub fn tokens_for_proto(state: &ProtoDocumentState) -> Vec<SemanticToken> {
+pub fn tokens_for_proto(state: &EmbeddedDocumentState) -> Vec<SemanticToken> {
     let mut all_tokens: Vec<RawToken> = Vec::new();
 
     for region_state in &state.regions {
         if let Some(ast) = &region_state.ast {
             // Generate tokens with CEL-local offsets
-            let mut collector = TokenCollector::new(&region_state.region.source);
+            let deprecated: Vec<_> = region_state
+                .deprecations()
+                .into_iter()
+                .map(|problem| problem.span)
+                .collect();
+            let mut collector = TokenCollector::new(&region_state.region.source, ast, &deprecated);
             collector.visit_expr(ast);
 
             // Convert to host coordinates
@@ -541,7 +589,8 @@ mod tests {
         assert!(leg.token_types.contains(&SemanticTokenType::KEYWORD));
         assert!(leg.token_types.contains(&SemanticTokenType::NUMBER));
         assert!(leg.token_types.contains(&SemanticTokenType::FUNCTION));
-        assert_eq!(leg.token_types.len(), 8); // Now includes punctuation
+        assert!(leg.token_types.contains(&SemanticTokenType::REGEXP));
+        assert_eq!(leg.token_types.len(), 9); // Includes punctuation and regexp
     }
 
     #[test]
@@ -551,7 +600,7 @@ mod tests {
         let ast = result.ast.unwrap();
         let line_index = LineIndex::new(source.to_string());
 
-        let tokens = tokens_for_ast(&line_index, &ast);
+        let tokens = tokens_for_ast(&line_index, &ast, &[]);
         // Should have: number(1), operator(+), number(2)
         assert_eq!(tokens.len(), 3);
     }
@@ -563,7 +612,7 @@ mod tests {
         let ast = result.ast.unwrap();
         let line_index = LineIndex::new(source.to_string());
 
-        let tokens = tokens_for_ast(&line_index, &ast);
+        let tokens = tokens_for_ast(&line_index, &ast, &[]);
         // Should have: function(size), (, variable(x), )
         assert_eq!(tokens.len(), 4);
         assert_eq!(tokens[0].token_type, token_types::FUNCTION);
@@ -573,6 +622,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn deprecated_names_get_the_deprecated_modifier() {
+        let source = "user.nick";
+        let result = parse(source);
+        let ast = result.ast.unwrap();
+        let line_index = LineIndex::new(source.to_string());
+
+        let deprecated = vec![Range { start: 5, end: 9 }];
+        let tokens = tokens_for_ast(&line_index, &ast, &deprecated);
+        // Should have: variable(user), dot, variable(nick)
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(tokens[0].token_modifiers_bitset, 0);
+        assert_eq!(
+            tokens[2].token_modifiers_bitset,
+            token_modifiers::DEPRECATED
+        );
+    }
+
     #[test]
     fn tokens_for_list() {
         let source = "[1, 2]";
@@ -580,7 +647,7 @@ mod tests {
         let ast = result.ast.unwrap();
         let line_index = LineIndex::new(source.to_string());
 
-        let tokens = tokens_for_ast(&line_index, &ast);
+        let tokens = tokens_for_ast(&line_index, &ast, &[]);
         // Should have: [, number(1), comma, number(2), ]
         assert_eq!(tokens.len(), 5);
         assert_eq!(tokens[0].token_type, token_types::PUNCTUATION); // [
@@ -594,13 +661,39 @@ mod tests {
         let ast = result.ast.unwrap();
         let line_index = LineIndex::new(source.to_string());
 
-        let tokens = tokens_for_ast(&line_index, &ast);
+        let tokens = tokens_for_ast(&line_index, &ast, &[]);
         // Should have: variable(a), ?, variable(b), :, variable(c)
         assert_eq!(tokens.len(), 5);
         assert_eq!(tokens[1].token_type, token_types::PUNCTUATION); // ?
         assert_eq!(tokens[3].token_type, token_types::PUNCTUATION); // :
     }
 
+    #[test]
+    fn tokens_for_regex_literal() {
+        let source = r"s.matches('^a\\d+')";
+        let result = parse(source);
+        let ast = result.ast.unwrap();
+        let line_index = LineIndex::new(source.to_string());
+
+        let tokens = tokens_for_ast(&line_index, &ast, &[]);
+        // s, ., matches, (, then the literal split up, )
+        let literal: Vec<_> = tokens[4..tokens.len() - 1]
+            .iter()
+            .map(|t| (t.token_type, t.length))
+            .collect();
+        assert_eq!(
+            literal,
+            vec![
+                (token_types::STRING, 1), // '
+                (token_types::REGEXP, 1), // ^
+                (token_types::STRING, 1), // a
+                (token_types::REGEXP, 3), // \\d
+                (token_types::REGEXP, 1), // +
+                (token_types::STRING, 1), // '
+            ]
+        );
+    }
+
     #[test]
     fn tokens_for_has_macro() {
         let source = "has(msg.field)";
@@ -608,7 +701,7 @@ mod tests {
         let ast = result.ast.unwrap();
         let line_index = LineIndex::new(source.to_string());
 
-        let tokens = tokens_for_ast(&line_index, &ast);
+        let tokens = tokens_for_ast(&line_index, &ast, &[]);
         // Should have: function(has), (, variable(msg), ., variable(field), )
         assert_eq!(tokens.len(), 6);
         assert_eq!(tokens[0].token_type, token_types::FUNCTION); // has
@@ -1,11 +1,47 @@
-use celsp::create_service;
-use tower_lsp::Server;
+use celsp::logging::{self, LogFilter};
+use celsp::{load_settings_file, parse_command, run_check, serve, Command, HELP};
 
 #[tokio::main]
 async fn main() {
-    let stdin = tokio::io::stdin();
-    let stdout = tokio::io::stdout();
+    let args: Vec<String> = std::env::args().skip(1).collect();
 
-    let (service, socket) = create_service();
-    Server::new(stdin, stdout, socket).serve(service).await;
+    let code = match parse_command(&args) {
+        Ok(Command::Check(check_args)) => {
+            let _ = logging::init(LogFilter::default(), None);
+            run_check(&check_args)
+        }
+        Ok(Command::Version) => {
+            println!("celsp {}", env!("CARGO_PKG_VERSION"));
+            0
+        }
+        Ok(Command::Help) => {
+            print!("{}", HELP);
+            0
+        }
+        Ok(Command::Serve(options)) => {
+            if let Some(Err(e)) = options.settings.as_deref().map(load_settings_file) {
+                eprintln!("error: {}", e);
+                std::process::exit(2);
+            }
+            let filter = options.log_level.clone().unwrap_or_default();
+            if let Err(e) = logging::init(filter, options.log_file.as_deref()) {
+                eprintln!("error: failed to open log file: {}", e);
+                std::process::exit(1);
+            }
+            match serve(options).await {
+                Ok(()) => 0,
+                Err(e) => {
+                    tracing::error!(target: "celsp", "server failed: {}", e);
+                    eprintln!("error: {}", e);
+                    1
+                }
+            }
+        }
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, HELP);
+            2
+        }
+    };
+
+    std::process::exit(code);
 }
@@ -0,0 +1,160 @@
+//! Syntax tree of a parsed `.proto` file.
+//!
+//! Only what is needed to build descriptors is kept: option values other than
+//! the few that affect the descriptor structure are skipped while parsing.
+
+use std::ops::Range;
+
+/// A value with its byte span in the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned<T> {
+    pub value: T,
+    pub span: Range<usize>,
+}
+
+/// The `syntax` of a file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Syntax {
+    #[default]
+    Proto2,
+    Proto3,
+}
+
+/// A parsed `.proto` file.
+#[derive(Debug, Default)]
+pub struct File {
+    pub syntax: Syntax,
+    pub package: Option<Spanned<String>>,
+    pub imports: Vec<Import>,
+    pub messages: Vec<Message>,
+    pub enums: Vec<Enum>,
+    pub extends: Vec<Extend>,
+    /// Spans of the comments in the file, in order.
+    pub comments: Vec<Range<usize>>,
+}
+
+/// An `import` statement.
+#[derive(Debug)]
+pub struct Import {
+    pub path: Spanned<String>,
+    pub public: bool,
+    pub weak: bool,
+}
+
+/// A message definition.
+#[derive(Debug, Default)]
+pub struct Message {
+    pub name: Spanned<String>,
+    pub span: Range<usize>,
+    pub fields: Vec<Field>,
+    pub oneofs: Vec<Spanned<String>>,
+    pub messages: Vec<Message>,
+    pub enums: Vec<Enum>,
+    pub extends: Vec<Extend>,
+    /// Extension ranges, end exclusive.
+    pub extension_ranges: Vec<Range<i32>>,
+    /// Reserved field numbers, end exclusive.
+    pub reserved_ranges: Vec<Range<i32>>,
+    pub reserved_names: Vec<String>,
+    pub deprecated: bool,
+}
+
+/// The label of a field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Label {
+    Optional,
+    Required,
+    Repeated,
+}
+
+/// The declared type of a field.
+#[derive(Debug, Clone)]
+pub enum FieldType {
+    /// A scalar type or a (possibly qualified) message or enum name.
+    Named(Spanned<String>),
+    /// `map<key, value>`.
+    Map {
+        key: Spanned<String>,
+        value: Spanned<String>,
+    },
+}
+
+/// A field of a message or an extension.
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub label: Option<Label>,
+    pub field_type: FieldType,
+    pub name: Spanned<String>,
+    pub number: Spanned<i32>,
+    /// Index into the enclosing message's oneofs.
+    pub oneof: Option<usize>,
+    pub span: Range<usize>,
+    pub options: FieldOptions,
+}
+
+/// Field options that affect the descriptor.
+#[derive(Debug, Clone, Default)]
+pub struct FieldOptions {
+    pub deprecated: bool,
+    pub packed: Option<bool>,
+    pub json_name: Option<String>,
+    pub default_value: Option<String>,
+    /// Extension options such as `(buf.validate.field).string.max_len`,
+    /// resolved by the descriptor pool.
+    pub custom: Vec<CustomOption>,
+}
+
+/// An option assignment whose name starts with an extension, e.g.
+/// `(buf.validate.field).string.max_len = 64`.
+#[derive(Debug, Clone)]
+pub struct CustomOption {
+    pub name: String,
+    pub value: Constant,
+}
+
+/// A constant in an option assignment.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    /// An identifier, e.g. `true` or an enum value.
+    Ident(String),
+    /// A number, including its sign.
+    Number(String),
+    Str(String),
+    /// A message literal, as the text between its braces.
+    Aggregate(String),
+}
+
+/// An enum definition.
+#[derive(Debug, Default)]
+pub struct Enum {
+    pub name: Spanned<String>,
+    pub span: Range<usize>,
+    pub values: Vec<EnumValue>,
+    pub allow_alias: bool,
+    pub deprecated: bool,
+}
+
+/// A value of an enum.
+#[derive(Debug)]
+pub struct EnumValue {
+    pub name: Spanned<String>,
+    pub number: Spanned<i32>,
+    pub span: Range<usize>,
+    pub deprecated: bool,
+}
+
+/// An `extend` block.
+#[derive(Debug)]
+pub struct Extend {
+    pub extendee: Spanned<String>,
+    pub fields: Vec<Field>,
+}
+
+impl Default for Spanned<String> {
+    fn default() -> Self {
+        Self {
+            value: String::new(),
+            span: 0..0,
+        }
+    }
+}
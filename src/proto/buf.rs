@@ -0,0 +1,245 @@
+//! Discovery of proto modules from `buf.yaml` and `buf.work.yaml`.
+//!
+//! Supports `buf.work.yaml` workspaces with v1 `buf.yaml` modules and v2
+//! `buf.yaml` files listing their modules. Dependencies pinned in `buf.lock`
+//! are read from the local buf module cache; nothing is downloaded.
+
+use std::path::{Path, PathBuf};
+
+use tracing::{debug, warn};
+
+use crate::kubernetes::{parse_documents, YamlNode};
+
+/// Module roots and dependency directories of a buf workspace.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BufLayout {
+    /// Directories containing the workspace's own `.proto` files.
+    pub modules: Vec<PathBuf>,
+    /// Paths excluded from the modules.
+    pub excludes: Vec<PathBuf>,
+    /// Cached dependency modules, used to resolve imports.
+    pub dependencies: Vec<PathBuf>,
+}
+
+/// Read the buf workspace in `dir`, if it has a `buf.work.yaml` or `buf.yaml`.
+pub fn discover_buf(dir: &Path) -> Option<BufLayout> {
+    discover_with_cache(dir, cache_dir().as_deref())
+}
+
+fn discover_with_cache(dir: &Path, cache: Option<&Path>) -> Option<BufLayout> {
+    let mut layout = BufLayout::default();
+    let work_file = dir.join("buf.work.yaml");
+    if work_file.is_file() {
+        let work = read_yaml(&work_file)?;
+        for directory in strings(&work, "directories") {
+            let root = dir.join(directory);
+            let config = read_yaml(&root.join("buf.yaml")).unwrap_or(YamlNode::Null);
+            add_v1_module(&mut layout, &root, &config);
+            add_dependencies(&mut layout, &root, &config, cache);
+        }
+        return Some(layout);
+    }
+
+    let config_file = dir.join("buf.yaml");
+    if !config_file.is_file() {
+        return None;
+    }
+    let config = read_yaml(&config_file)?;
+    if config.get("version").and_then(YamlNode::as_str) == Some("v2") {
+        let modules = config
+            .get("modules")
+            .map(YamlNode::items)
+            .unwrap_or_default();
+        if modules.is_empty() {
+            layout.modules.push(dir.to_path_buf());
+        }
+        for module in modules {
+            let Some(path) = module.get("path").and_then(YamlNode::as_str) else {
+                continue;
+            };
+            layout.modules.push(dir.join(path));
+            // v2 excludes are relative to the workspace
+            layout
+                .excludes
+                .extend(strings(module, "excludes").iter().map(|p| dir.join(p)));
+        }
+    } else {
+        add_v1_module(&mut layout, dir, &config);
+    }
+    add_dependencies(&mut layout, dir, &config, cache);
+    Some(layout)
+}
+
+fn add_v1_module(layout: &mut BufLayout, root: &Path, config: &YamlNode) {
+    layout.modules.push(root.to_path_buf());
+    if let Some(build) = config.get("build") {
+        layout
+            .excludes
+            .extend(strings(build, "excludes").iter().map(|p| root.join(p)));
+    }
+}
+
+/// Add the cached modules pinned in the `buf.lock` next to the `buf.yaml`.
+fn add_dependencies(layout: &mut BufLayout, dir: &Path, config: &YamlNode, cache: Option<&Path>) {
+    let lock_file = dir.join("buf.lock");
+    if !lock_file.is_file() {
+        if !strings(config, "deps").is_empty() {
+            warn!(
+                "'{}' has dependencies but no buf.lock; run `buf dep update`",
+                dir.join("buf.yaml").display()
+            );
+        }
+        return;
+    }
+    let Some(lock) = read_yaml(&lock_file) else {
+        return;
+    };
+    for dep in lock.get("deps").map(YamlNode::items).unwrap_or_default() {
+        let field = |key| dep.get(key).and_then(YamlNode::as_str);
+        let Some(commit) = field("commit") else {
+            continue;
+        };
+        // v2 locks have a `name`, v1 locks split it up
+        let name = match field("name") {
+            Some(name) => name.to_string(),
+            None => format!(
+                "{}/{}/{}",
+                field("remote").unwrap_or("buf.build"),
+                field("owner").unwrap_or_default(),
+                field("repository").unwrap_or_default()
+            ),
+        };
+        match cache.and_then(|cache| cached_module(cache, &name, commit)) {
+            Some(path) => {
+                if !layout.dependencies.contains(&path) {
+                    layout.dependencies.push(path);
+                }
+            }
+            None => warn!(
+                "buf dependency '{}' is not in the local buf cache; run `buf build` to download it",
+                name
+            ),
+        }
+    }
+}
+
+/// Find the files of a module at `commit` in the cache layouts used by buf.
+fn cached_module(cache: &Path, name: &str, commit: &str) -> Option<PathBuf> {
+    let candidates = [
+        cache
+            .join("v3/modules/b5")
+            .join(name)
+            .join(commit)
+            .join("files"),
+        cache
+            .join("v3/modules/shake256")
+            .join(name)
+            .join(commit)
+            .join("files"),
+        cache.join("v1/module/data").join(name).join(commit),
+    ];
+    candidates.into_iter().find(|path| path.is_dir())
+}
+
+/// The buf cache directory: `$BUF_CACHE_DIR`, else `$XDG_CACHE_HOME/buf`,
+/// else `~/.cache/buf`.
+fn cache_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("BUF_CACHE_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+    if let Some(dir) = std::env::var_os("XDG_CACHE_HOME") {
+        return Some(PathBuf::from(dir).join("buf"));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache").join("buf"))
+}
+
+/// The scalar items of the sequence under `key`.
+fn strings<'a>(node: &'a YamlNode, key: &str) -> Vec<&'a str> {
+    node.get(key)
+        .map(YamlNode::items)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(YamlNode::as_str)
+        .collect()
+}
+
+/// Read the first document of a YAML file.
+fn read_yaml(path: &Path) -> Option<YamlNode> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => parse_documents(&content).into_iter().next(),
+        Err(e) => {
+            debug!("failed to read '{}': {}", path.display(), e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::make_test_dir;
+
+    #[test]
+    fn reads_v2_modules_and_cached_dependencies() {
+        let dir = make_test_dir(
+            "buf-v2",
+            &[
+                (
+                    "ws/buf.yaml",
+                    "version: v2\nmodules:\n  - path: proto\n    excludes: [proto/internal]\ndeps:\n  - buf.build/acme/common\n",
+                ),
+                (
+                    "ws/buf.lock",
+                    "version: v2\ndeps:\n  - name: buf.build/acme/common\n    commit: abc123\n    digest: b5:00\n",
+                ),
+                (
+                    "cache/v3/modules/b5/buf.build/acme/common/abc123/files/acme/common.proto",
+                    "syntax = \"proto3\";",
+                ),
+            ],
+        );
+
+        let layout = discover_with_cache(&dir.join("ws"), Some(&dir.join("cache"))).unwrap();
+        assert_eq!(layout.modules, vec![dir.join("ws/proto")]);
+        assert_eq!(layout.excludes, vec![dir.join("ws/proto/internal")]);
+        assert_eq!(
+            layout.dependencies,
+            vec![dir.join("cache/v3/modules/b5/buf.build/acme/common/abc123/files")]
+        );
+    }
+
+    #[test]
+    fn reads_v1_workspace_directories() {
+        let dir = make_test_dir(
+            "buf-work",
+            &[
+                ("buf.work.yaml", "version: v1\ndirectories:\n  - api\n  - vendor\n"),
+                ("api/buf.yaml", "version: v1\nbuild:\n  excludes: [tmp]\n"),
+                (
+                    "api/buf.lock",
+                    "version: v1\ndeps:\n  - remote: buf.build\n    owner: acme\n    repository: common\n    commit: def456\n",
+                ),
+                (
+                    "cache/v1/module/data/buf.build/acme/common/def456/acme/common.proto",
+                    "syntax = \"proto3\";",
+                ),
+            ],
+        );
+
+        let layout = discover_with_cache(&dir, Some(&dir.join("cache"))).unwrap();
+        assert_eq!(layout.modules, vec![dir.join("api"), dir.join("vendor")]);
+        assert_eq!(layout.excludes, vec![dir.join("api/tmp")]);
+        assert_eq!(
+            layout.dependencies,
+            vec![dir.join("cache/v1/module/data/buf.build/acme/common/def456")]
+        );
+    }
+
+    #[test]
+    fn defaults_to_the_config_directory() {
+        let dir = make_test_dir("buf-default", &[("buf.yaml", "version: v2\n")]);
+        let layout = discover_with_cache(&dir, None).unwrap();
+        assert_eq!(layout.modules, vec![dir.clone()]);
+        assert!(discover_with_cache(&dir.join("missing"), None).is_none());
+    }
+}
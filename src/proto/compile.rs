@@ -0,0 +1,688 @@
+//! Loading and linking of `.proto` files.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use prost_reflect::prost_types::{FileDescriptorProto, FileDescriptorSet};
+use prost_reflect::DescriptorPool;
+use tracing::{debug, warn};
+
+use super::{ast, descriptor, parser, CompileError};
+use crate::workspace;
+
+/// Compile errors by canonical file path. Files without errors are omitted.
+pub type CompileErrors = HashMap<PathBuf, Vec<CompileError>>;
+
+/// Contents of unsaved `.proto` buffers by canonical file path.
+pub type Buffers = HashMap<PathBuf, String>;
+
+/// Protobuf definitions to build a descriptor pool from.
+#[derive(Debug, Default, Clone)]
+pub struct ProtoInputs {
+    /// Decoded descriptor sets.
+    pub descriptor_sets: Vec<FileDescriptorSet>,
+    /// `.proto` files or directories searched recursively.
+    pub sources: Vec<PathBuf>,
+    /// Directories that imports are resolved against.
+    pub include_paths: Vec<PathBuf>,
+    /// Files or directories left out of `sources`.
+    pub excludes: Vec<PathBuf>,
+    /// Built-in files as `(import name, source)`, compiled unless a
+    /// descriptor set, source or include path provides a file of that name.
+    pub bundled: Vec<(String, String)>,
+}
+
+impl ProtoInputs {
+    /// Whether no descriptor sets, sources or bundled files are configured.
+    pub fn is_empty(&self) -> bool {
+        self.descriptor_sets.is_empty() && self.sources.is_empty() && self.bundled.is_empty()
+    }
+
+    /// Build a pool with the well-known types, the descriptor sets and the
+    /// compiled sources.
+    ///
+    /// Files are named relative to the first include path containing them, or
+    /// else to their source directory, and imports are resolved against the
+    /// pool and then against those roots. Descriptor files are not recompiled
+    /// from source. Files with errors, along with the files importing them,
+    /// are left out.
+    ///
+    /// `buffers` are compiled in place of the files on disk, and replace the
+    /// descriptor file of the same name unless they fail to compile. Buffers
+    /// outside the configured directories are named like the descriptor file
+    /// their path ends with, or else relative to their directory.
+    pub fn build(&self, buffers: &Buffers) -> (DescriptorPool, CompileErrors) {
+        let sources: Vec<PathBuf> = self.sources.iter().map(|p| canonical(p)).collect();
+        let excludes: Vec<PathBuf> = self.excludes.iter().map(|p| canonical(p)).collect();
+        let mut roots: Vec<PathBuf> = self.include_paths.iter().map(|p| canonical(p)).collect();
+        for source in &sources {
+            let root = if source.is_dir() {
+                source.clone()
+            } else {
+                source.parent().map(Path::to_path_buf).unwrap_or_default()
+            };
+            if !roots.iter().any(|r| root.starts_with(r)) {
+                roots.push(root);
+            }
+        }
+
+        let descriptor_names: Vec<&str> = self
+            .descriptor_sets
+            .iter()
+            .flat_map(|set| &set.file)
+            .map(|file| file.name())
+            .collect();
+        let mut buffer_paths: Vec<&PathBuf> = buffers.keys().collect();
+        buffer_paths.sort();
+        for path in &buffer_paths {
+            if roots.iter().any(|r| path.starts_with(r)) {
+                continue;
+            }
+            let root = descriptor_names
+                .iter()
+                .filter(|name| path.ends_with(name))
+                .map(|name| Path::new(name).components().count())
+                .max()
+                .unwrap_or(1);
+            if let Some(root) = path.ancestors().nth(root) {
+                roots.push(root.to_path_buf());
+            }
+        }
+
+        let mut compiler = Compiler {
+            roots,
+            buffers,
+            bundled: &self.bundled,
+            units: Vec::new(),
+            by_name: HashMap::new(),
+        };
+
+        // Descriptor files replaced by a buffer, and the files importing them,
+        // are added after compiling
+        let replaced: HashSet<String> = buffer_paths
+            .iter()
+            .filter_map(|path| compiler.import_name(path))
+            .collect();
+        let deferred = dependents(&self.descriptor_sets, replaced);
+        let mut pool = DescriptorPool::global();
+        for set in &self.descriptor_sets {
+            let files = set.file.iter().filter(|f| !deferred.contains(f.name()));
+            if let Err(e) = pool.add_file_descriptor_protos(files.cloned()) {
+                warn!("failed to load proto descriptors: {}", e);
+            }
+        }
+
+        for source in &sources {
+            let files = if source.is_dir() {
+                workspace::source_files(source)
+                    .into_iter()
+                    .filter(|p| p.extension().is_some_and(|e| e == "proto"))
+                    .collect()
+            } else {
+                vec![source.clone()]
+            };
+            for path in files {
+                if excludes.iter().any(|e| path.starts_with(e)) {
+                    continue;
+                }
+                if let Some(name) = compiler.import_name(&path) {
+                    compiler.load(name, path, &pool);
+                }
+            }
+        }
+        for path in buffer_paths {
+            if let Some(name) = compiler.import_name(path) {
+                compiler.load(name, path.clone(), &pool);
+            }
+        }
+        for (name, _) in &self.bundled {
+            compiler.load_bundled(name, &pool);
+        }
+        let errors = compiler.link(&mut pool);
+
+        // Files already in the pool are skipped, so this only restores the
+        // descriptor version of buffers that failed and adds their dependents
+        if !deferred.is_empty() {
+            for set in &self.descriptor_sets {
+                let files = set.file.iter().filter(|f| deferred.contains(f.name()));
+                if let Err(e) = pool.add_file_descriptor_protos(files.cloned()) {
+                    debug!("failed to restore proto descriptors: {}", e);
+                }
+            }
+        }
+        (pool, errors)
+    }
+}
+
+/// Whether two pools define the same files, ignoring source locations.
+pub fn same_definitions(a: &DescriptorPool, b: &DescriptorPool) -> bool {
+    let files = |pool: &DescriptorPool| {
+        pool.file_descriptor_protos()
+            .map(|file| FileDescriptorProto {
+                source_code_info: None,
+                ..file.clone()
+            })
+            .collect::<Vec<_>>()
+    };
+    a.files().len() == b.files().len() && files(a) == files(b)
+}
+
+/// The names of `names` and of the descriptor files importing them, directly
+/// or indirectly.
+fn dependents(sets: &[FileDescriptorSet], mut names: HashSet<String>) -> HashSet<String> {
+    loop {
+        let before = names.len();
+        for file in sets.iter().flat_map(|set| &set.file) {
+            if file.dependency.iter().any(|d| names.contains(d)) {
+                names.insert(file.name().to_string());
+            }
+        }
+        if names.len() == before {
+            return names;
+        }
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// A loaded `.proto` file.
+struct Unit {
+    /// Import name, e.g. `acme/v1/user.proto`.
+    name: String,
+    path: PathBuf,
+    source: String,
+    file: ast::File,
+    errors: Vec<CompileError>,
+    /// Whether the file is one of [`ProtoInputs::bundled`].
+    bundled: bool,
+}
+
+struct Compiler<'a> {
+    /// Directories import names are relative to, in lookup order.
+    roots: Vec<PathBuf>,
+    buffers: &'a Buffers,
+    bundled: &'a [(String, String)],
+    units: Vec<Unit>,
+    by_name: HashMap<String, usize>,
+}
+
+impl Compiler<'_> {
+    /// The import name of a file under one of the roots.
+    fn import_name(&self, path: &Path) -> Option<String> {
+        self.roots.iter().find_map(|root| {
+            let relative = path.strip_prefix(root).ok()?;
+            let parts: Vec<_> = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect();
+            Some(parts.join("/"))
+        })
+    }
+
+    /// Load and parse a file and, recursively, the files it imports.
+    fn load(&mut self, name: String, path: PathBuf, pool: &DescriptorPool) {
+        if self.by_name.contains_key(&name) || pool.get_file_by_name(&name).is_some() {
+            return;
+        }
+        let source = match self.buffers.get(&path) {
+            Some(source) => Ok(source.clone()),
+            None => std::fs::read_to_string(&path),
+        };
+        let source = match source {
+            Ok(source) => source,
+            Err(e) => {
+                warn!("failed to read proto source '{}': {}", path.display(), e);
+                return;
+            }
+        };
+
+        self.add(name, path, source, false, pool);
+    }
+
+    /// Load a bundled file, unless a file of that name is already loaded.
+    fn load_bundled(&mut self, name: &str, pool: &DescriptorPool) {
+        if self.by_name.contains_key(name) || pool.get_file_by_name(name).is_some() {
+            return;
+        }
+        if let Some((_, source)) = self.bundled.iter().find(|(n, _)| n == name) {
+            let path = PathBuf::from(name);
+            self.add(name.to_string(), path, source.clone(), true, pool);
+        }
+    }
+
+    /// Parse a file and load the files it imports.
+    fn add(
+        &mut self,
+        name: String,
+        path: PathBuf,
+        source: String,
+        bundled: bool,
+        pool: &DescriptorPool,
+    ) {
+        let (file, errors) = parser::parse(&source);
+        let imports: Vec<_> = file.imports.iter().map(|i| i.path.clone()).collect();
+        let index = self.units.len();
+        self.by_name.insert(name.clone(), index);
+        self.units.push(Unit {
+            name,
+            path,
+            source,
+            file,
+            errors,
+            bundled,
+        });
+
+        for import in imports {
+            if self.by_name.contains_key(&import.value)
+                || pool.get_file_by_name(&import.value).is_some()
+            {
+                continue;
+            }
+            let found = self
+                .roots
+                .iter()
+                .map(|root| root.join(&import.value))
+                .find(|path| path.is_file());
+            match found {
+                Some(path) => self.load(import.value, canonical(&path), pool),
+                None if self.bundled.iter().any(|(n, _)| *n == import.value) => {
+                    self.load_bundled(&import.value, pool)
+                }
+                None => self.units[index].errors.push(CompileError::new(
+                    import.span,
+                    format!("import '{}' not found", import.value),
+                )),
+            }
+        }
+    }
+
+    /// Add the loaded files to `pool`, dependencies first.
+    fn link(mut self, pool: &mut DescriptorPool) -> CompileErrors {
+        let mut failed = HashSet::new();
+        for index in self.order() {
+            let unit = &mut self.units[index];
+            for import in &unit.file.imports {
+                if failed.contains(&import.path.value) {
+                    unit.errors.push(CompileError::new(
+                        import.path.span.clone(),
+                        format!("imported file '{}' has errors", import.path.value),
+                    ));
+                }
+            }
+
+            if unit.errors.is_empty() {
+                let (file, spans, errors) = descriptor::build(&unit.name, &unit.file, &unit.source);
+                unit.errors = errors;
+                if unit.errors.is_empty() {
+                    if let Err(e) = pool.add_file_descriptor_proto(file) {
+                        // Only the first error is exposed, located by its descriptor path
+                        let span = e
+                            .path()
+                            .filter(|_| e.file() == Some(unit.name.as_str()))
+                            .and_then(|path| spans.span(path))
+                            .unwrap_or(0..0);
+                        unit.errors.push(CompileError::new(span, e.to_string()));
+                    }
+                }
+            }
+            if !unit.errors.is_empty() {
+                failed.insert(unit.name.clone());
+            }
+        }
+
+        let (bundled, units): (Vec<_>, Vec<_>) = self
+            .units
+            .into_iter()
+            .filter(|unit| !unit.errors.is_empty())
+            .partition(|unit| unit.bundled);
+        for unit in bundled {
+            warn!(
+                "failed to compile bundled '{}': {}",
+                unit.name, unit.errors[0].message
+            );
+        }
+        units
+            .into_iter()
+            .map(|unit| (unit.path, unit.errors))
+            .collect()
+    }
+
+    /// Order units so that every file comes after the files it imports.
+    ///
+    /// Import cycles are reported on the import closing the cycle.
+    fn order(&mut self) -> Vec<usize> {
+        #[derive(Clone, Copy, PartialEq)]
+        enum Mark {
+            Unvisited,
+            Visiting,
+            Done,
+        }
+
+        fn visit(
+            compiler: &mut Compiler,
+            index: usize,
+            marks: &mut [Mark],
+            order: &mut Vec<usize>,
+        ) {
+            marks[index] = Mark::Visiting;
+            let imports: Vec<_> = compiler.units[index]
+                .file
+                .imports
+                .iter()
+                .map(|i| i.path.clone())
+                .collect();
+            for import in imports {
+                let Some(&dependency) = compiler.by_name.get(&import.value) else {
+                    continue;
+                };
+                match marks[dependency] {
+                    Mark::Unvisited => visit(compiler, dependency, marks, order),
+                    Mark::Visiting => compiler.units[index].errors.push(CompileError::new(
+                        import.span,
+                        format!("import cycle through '{}'", import.value),
+                    )),
+                    Mark::Done => {}
+                }
+            }
+            marks[index] = Mark::Done;
+            order.push(index);
+        }
+
+        let mut marks = vec![Mark::Unvisited; self.units.len()];
+        let mut order = Vec::new();
+        for index in 0..self.units.len() {
+            if marks[index] == Mark::Unvisited {
+                visit(self, index, &mut marks, &mut order);
+            }
+        }
+        order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::make_test_dir;
+    use std::fs;
+
+    fn sources(sources: &[PathBuf], include_paths: &[PathBuf]) -> ProtoInputs {
+        ProtoInputs {
+            sources: sources.to_vec(),
+            include_paths: include_paths.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn resolves_imports_of_bundled_files() {
+        let dir = make_test_dir(
+            "proto-bundled",
+            &[(
+                "user.proto",
+                "syntax = \"proto3\";\nimport \"acme/rules.proto\";\nmessage User { Rules rules = 1; }\n",
+            )],
+        );
+        let mut inputs = sources(std::slice::from_ref(&dir), &[]);
+        inputs.bundled = vec![
+            (
+                "acme/rules.proto".to_string(),
+                "syntax = \"proto3\";\nmessage Rules {}\n".to_string(),
+            ),
+            (
+                "acme/extra.proto".to_string(),
+                "syntax = \"proto3\";\nmessage Extra {}\n".to_string(),
+            ),
+        ];
+
+        let (pool, errors) = inputs.build(&Buffers::new());
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert!(pool.get_message_by_name("User").is_some());
+        // Bundled files are added even when nothing imports them
+        assert!(pool.get_message_by_name("Extra").is_some());
+
+        // A file on disk takes precedence over the bundled one
+        fs::create_dir_all(dir.join("acme")).unwrap();
+        fs::write(
+            dir.join("acme/rules.proto"),
+            "syntax = \"proto3\";\nmessage Rules { string id = 1; }\n",
+        )
+        .unwrap();
+        let (pool, _) = inputs.build(&Buffers::new());
+        let rules = pool.get_message_by_name("Rules").unwrap();
+        assert!(rules.get_field_by_name("id").is_some());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn compiles_files_with_imports() {
+        let dir = make_test_dir(
+            "proto-imports",
+            &[
+                (
+                    "acme/v1/user.proto",
+                    r#"
+                    syntax = "proto3";
+                    package acme.v1;
+                    import "acme/v1/common.proto";
+                    import "google/protobuf/timestamp.proto";
+                    message User {
+                      string name = 1;
+                      optional Address address = 2;
+                      map<string, Role> roles = 3;
+                      google.protobuf.Timestamp created = 4;
+                      enum Role { ROLE_UNSPECIFIED = 0; ADMIN = 1; }
+                    }
+                    "#,
+                ),
+                (
+                    "acme/v1/common.proto",
+                    "syntax = \"proto3\";\npackage acme.v1;\nmessage Address { string city = 1; }\n",
+                ),
+            ],
+        );
+
+        let (pool, errors) = sources(std::slice::from_ref(&dir), &[]).build(&Buffers::new());
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let user = pool.get_message_by_name("acme.v1.User").unwrap();
+        assert_eq!(user.parent_file().name(), "acme/v1/user.proto");
+        assert!(user.get_field_by_name("roles").unwrap().is_map());
+        assert!(user
+            .get_field_by_name("address")
+            .unwrap()
+            .supports_presence());
+        assert_eq!(
+            user.get_field_by_name("created")
+                .unwrap()
+                .kind()
+                .as_message()
+                .unwrap()
+                .full_name(),
+            "google.protobuf.Timestamp"
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn reports_errors_on_the_offending_file() {
+        let dir = make_test_dir(
+            "proto-errors",
+            &[
+                (
+                    "a.proto",
+                    "syntax = \"proto3\";\nimport \"b.proto\";\nmessage A { B b = 1; }\n",
+                ),
+                (
+                    "b.proto",
+                    "syntax = \"proto3\";\nmessage B { Missing m = 1; }\n",
+                ),
+                (
+                    "c.proto",
+                    "syntax = \"proto3\";\nimport \"nope.proto\";\nmessage C { int32 x = 1; int32 y = 1; }\n",
+                ),
+            ],
+        );
+
+        let (pool, errors) = sources(std::slice::from_ref(&dir), &[]).build(&Buffers::new());
+
+        let b = &errors[&dir.join("b.proto")];
+        let source = fs::read_to_string(dir.join("b.proto")).unwrap();
+        assert_eq!(&source[b[0].span.clone()], "Missing");
+        assert!(b[0].message.contains("Missing"), "{}", b[0].message);
+
+        let a = &errors[&dir.join("a.proto")];
+        assert_eq!(a[0].message, "imported file 'b.proto' has errors");
+        assert_eq!(
+            errors[&dir.join("c.proto")][0].message,
+            "import 'nope.proto' not found"
+        );
+        assert!(pool.get_message_by_name("A").is_none());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn interprets_extension_options() {
+        let dir = make_test_dir(
+            "proto-options",
+            &[
+                (
+                    "opts.proto",
+                    "syntax = \"proto3\";\npackage acme;\nimport \"google/protobuf/descriptor.proto\";\nmessage Rules { int32 max = 1; }\nextend google.protobuf.FieldOptions { Rules rules = 50000; }\n",
+                ),
+                (
+                    "user.proto",
+                    "syntax = \"proto3\";\nimport \"opts.proto\";\nmessage User {\n  string name = 1 [(acme.rules).max = 64];\n  string id = 2 [(acme.rules) = { max: 8 }];\n}\n",
+                ),
+                (
+                    "bad.proto",
+                    "syntax = \"proto3\";\nmessage Bad { string name = 1 [(acme.missing) = 1]; }\n",
+                ),
+            ],
+        );
+
+        let (pool, errors) = sources(std::slice::from_ref(&dir), &[]).build(&Buffers::new());
+        let extension = pool.get_extension_by_name("acme.rules").unwrap();
+        let user = pool.get_message_by_name("User").unwrap();
+        for (field, max) in [("name", 64), ("id", 8)] {
+            let options = user.get_field_by_name(field).unwrap().options();
+            let rules = options.get_extension(&extension);
+            let max_value = rules
+                .as_message()
+                .unwrap()
+                .get_field_by_name("max")
+                .unwrap();
+            assert_eq!(max_value.as_i32(), Some(max));
+        }
+
+        let bad = &errors[&dir.join("bad.proto")];
+        assert!(
+            bad[0].message.contains("acme.missing"),
+            "{}",
+            bad[0].message
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn names_files_relative_to_include_paths() {
+        let dir = make_test_dir(
+            "proto-include",
+            &[
+                (
+                    "proto/acme/user.proto",
+                    "syntax = \"proto3\";\npackage acme;\nimport \"acme/id.proto\";\nmessage User { Id id = 1; }\n",
+                ),
+                (
+                    "proto/acme/id.proto",
+                    "syntax = \"proto3\";\npackage acme;\nmessage Id { string value = 1; }\n",
+                ),
+            ],
+        );
+
+        let inputs = sources(&[dir.join("proto/acme")], &[dir.join("proto")]);
+        let (pool, errors) = inputs.build(&Buffers::new());
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert!(pool.get_file_by_name("acme/user.proto").is_some());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn buffers_replace_files_on_disk() {
+        let dir = make_test_dir(
+            "proto-buffers",
+            &[(
+                "user.proto",
+                "syntax = \"proto3\";\nmessage User { string name = 1; }\n",
+            )],
+        );
+        let buffers = Buffers::from([(
+            dir.join("user.proto"),
+            "syntax = \"proto3\";\nmessage User { string name = 1; int32 age = 2; }\n".to_string(),
+        )]);
+
+        let (pool, errors) = sources(std::slice::from_ref(&dir), &[]).build(&buffers);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let user = pool.get_message_by_name("User").unwrap();
+        assert!(user.get_field_by_name("age").is_some());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn buffers_replace_descriptor_files() {
+        let dir = make_test_dir(
+            "proto-buffer-descriptors",
+            &[
+                (
+                    "acme/user.proto",
+                    "syntax = \"proto3\";\npackage acme;\nmessage User { string name = 1; }\n",
+                ),
+                (
+                    "acme/team.proto",
+                    "syntax = \"proto3\";\npackage acme;\nimport \"acme/user.proto\";\nmessage Team { repeated User members = 1; }\n",
+                ),
+            ],
+        );
+        // Descriptor set as built by `buf build`, with files named from `dir`
+        let (pool, _) =
+            sources(std::slice::from_ref(&dir), std::slice::from_ref(&dir)).build(&Buffers::new());
+        let descriptor_sets = vec![FileDescriptorSet {
+            file: ["acme/user.proto", "acme/team.proto"]
+                .iter()
+                .map(|name| {
+                    pool.get_file_by_name(name)
+                        .unwrap()
+                        .file_descriptor_proto()
+                        .clone()
+                })
+                .collect(),
+        }];
+        let inputs = ProtoInputs {
+            descriptor_sets,
+            ..Default::default()
+        };
+
+        let path = dir.join("acme/user.proto");
+        let buffers = Buffers::from([(
+            path.clone(),
+            "syntax = \"proto3\";\npackage acme;\nmessage User { string name = 1; string email = 2; }\n"
+                .to_string(),
+        )]);
+        let (pool, errors) = inputs.build(&buffers);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let user = pool.get_message_by_name("acme.User").unwrap();
+        assert!(user.get_field_by_name("email").is_some());
+        assert!(pool.get_message_by_name("acme.Team").is_some());
+
+        // A buffer that fails to compile falls back to the descriptor
+        let buffers =
+            Buffers::from([(path.clone(), "syntax = \"proto3\";\nmessage {".to_string())]);
+        let (pool, errors) = inputs.build(&buffers);
+        assert_eq!(errors[&path].len(), 1);
+        let user = pool.get_message_by_name("acme.User").unwrap();
+        assert!(user.get_field_by_name("email").is_none());
+        assert!(pool.get_message_by_name("acme.Team").is_some());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,646 @@
+//! Conversion of parsed files to `FileDescriptorProto`s.
+//!
+//! Type names are kept as written: the descriptor pool resolves them relative
+//! to their scope and checks that the defining file is imported. Each element
+//! records its source span under its descriptor path, so errors reported by
+//! the pool can be located, along with the comments attached to it for
+//! hover and completion documentation.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use prost_reflect::prost_types::field_descriptor_proto::{Label as ProtoLabel, Type};
+use prost_reflect::prost_types::{
+    descriptor_proto, source_code_info, uninterpreted_option, DescriptorProto, EnumDescriptorProto,
+    EnumOptions, EnumValueDescriptorProto, EnumValueOptions, FieldDescriptorProto, FieldOptions,
+    FileDescriptorProto, MessageOptions, OneofDescriptorProto, SourceCodeInfo, UninterpretedOption,
+};
+
+use super::ast::{self, Constant, FieldType, Label, Syntax};
+use super::CompileError;
+
+/// Field numbers of descriptor fields, used in source paths.
+mod tag {
+    pub const FILE_MESSAGE: i32 = 4;
+    pub const FILE_ENUM: i32 = 5;
+    pub const FILE_EXTENSION: i32 = 7;
+
+    pub const MESSAGE_NAME: i32 = 1;
+    pub const MESSAGE_FIELD: i32 = 2;
+    pub const MESSAGE_NESTED: i32 = 3;
+    pub const MESSAGE_ENUM: i32 = 4;
+    pub const MESSAGE_EXTENSION: i32 = 6;
+    pub const MESSAGE_ONEOF: i32 = 8;
+
+    pub const FIELD_NAME: i32 = 1;
+    pub const FIELD_EXTENDEE: i32 = 2;
+    pub const FIELD_NUMBER: i32 = 3;
+    pub const FIELD_TYPE_NAME: i32 = 6;
+
+    pub const ENUM_NAME: i32 = 1;
+    pub const ENUM_VALUE: i32 = 2;
+    pub const VALUE_NAME: i32 = 1;
+    pub const VALUE_NUMBER: i32 = 2;
+}
+
+/// Source spans of descriptor elements by descriptor path.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    locations: Vec<(Vec<i32>, Range<usize>)>,
+    index: HashMap<Vec<i32>, usize>,
+}
+
+impl SourceMap {
+    fn record(&mut self, path: Vec<i32>, span: Range<usize>) {
+        self.index.insert(path.clone(), self.locations.len());
+        self.locations.push((path, span));
+    }
+
+    /// The span of the element at `path`, or of its closest recorded parent.
+    pub fn span(&self, path: &[i32]) -> Option<Range<usize>> {
+        (0..=path.len())
+            .rev()
+            .find_map(|len| self.index.get(&path[..len]))
+            .map(|&i| self.locations[i].1.clone())
+    }
+
+    /// Convert to `SourceCodeInfo` with 0-based line and byte column spans.
+    ///
+    /// Declarations (paths of `[tag, index]` pairs) get the leading and
+    /// trailing comments from `comments` attached to them.
+    fn source_code_info(&self, source: &str, comments: &[Range<usize>]) -> SourceCodeInfo {
+        let line_starts: Vec<usize> = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        let position = |offset: usize| {
+            let line = line_starts.partition_point(|&start| start <= offset) - 1;
+            (line as i32, (offset - line_starts[line]) as i32)
+        };
+
+        let location = self
+            .locations
+            .iter()
+            .map(|(path, span)| {
+                let (start_line, start_col) = position(span.start);
+                let (end_line, end_col) = position(span.end);
+                let location_span = if start_line == end_line {
+                    vec![start_line, start_col, end_col]
+                } else {
+                    vec![start_line, start_col, end_line, end_col]
+                };
+                let declaration = !path.is_empty() && path.len() % 2 == 0;
+                source_code_info::Location {
+                    path: path.clone(),
+                    span: location_span,
+                    leading_comments: declaration
+                        .then(|| leading_comments(source, comments, span))
+                        .flatten(),
+                    trailing_comments: declaration
+                        .then(|| trailing_comment(source, comments, span))
+                        .flatten(),
+                    ..Default::default()
+                }
+            })
+            .collect();
+        SourceCodeInfo { location }
+    }
+}
+
+/// The comments on the lines directly above `span`, each starting its own
+/// line, with no blank line in between.
+fn leading_comments(
+    source: &str,
+    comments: &[Range<usize>],
+    span: &Range<usize>,
+) -> Option<String> {
+    let before = comments.partition_point(|c| c.end <= span.start);
+    let mut next = span.start;
+    let mut attached = Vec::new();
+    for comment in comments[..before].iter().rev() {
+        let between = &source[comment.end..next];
+        let line_start = source[..comment.start].rfind('\n').map_or(0, |i| i + 1);
+        if !between.trim().is_empty()
+            || between.matches('\n').count() != 1
+            || !source[line_start..comment.start].trim().is_empty()
+        {
+            break;
+        }
+        attached.push(comment_text(&source[comment.clone()]));
+        next = comment.start;
+    }
+    attached.reverse();
+    (!attached.is_empty()).then(|| attached.concat())
+}
+
+/// The comment following `span` on the same line, or following the start of
+/// a declaration spanning several lines, such as `message User { // ...`.
+fn trailing_comment(
+    source: &str,
+    comments: &[Range<usize>],
+    span: &Range<usize>,
+) -> Option<String> {
+    let line_end = source[span.start..]
+        .find('\n')
+        .map_or(source.len(), |i| span.start + i);
+    let after = if span.end <= line_end {
+        span.end
+    } else {
+        span.start
+    };
+    comments
+        .iter()
+        .find(|c| c.start >= after && c.start < line_end)
+        .map(|c| comment_text(&source[c.clone()]))
+}
+
+/// The text of a comment without its delimiters, one `\n`-terminated line
+/// at a time like protoc's. Block comments lose the leading `*` of their
+/// lines and a last line holding only the closing delimiter.
+fn comment_text(comment: &str) -> String {
+    if let Some(line) = comment.strip_prefix("//") {
+        return format!("{}\n", line);
+    }
+    let inner = comment
+        .strip_prefix("/*")
+        .and_then(|c| c.strip_suffix("*/"))
+        .unwrap_or(comment);
+    let mut lines: Vec<&str> = inner
+        .split('\n')
+        .enumerate()
+        .map(|(i, line)| {
+            let line = if i == 0 { line } else { line.trim_start() };
+            line.strip_prefix('*').unwrap_or(line)
+        })
+        .collect();
+    if lines.len() > 1 && lines.last().is_some_and(|line| line.trim().is_empty()) {
+        lines.pop();
+    }
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+/// Build the descriptor of a parsed file named `name`.
+pub fn build(
+    name: &str,
+    file: &ast::File,
+    source: &str,
+) -> (FileDescriptorProto, SourceMap, Vec<CompileError>) {
+    let mut builder = Builder {
+        syntax: file.syntax,
+        spans: SourceMap::default(),
+        errors: Vec::new(),
+    };
+    let package = file.package.as_ref().map(|p| p.value.clone());
+    let scope = package.clone().unwrap_or_default();
+
+    let message_type = file
+        .messages
+        .iter()
+        .enumerate()
+        .map(|(i, m)| builder.message(m, vec![tag::FILE_MESSAGE, i as i32], &scope))
+        .collect();
+    let enum_type = file
+        .enums
+        .iter()
+        .enumerate()
+        .map(|(i, e)| builder.enumeration(e, vec![tag::FILE_ENUM, i as i32]))
+        .collect();
+    let extension = builder.extensions(&file.extends, vec![tag::FILE_EXTENSION]);
+
+    let index_of = |keep: fn(&ast::Import) -> bool| {
+        file.imports
+            .iter()
+            .enumerate()
+            .filter(|(_, import)| keep(import))
+            .map(|(i, _)| i as i32)
+            .collect()
+    };
+    let mut descriptor = FileDescriptorProto {
+        name: Some(name.to_string()),
+        package,
+        dependency: file.imports.iter().map(|i| i.path.value.clone()).collect(),
+        public_dependency: index_of(|i| i.public),
+        weak_dependency: index_of(|i| i.weak),
+        message_type,
+        enum_type,
+        extension,
+        syntax: Some(match file.syntax {
+            Syntax::Proto2 => "proto2".to_string(),
+            Syntax::Proto3 => "proto3".to_string(),
+        }),
+        ..Default::default()
+    };
+    descriptor.source_code_info = Some(builder.spans.source_code_info(source, &file.comments));
+    (descriptor, builder.spans, builder.errors)
+}
+
+struct Builder {
+    syntax: Syntax,
+    spans: SourceMap,
+    errors: Vec<CompileError>,
+}
+
+impl Builder {
+    fn message(&mut self, message: &ast::Message, path: Vec<i32>, scope: &str) -> DescriptorProto {
+        let full_name = join(scope, &message.name.value);
+        self.spans.record(path.clone(), message.span.clone());
+        self.spans.record(
+            child(&path, &[tag::MESSAGE_NAME]),
+            message.name.span.clone(),
+        );
+
+        let mut nested_type: Vec<DescriptorProto> = message
+            .messages
+            .iter()
+            .enumerate()
+            .map(|(i, m)| {
+                self.message(
+                    m,
+                    child(&path, &[tag::MESSAGE_NESTED, i as i32]),
+                    &full_name,
+                )
+            })
+            .collect();
+        let enum_type = message
+            .enums
+            .iter()
+            .enumerate()
+            .map(|(i, e)| self.enumeration(e, child(&path, &[tag::MESSAGE_ENUM, i as i32])))
+            .collect();
+
+        let mut oneof_decl: Vec<OneofDescriptorProto> = message
+            .oneofs
+            .iter()
+            .enumerate()
+            .map(|(i, oneof)| {
+                self.spans.record(
+                    child(&path, &[tag::MESSAGE_ONEOF, i as i32]),
+                    oneof.span.clone(),
+                );
+                OneofDescriptorProto {
+                    name: Some(oneof.value.clone()),
+                    options: None,
+                }
+            })
+            .collect();
+
+        let mut fields = Vec::new();
+        for (i, field) in message.fields.iter().enumerate() {
+            let field_path = child(&path, &[tag::MESSAGE_FIELD, i as i32]);
+            let mut descriptor = self.field(field, field_path);
+
+            if let FieldType::Map { key, value } = &field.field_type {
+                let entry = self.map_entry(&field.name.value, key, value);
+                descriptor.type_name = Some(format!(".{}.{}", full_name, entry.name()));
+                nested_type.push(entry);
+            }
+            if descriptor.proto3_optional() {
+                // proto3 `optional` fields are wrapped in a synthetic oneof,
+                // declared after all real oneofs
+                descriptor.oneof_index = Some(message.oneofs.len() as i32 + synthetic(&fields));
+                oneof_decl.push(OneofDescriptorProto {
+                    name: Some(format!("_{}", field.name.value)),
+                    options: None,
+                });
+            }
+            fields.push(descriptor);
+        }
+        let extension = self.extensions(&message.extends, child(&path, &[tag::MESSAGE_EXTENSION]));
+
+        DescriptorProto {
+            name: Some(message.name.value.clone()),
+            field: fields,
+            extension,
+            nested_type,
+            enum_type,
+            extension_range: message
+                .extension_ranges
+                .iter()
+                .map(|range| descriptor_proto::ExtensionRange {
+                    start: Some(range.start),
+                    end: Some(range.end),
+                    options: None,
+                })
+                .collect(),
+            oneof_decl,
+            options: message.deprecated.then(|| MessageOptions {
+                deprecated: Some(true),
+                ..Default::default()
+            }),
+            reserved_range: message
+                .reserved_ranges
+                .iter()
+                .map(|range| descriptor_proto::ReservedRange {
+                    start: Some(range.start),
+                    end: Some(range.end),
+                })
+                .collect(),
+            reserved_name: message.reserved_names.clone(),
+        }
+    }
+
+    /// Build the fields of `extend` blocks, numbering paths under `path`.
+    fn extensions(&mut self, extends: &[ast::Extend], path: Vec<i32>) -> Vec<FieldDescriptorProto> {
+        let mut extensions = Vec::new();
+        for extend in extends {
+            for field in &extend.fields {
+                let field_path = child(&path, &[extensions.len() as i32]);
+                if matches!(field.field_type, FieldType::Map { .. }) {
+                    self.errors.push(CompileError::new(
+                        field.span.clone(),
+                        "extensions cannot be map fields".to_string(),
+                    ));
+                    continue;
+                }
+                self.spans.record(
+                    child(&field_path, &[tag::FIELD_EXTENDEE]),
+                    extend.extendee.span.clone(),
+                );
+                let mut descriptor = self.field(field, field_path);
+                descriptor.extendee = Some(extend.extendee.value.clone());
+                descriptor.proto3_optional = None;
+                extensions.push(descriptor);
+            }
+        }
+        extensions
+    }
+
+    fn field(&mut self, field: &ast::Field, path: Vec<i32>) -> FieldDescriptorProto {
+        self.spans.record(path.clone(), field.span.clone());
+        self.spans
+            .record(child(&path, &[tag::FIELD_NAME]), field.name.span.clone());
+        self.spans.record(
+            child(&path, &[tag::FIELD_NUMBER]),
+            field.number.span.clone(),
+        );
+
+        let is_map = matches!(field.field_type, FieldType::Map { .. });
+        let label = match (field.label, self.syntax) {
+            (Some(_), _) if is_map => {
+                self.errors.push(CompileError::new(
+                    field.span.clone(),
+                    "map fields cannot have a label".to_string(),
+                ));
+                Label::Repeated
+            }
+            (None, _) if is_map => Label::Repeated,
+            (Some(Label::Required), Syntax::Proto3) => {
+                self.errors.push(CompileError::new(
+                    field.span.clone(),
+                    "required fields are not allowed in proto3".to_string(),
+                ));
+                Label::Required
+            }
+            (None, Syntax::Proto2) if field.oneof.is_none() => {
+                self.errors.push(CompileError::new(
+                    field.name.span.clone(),
+                    format!(
+                        "field '{}' needs a label (optional, required or repeated) in proto2",
+                        field.name.value
+                    ),
+                ));
+                Label::Optional
+            }
+            (label, _) => label.unwrap_or(Label::Optional),
+        };
+
+        let (r#type, type_name) = match &field.field_type {
+            FieldType::Named(name) => {
+                self.spans
+                    .record(child(&path, &[tag::FIELD_TYPE_NAME]), name.span.clone());
+                match scalar_type(&name.value) {
+                    Some(scalar) => (Some(scalar as i32), None),
+                    None => (None, Some(name.value.clone())),
+                }
+            }
+            FieldType::Map { .. } => (Some(Type::Message as i32), None),
+        };
+
+        let options = &field.options;
+        let has_options =
+            options.deprecated || options.packed.is_some() || !options.custom.is_empty();
+        FieldDescriptorProto {
+            name: Some(field.name.value.clone()),
+            number: Some(field.number.value),
+            label: Some(proto_label(label) as i32),
+            r#type,
+            type_name,
+            default_value: options.default_value.clone(),
+            oneof_index: field.oneof.map(|i| i as i32),
+            json_name: options.json_name.clone(),
+            options: has_options.then(|| FieldOptions {
+                deprecated: options.deprecated.then_some(true),
+                packed: options.packed,
+                uninterpreted_option: options.custom.iter().map(uninterpreted).collect(),
+                ..Default::default()
+            }),
+            proto3_optional: (self.syntax == Syntax::Proto3
+                && field.label == Some(Label::Optional))
+            .then_some(true),
+            ..Default::default()
+        }
+    }
+
+    /// Build the nested entry message of a map field.
+    fn map_entry(
+        &mut self,
+        field_name: &str,
+        key: &ast::Spanned<String>,
+        value: &ast::Spanned<String>,
+    ) -> DescriptorProto {
+        let key_type = scalar_type(&key.value)
+            .filter(|t| !matches!(t, Type::Float | Type::Double | Type::Bytes));
+        if key_type.is_none() {
+            self.errors.push(CompileError::new(
+                key.span.clone(),
+                format!("invalid map key type '{}'", key.value),
+            ));
+        }
+        let entry_field =
+            |name: &str, number: i32, scalar: Option<Type>, type_name: Option<String>| {
+                FieldDescriptorProto {
+                    name: Some(name.to_string()),
+                    number: Some(number),
+                    label: Some(ProtoLabel::Optional as i32),
+                    r#type: scalar.map(|t| t as i32),
+                    type_name,
+                    json_name: Some(name.to_string()),
+                    ..Default::default()
+                }
+            };
+        let value_type = scalar_type(&value.value);
+
+        DescriptorProto {
+            name: Some(map_entry_name(field_name)),
+            field: vec![
+                entry_field("key", 1, Some(key_type.unwrap_or(Type::String)), None),
+                entry_field(
+                    "value",
+                    2,
+                    value_type,
+                    value_type.is_none().then(|| value.value.clone()),
+                ),
+            ],
+            options: Some(MessageOptions {
+                map_entry: Some(true),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn enumeration(&mut self, enumeration: &ast::Enum, path: Vec<i32>) -> EnumDescriptorProto {
+        self.spans.record(path.clone(), enumeration.span.clone());
+        self.spans.record(
+            child(&path, &[tag::ENUM_NAME]),
+            enumeration.name.span.clone(),
+        );
+
+        let value = enumeration
+            .values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let value_path = child(&path, &[tag::ENUM_VALUE, i as i32]);
+                self.spans.record(value_path.clone(), value.span.clone());
+                self.spans.record(
+                    child(&value_path, &[tag::VALUE_NAME]),
+                    value.name.span.clone(),
+                );
+                self.spans.record(
+                    child(&value_path, &[tag::VALUE_NUMBER]),
+                    value.number.span.clone(),
+                );
+                EnumValueDescriptorProto {
+                    name: Some(value.name.value.clone()),
+                    number: Some(value.number.value),
+                    options: value.deprecated.then(|| EnumValueOptions {
+                        deprecated: Some(true),
+                        ..Default::default()
+                    }),
+                }
+            })
+            .collect();
+
+        let has_options = enumeration.allow_alias || enumeration.deprecated;
+        EnumDescriptorProto {
+            name: Some(enumeration.name.value.clone()),
+            value,
+            options: has_options.then(|| EnumOptions {
+                allow_alias: enumeration.allow_alias.then_some(true),
+                deprecated: enumeration.deprecated.then_some(true),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+/// Number of synthetic oneofs among the fields built so far.
+fn synthetic(fields: &[FieldDescriptorProto]) -> i32 {
+    fields.iter().filter(|f| f.proto3_optional()).count() as i32
+}
+
+/// Record an extension option as uninterpreted, like protoc's parser does;
+/// the descriptor pool resolves it against the extension's definition.
+fn uninterpreted(option: &ast::CustomOption) -> UninterpretedOption {
+    let mut name = Vec::new();
+    let mut rest = option.name.as_str();
+    while !rest.is_empty() {
+        let (part, is_extension, tail) = match rest.strip_prefix('(') {
+            Some(inner) => {
+                let end = inner.find(')').unwrap_or(inner.len());
+                (&inner[..end], true, inner.get(end + 1..).unwrap_or(""))
+            }
+            None => {
+                let end = rest.find('.').unwrap_or(rest.len());
+                (&rest[..end], false, &rest[end..])
+            }
+        };
+        name.push(uninterpreted_option::NamePart {
+            name_part: part.to_string(),
+            is_extension,
+        });
+        rest = tail.strip_prefix('.').unwrap_or(tail);
+    }
+
+    let mut uninterpreted = UninterpretedOption {
+        name,
+        ..Default::default()
+    };
+    match &option.value {
+        Constant::Ident(ident) => uninterpreted.identifier_value = Some(ident.clone()),
+        Constant::Str(value) => uninterpreted.string_value = Some(value.clone().into_bytes()),
+        Constant::Aggregate(text) => uninterpreted.aggregate_value = Some(text.clone()),
+        Constant::Number(number) => {
+            if let Ok(value) = number.parse() {
+                uninterpreted.positive_int_value = Some(value);
+            } else if let Ok(value) = number.parse() {
+                uninterpreted.negative_int_value = Some(value);
+            } else {
+                uninterpreted.double_value = number.parse().ok();
+            }
+        }
+    }
+    uninterpreted
+}
+
+fn child(path: &[i32], tail: &[i32]) -> Vec<i32> {
+    let mut path = path.to_vec();
+    path.extend_from_slice(tail);
+    path
+}
+
+fn join(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", scope, name)
+    }
+}
+
+/// Name of the entry message of a map field, e.g. `LabelsEntry` for `labels`.
+fn map_entry_name(field_name: &str) -> String {
+    let mut name = String::new();
+    let mut upper = true;
+    for c in field_name.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            name.push(c.to_ascii_uppercase());
+            upper = false;
+        } else {
+            name.push(c);
+        }
+    }
+    name.push_str("Entry");
+    name
+}
+
+fn proto_label(label: Label) -> ProtoLabel {
+    match label {
+        Label::Optional => ProtoLabel::Optional,
+        Label::Required => ProtoLabel::Required,
+        Label::Repeated => ProtoLabel::Repeated,
+    }
+}
+
+fn scalar_type(name: &str) -> Option<Type> {
+    Some(match name {
+        "double" => Type::Double,
+        "float" => Type::Float,
+        "int32" => Type::Int32,
+        "int64" => Type::Int64,
+        "uint32" => Type::Uint32,
+        "uint64" => Type::Uint64,
+        "sint32" => Type::Sint32,
+        "sint64" => Type::Sint64,
+        "fixed32" => Type::Fixed32,
+        "fixed64" => Type::Fixed64,
+        "sfixed32" => Type::Sfixed32,
+        "sfixed64" => Type::Sfixed64,
+        "bool" => Type::Bool,
+        "string" => Type::String,
+        "bytes" => Type::Bytes,
+        _ => return None,
+    })
+}
@@ -0,0 +1,313 @@
+//! Tokenizer for the protobuf language.
+
+use std::ops::Range;
+
+use super::CompileError;
+
+/// A token of a `.proto` file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    /// An identifier or keyword.
+    Ident(String),
+    /// An integer literal.
+    Int(u64),
+    /// A floating point literal.
+    Float(f64),
+    /// A string literal with escapes decoded.
+    Str(String),
+    /// Any other single character.
+    Symbol(char),
+}
+
+/// A token with its byte span in the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Range<usize>,
+}
+
+/// Split `source` into tokens, skipping whitespace. Comments are returned
+/// separately as byte spans, in order.
+///
+/// Invalid input is reported and skipped, so a token list is always returned.
+pub fn tokenize(source: &str) -> (Vec<Token>, Vec<Range<usize>>, Vec<CompileError>) {
+    let mut lexer = Lexer {
+        source,
+        bytes: source.as_bytes(),
+        pos: 0,
+        tokens: Vec::new(),
+        comments: Vec::new(),
+        errors: Vec::new(),
+    };
+    lexer.run();
+    (lexer.tokens, lexer.comments, lexer.errors)
+}
+
+struct Lexer<'a> {
+    source: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+    tokens: Vec<Token>,
+    comments: Vec<Range<usize>>,
+    errors: Vec<CompileError>,
+}
+
+impl Lexer<'_> {
+    fn run(&mut self) {
+        while let Some(c) = self.peek(0) {
+            let start = self.pos;
+            match c {
+                b' ' | b'\t' | b'\r' | b'\n' | b'\x0b' | b'\x0c' => self.pos += 1,
+                b'/' if self.peek(1) == Some(b'/') => self.line_comment(),
+                b'/' if self.peek(1) == Some(b'*') => self.block_comment(),
+                b'a'..=b'z' | b'A'..=b'Z' | b'_' => self.ident(),
+                b'0'..=b'9' => self.number(),
+                b'.' if self.peek(1).is_some_and(|c| c.is_ascii_digit()) => self.number(),
+                b'"' | b'\'' => self.string(c),
+                _ => {
+                    let ch = self.source[start..].chars().next().unwrap_or('\u{fffd}');
+                    self.pos += ch.len_utf8();
+                    if ch.is_ascii_punctuation() {
+                        self.push(TokenKind::Symbol(ch), start);
+                    } else {
+                        self.error(start, format!("unexpected character '{}'", ch));
+                    }
+                }
+            }
+        }
+    }
+
+    fn peek(&self, ahead: usize) -> Option<u8> {
+        self.bytes.get(self.pos + ahead).copied()
+    }
+
+    fn push(&mut self, kind: TokenKind, start: usize) {
+        self.tokens.push(Token {
+            kind,
+            span: start..self.pos,
+        });
+    }
+
+    fn error(&mut self, start: usize, message: String) {
+        self.errors
+            .push(CompileError::new(start..self.pos, message));
+    }
+
+    fn line_comment(&mut self) {
+        let start = self.pos;
+        while self.peek(0).is_some_and(|c| c != b'\n') {
+            self.pos += 1;
+        }
+        self.comments.push(start..self.pos);
+    }
+
+    fn block_comment(&mut self) {
+        let start = self.pos;
+        match self.source[start + 2..].find("*/") {
+            Some(end) => {
+                self.pos = start + 2 + end + 2;
+                self.comments.push(start..self.pos);
+            }
+            None => {
+                self.pos = self.bytes.len();
+                self.error(start, "unterminated comment".to_string());
+            }
+        }
+    }
+
+    fn ident(&mut self) {
+        let start = self.pos;
+        while self
+            .peek(0)
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == b'_')
+        {
+            self.pos += 1;
+        }
+        let ident = self.source[start..self.pos].to_string();
+        self.push(TokenKind::Ident(ident), start);
+    }
+
+    fn number(&mut self) {
+        let start = self.pos;
+        let hex = self.peek(0) == Some(b'0') && matches!(self.peek(1), Some(b'x' | b'X'));
+        let mut float = false;
+        if hex {
+            self.pos += 2;
+            while self.peek(0).is_some_and(|c| c.is_ascii_hexdigit()) {
+                self.pos += 1;
+            }
+        } else {
+            while let Some(c) = self.peek(0) {
+                match c {
+                    b'0'..=b'9' => {}
+                    b'.' => float = true,
+                    b'e' | b'E' => {
+                        float = true;
+                        if matches!(self.peek(1), Some(b'+' | b'-')) {
+                            self.pos += 1;
+                        }
+                    }
+                    _ => break,
+                }
+                self.pos += 1;
+            }
+        }
+        // Identifier characters directly after a number are invalid
+        let suffix = self.pos;
+        while self
+            .peek(0)
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == b'_')
+        {
+            self.pos += 1;
+        }
+
+        let text = &self.source[start..self.pos];
+        let kind = if suffix != self.pos {
+            None
+        } else if hex {
+            u64::from_str_radix(&text[2..], 16).ok().map(TokenKind::Int)
+        } else if float {
+            text.parse().ok().map(TokenKind::Float)
+        } else if text.len() > 1 && text.starts_with('0') {
+            u64::from_str_radix(&text[1..], 8).ok().map(TokenKind::Int)
+        } else {
+            text.parse().ok().map(TokenKind::Int)
+        };
+        match kind {
+            Some(kind) => self.push(kind, start),
+            None => self.error(start, format!("invalid number '{}'", text)),
+        }
+    }
+
+    fn string(&mut self, quote: u8) {
+        let start = self.pos;
+        self.pos += 1;
+        let mut value = Vec::new();
+        loop {
+            let Some(c) = self.peek(0) else {
+                self.error(start, "unterminated string".to_string());
+                return;
+            };
+            match c {
+                b'\n' => {
+                    self.error(start, "unterminated string".to_string());
+                    return;
+                }
+                b'\\' => self.escape(&mut value),
+                c if c == quote => {
+                    self.pos += 1;
+                    break;
+                }
+                c => {
+                    value.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+        let value = String::from_utf8_lossy(&value).into_owned();
+        self.push(TokenKind::Str(value), start);
+    }
+
+    /// Decode the escape sequence at the current position into `value`.
+    fn escape(&mut self, value: &mut Vec<u8>) {
+        let start = self.pos;
+        self.pos += 1;
+        let Some(c) = self.peek(0) else {
+            return;
+        };
+        self.pos += 1;
+        match c {
+            b'a' => value.push(0x07),
+            b'b' => value.push(0x08),
+            b'f' => value.push(0x0c),
+            b'n' => value.push(b'\n'),
+            b'r' => value.push(b'\r'),
+            b't' => value.push(b'\t'),
+            b'v' => value.push(0x0b),
+            b'\\' | b'\'' | b'"' | b'?' => value.push(c),
+            b'x' | b'X' => {
+                let digits = self.digits(2, 16);
+                value.push(digits as u8);
+            }
+            b'0'..=b'7' => {
+                self.pos -= 1;
+                let digits = self.digits(3, 8);
+                value.push(digits as u8);
+            }
+            b'u' | b'U' => {
+                let len = if c == b'u' { 4 } else { 8 };
+                let code = self.digits(len, 16);
+                let ch = char::from_u32(code).unwrap_or('\u{fffd}');
+                value.extend_from_slice(ch.to_string().as_bytes());
+            }
+            _ => self.error(start, format!("invalid escape '\\{}'", c as char)),
+        }
+    }
+
+    /// Read up to `max` digits in `radix`.
+    fn digits(&mut self, max: usize, radix: u32) -> u32 {
+        let mut value = 0u32;
+        for _ in 0..max {
+            let Some(digit) = self.peek(0).and_then(|c| (c as char).to_digit(radix)) else {
+                break;
+            };
+            value = value.wrapping_mul(radix).wrapping_add(digit);
+            self.pos += 1;
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(source: &str) -> Vec<TokenKind> {
+        let (tokens, _, errors) = tokenize(source);
+        assert!(errors.is_empty(), "{:?}", errors);
+        tokens.into_iter().map(|t| t.kind).collect()
+    }
+
+    #[test]
+    fn tokenizes_statements() {
+        assert_eq!(
+            kinds("int32 id = 0x1F; // trailing\n/* block */ float f = 1.5e3;"),
+            vec![
+                TokenKind::Ident("int32".into()),
+                TokenKind::Ident("id".into()),
+                TokenKind::Symbol('='),
+                TokenKind::Int(31),
+                TokenKind::Symbol(';'),
+                TokenKind::Ident("float".into()),
+                TokenKind::Ident("f".into()),
+                TokenKind::Symbol('='),
+                TokenKind::Float(1500.0),
+                TokenKind::Symbol(';'),
+            ]
+        );
+    }
+
+    #[test]
+    fn collects_comments() {
+        let source = "// a\nint32 id = 1; /* b */\n";
+        let (_, comments, _) = tokenize(source);
+        let texts: Vec<&str> = comments.into_iter().map(|c| &source[c]).collect();
+        assert_eq!(texts, vec!["// a", "/* b */"]);
+    }
+
+    #[test]
+    fn decodes_string_escapes() {
+        assert_eq!(
+            kinds(r#"'a\n' "\x41\101é""#),
+            vec![TokenKind::Str("a\n".into()), TokenKind::Str("AAé".into())]
+        );
+    }
+
+    #[test]
+    fn reports_invalid_input() {
+        let (_, _, errors) = tokenize("x = \"open\n");
+        assert_eq!(errors[0].message, "unterminated string");
+        let (_, _, errors) = tokenize("x = 12ab;");
+        assert_eq!(errors[0].message, "invalid number '12ab'");
+    }
+}
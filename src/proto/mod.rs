@@ -0,0 +1,35 @@
+//! In-process compiler for `.proto` sources.
+//!
+//! Parses `.proto` files and links them into a [`DescriptorPool`], so message
+//! types are available without building descriptor sets first. proto2 and
+//! proto3 files are supported; editions and groups are reported as errors, and
+//! services are skipped. Extension options are resolved by the pool, and
+//! comments are kept in `source_code_info` for documentation.
+//!
+//! [`DescriptorPool`]: prost_reflect::DescriptorPool
+
+mod ast;
+mod buf;
+mod compile;
+mod descriptor;
+mod lexer;
+mod parser;
+
+use std::ops::Range;
+
+pub use buf::discover_buf;
+pub use compile::{same_definitions, Buffers, CompileErrors, ProtoInputs};
+
+/// An error in a `.proto` file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileError {
+    /// Byte span in the file.
+    pub span: Range<usize>,
+    pub message: String,
+}
+
+impl CompileError {
+    pub fn new(span: Range<usize>, message: String) -> Self {
+        Self { span, message }
+    }
+}
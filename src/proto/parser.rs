@@ -0,0 +1,743 @@
+//! Recursive descent parser for `.proto` files.
+//!
+//! Statements that fail to parse are reported and skipped, so a single typo
+//! does not hide the rest of the file from the linker.
+
+use std::ops::Range;
+
+use super::ast::*;
+use super::lexer::{tokenize, Token, TokenKind};
+use super::CompileError;
+
+/// Largest valid field number.
+pub const MAX_FIELD_NUMBER: i32 = 536_870_911;
+
+/// Parse a `.proto` file, returning the syntax tree and every error found.
+pub fn parse(source: &str) -> (File, Vec<CompileError>) {
+    let (tokens, comments, errors) = tokenize(source);
+    let mut parser = Parser {
+        source,
+        tokens: &tokens,
+        pos: 0,
+        end: source.len(),
+        errors,
+    };
+    let mut file = parser.file();
+    file.comments = comments;
+    (file, parser.errors)
+}
+
+type PResult<T> = Result<T, CompileError>;
+
+impl Constant {
+    fn as_bool(&self) -> Option<bool> {
+        match self {
+            Constant::Ident(s) if s == "true" => Some(true),
+            Constant::Ident(s) if s == "false" => Some(false),
+            _ => None,
+        }
+    }
+
+    fn into_text(self) -> Option<String> {
+        match self {
+            Constant::Ident(s) | Constant::Number(s) | Constant::Str(s) => Some(s),
+            Constant::Aggregate(_) => None,
+        }
+    }
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: &'a [Token],
+    pos: usize,
+    end: usize,
+    errors: Vec<CompileError>,
+}
+
+impl Parser<'_> {
+    fn file(&mut self) -> File {
+        let mut file = File::default();
+        while self.pos < self.tokens.len() {
+            if let Err(e) = self.file_statement(&mut file) {
+                self.errors.push(e);
+                self.recover();
+            }
+        }
+        file
+    }
+
+    fn file_statement(&mut self, file: &mut File) -> PResult<()> {
+        if self.eat_symbol(';') {
+            return Ok(());
+        }
+        let keyword = self.expect_ident("a statement")?;
+        match keyword.value.as_str() {
+            "syntax" => {
+                self.expect_symbol('=')?;
+                let syntax = self.string()?;
+                self.expect_symbol(';')?;
+                file.syntax = match syntax.value.as_str() {
+                    "proto2" => Syntax::Proto2,
+                    "proto3" => Syntax::Proto3,
+                    other => {
+                        return Err(CompileError::new(
+                            syntax.span,
+                            format!("unknown syntax '{}'", other),
+                        ))
+                    }
+                };
+            }
+            "edition" => {
+                return Err(CompileError::new(
+                    keyword.span,
+                    "editions are not supported; use proto2 or proto3 syntax".to_string(),
+                ))
+            }
+            "package" => {
+                let package = self.full_ident()?;
+                self.expect_symbol(';')?;
+                file.package = Some(package);
+            }
+            "import" => {
+                let public = self.eat_keyword("public");
+                let weak = !public && self.eat_keyword("weak");
+                let path = self.string()?;
+                self.expect_symbol(';')?;
+                file.imports.push(Import { path, public, weak });
+            }
+            "option" => {
+                self.option_statement()?;
+            }
+            "message" => file.messages.push(self.message(keyword.span.start)?),
+            "enum" => file.enums.push(self.enumeration(keyword.span.start)?),
+            "extend" => file.extends.push(self.extend()?),
+            "service" => {
+                self.expect_ident("a service name")?;
+                self.skip_block()?;
+            }
+            other => {
+                return Err(CompileError::new(
+                    keyword.span,
+                    format!("unexpected '{}'", other),
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    fn message(&mut self, start: usize) -> PResult<Message> {
+        let name = self.expect_ident("a message name")?;
+        self.expect_symbol('{')?;
+        let mut message = Message {
+            name,
+            ..Default::default()
+        };
+        self.body(|parser| parser.message_statement(&mut message))?;
+        message.span = start..self.previous_end();
+        Ok(message)
+    }
+
+    fn message_statement(&mut self, message: &mut Message) -> PResult<()> {
+        if self.eat_symbol(';') {
+            return Ok(());
+        }
+        let start = self.current_span().start;
+        match self.peek_ident() {
+            Some("message") => {
+                self.pos += 1;
+                message.messages.push(self.message(start)?);
+            }
+            Some("enum") => {
+                self.pos += 1;
+                message.enums.push(self.enumeration(start)?);
+            }
+            Some("extend") => {
+                self.pos += 1;
+                message.extends.push(self.extend()?);
+            }
+            Some("option") => {
+                self.pos += 1;
+                let (name, value) = self.option_statement()?;
+                if name == "deprecated" {
+                    message.deprecated = value.as_bool().unwrap_or(false);
+                }
+            }
+            Some("oneof") => {
+                self.pos += 1;
+                let name = self.expect_ident("a oneof name")?;
+                let index = message.oneofs.len();
+                message.oneofs.push(name);
+                self.expect_symbol('{')?;
+                self.body(|parser| {
+                    if parser.eat_symbol(';') {
+                        return Ok(());
+                    }
+                    if parser.eat_keyword("option") {
+                        parser.option_statement()?;
+                        return Ok(());
+                    }
+                    let mut field = parser.field(None)?;
+                    field.oneof = Some(index);
+                    message.fields.push(field);
+                    Ok(())
+                })?;
+            }
+            Some("reserved") => {
+                self.pos += 1;
+                if matches!(self.peek(), Some(TokenKind::Str(_))) {
+                    loop {
+                        message.reserved_names.push(self.string()?.value);
+                        if !self.eat_symbol(',') {
+                            break;
+                        }
+                    }
+                } else {
+                    message.reserved_ranges.extend(self.ranges()?);
+                }
+                self.expect_symbol(';')?;
+            }
+            Some("extensions") => {
+                self.pos += 1;
+                message.extension_ranges.extend(self.ranges()?);
+                if self.eat_symbol('[') {
+                    self.option_list(|_, _| {})?;
+                }
+                self.expect_symbol(';')?;
+            }
+            _ => {
+                let label = self.label();
+                message.fields.push(self.field(label)?);
+            }
+        }
+        Ok(())
+    }
+
+    fn label(&mut self) -> Option<Label> {
+        let label = match self.peek_ident()? {
+            "optional" => Label::Optional,
+            "required" => Label::Required,
+            "repeated" => Label::Repeated,
+            _ => return None,
+        };
+        self.pos += 1;
+        Some(label)
+    }
+
+    /// Parse a field after its label.
+    fn field(&mut self, label: Option<Label>) -> PResult<Field> {
+        // The span starts at the label, the token before the type
+        let start = match label {
+            Some(_) => self.tokens[self.pos - 1].span.start,
+            None => self.current_span().start,
+        };
+        if self.peek_ident() == Some("group") {
+            return Err(CompileError::new(
+                self.current_span(),
+                "groups are not supported".to_string(),
+            ));
+        }
+        let field_type = if self.peek_ident() == Some("map") && self.peek_symbol_at(1, '<') {
+            self.pos += 2;
+            let key = self.type_name()?;
+            self.expect_symbol(',')?;
+            let value = self.type_name()?;
+            self.expect_symbol('>')?;
+            FieldType::Map { key, value }
+        } else {
+            FieldType::Named(self.type_name()?)
+        };
+        let name = self.expect_ident("a field name")?;
+        self.expect_symbol('=')?;
+        let number = self.int32()?;
+
+        let mut options = FieldOptions::default();
+        if self.eat_symbol('[') {
+            self.option_list(|name, value| match name {
+                "deprecated" => options.deprecated = value.as_bool().unwrap_or(false),
+                "packed" => options.packed = value.as_bool(),
+                "json_name" => options.json_name = value.into_text(),
+                "default" => options.default_value = value.into_text(),
+                _ if name.starts_with('(') => options.custom.push(CustomOption {
+                    name: name.to_string(),
+                    value,
+                }),
+                _ => {}
+            })?;
+        }
+        self.expect_symbol(';')?;
+
+        Ok(Field {
+            label,
+            field_type,
+            name,
+            number,
+            oneof: None,
+            span: start..self.previous_end(),
+            options,
+        })
+    }
+
+    fn enumeration(&mut self, start: usize) -> PResult<Enum> {
+        let name = self.expect_ident("an enum name")?;
+        self.expect_symbol('{')?;
+        let mut enumeration = Enum {
+            name,
+            ..Default::default()
+        };
+        self.body(|parser| {
+            if parser.eat_symbol(';') {
+                return Ok(());
+            }
+            if parser.eat_keyword("option") {
+                let (name, value) = parser.option_statement()?;
+                match name.as_str() {
+                    "allow_alias" => enumeration.allow_alias = value.as_bool().unwrap_or(false),
+                    "deprecated" => enumeration.deprecated = value.as_bool().unwrap_or(false),
+                    _ => {}
+                }
+                return Ok(());
+            }
+            if parser.eat_keyword("reserved") {
+                while parser.peek().is_some_and(|t| *t != TokenKind::Symbol(';')) {
+                    parser.pos += 1;
+                }
+                parser.expect_symbol(';')?;
+                return Ok(());
+            }
+
+            let name = parser.expect_ident("an enum value name")?;
+            parser.expect_symbol('=')?;
+            let number = parser.int32()?;
+            let mut deprecated = false;
+            if parser.eat_symbol('[') {
+                parser.option_list(|name, value| {
+                    if name == "deprecated" {
+                        deprecated = value.as_bool().unwrap_or(false);
+                    }
+                })?;
+            }
+            parser.expect_symbol(';')?;
+            enumeration.values.push(EnumValue {
+                span: name.span.start..parser.previous_end(),
+                name,
+                number,
+                deprecated,
+            });
+            Ok(())
+        })?;
+        enumeration.span = start..self.previous_end();
+        Ok(enumeration)
+    }
+
+    fn extend(&mut self) -> PResult<Extend> {
+        let extendee = self.type_name()?;
+        self.expect_symbol('{')?;
+        let mut fields = Vec::new();
+        self.body(|parser| {
+            if parser.eat_symbol(';') {
+                return Ok(());
+            }
+            let label = parser.label();
+            fields.push(parser.field(label)?);
+            Ok(())
+        })?;
+        Ok(Extend { extendee, fields })
+    }
+
+    /// Parse statements up to the closing `}` of a block whose `{` was consumed.
+    fn body(&mut self, mut statement: impl FnMut(&mut Self) -> PResult<()>) -> PResult<()> {
+        loop {
+            if self.eat_symbol('}') {
+                return Ok(());
+            }
+            if self.pos >= self.tokens.len() {
+                return Err(CompileError::new(
+                    self.end..self.end,
+                    "expected '}'".to_string(),
+                ));
+            }
+            if let Err(e) = statement(self) {
+                self.errors.push(e);
+                self.recover();
+            }
+        }
+    }
+
+    /// Parse `option name = constant;` after the `option` keyword.
+    fn option_statement(&mut self) -> PResult<(String, Constant)> {
+        let name = self.option_name()?;
+        self.expect_symbol('=')?;
+        let value = self.constant()?;
+        self.expect_symbol(';')?;
+        Ok((name, value))
+    }
+
+    /// Parse `name = constant, ...]` after the opening `[`.
+    fn option_list(&mut self, mut apply: impl FnMut(&str, Constant)) -> PResult<()> {
+        loop {
+            let name = self.option_name()?;
+            self.expect_symbol('=')?;
+            let value = self.constant()?;
+            apply(&name, value);
+            if !self.eat_symbol(',') {
+                break;
+            }
+        }
+        self.expect_symbol(']')?;
+        Ok(())
+    }
+
+    /// Parse an option name such as `deprecated` or `(buf.validate.field).cel`.
+    fn option_name(&mut self) -> PResult<String> {
+        let mut name = String::new();
+        loop {
+            if self.eat_symbol('(') {
+                let extension = self.type_name()?;
+                self.expect_symbol(')')?;
+                name.push('(');
+                name.push_str(&extension.value);
+                name.push(')');
+            } else {
+                name.push_str(&self.expect_ident("an option name")?.value);
+            }
+            if !self.eat_symbol('.') {
+                return Ok(name);
+            }
+            name.push('.');
+        }
+    }
+
+    fn constant(&mut self) -> PResult<Constant> {
+        let span = self.current_span();
+        match self.peek().cloned() {
+            Some(TokenKind::Ident(ident)) => {
+                self.pos += 1;
+                Ok(Constant::Ident(ident))
+            }
+            Some(TokenKind::Str(_)) => {
+                let mut value = String::new();
+                while let Some(TokenKind::Str(part)) = self.peek() {
+                    value.push_str(part);
+                    self.pos += 1;
+                }
+                Ok(Constant::Str(value))
+            }
+            Some(TokenKind::Symbol('{')) => {
+                let block = self.skip_block()?;
+                let text = &self.source[block.start + 1..block.end - 1];
+                Ok(Constant::Aggregate(text.trim().to_string()))
+            }
+            Some(TokenKind::Symbol(sign @ ('-' | '+'))) => {
+                self.pos += 1;
+                let number = self.number_text()?;
+                Ok(Constant::Number(if sign == '-' {
+                    format!("-{}", number)
+                } else {
+                    number
+                }))
+            }
+            Some(TokenKind::Int(_) | TokenKind::Float(_)) => {
+                Ok(Constant::Number(self.number_text()?))
+            }
+            _ => Err(self.expected("a constant", span)),
+        }
+    }
+
+    /// Read a number literal, or `inf`/`nan`, as text.
+    fn number_text(&mut self) -> PResult<String> {
+        let span = self.current_span();
+        let text = match self.peek() {
+            Some(TokenKind::Int(n)) => n.to_string(),
+            Some(TokenKind::Float(f)) => f.to_string(),
+            Some(TokenKind::Ident(s)) if s == "inf" || s == "nan" => s.clone(),
+            _ => return Err(self.expected("a number", span)),
+        };
+        self.pos += 1;
+        Ok(text)
+    }
+
+    /// Parse comma separated ranges such as `1, 5 to 10, 100 to max`.
+    fn ranges(&mut self) -> PResult<Vec<Range<i32>>> {
+        let mut ranges = Vec::new();
+        loop {
+            let start = self.int32()?.value;
+            let end = if self.eat_keyword("to") {
+                if self.eat_keyword("max") {
+                    MAX_FIELD_NUMBER
+                } else {
+                    self.int32()?.value
+                }
+            } else {
+                start
+            };
+            ranges.push(start..end.saturating_add(1));
+            if !self.eat_symbol(',') {
+                return Ok(ranges);
+            }
+        }
+    }
+
+    fn int32(&mut self) -> PResult<Spanned<i32>> {
+        let start = self.current_span().start;
+        let negative = self.eat_symbol('-');
+        let span = self.current_span();
+        let Some(TokenKind::Int(n)) = self.peek() else {
+            return Err(self.expected("an integer", span));
+        };
+        let value = if negative { -(*n as i128) } else { *n as i128 };
+        let value = i32::try_from(value)
+            .map_err(|_| CompileError::new(start..span.end, "integer out of range".to_string()))?;
+        self.pos += 1;
+        Ok(Spanned {
+            value,
+            span: start..span.end,
+        })
+    }
+
+    fn string(&mut self) -> PResult<Spanned<String>> {
+        let span = self.current_span();
+        match self.peek() {
+            Some(TokenKind::Str(value)) => {
+                let value = value.clone();
+                self.pos += 1;
+                Ok(Spanned { value, span })
+            }
+            _ => Err(self.expected("a string", span)),
+        }
+    }
+
+    /// Parse a possibly fully-qualified type name such as `.pkg.Message`.
+    fn type_name(&mut self) -> PResult<Spanned<String>> {
+        let start = self.current_span().start;
+        let leading_dot = self.eat_symbol('.');
+        let mut name = self.full_ident()?;
+        if leading_dot {
+            name.value.insert(0, '.');
+            name.span.start = start;
+        }
+        Ok(name)
+    }
+
+    /// Parse dot-separated identifiers.
+    fn full_ident(&mut self) -> PResult<Spanned<String>> {
+        let mut name = self.expect_ident("an identifier")?;
+        while self.peek_symbol_at(0, '.') {
+            self.pos += 1;
+            let part = self.expect_ident("an identifier")?;
+            name.value.push('.');
+            name.value.push_str(&part.value);
+            name.span.end = part.span.end;
+        }
+        Ok(name)
+    }
+
+    /// Skip a balanced `{ ... }` block starting at the current token,
+    /// returning its span.
+    fn skip_block(&mut self) -> PResult<Range<usize>> {
+        let start = self.current_span().start;
+        self.expect_symbol('{')?;
+        let mut depth = 1;
+        while let Some(token) = self.tokens.get(self.pos) {
+            self.pos += 1;
+            match token.kind {
+                TokenKind::Symbol('{') => depth += 1,
+                TokenKind::Symbol('}') => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(start..token.span.end);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Err(CompileError::new(
+            self.end..self.end,
+            "expected '}'".to_string(),
+        ))
+    }
+
+    /// Skip to the end of the current statement after an error.
+    ///
+    /// Stops after a `;` or a complete block, or before a `}` that closes the
+    /// enclosing block.
+    fn recover(&mut self) {
+        let mut depth = 0;
+        while let Some(token) = self.tokens.get(self.pos) {
+            match token.kind {
+                TokenKind::Symbol(';') if depth == 0 => {
+                    self.pos += 1;
+                    return;
+                }
+                TokenKind::Symbol('{') => depth += 1,
+                TokenKind::Symbol('}') if depth == 0 => return,
+                TokenKind::Symbol('}') => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.pos += 1;
+                        return;
+                    }
+                }
+                _ => {}
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.pos).map(|t| &t.kind)
+    }
+
+    fn peek_ident(&self) -> Option<&str> {
+        match self.peek() {
+            Some(TokenKind::Ident(ident)) => Some(ident),
+            _ => None,
+        }
+    }
+
+    fn peek_symbol_at(&self, ahead: usize, symbol: char) -> bool {
+        matches!(
+            self.tokens.get(self.pos + ahead).map(|t| &t.kind),
+            Some(TokenKind::Symbol(c)) if *c == symbol
+        )
+    }
+
+    fn eat_symbol(&mut self, symbol: char) -> bool {
+        let matched = self.peek_symbol_at(0, symbol);
+        if matched {
+            self.pos += 1;
+        }
+        matched
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let matched = self.peek_ident() == Some(keyword);
+        if matched {
+            self.pos += 1;
+        }
+        matched
+    }
+
+    fn expect_symbol(&mut self, symbol: char) -> PResult<Range<usize>> {
+        let span = self.current_span();
+        if self.eat_symbol(symbol) {
+            Ok(span)
+        } else {
+            Err(self.expected(&format!("'{}'", symbol), span))
+        }
+    }
+
+    fn expect_ident(&mut self, what: &str) -> PResult<Spanned<String>> {
+        let span = self.current_span();
+        match self.peek_ident() {
+            Some(ident) => {
+                let value = ident.to_string();
+                self.pos += 1;
+                Ok(Spanned { value, span })
+            }
+            None => Err(self.expected(what, span)),
+        }
+    }
+
+    fn expected(&self, what: &str, span: Range<usize>) -> CompileError {
+        let found = match self.peek() {
+            Some(TokenKind::Ident(ident)) => format!("'{}'", ident),
+            Some(TokenKind::Int(n)) => format!("'{}'", n),
+            Some(TokenKind::Float(f)) => format!("'{}'", f),
+            Some(TokenKind::Str(_)) => "a string".to_string(),
+            Some(TokenKind::Symbol(c)) => format!("'{}'", c),
+            None => "end of file".to_string(),
+        };
+        CompileError::new(span, format!("expected {}, found {}", what, found))
+    }
+
+    /// Span of the current token, or an empty span at the end of the file.
+    fn current_span(&self) -> Range<usize> {
+        match self.tokens.get(self.pos) {
+            Some(token) => token.span.clone(),
+            None => self.end..self.end,
+        }
+    }
+
+    /// End offset of the last consumed token.
+    fn previous_end(&self) -> usize {
+        self.pos
+            .checked_sub(1)
+            .and_then(|i| self.tokens.get(i))
+            .map_or(0, |t| t.span.end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_definitions() {
+        let (file, errors) = parse(
+            r#"
+            syntax = "proto3";
+            package acme.v1;
+            import public "other.proto";
+            option go_package = "acme/v1";
+
+            message User {
+              option deprecated = true;
+              string name = 1 [(buf.validate.field).string = { min_len: 1 }];
+              map<string, int64> scores = 2;
+              oneof contact {
+                string email = 3;
+                string phone = 4 [deprecated = true];
+              }
+              repeated .acme.v1.User friends = 5;
+              reserved 8, 10 to max;
+              reserved "legacy";
+              enum Status { STATUS_UNSPECIFIED = 0; ACTIVE = 1; }
+            }
+
+            service Users { rpc Get(User) returns (User); }
+            "#,
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(file.syntax, Syntax::Proto3);
+        assert_eq!(file.package.unwrap().value, "acme.v1");
+        assert!(file.imports[0].public);
+
+        let user = &file.messages[0];
+        assert!(user.deprecated);
+        assert_eq!(user.fields.len(), 5);
+        assert!(matches!(user.fields[1].field_type, FieldType::Map { .. }));
+        assert_eq!(user.fields[3].oneof, Some(0));
+        assert!(user.fields[3].options.deprecated);
+        assert_eq!(user.fields[4].label, Some(Label::Repeated));
+        assert_eq!(user.reserved_ranges, vec![8..9, 10..MAX_FIELD_NUMBER + 1]);
+        assert_eq!(user.reserved_names, vec!["legacy"]);
+        assert_eq!(user.enums[0].values.len(), 2);
+    }
+
+    #[test]
+    fn recovers_after_errors() {
+        let (file, errors) = parse(
+            r#"
+            syntax = "proto3";
+            message A {
+              string name = ;
+              int32 age = 2;
+            }
+            message B {}
+            "#,
+        );
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "expected an integer, found ';'");
+        assert_eq!(file.messages.len(), 2);
+        assert_eq!(file.messages[0].fields.len(), 1);
+    }
+
+    #[test]
+    fn rejects_editions_and_groups() {
+        let (_, errors) = parse(r#"edition = "2023";"#);
+        assert!(errors[0].message.starts_with("editions are not supported"));
+
+        let (_, errors) = parse("message A { optional group G = 1 { } }");
+        assert_eq!(errors[0].message, "groups are not supported");
+    }
+}
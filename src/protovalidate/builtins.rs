@@ -4,87 +4,234 @@
 //! validation library from Buf. These functions are available in CEL expressions
 //! within protovalidate annotations.
 //!
+//! [`LIBRARY`] is the single list of functions: declarations for the checker
+//! and docs for hover and completion are both derived from it, gated by the
+//! release that added each function.
+//!
 //! See: https://buf.build/docs/protovalidate/
 
 use std::collections::HashMap;
 use std::sync::LazyLock;
 
+use cel_core::types::{FunctionDecl, OverloadDecl};
+use cel_core::CelType;
+
+use super::ProtovalidateVersion;
 use crate::types::FunctionDef;
 
-/// Protovalidate-specific extension functions, lazily initialized.
-pub static PROTOVALIDATE_BUILTINS: LazyLock<HashMap<&'static str, FunctionDef>> = LazyLock::new(
-    || {
-        let defs = vec![
+/// A function of the protovalidate CEL library.
+pub struct LibraryFunction {
+    /// Documentation for hover and completion.
+    pub def: FunctionDef,
+    /// The release that added the function.
+    pub since: ProtovalidateVersion,
+    /// Overloads as `(id, argument types, result type)`. The first argument
+    /// is the receiver unless the function is `global`.
+    overloads: Vec<(&'static str, Vec<CelType>, CelType)>,
+    /// Whether the function is called as `f(x)` rather than `x.f()`.
+    global: bool,
+}
+
+impl LibraryFunction {
+    /// The checker declaration of the function.
+    pub fn decl(&self) -> FunctionDecl {
+        let overloads = self.overloads.iter().map(|(id, params, result)| {
+            if self.global {
+                OverloadDecl::function(*id, params.clone(), result.clone())
+            } else {
+                OverloadDecl::method(*id, params.clone(), result.clone())
+            }
+        });
+        FunctionDecl::new(self.def.name).with_overloads(overloads)
+    }
+}
+
+fn method(
+    def: FunctionDef,
+    since: ProtovalidateVersion,
+    overloads: Vec<(&'static str, Vec<CelType>, CelType)>,
+) -> LibraryFunction {
+    LibraryFunction {
+        def,
+        since,
+        overloads,
+        global: false,
+    }
+}
+
+/// All protovalidate library functions, lazily initialized.
+pub static LIBRARY: LazyLock<Vec<LibraryFunction>> = LazyLock::new(|| {
+    use CelType::{Bool, Bytes, Double, Dyn, Int, String, UInt};
+    let oldest = ProtovalidateVersion::OLDEST;
+
+    vec![
         // ==================== String Validation Methods ====================
-        FunctionDef {
-            name: "isEmail",
-            signature: "(string) -> bool",
-            description: "Returns true if the string is a valid email address according to RFC 5322.",
-            example: Some("this.isEmail()"),
-        },
-        FunctionDef {
-            name: "isHostname",
-            signature: "(string) -> bool",
-            description: "Returns true if the string is a valid hostname according to RFC 1123.",
-            example: Some("this.isHostname()"),
-        },
-        FunctionDef {
-            name: "isIp",
-            signature: "(string, version?) -> bool",
-            description: "Returns true if the string is a valid IP address. Optional version parameter: 4 for IPv4, 6 for IPv6.",
-            example: Some("this.isIp() || this.isIp(4)"),
-        },
-        FunctionDef {
-            name: "isIpPrefix",
-            signature: "(string, version?, strict?) -> bool",
-            description: "Returns true if the string is a valid IP prefix (CIDR notation). Optional version (4 or 6) and strict mode parameters.",
-            example: Some("this.isIpPrefix()"),
-        },
-        FunctionDef {
-            name: "isUri",
-            signature: "(string) -> bool",
-            description: "Returns true if the string is a valid URI according to RFC 3986.",
-            example: Some("this.isUri()"),
-        },
-        FunctionDef {
-            name: "isUriRef",
-            signature: "(string) -> bool",
-            description: "Returns true if the string is a valid URI reference (can be relative).",
-            example: Some("this.isUriRef()"),
-        },
+        method(
+            FunctionDef {
+                name: "isEmail",
+                signature: "(string) -> bool",
+                description: "Returns true if the string is a valid email address according to RFC 5322.",
+                example: Some("this.isEmail()"),
+            },
+            oldest,
+            vec![("isEmail_string", vec![String], Bool)],
+        ),
+        method(
+            FunctionDef {
+                name: "isHostname",
+                signature: "(string) -> bool",
+                description: "Returns true if the string is a valid hostname according to RFC 1123.",
+                example: Some("this.isHostname()"),
+            },
+            oldest,
+            vec![("isHostname_string", vec![String], Bool)],
+        ),
+        method(
+            FunctionDef {
+                name: "isIp",
+                signature: "(string, version?) -> bool",
+                description: "Returns true if the string is a valid IP address. Optional version parameter: 4 for IPv4, 6 for IPv6.",
+                example: Some("this.isIp() || this.isIp(4)"),
+            },
+            oldest,
+            vec![
+                ("isIp_string", vec![String], Bool),
+                ("isIp_string_int", vec![String, Int], Bool),
+            ],
+        ),
+        method(
+            FunctionDef {
+                name: "isIpPrefix",
+                signature: "(string, version?, strict?) -> bool",
+                description: "Returns true if the string is a valid IP prefix (CIDR notation). Optional version (4 or 6) and strict mode parameters.",
+                example: Some("this.isIpPrefix()"),
+            },
+            oldest,
+            vec![
+                ("isIpPrefix_string", vec![String], Bool),
+                ("isIpPrefix_string_int", vec![String, Int], Bool),
+                ("isIpPrefix_string_bool", vec![String, Bool], Bool),
+                ("isIpPrefix_string_int_bool", vec![String, Int, Bool], Bool),
+            ],
+        ),
+        method(
+            FunctionDef {
+                name: "isUri",
+                signature: "(string) -> bool",
+                description: "Returns true if the string is a valid URI according to RFC 3986.",
+                example: Some("this.isUri()"),
+            },
+            oldest,
+            vec![("isUri_string", vec![String], Bool)],
+        ),
+        method(
+            FunctionDef {
+                name: "isUriRef",
+                signature: "(string) -> bool",
+                description: "Returns true if the string is a valid URI reference (can be relative).",
+                example: Some("this.isUriRef()"),
+            },
+            oldest,
+            vec![("isUriRef_string", vec![String], Bool)],
+        ),
+        method(
+            FunctionDef {
+                name: "isHostAndPort",
+                signature: "(string, port_required) -> bool",
+                description: "Returns true if the string is a valid hostname or IP address, optionally followed by a port. IPv6 addresses must be enclosed in brackets. If port_required is true, the port is mandatory.",
+                example: Some("this.isHostAndPort(true)"),
+            },
+            ProtovalidateVersion::new(0, 6),
+            vec![("isHostAndPort_string_bool", vec![String, Bool], Bool)],
+        ),
 
         // ==================== List Methods ====================
-        FunctionDef {
-            name: "unique",
-            signature: "(list) -> bool",
-            description: "Returns true if all elements in the list are unique.",
-            example: Some("this.unique()"),
-        },
+        method(
+            FunctionDef {
+                name: "unique",
+                signature: "(list<T>) -> bool",
+                description: "Returns true if all elements in the list are unique. Supported for lists of bool, int, uint, double, string and bytes.",
+                example: Some("this.unique()"),
+            },
+            oldest,
+            vec![
+                ("unique_list_bool", vec![CelType::list(Bool)], Bool),
+                ("unique_list_int", vec![CelType::list(Int)], Bool),
+                ("unique_list_uint", vec![CelType::list(UInt)], Bool),
+                ("unique_list_double", vec![CelType::list(Double)], Bool),
+                ("unique_list_string", vec![CelType::list(String)], Bool),
+                ("unique_list_bytes", vec![CelType::list(Bytes)], Bool),
+            ],
+        ),
 
         // ==================== Numeric Methods ====================
-        FunctionDef {
-            name: "isNan",
-            signature: "(double) -> bool",
-            description: "Returns true if the double value is NaN (Not a Number).",
-            example: Some("this.isNan()"),
-        },
-        FunctionDef {
-            name: "isInf",
-            signature: "(double, sign?) -> bool",
-            description: "Returns true if the double value is infinite. Optional sign: 1 for +Inf, -1 for -Inf, 0 for either.",
-            example: Some("this.isInf() || this.isInf(1)"),
+        method(
+            FunctionDef {
+                name: "isNan",
+                signature: "(double) -> bool",
+                description: "Returns true if the double value is NaN (Not a Number).",
+                example: Some("this.isNan()"),
+            },
+            oldest,
+            vec![("isNan_double", vec![Double], Bool)],
+        ),
+        method(
+            FunctionDef {
+                name: "isInf",
+                signature: "(double, sign?) -> bool",
+                description: "Returns true if the double value is infinite. Optional sign: 1 for +Inf, -1 for -Inf, 0 for either.",
+                example: Some("this.isInf() || this.isInf(1)"),
+            },
+            oldest,
+            vec![
+                ("isInf_double", vec![Double], Bool),
+                ("isInf_double_int", vec![Double, Int], Bool),
+            ],
+        ),
+
+        // ==================== Messages ====================
+        LibraryFunction {
+            def: FunctionDef {
+                name: "getField",
+                signature: "(message, string) -> dyn",
+                description: "Returns the value of the named field of a message. Useful for fields whose names are CEL reserved words.",
+                example: Some("getField(this, 'package') != ''"),
+            },
+            since: ProtovalidateVersion::new(0, 11),
+            overloads: vec![("getField_dyn_string", vec![Dyn, String], Dyn)],
+            global: true,
         },
-    ];
+    ]
+});
+
+/// Protovalidate-specific extension functions, lazily initialized.
+pub static PROTOVALIDATE_BUILTINS: LazyLock<HashMap<&'static str, FunctionDef>> =
+    LazyLock::new(|| {
+        LIBRARY
+            .iter()
+            .map(|f| (f.def.name, f.def.clone()))
+            .collect()
+    });
 
-        defs.into_iter().map(|f| (f.name, f)).collect()
-    },
-);
+/// Declarations of the library functions available in `version`.
+pub fn protovalidate_library(version: ProtovalidateVersion) -> Vec<FunctionDecl> {
+    LIBRARY
+        .iter()
+        .filter(|f| f.since <= version)
+        .map(LibraryFunction::decl)
+        .collect()
+}
 
 /// Get documentation for a protovalidate function by name.
 pub fn get_protovalidate_builtin(name: &str) -> Option<&'static FunctionDef> {
     PROTOVALIDATE_BUILTINS.get(name)
 }
 
+/// Get a library function by name.
+pub fn get_library_function(name: &str) -> Option<&'static LibraryFunction> {
+    LIBRARY.iter().find(|f| f.def.name == name)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -95,6 +242,8 @@ mod tests {
         assert!(PROTOVALIDATE_BUILTINS.contains_key("isUri"));
         assert!(PROTOVALIDATE_BUILTINS.contains_key("unique"));
         assert!(PROTOVALIDATE_BUILTINS.contains_key("isNan"));
+        assert!(PROTOVALIDATE_BUILTINS.contains_key("isHostAndPort"));
+        assert!(PROTOVALIDATE_BUILTINS.contains_key("getField"));
     }
 
     #[test]
@@ -119,4 +268,19 @@ mod tests {
             assert!(!builtin.description.is_empty());
         }
     }
+
+    #[test]
+    fn library_is_gated_by_version() {
+        let names = |version| -> Vec<_> {
+            protovalidate_library(version)
+                .into_iter()
+                .map(|f| f.name.clone())
+                .collect()
+        };
+        let oldest = names(ProtovalidateVersion::OLDEST);
+        assert!(oldest.contains(&"isEmail".to_string()));
+        assert!(!oldest.contains(&"isHostAndPort".to_string()));
+        assert!(!oldest.contains(&"getField".to_string()));
+        assert_eq!(names(ProtovalidateVersion::LATEST).len(), LIBRARY.len());
+    }
 }
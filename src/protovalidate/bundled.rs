@@ -0,0 +1,119 @@
+//! Bundled `buf/validate/validate.proto`.
+//!
+//! Compiled into every proto registry so `buf.validate` options and rule
+//! messages type-check without the user's descriptors including them. One
+//! source is embedded per release that changed the definitions, so rules a
+//! release cannot express fail to compile.
+//!
+//! The sources are trimmed copies, not the upstream files: they keep the
+//! messages, fields and enums needed for type checking, without the CEL
+//! expressions of the standard rules, and their comments may differ from
+//! upstream. Field docs shown on hover are therefore approximate. Releases between two embedded
+//! sources use the older one; in particular the 0.11 source stands in for
+//! every release up to 1.0, so additions after 0.11 are missing. Users who
+//! need exact definitions can provide `buf/validate/validate.proto` through
+//! `descriptors`, `sources` or `include_paths`, which takes precedence.
+
+use super::ProtovalidateVersion;
+
+/// Import name of the bundled file.
+pub const VALIDATE_PROTO: &str = "buf/validate/validate.proto";
+
+/// Embedded sources by the first release they apply to, newest first.
+const SOURCES: &[(ProtovalidateVersion, &str)] = &[
+    // `MessageOneofRule` and `MessageRules.oneof`
+    (
+        ProtovalidateVersion::new(0, 11),
+        include_str!("validate/v0.11.proto"),
+    ),
+    // `Constraint` messages renamed to `Rule`, `IGNORE_IF_ZERO_VALUE`
+    (
+        ProtovalidateVersion::RULES,
+        include_str!("validate/v0.10.proto"),
+    ),
+    // `example` fields, structured violation paths
+    (
+        ProtovalidateVersion::new(0, 9),
+        include_str!("validate/v0.9.proto"),
+    ),
+    // `PredefinedConstraints` and extension ranges on the rule messages
+    (
+        ProtovalidateVersion::new(0, 8),
+        include_str!("validate/v0.8.proto"),
+    ),
+    (
+        ProtovalidateVersion::OLDEST,
+        include_str!("validate/v0.5.proto"),
+    ),
+];
+
+/// The source of `buf/validate/validate.proto` for `version`.
+pub fn validate_proto(version: ProtovalidateVersion) -> &'static str {
+    SOURCES
+        .iter()
+        .find(|(since, _)| version >= *since)
+        .map_or(SOURCES[SOURCES.len() - 1].1, |(_, source)| source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{Buffers, ProtoInputs};
+
+    /// Whether `source` compiles against the bundled file of `version`.
+    fn compiles(version: &str, source: &str) -> bool {
+        let inputs = ProtoInputs {
+            bundled: vec![
+                (
+                    VALIDATE_PROTO.to_string(),
+                    validate_proto(version.parse().unwrap()).to_string(),
+                ),
+                ("user.proto".to_string(), source.to_string()),
+            ],
+            ..Default::default()
+        };
+        // Bundled files with errors are left out of the pool
+        let (pool, _) = inputs.build(&Buffers::new());
+        pool.get_file_by_name("user.proto").is_some()
+    }
+
+    #[test]
+    fn every_release_compiles() {
+        for (version, _) in SOURCES {
+            let source = "syntax = \"proto3\";\nimport \"buf/validate/validate.proto\";\n";
+            assert!(compiles(&version.to_string(), source), "{}", version);
+        }
+    }
+
+    #[test]
+    fn older_releases_lack_newer_rules() {
+        // Message options are not interpreted, so check the definitions
+        let oneof_rule = |version: &str| {
+            let source = validate_proto(version.parse().unwrap());
+            source.contains("message MessageOneofRule")
+        };
+        assert!(oneof_rule("1.0"));
+        assert!(!oneof_rule("0.10"));
+
+        let example = r#"syntax = "proto3";
+import "buf/validate/validate.proto";
+message User {
+  string name = 1 [(buf.validate.field).string.example = "ada"];
+}
+"#;
+        assert!(compiles("0.9", example));
+        assert!(!compiles("0.8", example));
+
+        let predefined = r#"syntax = "proto2";
+import "buf/validate/validate.proto";
+extend buf.validate.StringRules {
+  optional bool short = 1000 [(buf.validate.predefined).cel = {
+    id: "short"
+    expression: "this.size() < 8"
+  }];
+}
+"#;
+        assert!(compiles("0.8", predefined));
+        assert!(!compiles("0.7", predefined));
+    }
+}
@@ -1,12 +1,20 @@
 //! Protovalidate support for CEL.
 //!
 //! This module provides:
-//! - Protovalidate extension functions (isEmail, isUri, etc.) for hover documentation
+//! - Protovalidate extension functions (isEmail, isUri, etc.) per release, with
+//!   declarations and hover documentation from one list
 //! - Proto file parsing to extract CEL expressions
 //! - Context extraction for typed protovalidate validation
+//! - A bundled `buf/validate/validate.proto` for each supported release
 
 mod builtins;
+mod bundled;
 pub mod proto_parser;
+mod version;
 
-pub use builtins::{get_protovalidate_builtin, PROTOVALIDATE_BUILTINS};
+pub use builtins::{
+    get_library_function, get_protovalidate_builtin, protovalidate_library, PROTOVALIDATE_BUILTINS,
+};
+pub use bundled::{validate_proto, VALIDATE_PROTO};
 pub use proto_parser::{extract_cel_regions, ProtovalidateContext};
+pub use version::ProtovalidateVersion;
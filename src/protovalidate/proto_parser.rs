@@ -5,6 +5,7 @@
 //! `(buf.validate.message).cel`.
 
 use regex::Regex;
+use std::collections::HashMap;
 use std::sync::LazyLock;
 
 use cel_core::CelType;
@@ -112,6 +113,13 @@ pub struct ExtractedRegion {
 
     /// The context for this CEL expression (field, message, or predefined).
     pub context: ProtovalidateContext,
+
+    /// Host range of the option block containing the expression.
+    pub block: std::ops::Range<usize>,
+
+    /// Maximum sizes from `max_len`, `max_bytes`, `max_items` and `max_pairs`
+    /// rules, by path (`this`, `this.name`, `this.tags[]`).
+    pub size_hints: HashMap<String, u64>,
 }
 
 impl ExtractedRegion {
@@ -180,6 +188,11 @@ pub fn extract_cel_regions(source: &str) -> Vec<ExtractedRegion> {
                     let expr_start_in_source = mat.end() + expr_start_in_block;
 
                     // Determine context based on pattern type
+                    let size_hints = match context_type {
+                        ContextType::Field => field_size_hints(source, mat.start()),
+                        ContextType::Message => message_size_hints(source, mat.start()),
+                        ContextType::Predefined => HashMap::new(),
+                    };
                     let context = match context_type {
                         ContextType::Field => {
                             // Try to extract field and message info from surrounding context
@@ -202,7 +215,11 @@ pub fn extract_cel_regions(source: &str) -> Vec<ExtractedRegion> {
                     if let Some(extracted) =
                         extract_string_literal(source, expr_start_in_source, context)
                     {
-                        regions.push(extracted);
+                        regions.push(ExtractedRegion {
+                            block: mat.start()..block_end + 1,
+                            size_hints,
+                            ..extracted
+                        });
                     }
                 }
             }
@@ -265,6 +282,129 @@ fn extract_message_context(source: &str, position: usize) -> Option<String> {
     messages.pop().map(|(_, name)| name)
 }
 
+/// Pattern for standard rules that bound a field's size, e.g.
+/// `(buf.validate.field).string.max_len = 64` or
+/// `(buf.validate.field).repeated = { max_items: 10 }`.
+static SIZE_RULE_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\(\s*buf\.validate\.field\s*\)\s*\.\s*(string|bytes|repeated|map)\b").unwrap()
+});
+
+/// Pattern for the size limits of a standard rule.
+static MAX_SIZE_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\b(max_len|max_bytes|max_items|max_pairs)\s*[:=]\s*(\d+)").unwrap()
+});
+
+/// Pattern for a field declaration, capturing the field name.
+static FIELD_DECL_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?m)^\s*(?:repeated\s+|optional\s+)?(?:map\s*<[^>]*>|[\w.]+)\s+(\w+)\s*=\s*\d+")
+        .unwrap()
+});
+
+/// Size hints for a field-level rule: the sizes of `this` and its elements.
+fn field_size_hints(source: &str, annotation_start: usize) -> HashMap<String, u64> {
+    let line_start = source[..annotation_start]
+        .rfind('\n')
+        .map(|p| p + 1)
+        .unwrap_or(0);
+    let declaration = &source[line_start..statement_end(source, annotation_start)];
+    size_hints(declaration, "this")
+}
+
+/// Size hints for a message-level rule: the sizes of the message's fields.
+fn message_size_hints(source: &str, position: usize) -> HashMap<String, u64> {
+    static MESSAGE_PATTERN: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"\b(?:message|enum)\s+\w+\s*\{").unwrap());
+
+    // The innermost message whose body contains the rule
+    let bodies: Vec<_> = MESSAGE_PATTERN
+        .find_iter(source)
+        .filter_map(|m| Some(m.end()..find_matching_brace(source, m.end() - 1)?))
+        .collect();
+    let Some(body) = bodies
+        .iter()
+        .filter(|body| body.contains(&position))
+        .max_by_key(|body| body.start)
+    else {
+        return HashMap::new();
+    };
+    let nested: Vec<_> = bodies
+        .iter()
+        .filter(|inner| inner.start > body.start && inner.end <= body.end)
+        .collect();
+
+    let mut hints = HashMap::new();
+    for caps in FIELD_DECL_PATTERN.captures_iter(&source[body.clone()]) {
+        let decl = caps.get(0).unwrap();
+        let start = body.start + decl.start();
+        if nested.iter().any(|inner| inner.contains(&start)) {
+            continue;
+        }
+        let declaration = &source[start..statement_end(source, body.start + decl.end())];
+        hints.extend(size_hints(declaration, &format!("this.{}", &caps[1])));
+    }
+    hints
+}
+
+/// Sizes bounded by the standard rules in a field declaration, by path
+/// relative to `path`.
+fn size_hints(declaration: &str, path: &str) -> HashMap<String, u64> {
+    let mut hints = HashMap::new();
+    for rule in SIZE_RULE_PATTERN.captures_iter(declaration) {
+        let rest_start = rule.get(0).unwrap().end();
+        let rest = &declaration[rest_start..];
+        let trimmed = rest.trim_start();
+        // `kind = { ... }` or `kind.limit = N` up to the next option
+        let limits = match trimmed.strip_prefix('=').map(str::trim_start) {
+            Some(value) if value.starts_with('{') => {
+                let open = declaration.len() - value.len();
+                match find_matching_brace(declaration, open) {
+                    Some(close) => &declaration[open..close],
+                    None => continue,
+                }
+            }
+            _ => rest.split([',', ']', ';']).next().unwrap_or_default(),
+        };
+        for limit in MAX_SIZE_PATTERN.captures_iter(limits) {
+            let suffix = match (&rule[1], &limit[1]) {
+                ("string" | "bytes", "max_len" | "max_bytes")
+                | ("repeated", "max_items")
+                | ("map", "max_pairs") => "",
+                ("repeated", "max_len" | "max_bytes") => "[]",
+                _ => continue,
+            };
+            let Ok(size) = limit[2].parse::<u64>() else {
+                continue;
+            };
+            hints
+                .entry(format!("{}{}", path, suffix))
+                .and_modify(|current: &mut u64| *current = (*current).min(size))
+                .or_insert(size);
+        }
+    }
+    hints
+}
+
+/// Find the end of the statement containing `position`: its `;`, skipping
+/// nested option blocks and strings, or the end of the source.
+fn statement_end(source: &str, position: usize) -> usize {
+    let bytes = source.as_bytes();
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut pos = position;
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'\\' if in_string => pos += 1,
+            b'"' => in_string = !in_string,
+            b'{' | b'[' if !in_string => depth += 1,
+            b'}' | b']' if !in_string => depth = depth.saturating_sub(1),
+            b';' if !in_string && depth == 0 => return pos,
+            _ => {}
+        }
+        pos += 1;
+    }
+    bytes.len()
+}
+
 /// Find byte ranges of all comments in a proto source file.
 ///
 /// Handles both `//` line comments and `/* */` block comments,
@@ -418,6 +558,8 @@ fn extract_string_literal(
                 host_offset: content_start,
                 escape_adjustments,
                 context,
+                block: start..pos + 1,
+                size_hints: HashMap::new(),
             });
         } else if c == b'\\' && pos + 1 < bytes.len() {
             // Handle escape sequence
@@ -590,6 +732,19 @@ message User {
         assert_eq!(find_matching_brace(s, 0), Some(14));
     }
 
+    #[test]
+    fn records_option_block() {
+        let proto = r#"string id = 1 [(buf.validate.field).cel = {
+    id: "x"
+    expression: "this != ''"
+}];"#;
+        let regions = extract_cel_regions(proto);
+        assert_eq!(regions.len(), 1);
+        let block = &proto[regions[0].block.clone()];
+        assert!(block.starts_with("(buf.validate.field).cel = {"));
+        assert!(block.ends_with("this != ''\"\n}"));
+    }
+
     #[test]
     fn extracts_field_context() {
         let proto = r#"
@@ -616,6 +771,47 @@ message User {
         }
     }
 
+    #[test]
+    fn extracts_size_hints() {
+        let proto = r#"
+message User {
+    string name = 1 [(buf.validate.field).string.max_len = 64, (buf.validate.field).cel = {
+        expression: "this.startsWith('a')"
+    }];
+    repeated string tags = 2 [(buf.validate.field).repeated = {
+        max_items: 10, items: { string: { max_len: 32 } }
+    }];
+    map<string, string> labels = 3 [(buf.validate.field).map.max_pairs = 5];
+    message Inner {
+        string id = 1 [(buf.validate.field).string.max_len = 8];
+    }
+    option (buf.validate.message).cel = {
+        expression: "this.tags.all(t, t != this.name)"
+    };
+}
+"#;
+        let regions = extract_cel_regions(proto);
+        let hints = |i: usize| {
+            let mut hints: Vec<_> = regions[i]
+                .size_hints
+                .iter()
+                .map(|(path, size)| (path.as_str(), *size))
+                .collect();
+            hints.sort_unstable();
+            hints
+        };
+        assert_eq!(hints(0), vec![("this", 64)]);
+        assert_eq!(
+            hints(1),
+            vec![
+                ("this.labels", 5),
+                ("this.name", 64),
+                ("this.tags", 10),
+                ("this.tags[]", 32)
+            ]
+        );
+    }
+
     #[test]
     fn context_this_type_for_string_field() {
         let context = ProtovalidateContext::Field {
@@ -0,0 +1,77 @@
+//! Protovalidate release selection.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// A protovalidate `major.minor` release selecting the bundled definitions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ProtovalidateVersion {
+    major: u32,
+    minor: u32,
+}
+
+impl ProtovalidateVersion {
+    /// The oldest supported release, the first with proto2 `validate.proto`.
+    pub const OLDEST: Self = Self::new(0, 5);
+    /// The newest supported release, used when no version is configured.
+    pub const LATEST: Self = Self::new(1, 0);
+    /// The release that renamed `Constraint` messages to `Rule`.
+    pub const RULES: Self = Self::new(0, 10);
+
+    pub(super) const fn new(major: u32, minor: u32) -> Self {
+        Self { major, minor }
+    }
+}
+
+impl Default for ProtovalidateVersion {
+    fn default() -> Self {
+        Self::LATEST
+    }
+}
+
+impl FromStr for ProtovalidateVersion {
+    type Err = String;
+
+    /// Parse `0.14`, `v1.0` or `1.0.2`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed = s.trim();
+        let trimmed = trimmed.strip_prefix('v').unwrap_or(trimmed);
+        let mut parts = trimmed.split('.');
+        let major = parts.next().and_then(|p| p.parse::<u32>().ok());
+        let minor = parts.next().and_then(|p| p.parse::<u32>().ok());
+        let (Some(major), Some(minor)) = (major, minor) else {
+            return Err(format!("invalid protovalidate version '{}'", s));
+        };
+
+        let version = Self::new(major, minor);
+        if version < Self::OLDEST || version > Self::LATEST {
+            return Err(format!(
+                "unsupported protovalidate version '{}' (supported: {} to {})",
+                s,
+                Self::OLDEST,
+                Self::LATEST
+            ));
+        }
+        Ok(version)
+    }
+}
+
+impl fmt::Display for ProtovalidateVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_versions() {
+        assert_eq!("0.14".parse(), Ok(ProtovalidateVersion::new(0, 14)));
+        assert_eq!("v1.0.2".parse(), Ok(ProtovalidateVersion::LATEST));
+        assert!("1".parse::<ProtovalidateVersion>().is_err());
+        assert!("0.1".parse::<ProtovalidateVersion>().is_err());
+        assert!("2.0".parse::<ProtovalidateVersion>().is_err());
+    }
+}
@@ -0,0 +1,472 @@
+//! Object types imported from JSON Schema and OpenAPI documents.
+//!
+//! `[env.schemas]` names schema documents (JSON or YAML), and type strings
+//! refer to schemas inside them as `name:#/json/pointer`, for example
+//! `openapi:#/components/schemas/User`. Referenced schemas are converted on
+//! demand into synthetic types: objects with `properties` become messages,
+//! arrays become lists, `additionalProperties` becomes `map(string, T)`, and
+//! string formats select timestamp, duration or bytes. Local `$ref`s and
+//! `allOf` compositions are followed.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use cel_core::CelType;
+use serde_json::{Map, Value};
+use tracing::warn;
+
+use crate::kubernetes::{parse_documents, YamlNode};
+use crate::types::SyntheticTypes;
+
+/// Maximum depth of nested `allOf` compositions that are merged.
+const MAX_ALL_OF_DEPTH: usize = 16;
+
+/// Schema documents and the types converted from them so far.
+#[derive(Debug, Default)]
+pub struct SchemaImporter {
+    /// Schema documents by name.
+    documents: HashMap<String, Value>,
+    /// Types of converted schemas, keyed by `name:#pointer`.
+    imported: HashMap<String, CelType>,
+    /// The reference each message name was assigned to.
+    names: HashMap<String, String>,
+}
+
+impl SchemaImporter {
+    /// Load schema documents, resolving relative paths against `root`.
+    ///
+    /// Documents that cannot be read or parsed are skipped with a warning.
+    pub fn load(schemas: &HashMap<String, PathBuf>, root: Option<&Path>) -> Self {
+        let mut importer = Self::default();
+        for (name, path) in schemas {
+            let full_path = match root {
+                Some(root) if path.is_relative() => root.join(path),
+                _ => path.clone(),
+            };
+            match std::fs::read_to_string(&full_path) {
+                Ok(source) => match parse_schema_document(&source) {
+                    Some(document) => importer.add_document(name, document),
+                    None => {
+                        warn!("failed to parse schema document '{}'", full_path.display());
+                    }
+                },
+                Err(e) => {
+                    warn!(
+                        "failed to read schema document '{}': {}",
+                        full_path.display(),
+                        e
+                    );
+                }
+            }
+        }
+        importer
+    }
+
+    /// Add a parsed schema document under `name`.
+    pub fn add_document(&mut self, name: &str, document: Value) {
+        self.documents.insert(name.to_string(), document);
+    }
+
+    /// Resolve a schema reference such as `openapi:#/components/schemas/User`,
+    /// declaring the message types it needs in `types`.
+    pub fn resolve(
+        &mut self,
+        reference: &str,
+        types: &mut SyntheticTypes,
+    ) -> Result<CelType, String> {
+        let (document, fragment) = reference
+            .split_once(':')
+            .ok_or_else(|| format!("malformed schema reference '{}'", reference))?;
+        let pointer = fragment.strip_prefix('#').ok_or_else(|| {
+            format!(
+                "schema reference '{}' must use a '#' fragment, e.g. '{}:#/definitions/Name'",
+                reference, document
+            )
+        })?;
+        let Some(root) = self.documents.get(document) else {
+            return Err(format!("unknown schema document '{}'", document));
+        };
+        if root.pointer(pointer).is_none() {
+            return Err(format!("schema '{}' not found", reference));
+        }
+
+        let mut converter = Converter {
+            documents: &self.documents,
+            imported: &mut self.imported,
+            names: &mut self.names,
+            types,
+        };
+        Ok(converter.import(document, pointer))
+    }
+}
+
+/// Converts schemas of one importer into synthetic types.
+struct Converter<'a> {
+    documents: &'a HashMap<String, Value>,
+    imported: &'a mut HashMap<String, CelType>,
+    names: &'a mut HashMap<String, String>,
+    types: &'a mut SyntheticTypes,
+}
+
+impl<'a> Converter<'a> {
+    /// Convert the schema at `pointer` in `document`, once.
+    fn import(&mut self, document: &str, pointer: &str) -> CelType {
+        let key = format!("{}:#{}", document, pointer);
+        if let Some(cel_type) = self.imported.get(&key) {
+            return cel_type.clone();
+        }
+        let documents = self.documents;
+        let Some(schema) = documents.get(document).and_then(|d| d.pointer(pointer)) else {
+            warn!("schema reference '{}' not found", key);
+            return CelType::Dyn;
+        };
+
+        let name = self.message_name(document, pointer, &key);
+        if self.is_object(document, schema) {
+            // Register the message first so recursive references terminate
+            let message = CelType::message(&name);
+            self.imported.insert(key, message.clone());
+            self.convert_object(document, schema, &name);
+            message
+        } else {
+            self.imported.insert(key.clone(), CelType::Dyn);
+            let cel_type = self.schema_type(document, schema, &name);
+            self.imported.insert(key, cel_type.clone());
+            cel_type
+        }
+    }
+
+    /// Name a message after the last pointer segment, qualifying it with the
+    /// document name if another schema already uses that name.
+    fn message_name(&mut self, document: &str, pointer: &str, key: &str) -> String {
+        let base = pointer
+            .rsplit('/')
+            .next()
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| segment.replace("~1", "/").replace("~0", "~"))
+            .unwrap_or_else(|| document.to_string());
+        let name = match self.names.get(&base) {
+            Some(owner) if owner != key => format!("{}.{}", document, base),
+            _ => base,
+        };
+        self.names.insert(name.clone(), key.to_string());
+        name
+    }
+
+    fn schema_type(&mut self, document: &str, schema: &'a Value, name: &str) -> CelType {
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            return self.follow_ref(document, reference);
+        }
+        if self.is_object(document, schema) {
+            return self.convert_object(document, schema, name);
+        }
+
+        let type_name = match schema.get("type") {
+            Some(Value::String(type_name)) => Some(type_name.as_str()),
+            // JSON Schema type unions such as ["string", "null"]
+            Some(Value::Array(type_names)) => {
+                let mut non_null = type_names
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .filter(|t| *t != "null");
+                match (non_null.next(), non_null.next()) {
+                    (Some(type_name), None) => Some(type_name),
+                    _ => return CelType::Dyn,
+                }
+            }
+            _ => None,
+        };
+
+        match type_name {
+            Some("string") => match schema.get("format").and_then(Value::as_str) {
+                Some("date-time") => CelType::Timestamp,
+                Some("duration") => CelType::Duration,
+                Some("byte" | "binary") => CelType::Bytes,
+                _ => CelType::String,
+            },
+            Some("integer") => CelType::Int,
+            Some("number") => CelType::Double,
+            Some("boolean") => CelType::Bool,
+            Some("null") => CelType::Null,
+            Some("array") => self.array_type(document, schema, name),
+            Some("object") => self.convert_object(document, schema, name),
+            None if schema.get("items").is_some() => self.array_type(document, schema, name),
+            _ => CelType::Dyn,
+        }
+    }
+
+    fn array_type(&mut self, document: &str, schema: &'a Value, name: &str) -> CelType {
+        match schema.get("items") {
+            Some(items @ Value::Object(_)) => {
+                CelType::list(self.schema_type(document, items, &format!("{}.@items", name)))
+            }
+            _ => CelType::list(CelType::Dyn),
+        }
+    }
+
+    fn follow_ref(&mut self, document: &str, reference: &str) -> CelType {
+        match reference.strip_prefix('#') {
+            Some(pointer) => self.import(document, pointer),
+            None => {
+                warn!("external schema reference '{}' is not supported", reference);
+                CelType::Dyn
+            }
+        }
+    }
+
+    /// Check whether a schema declares properties, directly or through `allOf`.
+    fn is_object(&self, document: &str, schema: &'a Value) -> bool {
+        let mut properties = Vec::new();
+        self.collect_properties(document, schema, &mut properties, 0);
+        !properties.is_empty()
+    }
+
+    /// Collect the properties of a schema and the schemas it composes with `allOf`.
+    fn collect_properties(
+        &self,
+        document: &str,
+        schema: &'a Value,
+        out: &mut Vec<(&'a String, &'a Value)>,
+        depth: usize,
+    ) {
+        if depth > MAX_ALL_OF_DEPTH {
+            return;
+        }
+        if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+            out.extend(properties.iter());
+        }
+        let parts = schema.get("allOf").and_then(Value::as_array);
+        for part in parts.into_iter().flatten() {
+            let part = match part.get("$ref").and_then(Value::as_str) {
+                Some(reference) => match self.resolve_local(document, reference) {
+                    Some(target) => target,
+                    None => continue,
+                },
+                None => part,
+            };
+            self.collect_properties(document, part, out, depth + 1);
+        }
+    }
+
+    fn resolve_local(&self, document: &str, reference: &str) -> Option<&'a Value> {
+        let pointer = reference.strip_prefix('#')?;
+        self.documents.get(document)?.pointer(pointer)
+    }
+
+    fn convert_object(&mut self, document: &str, schema: &'a Value, name: &str) -> CelType {
+        let mut properties = Vec::new();
+        self.collect_properties(document, schema, &mut properties, 0);
+
+        if !properties.is_empty() {
+            self.types.add_message(name);
+            for (field, property) in properties {
+                if !is_identifier(field) {
+                    continue;
+                }
+                let field_type =
+                    self.schema_type(document, property, &format!("{}.{}", name, field));
+                self.types
+                    .add_field(name, field, field_type, description(property));
+            }
+            return CelType::message(name);
+        }
+
+        match schema.get("additionalProperties") {
+            Some(values @ Value::Object(_)) => CelType::map(
+                CelType::String,
+                self.schema_type(document, values, &format!("{}.@values", name)),
+            ),
+            _ => CelType::map(CelType::String, CelType::Dyn),
+        }
+    }
+}
+
+/// Get the `description` of a schema.
+fn description(schema: &Value) -> Option<String> {
+    schema
+        .get("description")
+        .and_then(Value::as_str)
+        .map(|d| d.trim().to_string())
+        .filter(|d| !d.is_empty())
+}
+
+/// Check whether a property name can be selected as a CEL field.
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Parse a schema document as JSON, falling back to YAML.
+fn parse_schema_document(source: &str) -> Option<Value> {
+    if let Ok(value) = serde_json::from_str(source) {
+        return Some(value);
+    }
+    match parse_documents(source).first() {
+        Some(node @ YamlNode::Mapping(_)) => Some(yaml_to_json(node)),
+        _ => None,
+    }
+}
+
+/// Convert a YAML node to JSON, typing plain scalars the way YAML 1.2 does.
+fn yaml_to_json(node: &YamlNode) -> Value {
+    match node {
+        YamlNode::Null => Value::Null,
+        YamlNode::Scalar(scalar) => match scalar.value.as_str() {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            "null" | "~" => Value::Null,
+            value => value
+                .parse::<i64>()
+                .map(Value::from)
+                .or_else(|_| value.parse::<f64>().map(Value::from))
+                .unwrap_or_else(|_| Value::String(value.to_string())),
+        },
+        YamlNode::Mapping(entries) => Value::Object(
+            entries
+                .iter()
+                .map(|(key, value)| (key.value.clone(), yaml_to_json(value)))
+                .collect::<Map<_, _>>(),
+        ),
+        YamlNode::Sequence(items) => Value::Array(items.iter().map(yaml_to_json).collect()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn importer() -> SchemaImporter {
+        let mut importer = SchemaImporter::default();
+        importer.add_document(
+            "openapi",
+            json!({
+                "components": {
+                    "schemas": {
+                        "User": {
+                            "type": "object",
+                            "properties": {
+                                "name": { "type": "string", "description": "Display name." },
+                                "created": { "type": "string", "format": "date-time" },
+                                "avatar": { "type": "string", "format": "byte" },
+                                "age": { "type": ["integer", "null"] },
+                                "address": { "$ref": "#/components/schemas/Address" },
+                                "tags": { "type": "array", "items": { "type": "string" } },
+                                "labels": {
+                                    "type": "object",
+                                    "additionalProperties": { "type": "string" }
+                                },
+                                "manager": { "$ref": "#/components/schemas/User" },
+                                "x-trace-id": { "type": "string" }
+                            }
+                        },
+                        "Address": {
+                            "properties": {
+                                "city": { "type": "string" },
+                                "geo": {
+                                    "type": "object",
+                                    "properties": { "lat": { "type": "number" } }
+                                }
+                            }
+                        },
+                        "Admin": {
+                            "allOf": [
+                                { "$ref": "#/components/schemas/User" },
+                                { "properties": { "scopes": { "type": "array" } } }
+                            ]
+                        },
+                        "Status": { "type": "string", "enum": ["active", "disabled"] }
+                    }
+                }
+            }),
+        );
+        importer
+    }
+
+    #[test]
+    fn converts_openapi_schemas() {
+        let mut importer = importer();
+        let mut types = SyntheticTypes::new();
+        let user = importer
+            .resolve("openapi:#/components/schemas/User", &mut types)
+            .unwrap();
+        assert_eq!(user, CelType::message("User"));
+
+        let field = |m: &str, f: &str| types.field(m, f).map(|f| f.cel_type.clone());
+        assert_eq!(field("User", "name"), Some(CelType::String));
+        assert_eq!(field("User", "created"), Some(CelType::Timestamp));
+        assert_eq!(field("User", "avatar"), Some(CelType::Bytes));
+        assert_eq!(field("User", "age"), Some(CelType::Int));
+        assert_eq!(field("User", "address"), Some(CelType::message("Address")));
+        assert_eq!(field("User", "manager"), Some(CelType::message("User")));
+        assert_eq!(field("User", "tags"), Some(CelType::list(CelType::String)));
+        assert_eq!(
+            field("User", "labels"),
+            Some(CelType::map(CelType::String, CelType::String))
+        );
+        assert_eq!(field("User", "x-trace-id"), None);
+        assert_eq!(
+            field("Address", "geo"),
+            Some(CelType::message("Address.geo"))
+        );
+        assert_eq!(field("Address.geo", "lat"), Some(CelType::Double));
+        assert_eq!(
+            types.field("User", "name").unwrap().description.as_deref(),
+            Some("Display name.")
+        );
+    }
+
+    #[test]
+    fn merges_all_of() {
+        let mut importer = importer();
+        let mut types = SyntheticTypes::new();
+        let admin = importer
+            .resolve("openapi:#/components/schemas/Admin", &mut types)
+            .unwrap();
+        assert_eq!(admin, CelType::message("Admin"));
+        assert!(types.field("Admin", "name").is_some());
+        assert!(types.field("Admin", "scopes").is_some());
+    }
+
+    #[test]
+    fn resolves_non_object_schemas_and_reports_errors() {
+        let mut importer = importer();
+        let mut types = SyntheticTypes::new();
+        assert_eq!(
+            importer.resolve("openapi:#/components/schemas/Status", &mut types),
+            Ok(CelType::String)
+        );
+        assert!(importer
+            .resolve("openapi:#/components/schemas/Missing", &mut types)
+            .is_err());
+        assert!(importer.resolve("other:#/User", &mut types).is_err());
+        assert!(importer.resolve("openapi:User", &mut types).is_err());
+    }
+
+    #[test]
+    fn parses_yaml_documents() {
+        let source = "\
+openapi: 3.0.3
+components:
+  schemas:
+    Pet:
+      type: object
+      additionalProperties: false
+      properties:
+        id:
+          type: integer
+";
+        let document = parse_schema_document(source).unwrap();
+        assert_eq!(
+            document.pointer("/components/schemas/Pet/properties/id/type"),
+            Some(&json!("integer"))
+        );
+        assert_eq!(
+            document.pointer("/components/schemas/Pet/additionalProperties"),
+            Some(&json!(false))
+        );
+    }
+}
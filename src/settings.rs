@@ -7,23 +7,83 @@ use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use cel_core::{ext, types::FunctionDecl, CelType, Env};
+use cel_core::{ext, CelType, Env};
 use cel_core_proto::ProstProtoRegistry;
+use prost_reflect::prost::Message;
+use prost_reflect::prost_types::FileDescriptorSet;
 use serde::Deserialize;
+use tracing::warn;
+
+use crate::analysis::{ComplexityLimits, CostBudget};
+use crate::document::HostVersions;
+use crate::envoy::with_envoy_attributes;
+use crate::iam::with_iam_conditions;
+use crate::kubernetes::{is_kubernetes_type, with_kubernetes_libraries, KubernetesVersion};
+use crate::proto::{discover_buf, Buffers, CompileErrors, ProtoInputs};
+use crate::protovalidate::{validate_proto, ProtovalidateVersion, VALIDATE_PROTO};
+use crate::schemas::SchemaImporter;
+use crate::types::SyntheticTypes;
 
 /// Root settings structure loaded from settings.toml.
-#[derive(Debug, Default, Deserialize)]
+///
+/// Editors can send the same structure as JSON through `initializationOptions`
+/// and `workspace/configuration` (section `celsp`). Sources are layered with
+/// `Settings::merge` in increasing precedence:
+/// 1. Built-in defaults
+/// 2. settings.toml
+/// 3. `initializationOptions`
+/// 4. `workspace/configuration` and `workspace/didChangeConfiguration`
+#[derive(Debug, Default, Clone, Deserialize)]
 pub struct Settings {
     /// Environment configuration.
     pub env: Option<EnvSettings>,
+
+    /// Diagnostic categories to report.
+    pub diagnostics: Option<DiagnosticsSettings>,
+
+    /// Inlay hint configuration.
+    pub inlay_hints: Option<InlayHintSettings>,
 }
 
 /// Environment settings for configuring the CEL Env.
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Default, Clone, Deserialize)]
 pub struct EnvSettings {
     /// Container namespace for qualified name resolution.
     pub container: Option<String>,
 
+    /// Library preset for a CEL host: "kubernetes", "envoy" or "iam-conditions".
+    pub preset: Option<String>,
+
+    /// Version of the preset's host, e.g. "1.30" for Kubernetes (default: latest).
+    pub preset_version: Option<String>,
+
+    /// Protovalidate release to target, from "0.5" to "1.0", e.g. "0.11" (default: latest).
+    pub protovalidate_version: Option<String>,
+
+    /// Result type `.cel` files must evaluate to, e.g. "bool" or "bool | string".
+    pub expected_type: Option<String>,
+
+    /// Result types by file name pattern, e.g. `"*.message.cel" = "string"`.
+    /// `*` matches any characters; patterns with a `/` match the whole path.
+    /// Matching patterns take precedence over `expected_type`.
+    pub expected_types: Option<HashMap<String, String>>,
+
+    /// Estimated cost above which expressions are reported.
+    pub cost_limit: Option<u64>,
+
+    /// Maximum sizes of lists, maps, strings and bytes by path, e.g.
+    /// `"request.items" = 100`; `[]` selects list elements (`"request.items[]"`).
+    pub max_sizes: Option<HashMap<String, u64>>,
+
+    /// Maximum nesting depth of an expression's AST.
+    pub max_depth: Option<usize>,
+
+    /// Maximum number of nodes in an expression's AST.
+    pub max_nodes: Option<usize>,
+
+    /// Maximum number of nested comprehensions, such as `all()` inside `map()`.
+    pub max_comprehension_nesting: Option<usize>,
+
     /// Extensions to enable: ["strings", "math", "encoders", "optionals", "all"]
     pub extensions: Option<Vec<String>>,
 
@@ -34,6 +94,14 @@ pub struct EnvSettings {
     /// Type strings are parsed using `parse_type_string`.
     pub variables: Option<HashMap<String, String>>,
 
+    /// Object type declarations: type name -> field name -> field.
+    /// Declared types behave like protobuf messages without needing descriptors.
+    pub types: Option<HashMap<String, HashMap<String, FieldSettings>>>,
+
+    /// JSON Schema and OpenAPI documents: name -> path.
+    /// Type strings refer to their schemas as `name:#/json/pointer`.
+    pub schemas: Option<HashMap<String, PathBuf>>,
+
     /// Abbreviations for qualified name shortcuts.
     pub abbreviations: Option<Vec<String>>,
 
@@ -41,12 +109,243 @@ pub struct EnvSettings {
     pub proto: Option<ProtoSettings>,
 }
 
+/// A field of a declared object type.
+///
+/// Either a type string (`name = "string"`) or a table with a description
+/// (`name = { type = "string", description = "..." }`).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum FieldSettings {
+    /// Just the type string.
+    Type(String),
+    /// A type string with documentation for hover and completion.
+    Documented {
+        #[serde(rename = "type")]
+        type_name: String,
+        description: Option<String>,
+    },
+}
+
+impl FieldSettings {
+    /// The field's type string.
+    pub fn type_name(&self) -> &str {
+        match self {
+            Self::Type(type_name) | Self::Documented { type_name, .. } => type_name,
+        }
+    }
+
+    /// The field's documentation, if any.
+    pub fn description(&self) -> Option<&str> {
+        match self {
+            Self::Type(_) => None,
+            Self::Documented { description, .. } => description.as_deref(),
+        }
+    }
+}
+
 /// Proto-specific settings.
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Default, Clone, Deserialize)]
 pub struct ProtoSettings {
     /// Paths to file descriptor set files (.binpb).
     /// Paths are relative to the workspace root.
+    #[serde(default)]
     pub descriptors: Vec<PathBuf>,
+
+    /// `.proto` files or directories to compile in-process.
+    #[serde(default)]
+    pub sources: Vec<PathBuf>,
+
+    /// Directories that imports of compiled sources are resolved against.
+    #[serde(default)]
+    pub include_paths: Vec<PathBuf>,
+
+    /// Whether to compile the modules of a `buf.yaml` or `buf.work.yaml` in
+    /// the workspace root (default: true).
+    pub buf: Option<bool>,
+
+    /// Whether to include the bundled `buf/validate/validate.proto`
+    /// (default: true).
+    pub bundled: Option<bool>,
+}
+
+/// Toggles for diagnostic categories.
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct DiagnosticsSettings {
+    /// Report syntax errors (default: true).
+    pub parse: Option<bool>,
+
+    /// Report type-check errors (default: true).
+    pub check: Option<bool>,
+
+    /// Severity of each lint rule by name, overriding its default.
+    pub lints: Option<HashMap<String, LintLevel>>,
+}
+
+/// Severity a lint rule is reported with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LintLevel {
+    /// Not reported.
+    Off,
+    Hint,
+    Warning,
+    Error,
+}
+
+impl DiagnosticsSettings {
+    /// Whether syntax errors are reported.
+    pub fn parse_enabled(&self) -> bool {
+        self.parse.unwrap_or(true)
+    }
+
+    /// Whether type-check errors are reported.
+    pub fn check_enabled(&self) -> bool {
+        self.check.unwrap_or(true)
+    }
+
+    /// The configured level of the lint rule `name`, if any.
+    pub fn lint_level(&self, name: &str) -> Option<LintLevel> {
+        self.lints.as_ref()?.get(name).copied()
+    }
+}
+
+/// Inlay hint settings.
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct InlayHintSettings {
+    /// Show the inferred result type after each expression (default: false).
+    pub result_types: Option<bool>,
+}
+
+impl InlayHintSettings {
+    /// Whether result type hints are shown.
+    pub fn result_types_enabled(&self) -> bool {
+        self.result_types.unwrap_or(false)
+    }
+}
+
+impl Settings {
+    /// Layer `overrides` on top of these settings.
+    ///
+    /// Values set in `overrides` replace existing ones, with two exceptions:
+    /// - variables, types, schemas, expected types by pattern, size hints and
+    ///   lint levels are merged by name, with `overrides` winning
+    /// - extensions, abbreviations and proto descriptors, sources and include
+    ///   paths are appended, so `overrides` cannot remove entries
+    pub fn merge(&mut self, overrides: Settings) {
+        if let Some(env) = overrides.env {
+            self.env.get_or_insert_with(Default::default).merge(env);
+        }
+        if let Some(diagnostics) = overrides.diagnostics {
+            let current = self.diagnostics.get_or_insert_with(Default::default);
+            current.parse = diagnostics.parse.or(current.parse);
+            current.check = diagnostics.check.or(current.check);
+            if let Some(lints) = diagnostics.lints {
+                current.lints.get_or_insert_with(HashMap::new).extend(lints);
+            }
+        }
+        if let Some(inlay_hints) = overrides.inlay_hints {
+            let current = self.inlay_hints.get_or_insert_with(Default::default);
+            current.result_types = inlay_hints.result_types.or(current.result_types);
+        }
+    }
+
+    /// Diagnostic toggles, with defaults when unset.
+    pub fn diagnostics(&self) -> DiagnosticsSettings {
+        self.diagnostics.clone().unwrap_or_default()
+    }
+
+    /// Inlay hint settings, with defaults when unset.
+    pub fn inlay_hints(&self) -> InlayHintSettings {
+        self.inlay_hints.clone().unwrap_or_default()
+    }
+}
+
+impl EnvSettings {
+    fn merge(&mut self, overrides: EnvSettings) {
+        self.container = overrides.container.or(self.container.take());
+        self.preset = overrides.preset.or(self.preset.take());
+        self.preset_version = overrides.preset_version.or(self.preset_version.take());
+        self.protovalidate_version = overrides
+            .protovalidate_version
+            .or(self.protovalidate_version.take());
+        self.expected_type = overrides.expected_type.or(self.expected_type.take());
+        if let Some(expected_types) = overrides.expected_types {
+            self.expected_types
+                .get_or_insert_with(HashMap::new)
+                .extend(expected_types);
+        }
+        self.cost_limit = overrides.cost_limit.or(self.cost_limit);
+        if let Some(max_sizes) = overrides.max_sizes {
+            self.max_sizes
+                .get_or_insert_with(HashMap::new)
+                .extend(max_sizes);
+        }
+        self.max_depth = overrides.max_depth.or(self.max_depth);
+        self.max_nodes = overrides.max_nodes.or(self.max_nodes);
+        self.max_comprehension_nesting = overrides
+            .max_comprehension_nesting
+            .or(self.max_comprehension_nesting);
+        self.strong_enums = overrides.strong_enums.or(self.strong_enums);
+        if let Some(extensions) = overrides.extensions {
+            append_unique(self.extensions.get_or_insert_with(Vec::new), extensions);
+        }
+        if let Some(variables) = overrides.variables {
+            self.variables
+                .get_or_insert_with(HashMap::new)
+                .extend(variables);
+        }
+        if let Some(types) = overrides.types {
+            self.types.get_or_insert_with(HashMap::new).extend(types);
+        }
+        if let Some(schemas) = overrides.schemas {
+            self.schemas
+                .get_or_insert_with(HashMap::new)
+                .extend(schemas);
+        }
+        if let Some(abbreviations) = overrides.abbreviations {
+            append_unique(
+                self.abbreviations.get_or_insert_with(Vec::new),
+                abbreviations,
+            );
+        }
+        if let Some(proto) = overrides.proto {
+            let current = self.proto.get_or_insert_with(Default::default);
+            append_unique(&mut current.descriptors, proto.descriptors);
+            append_unique(&mut current.sources, proto.sources);
+            append_unique(&mut current.include_paths, proto.include_paths);
+            current.buf = proto.buf.or(current.buf);
+            current.bundled = proto.bundled.or(current.bundled);
+        }
+    }
+}
+
+/// Append the items of `extra` that are not already present in `list`.
+fn append_unique<T: PartialEq>(list: &mut Vec<T>, extra: Vec<T>) {
+    for item in extra {
+        if !list.contains(&item) {
+            list.push(item);
+        }
+    }
+}
+
+/// Parse settings sent by the client as `initializationOptions` or a
+/// `workspace/configuration` result.
+///
+/// Accepts the settings object itself or one wrapped in a `celsp` section, as
+/// sent by clients that push `workspace/didChangeConfiguration` settings.
+/// Returns `None` for `null` or invalid values.
+pub fn parse_client_settings(value: &serde_json::Value) -> Option<Settings> {
+    let value = value.get("celsp").unwrap_or(value);
+    if value.is_null() {
+        return None;
+    }
+    match serde_json::from_value(value.clone()) {
+        Ok(settings) => Some(settings),
+        Err(e) => {
+            warn!("failed to parse client settings: {}", e);
+            None
+        }
+    }
 }
 
 /// Parse a type string into a CelType.
@@ -55,6 +354,8 @@ pub struct ProtoSettings {
 /// - Primitives: bool, int, uint, double, string, bytes
 /// - Special: null, dyn, timestamp, duration
 /// - Parameterized: list(T), map(K, V), optional(T)
+/// - Kubernetes library types: kubernetes.Quantity, net.IP, net.CIDR, ...
+/// - Schema references: openapi:#/components/schemas/User (see `parse_type_string_with`)
 /// - Message types: any.other.name
 ///
 /// # Examples
@@ -65,7 +366,19 @@ pub struct ProtoSettings {
 /// assert_eq!(parse_type_string("int").unwrap(), CelType::Int);
 /// assert_eq!(parse_type_string("list(string)").unwrap(), CelType::list(CelType::String));
 /// ```
+#[cfg(test)]
 pub fn parse_type_string(s: &str) -> Result<CelType, String> {
+    parse_type_string_with(s, &mut |reference| {
+        Err(format!("unknown schema reference '{}'", reference))
+    })
+}
+
+/// Parse a type string, resolving schema references (`key:#/pointer`) with
+/// `resolve_ref`.
+pub fn parse_type_string_with(
+    s: &str,
+    resolve_ref: &mut dyn FnMut(&str) -> Result<CelType, String>,
+) -> Result<CelType, String> {
     let s = s.trim();
 
     // Handle parameterized types first
@@ -82,26 +395,26 @@ pub fn parse_type_string(s: &str) -> Result<CelType, String> {
 
         return match type_name {
             "list" => {
-                let elem = parse_type_string(inner)?;
+                let elem = parse_type_string_with(inner, resolve_ref)?;
                 Ok(CelType::list(elem))
             }
             "map" => {
                 // Split on comma, respecting nested parens
                 let (key_str, val_str) = split_map_types(inner)?;
-                let key = parse_type_string(key_str)?;
-                let val = parse_type_string(val_str)?;
+                let key = parse_type_string_with(key_str, resolve_ref)?;
+                let val = parse_type_string_with(val_str, resolve_ref)?;
                 Ok(CelType::map(key, val))
             }
             "optional" => {
-                let elem = parse_type_string(inner)?;
+                let elem = parse_type_string_with(inner, resolve_ref)?;
                 Ok(CelType::optional(elem))
             }
             "type" => {
-                let elem = parse_type_string(inner)?;
+                let elem = parse_type_string_with(inner, resolve_ref)?;
                 Ok(CelType::type_of(elem))
             }
             "wrapper" => {
-                let elem = parse_type_string(inner)?;
+                let elem = parse_type_string_with(inner, resolve_ref)?;
                 Ok(CelType::wrapper(elem))
             }
             _ => Err(format!("unknown parameterized type: '{}'", type_name)),
@@ -123,6 +436,9 @@ pub fn parse_type_string(s: &str) -> Result<CelType, String> {
         "error" => Ok(CelType::Error),
         // Empty string is an error
         "" => Err("empty type string".to_string()),
+        _ if is_kubernetes_type(s) => Ok(CelType::abstract_type(s, &[])),
+        // Schema references, e.g. "openapi:#/components/schemas/User"
+        _ if s.contains(':') => resolve_ref(s),
         // Anything else is a message type
         _ => Ok(CelType::message(s)),
     }
@@ -161,7 +477,7 @@ pub fn load_settings(path: &Path) -> Settings {
         Ok(content) => match toml::from_str(&content) {
             Ok(settings) => settings,
             Err(e) => {
-                eprintln!("Warning: failed to parse settings.toml: {}", e);
+                warn!("failed to parse settings.toml: {}", e);
                 Settings::default()
             }
         },
@@ -204,39 +520,91 @@ pub fn discover_settings(start_dir: &Path) -> (Settings, PathBuf) {
     (Settings::default(), start_dir.to_path_buf())
 }
 
+/// Load an explicitly specified settings.toml, bypassing discovery.
+///
+/// Returns `(settings, settings_dir)` like `discover_settings`, where
+/// `settings_dir` is the directory containing the file. Unlike discovered
+/// files, a file that cannot be read or parsed is an error.
+pub fn load_settings_file(path: &Path) -> Result<(Settings, PathBuf), String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("cannot read settings file '{}': {}", path.display(), e))?;
+    let settings = toml::from_str(&content)
+        .map_err(|e| format!("invalid settings file '{}': {}", path.display(), e))?;
+    let dir = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
+    Ok((settings, dir))
+}
+
+/// Proto types loaded from descriptor sets and compiled sources.
+#[derive(Default)]
+pub struct LoadedProtos {
+    /// Registry with all loaded types, if any descriptors or sources are configured.
+    pub registry: Option<Arc<ProstProtoRegistry>>,
+    /// Compile errors of `.proto` sources by canonical path.
+    pub errors: CompileErrors,
+}
+
 /// Load proto registry from file descriptor set files specified in settings.
 ///
-/// Returns None if no descriptors are configured or if loading fails.
+/// Returns None if no descriptors or sources are configured. Descriptors that
+/// fail to load are logged; compile errors of sources are discarded, use
+/// [`load_protos`] to get them.
 pub fn load_proto_registry(
     settings: &Settings,
     workspace_root: &Path,
 ) -> Option<Arc<ProstProtoRegistry>> {
-    let proto = settings.env.as_ref()?.proto.as_ref()?;
-    if proto.descriptors.is_empty() {
-        return None;
-    }
+    load_protos(settings, workspace_root).registry
+}
 
-    let mut registry = ProstProtoRegistry::new();
-    for path in &proto.descriptors {
-        let full_path = if path.is_absolute() {
+/// Load descriptor sets and compile `.proto` sources specified in settings.
+///
+/// Paths are relative to `workspace_root`. Sources are compiled after the
+/// descriptor sets, so they can import files from them.
+pub fn load_protos(settings: &Settings, workspace_root: &Path) -> LoadedProtos {
+    build_protos(&proto_inputs(settings, workspace_root), &Buffers::new())
+}
+
+/// Read the descriptor sets and resolve the source paths specified in settings.
+///
+/// Paths are relative to `workspace_root`. Descriptor sets that fail to load
+/// are logged and skipped. Unless disabled, the modules of a buf workspace in
+/// `workspace_root` are added to the sources and their cached dependencies to
+/// the include paths, and `buf/validate/validate.proto` is bundled for the
+/// configured protovalidate version.
+pub fn proto_inputs(settings: &Settings, workspace_root: &Path) -> ProtoInputs {
+    let default = ProtoSettings::default();
+    let proto = settings
+        .env
+        .as_ref()
+        .and_then(|env| env.proto.as_ref())
+        .unwrap_or(&default);
+    let resolve = |path: &PathBuf| {
+        if path.is_absolute() {
             path.clone()
         } else {
             workspace_root.join(path)
-        };
+        }
+    };
 
+    let mut descriptor_sets = Vec::new();
+    for full_path in proto.descriptors.iter().map(resolve) {
         match std::fs::read(&full_path) {
-            Ok(bytes) => {
-                if let Err(e) = registry.add_file_descriptor_set(&bytes) {
-                    eprintln!(
-                        "Warning: failed to load proto descriptor '{}': {}",
+            Ok(bytes) => match FileDescriptorSet::decode(bytes.as_slice()) {
+                Ok(set) => descriptor_sets.push(set),
+                Err(e) => {
+                    warn!(
+                        "failed to load proto descriptor '{}': {}",
                         full_path.display(),
                         e
                     );
                 }
-            }
+            },
             Err(e) => {
-                eprintln!(
-                    "Warning: failed to read proto descriptor file '{}': {}",
+                warn!(
+                    "failed to read proto descriptor file '{}': {}",
                     full_path.display(),
                     e
                 );
@@ -244,7 +612,199 @@ pub fn load_proto_registry(
         }
     }
 
-    Some(Arc::new(registry))
+    let mut inputs = ProtoInputs {
+        descriptor_sets,
+        sources: proto.sources.iter().map(resolve).collect(),
+        include_paths: proto.include_paths.iter().map(resolve).collect(),
+        excludes: Vec::new(),
+        bundled: Vec::new(),
+    };
+    if proto.bundled.unwrap_or(true) {
+        let version = protovalidate_version(settings);
+        inputs.bundled.push((
+            VALIDATE_PROTO.to_string(),
+            validate_proto(version).to_string(),
+        ));
+    }
+    if proto.buf.unwrap_or(true) {
+        if let Some(layout) = discover_buf(workspace_root) {
+            inputs.include_paths.extend(layout.modules.iter().cloned());
+            inputs.include_paths.extend(layout.dependencies);
+            inputs.sources.extend(layout.modules);
+            inputs.excludes = layout.excludes;
+        }
+    }
+    inputs
+}
+
+/// The protovalidate release selected in settings, or the latest one.
+pub fn protovalidate_version(settings: &Settings) -> ProtovalidateVersion {
+    let version = settings
+        .env
+        .as_ref()
+        .and_then(|env| env.protovalidate_version.as_deref());
+    match version.map(str::parse::<ProtovalidateVersion>) {
+        Some(Ok(version)) => version,
+        Some(Err(e)) => {
+            warn!("{}; using {}", e, ProtovalidateVersion::LATEST);
+            ProtovalidateVersion::LATEST
+        }
+        None => ProtovalidateVersion::LATEST,
+    }
+}
+
+/// The Kubernetes version selected by the `kubernetes` preset, or the latest one.
+///
+/// An invalid version was already reported when the preset was applied.
+pub fn kubernetes_version(settings: &Settings) -> KubernetesVersion {
+    let env = settings.env.as_ref();
+    match env.and_then(|env| env.preset.as_deref()) {
+        Some("kubernetes") => env
+            .and_then(|env| env.preset_version.as_deref())
+            .and_then(|version| version.parse().ok())
+            .unwrap_or(KubernetesVersion::LATEST),
+        _ => KubernetesVersion::LATEST,
+    }
+}
+
+/// The host releases selected in settings.
+pub fn host_versions(settings: &Settings) -> HostVersions {
+    HostVersions {
+        protovalidate: protovalidate_version(settings),
+        kubernetes: kubernetes_version(settings),
+    }
+}
+
+/// Result types `.cel` files must evaluate to.
+#[derive(Debug, Default, Clone)]
+pub struct ExpectedTypes {
+    /// Types for files matching no pattern.
+    default: Vec<CelType>,
+    /// Types by file name pattern, longest pattern first.
+    patterns: Vec<(String, Vec<CelType>)>,
+}
+
+impl ExpectedTypes {
+    /// The types the file at `path` must evaluate to; empty accepts any type.
+    pub fn for_path(&self, path: Option<&Path>) -> &[CelType] {
+        let Some(path) = path else {
+            return &self.default;
+        };
+        let full = path.to_string_lossy();
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        self.patterns
+            .iter()
+            .find(|(pattern, _)| {
+                let subject = if pattern.contains('/') { &full } else { &name };
+                matches_pattern(pattern, subject)
+            })
+            .map_or(&self.default, |(_, types)| types)
+    }
+}
+
+/// The result types configured with `expected_type` and `expected_types`.
+///
+/// Invalid type strings are logged and accept any type.
+pub fn expected_types(settings: &Settings) -> ExpectedTypes {
+    let Some(env) = settings.env.as_ref() else {
+        return ExpectedTypes::default();
+    };
+    let mut patterns: Vec<_> = env
+        .expected_types
+        .iter()
+        .flatten()
+        .map(|(pattern, types)| (pattern.clone(), parse_expected_type(types)))
+        .collect();
+    patterns.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+    ExpectedTypes {
+        default: env
+            .expected_type
+            .as_deref()
+            .map(parse_expected_type)
+            .unwrap_or_default(),
+        patterns,
+    }
+}
+
+/// The cost limit and size hints configured with `cost_limit` and `max_sizes`.
+pub fn cost_budget(settings: &Settings) -> CostBudget {
+    let Some(env) = settings.env.as_ref() else {
+        return CostBudget::default();
+    };
+    CostBudget {
+        limit: env.cost_limit,
+        max_sizes: env.max_sizes.clone().unwrap_or_default(),
+    }
+}
+
+/// The structural limits configured with `max_depth`, `max_nodes` and
+/// `max_comprehension_nesting`.
+pub fn complexity_limits(settings: &Settings) -> ComplexityLimits {
+    let Some(env) = settings.env.as_ref() else {
+        return ComplexityLimits::default();
+    };
+    ComplexityLimits {
+        max_depth: env.max_depth,
+        max_nodes: env.max_nodes,
+        max_comprehension_nesting: env.max_comprehension_nesting,
+    }
+}
+
+/// Parse `|`-separated alternative type strings.
+fn parse_expected_type(s: &str) -> Vec<CelType> {
+    let parsed: Result<Vec<_>, _> = s
+        .split('|')
+        .map(|t| {
+            parse_type_string_with(t, &mut |reference| {
+                Err(format!(
+                    "schema reference '{}' is not a result type",
+                    reference
+                ))
+            })
+        })
+        .collect();
+    parsed.unwrap_or_else(|e| {
+        warn!("invalid expected type '{}': {}", s, e);
+        Vec::new()
+    })
+}
+
+/// Match `text` against a pattern where `*` matches any characters.
+fn matches_pattern(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<_> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// Build the proto registry from `inputs`, with unsaved `.proto` buffers
+/// compiled in place of the files on disk.
+///
+/// Returns no registry when nothing is configured and no buffers are given.
+pub fn build_protos(inputs: &ProtoInputs, buffers: &Buffers) -> LoadedProtos {
+    if inputs.is_empty() && buffers.is_empty() {
+        return LoadedProtos::default();
+    }
+    let (pool, errors) = inputs.build(buffers);
+    LoadedProtos {
+        registry: Some(Arc::new(ProstProtoRegistry::from_pool(pool))),
+        errors,
+    }
 }
 
 /// Build a CEL Env from settings with proto support.
@@ -252,31 +812,75 @@ pub fn load_proto_registry(
 /// This creates an Env with the standard library, applies all settings,
 /// and loads proto descriptors from the specified paths relative to workspace_root.
 pub fn build_env_with_protos(settings: &Settings, workspace_root: &Path) -> Env {
-    build_env_from_settings_impl(settings, Some(workspace_root))
+    let registry = load_proto_registry(settings, workspace_root);
+    build_env_with_registry(settings, workspace_root, registry)
+}
+
+/// Build a CEL Env from settings with an already loaded proto registry.
+pub fn build_env_with_registry(
+    settings: &Settings,
+    workspace_root: &Path,
+    registry: Option<Arc<ProstProtoRegistry>>,
+) -> Env {
+    build_env_from_settings_impl(settings, Some(workspace_root), registry)
 }
 
 /// Internal implementation for building Env from settings.
-fn build_env_from_settings_impl(settings: &Settings, workspace_root: Option<&Path>) -> Env {
+fn build_env_from_settings_impl(
+    settings: &Settings,
+    workspace_root: Option<&Path>,
+    registry: Option<Arc<ProstProtoRegistry>>,
+) -> Env {
     let mut env = Env::with_standard_library();
+    let mut types = SyntheticTypes::new();
 
     if let Some(ref env_settings) = settings.env {
-        // Apply extensions
-        if let Some(ref extensions) = env_settings.extensions {
-            env = apply_extensions(env, extensions);
+        // Apply preset libraries
+        if let Some(ref preset) = env_settings.preset {
+            env = apply_preset(
+                env,
+                &mut types,
+                preset,
+                env_settings.preset_version.as_deref(),
+            );
+        }
+
+        // Load schema documents for `name:#/pointer` type references
+        let mut schemas = match env_settings.schemas {
+            Some(ref schemas) => SchemaImporter::load(schemas, workspace_root),
+            None => SchemaImporter::default(),
+        };
+        let mut parse_type = |type_str: &str, types: &mut SyntheticTypes| {
+            parse_type_string_with(type_str, &mut |reference| schemas.resolve(reference, types))
+        };
+
+        // Declare object types
+        if let Some(ref declared) = env_settings.types {
+            declare_types(&mut types, declared, &mut parse_type);
+        }
+
+        // Apply extensions, which would bring back functions that the
+        // iam-conditions preset removes
+        match (&env_settings.extensions, env_settings.preset.as_deref()) {
+            (Some(extensions), Some("iam-conditions")) if !extensions.is_empty() => {
+                warn!(
+                    "extensions are not available with the iam-conditions preset; ignoring {}",
+                    extensions.join(", ")
+                );
+            }
+            (Some(extensions), _) => env = apply_extensions(env, extensions),
+            (None, _) => {}
         }
 
         // Apply variables
         if let Some(ref variables) = env_settings.variables {
             for (name, type_str) in variables {
-                match parse_type_string(type_str) {
+                match parse_type(type_str, &mut types) {
                     Ok(cel_type) => {
                         env.add_variable(name, cel_type);
                     }
                     Err(e) => {
-                        eprintln!(
-                            "Warning: failed to parse type for variable '{}': {}",
-                            name, e
-                        );
+                        warn!("failed to parse type for variable '{}': {}", name, e);
                     }
                 }
             }
@@ -294,7 +898,7 @@ fn build_env_from_settings_impl(settings: &Settings, workspace_root: Option<&Pat
                 match abbrevs.clone().with_abbreviation(name) {
                     Ok(a) => abbrevs = a,
                     Err(e) => {
-                        eprintln!("Warning: failed to add abbreviation '{}': {}", name, e);
+                        warn!("failed to add abbreviation '{}': {}", name, e);
                     }
                 }
             }
@@ -307,14 +911,79 @@ fn build_env_from_settings_impl(settings: &Settings, workspace_root: Option<&Pat
         }
     }
 
-    // Load proto registry if workspace_root is provided and descriptors are configured
-    if let Some(root) = workspace_root {
-        if let Some(registry) = load_proto_registry(settings, root) {
-            env = env.with_proto_registry(registry);
+    // Declared types take precedence over descriptors with the same name
+    match registry {
+        Some(registry) if types.is_empty() => env.with_proto_registry(registry),
+        Some(registry) => env.with_proto_registry(Arc::new(types.with_fallback(registry))),
+        None if !types.is_empty() => env.with_proto_registry(Arc::new(types)),
+        None => env,
+    }
+}
+
+/// Apply the libraries of a preset, optionally for a specific host version.
+///
+/// Message types declared by the preset are added to `types`.
+fn apply_preset(env: Env, types: &mut SyntheticTypes, preset: &str, version: Option<&str>) -> Env {
+    match preset {
+        "envoy" => {
+            warn_unversioned(preset, version);
+            with_envoy_attributes(env, types)
+        }
+        "iam-conditions" => {
+            warn_unversioned(preset, version);
+            with_iam_conditions(env, types)
+        }
+        "kubernetes" => {
+            let version = match version.map(str::parse::<KubernetesVersion>) {
+                Some(Ok(version)) => version,
+                Some(Err(e)) => {
+                    warn!("{}; using {}", e, KubernetesVersion::LATEST);
+                    KubernetesVersion::LATEST
+                }
+                None => KubernetesVersion::LATEST,
+            };
+            with_kubernetes_libraries(env, version)
+        }
+        other => {
+            warn!("unknown preset: '{}'", other);
+            env
         }
     }
+}
 
-    env
+/// Declare object types from settings as synthetic messages.
+fn declare_types(
+    types: &mut SyntheticTypes,
+    declared: &HashMap<String, HashMap<String, FieldSettings>>,
+    parse_type: &mut dyn FnMut(&str, &mut SyntheticTypes) -> Result<CelType, String>,
+) {
+    for (type_name, fields) in declared {
+        types.add_message(type_name);
+        for (field_name, field) in fields {
+            match parse_type(field.type_name(), types) {
+                Ok(cel_type) => {
+                    let description = field.description().map(str::to_string);
+                    types.add_field(type_name, field_name, cel_type, description);
+                }
+                Err(e) => {
+                    warn!(
+                        "failed to parse type for field '{}.{}': {}",
+                        type_name, field_name, e
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Warn when a version is given for a preset without versions.
+fn warn_unversioned(preset: &str, version: Option<&str>) {
+    if let Some(version) = version {
+        warn!(
+            "preset '{}' does not support versions; ignoring '{}'",
+            preset, version
+        );
+    }
 }
 
 /// Apply extension libraries based on extension names.
@@ -337,112 +1006,27 @@ fn apply_extensions(mut env: Env, extensions: &[String]) -> Env {
                 env = env.with_extension(ext::optionals_extension());
             }
             other => {
-                eprintln!("Warning: unknown extension: '{}'", other);
+                warn!("unknown extension: '{}'", other);
             }
         }
     }
     env
 }
 
-/// Add protovalidate extension functions to an environment.
-///
-/// This adds the protovalidate-specific functions like isEmail, isUri, etc.
-pub fn protovalidate_extension() -> Vec<FunctionDecl> {
-    use cel_core::types::OverloadDecl;
-
-    vec![
-        // isEmail(string) -> bool
-        FunctionDecl::new("isEmail").with_overload(OverloadDecl::method(
-            "isEmail_string",
-            vec![CelType::String], // receiver in params
-            CelType::Bool,
-        )),
-        // isHostname(string) -> bool
-        FunctionDecl::new("isHostname").with_overload(OverloadDecl::method(
-            "isHostname_string",
-            vec![CelType::String],
-            CelType::Bool,
-        )),
-        // isIp(string) -> bool, isIp(string, int) -> bool
-        FunctionDecl::new("isIp")
-            .with_overload(OverloadDecl::method(
-                "isIp_string",
-                vec![CelType::String],
-                CelType::Bool,
-            ))
-            .with_overload(OverloadDecl::method(
-                "isIp_string_int",
-                vec![CelType::String, CelType::Int],
-                CelType::Bool,
-            )),
-        // isIpPrefix(string) -> bool, isIpPrefix(string, int) -> bool, isIpPrefix(string, int, bool) -> bool
-        FunctionDecl::new("isIpPrefix")
-            .with_overload(OverloadDecl::method(
-                "isIpPrefix_string",
-                vec![CelType::String],
-                CelType::Bool,
-            ))
-            .with_overload(OverloadDecl::method(
-                "isIpPrefix_string_int",
-                vec![CelType::String, CelType::Int],
-                CelType::Bool,
-            ))
-            .with_overload(OverloadDecl::method(
-                "isIpPrefix_string_int_bool",
-                vec![CelType::String, CelType::Int, CelType::Bool],
-                CelType::Bool,
-            )),
-        // isUri(string) -> bool
-        FunctionDecl::new("isUri").with_overload(OverloadDecl::method(
-            "isUri_string",
-            vec![CelType::String],
-            CelType::Bool,
-        )),
-        // isUriRef(string) -> bool
-        FunctionDecl::new("isUriRef").with_overload(OverloadDecl::method(
-            "isUriRef_string",
-            vec![CelType::String],
-            CelType::Bool,
-        )),
-        // unique(list) -> bool
-        FunctionDecl::new("unique").with_overload(OverloadDecl::method(
-            "unique_list",
-            vec![CelType::list(CelType::Dyn)],
-            CelType::Bool,
-        )),
-        // isNan(double) -> bool
-        FunctionDecl::new("isNan").with_overload(OverloadDecl::method(
-            "isNan_double",
-            vec![CelType::Double],
-            CelType::Bool,
-        )),
-        // isInf(double) -> bool, isInf(double, int) -> bool
-        FunctionDecl::new("isInf")
-            .with_overload(OverloadDecl::method(
-                "isInf_double",
-                vec![CelType::Double],
-                CelType::Bool,
-            ))
-            .with_overload(OverloadDecl::method(
-                "isInf_double_int",
-                vec![CelType::Double, CelType::Int],
-                CelType::Bool,
-            )),
-    ]
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::protovalidate::protovalidate_library;
+    use crate::test_util::make_test_dir;
 
     fn build_env_from_settings(settings: &Settings) -> Env {
-        build_env_from_settings_impl(settings, None)
+        build_env_from_settings_impl(settings, None, None)
     }
 
     fn build_protovalidate_env() -> Env {
         Env::with_standard_library()
             .with_all_extensions()
-            .with_extension(protovalidate_extension())
+            .with_extension(protovalidate_library(ProtovalidateVersion::LATEST))
             .with_variable("this", CelType::Dyn)
             .with_variable("rules", CelType::Dyn)
             .with_variable("now", CelType::Timestamp)
@@ -558,6 +1142,7 @@ mod tests {
                 ),
                 ..Default::default()
             }),
+            ..Default::default()
         };
 
         let env = build_env_from_settings(&settings);
@@ -575,6 +1160,7 @@ mod tests {
                 container: Some("my.package".to_string()),
                 ..Default::default()
             }),
+            ..Default::default()
         };
 
         let env = build_env_from_settings(&settings);
@@ -588,6 +1174,7 @@ mod tests {
                 extensions: Some(vec!["strings".to_string(), "math".to_string()]),
                 ..Default::default()
             }),
+            ..Default::default()
         };
 
         let env = build_env_from_settings(&settings);
@@ -604,6 +1191,7 @@ mod tests {
                 extensions: Some(vec!["all".to_string()]),
                 ..Default::default()
             }),
+            ..Default::default()
         };
 
         let env = build_env_from_settings(&settings);
@@ -613,9 +1201,135 @@ mod tests {
         assert!(env.functions().contains_key("optional.of"));
     }
 
+    #[test]
+    fn build_env_with_kubernetes_preset() {
+        let preset = |version: Option<&str>| Settings {
+            env: Some(EnvSettings {
+                preset: Some("kubernetes".to_string()),
+                preset_version: version.map(str::to_string),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let env = build_env_from_settings(&preset(None));
+        assert!(env.functions().contains_key("quantity"));
+        assert!(env.functions().contains_key("format.named"));
+        assert!(env.functions().contains_key("isSorted"));
+
+        let env = build_env_from_settings(&preset(Some("1.27")));
+        assert!(env.functions().contains_key("isSorted"));
+        assert!(!env.functions().contains_key("quantity"));
+
+        assert_eq!(
+            kubernetes_version(&preset(Some("1.27"))),
+            "1.27".parse().unwrap()
+        );
+        assert_eq!(
+            kubernetes_version(&preset(Some("2.0"))),
+            KubernetesVersion::LATEST
+        );
+        assert_eq!(
+            kubernetes_version(&Settings::default()),
+            KubernetesVersion::LATEST
+        );
+    }
+
+    #[test]
+    fn iam_conditions_preset_ignores_extensions() {
+        let settings = Settings {
+            env: Some(EnvSettings {
+                preset: Some("iam-conditions".to_string()),
+                extensions: Some(vec!["strings".to_string(), "math".to_string()]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let env = build_env_from_settings(&settings);
+        assert!(env.functions().contains_key("startsWith"));
+        assert!(!env.functions().contains_key("charAt"));
+        assert!(!env.functions().contains_key("size"));
+    }
+
+    #[test]
+    fn build_env_with_envoy_preset() {
+        let settings = Settings {
+            env: Some(EnvSettings {
+                preset: Some("envoy".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let env = build_env_from_settings(&settings);
+        assert_eq!(
+            env.variables().get("request"),
+            Some(&CelType::message("envoy.attributes.Request"))
+        );
+        let registry = env.proto_registry().unwrap();
+        assert_eq!(
+            registry.get_field_type("envoy.attributes.Request", "time"),
+            Some(CelType::Timestamp)
+        );
+        assert_eq!(
+            registry.get_field_type(
+                "envoy.attributes.Connection",
+                "sha256_peer_certificate_digest"
+            ),
+            Some(CelType::String)
+        );
+    }
+
+    #[test]
+    fn build_env_with_declared_types() {
+        let settings: Settings = toml::from_str(
+            r#"
+[env]
+variables = { user = "User" }
+
+[env.types.User]
+name = "string"
+tags = "list(string)"
+address = { type = "Address", description = "Postal address." }
+
+[env.types.Address]
+city = "string"
+"#,
+        )
+        .unwrap();
+
+        let env = build_env_from_settings(&settings);
+        let registry = env.proto_registry().unwrap();
+        assert_eq!(
+            registry.get_field_type("User", "address"),
+            Some(CelType::message("Address"))
+        );
+        assert_eq!(
+            registry.get_field_type("User", "tags"),
+            Some(CelType::list(CelType::String))
+        );
+
+        let ast = env.compile("user.address.city == 'Oslo' && 'a' in user.tags");
+        assert!(ast.is_ok(), "{:?}", ast.err());
+        assert!(env.compile("user.email").is_err());
+    }
+
+    #[test]
+    fn parse_kubernetes_types() {
+        assert_eq!(
+            parse_type_string("kubernetes.Quantity").unwrap(),
+            CelType::abstract_type("kubernetes.Quantity", &[])
+        );
+        assert_eq!(
+            parse_type_string("list(net.IP)").unwrap(),
+            CelType::list(CelType::abstract_type("net.IP", &[]))
+        );
+    }
+
     #[test]
     fn protovalidate_extension_functions() {
-        let funcs = protovalidate_extension();
+        let funcs = protovalidate_library(ProtovalidateVersion::LATEST);
         let names: Vec<_> = funcs.iter().map(|f| f.name.as_str()).collect();
 
         assert!(names.contains(&"isEmail"));
@@ -649,21 +1363,54 @@ mod tests {
     }
 
     #[test]
-    fn load_proto_registry_with_no_settings() {
+    fn load_proto_registry_bundles_protovalidate() {
         let settings = Settings::default();
-        let result = load_proto_registry(&settings, std::path::Path::new("."));
-        assert!(result.is_none());
+        let registry = load_proto_registry(&settings, std::path::Path::new(".")).unwrap();
+        let pool = registry.pool();
+        assert!(pool
+            .get_message_by_name("buf.validate.FieldRules")
+            .is_some());
+        assert!(pool
+            .get_message_by_name("buf.validate.StringRules")
+            .is_some());
+        assert!(pool
+            .get_message_by_name("google.protobuf.Timestamp")
+            .is_some());
     }
 
     #[test]
-    fn load_proto_registry_with_empty_descriptors() {
+    fn load_proto_registry_for_protovalidate_version() {
+        let settings = Settings {
+            env: Some(EnvSettings {
+                protovalidate_version: Some("0.8".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let registry = load_proto_registry(&settings, std::path::Path::new(".")).unwrap();
+        let pool = registry.pool();
+        assert!(pool
+            .get_message_by_name("buf.validate.FieldConstraints")
+            .is_some());
+        assert!(pool
+            .get_message_by_name("buf.validate.FieldRules")
+            .is_none());
+        let ignore = pool.get_enum_by_name("buf.validate.Ignore").unwrap();
+        assert!(ignore.get_value_by_name("IGNORE_IF_UNPOPULATED").is_some());
+    }
+
+    #[test]
+    fn load_proto_registry_without_bundled_files() {
         let settings = Settings {
             env: Some(EnvSettings {
                 proto: Some(ProtoSettings {
                     descriptors: vec![],
+                    bundled: Some(false),
+                    ..Default::default()
                 }),
                 ..Default::default()
             }),
+            ..Default::default()
         };
         let result = load_proto_registry(&settings, std::path::Path::new("."));
         assert!(result.is_none());
@@ -678,14 +1425,66 @@ mod tests {
         assert!(env.functions().contains_key("size"));
     }
 
-    /// Create a unique temp directory for test isolation.
-    fn make_test_dir(name: &str) -> PathBuf {
-        let dir = std::env::temp_dir()
-            .join("cel-core-lsp-test")
-            .join(name)
-            .join(format!("{}", std::process::id()));
-        std::fs::create_dir_all(&dir).unwrap();
-        dir
+    #[test]
+    fn build_protos_with_buffers() {
+        let dir = make_test_dir("build-protos-buffers", &[]);
+        let file = dir.join("user.proto");
+        std::fs::write(&file, "syntax = \"proto3\";\nmessage User {}\n").unwrap();
+        let inputs = ProtoInputs {
+            sources: vec![dir.clone()],
+            ..Default::default()
+        };
+
+        let buffers = Buffers::from([(
+            std::fs::canonicalize(&file).unwrap(),
+            "syntax = \"proto3\";\nmessage User { string name = 1; }\n".to_string(),
+        )]);
+        let loaded = build_protos(&inputs, &buffers);
+        let registry = loaded.registry.unwrap();
+        let user = registry.pool().get_message_by_name("User").unwrap();
+        assert!(user.get_field_by_name("name").is_some());
+        assert!(loaded.errors.values().all(|errors| errors.is_empty()));
+
+        let unsaved = build_protos(&inputs, &Buffers::new()).registry.unwrap();
+        assert!(!crate::proto::same_definitions(
+            registry.pool(),
+            unsaved.pool()
+        ));
+        let again = build_protos(&inputs, &buffers).registry.unwrap();
+        assert!(crate::proto::same_definitions(
+            registry.pool(),
+            again.pool()
+        ));
+
+        cleanup_test_dir(&dir);
+    }
+
+    #[test]
+    fn proto_inputs_from_buf_yaml() {
+        let dir = make_test_dir("proto-inputs-buf", &[]);
+        std::fs::write(
+            dir.join("buf.yaml"),
+            "version: v2\nmodules:\n  - path: proto\n",
+        )
+        .unwrap();
+
+        let inputs = proto_inputs(&Settings::default(), &dir);
+        assert_eq!(inputs.sources, vec![dir.join("proto")]);
+        assert_eq!(inputs.include_paths, vec![dir.join("proto")]);
+
+        let settings = Settings {
+            env: Some(EnvSettings {
+                proto: Some(ProtoSettings {
+                    buf: Some(false),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(proto_inputs(&settings, &dir).sources.is_empty());
+
+        cleanup_test_dir(&dir);
     }
 
     /// Clean up a test directory.
@@ -695,7 +1494,7 @@ mod tests {
 
     #[test]
     fn discover_settings_in_current_dir() {
-        let dir = make_test_dir("discover-current");
+        let dir = make_test_dir("discover-current", &[]);
         let settings_content = r#"
 [env]
 variables = { x = "int" }
@@ -713,7 +1512,7 @@ variables = { x = "int" }
 
     #[test]
     fn discover_settings_in_parent_dir() {
-        let parent = make_test_dir("discover-parent");
+        let parent = make_test_dir("discover-parent", &[]);
         let child = parent.join("subdir");
         std::fs::create_dir_all(&child).unwrap();
 
@@ -734,7 +1533,7 @@ variables = { name = "string" }
 
     #[test]
     fn discover_settings_in_child_dir() {
-        let parent = make_test_dir("discover-child");
+        let parent = make_test_dir("discover-child", &[]);
         let child = parent.join("config");
         std::fs::create_dir_all(&child).unwrap();
 
@@ -753,9 +1552,28 @@ extensions = ["strings"]
         cleanup_test_dir(&parent);
     }
 
+    #[test]
+    fn load_settings_file_uses_parent_dir() {
+        let dir = make_test_dir("load-settings-file", &[]);
+        let path = dir.join("custom.toml");
+        std::fs::write(&path, "[env]\nvariables = { y = \"bool\" }\n").unwrap();
+
+        let (settings, settings_dir) = load_settings_file(&path).unwrap();
+        assert_eq!(settings_dir, dir);
+        let vars = settings.env.unwrap().variables.unwrap();
+        assert_eq!(vars.get("y").unwrap(), "bool");
+
+        let error = load_settings_file(&dir.join("missing.toml")).unwrap_err();
+        assert!(error.contains("missing.toml"), "{}", error);
+        std::fs::write(&path, "[env\n").unwrap();
+        assert!(load_settings_file(&path).is_err());
+
+        cleanup_test_dir(&dir);
+    }
+
     #[test]
     fn discover_settings_not_found() {
-        let dir = make_test_dir("discover-none");
+        let dir = make_test_dir("discover-none", &[]);
 
         let (settings, settings_dir) = discover_settings(&dir);
         assert_eq!(settings_dir, dir);
@@ -766,7 +1584,7 @@ extensions = ["strings"]
 
     #[test]
     fn discover_settings_parent_preferred_over_child() {
-        let parent = make_test_dir("discover-priority");
+        let parent = make_test_dir("discover-priority", &[]);
         let child = parent.join("nested");
         std::fs::create_dir_all(&child).unwrap();
 
@@ -790,4 +1608,166 @@ extensions = ["strings"]
 
         cleanup_test_dir(&parent);
     }
+
+    #[test]
+    fn merge_layers_overrides() {
+        let mut settings: Settings = toml::from_str(
+            r#"
+[env]
+container = "base"
+extensions = ["strings"]
+variables = { x = "int", y = "string" }
+
+[diagnostics]
+check = false
+
+[diagnostics.lints]
+invalid-regex = "warning"
+unsupported-regex = "off"
+"#,
+        )
+        .unwrap();
+        let overrides = parse_client_settings(&serde_json::json!({
+            "env": {
+                "extensions": ["math", "strings"],
+                "variables": { "y": "bool", "z": "double" },
+                "proto": { "descriptors": ["extra.binpb"] }
+            },
+            "diagnostics": { "parse": false, "lints": { "invalid-regex": "hint" } },
+            "inlay_hints": { "result_types": true }
+        }))
+        .unwrap();
+
+        settings.merge(overrides);
+
+        let env = settings.env.as_ref().unwrap();
+        assert_eq!(env.container.as_deref(), Some("base"));
+        assert_eq!(
+            env.extensions.as_deref(),
+            Some(&["strings".to_string(), "math".to_string()][..])
+        );
+        let vars = env.variables.as_ref().unwrap();
+        assert_eq!(vars.get("x").unwrap(), "int");
+        assert_eq!(vars.get("y").unwrap(), "bool");
+        assert_eq!(vars.get("z").unwrap(), "double");
+        assert_eq!(
+            env.proto.as_ref().unwrap().descriptors,
+            vec![PathBuf::from("extra.binpb")]
+        );
+        assert!(!settings.diagnostics().parse_enabled());
+        assert!(!settings.diagnostics().check_enabled());
+        assert_eq!(
+            settings.diagnostics().lint_level("invalid-regex"),
+            Some(LintLevel::Hint)
+        );
+        assert_eq!(
+            settings.diagnostics().lint_level("unsupported-regex"),
+            Some(LintLevel::Off)
+        );
+        assert_eq!(settings.diagnostics().lint_level("type-mismatch"), None);
+        assert!(settings.inlay_hints().result_types_enabled());
+    }
+
+    #[test]
+    fn expected_types_by_pattern() {
+        let settings: Settings = toml::from_str(
+            r#"
+[env]
+expected_type = "bool"
+
+[env.expected_types]
+"*.message.cel" = "string"
+"*/audit/*.cel" = "string | null"
+"bad.cel" = "nope("
+"#,
+        )
+        .unwrap();
+        let expected = expected_types(&settings);
+        let types = |path: &str| expected.for_path(Some(Path::new(path))).to_vec();
+
+        assert_eq!(types("/ws/rules/size.cel"), vec![CelType::Bool]);
+        assert_eq!(types("/ws/rules/size.message.cel"), vec![CelType::String]);
+        assert_eq!(
+            types("/ws/audit/name.cel"),
+            vec![CelType::String, CelType::Null]
+        );
+        assert!(types("/ws/bad.cel").is_empty());
+        assert_eq!(expected.for_path(None), &[CelType::Bool]);
+        assert!(expected_types(&Settings::default())
+            .for_path(None)
+            .is_empty());
+    }
+
+    #[test]
+    fn cost_budget_merges_size_hints() {
+        let mut settings: Settings = toml::from_str(
+            r#"
+[env]
+cost_limit = 1000
+
+[env.max_sizes]
+"request.items" = 10
+"request.items[]" = 64
+"#,
+        )
+        .unwrap();
+        settings.merge(
+            parse_client_settings(&serde_json::json!({
+                "env": { "max_sizes": { "request.items": 20 } }
+            }))
+            .unwrap(),
+        );
+
+        let budget = cost_budget(&settings);
+        assert_eq!(budget.limit, Some(1000));
+        assert_eq!(budget.max_sizes.get("request.items"), Some(&20));
+        assert_eq!(budget.max_sizes.get("request.items[]"), Some(&64));
+        assert_eq!(cost_budget(&Settings::default()), CostBudget::default());
+    }
+
+    #[test]
+    fn complexity_limits_override() {
+        let mut settings: Settings = toml::from_str(
+            r#"
+[env]
+max_depth = 32
+max_nodes = 1000
+"#,
+        )
+        .unwrap();
+        settings.merge(
+            parse_client_settings(&serde_json::json!({
+                "env": { "max_nodes": 500, "max_comprehension_nesting": 2 }
+            }))
+            .unwrap(),
+        );
+
+        assert_eq!(
+            complexity_limits(&settings),
+            ComplexityLimits {
+                max_depth: Some(32),
+                max_nodes: Some(500),
+                max_comprehension_nesting: Some(2),
+            }
+        );
+    }
+
+    #[test]
+    fn matches_wildcard_patterns() {
+        assert!(matches_pattern("*.cel", "rule.cel"));
+        assert!(matches_pattern("rule.cel", "rule.cel"));
+        assert!(matches_pattern("*rule*", "my-rules.cel"));
+        assert!(!matches_pattern("*.cel", "rule.yaml"));
+        assert!(!matches_pattern("a*a", "a"));
+    }
+
+    #[test]
+    fn parse_client_settings_accepts_wrapped_section() {
+        let wrapped = serde_json::json!({ "celsp": { "env": { "container": "acme" } } });
+        let settings = parse_client_settings(&wrapped).unwrap();
+        assert_eq!(settings.env.unwrap().container.as_deref(), Some("acme"));
+
+        assert!(parse_client_settings(&serde_json::Value::Null).is_none());
+        assert!(parse_client_settings(&serde_json::json!({ "env": 1 })).is_none());
+    }
 }
@@ -0,0 +1,21 @@
+//! Helpers shared by unit tests.
+
+use std::fs;
+use std::path::PathBuf;
+
+/// Create a fresh per-process temp directory holding `files`, given as
+/// `(relative path, contents)` pairs, and return its canonical path.
+pub(crate) fn make_test_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir()
+        .join("cel-core-lsp-test")
+        .join(name)
+        .join(format!("{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for (file, contents) in files {
+        let path = dir.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+    fs::canonicalize(&dir).unwrap()
+}
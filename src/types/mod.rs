@@ -4,9 +4,17 @@
 //! - `FunctionKind` to distinguish standalone functions from methods
 //! - `Arity` to specify expected argument counts
 //! - Builtin function definitions with type information for hover docs
+//! - `SyntheticTypes` for message types declared outside of protobuf descriptors
+//! - Documentation and deprecation of protobuf fields, messages and enum values
 
 mod builtins;
 mod function;
+mod proto_docs;
+mod synthetic;
 
 pub use builtins::{get_builtin, is_builtin};
 pub use function::FunctionDef;
+pub use proto_docs::{
+    field_documentation, is_deprecated_enum_value, is_deprecated_field, message_documentation,
+};
+pub use synthetic::SyntheticTypes;
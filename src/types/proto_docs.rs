@@ -0,0 +1,334 @@
+//! Documentation and deprecation of message types, fields and enum values
+//! for hover, completion and diagnostics.
+//!
+//! Synthetic fields carry their own description. Protobuf fields and
+//! messages are documented from their descriptor: the comments recorded in
+//! `source_code_info`, the field's declaration and the protovalidate
+//! standard constraints set by its `(buf.validate.field)` option.
+
+use cel_core::ProtoRegistry;
+use cel_core_proto::ProstProtoRegistry;
+use prost_reflect::prost_types::field_descriptor_proto::{Label, Type};
+use prost_reflect::{
+    DynamicMessage, EnumDescriptor, FieldDescriptor, FileDescriptor, MessageDescriptor, Syntax,
+    Value,
+};
+
+use super::SyntheticTypes;
+
+/// Extension holding protovalidate field constraints.
+const VALIDATE_FIELD: &str = "buf.validate.field";
+
+/// Markdown documentation of a field: the description of a synthetic field,
+/// or the declaration, comments and constraints of a protobuf field.
+pub fn field_documentation(
+    registry: &dyn ProtoRegistry,
+    message: &str,
+    field: &str,
+) -> Option<String> {
+    if let Some(types) = registry.as_any().downcast_ref::<SyntheticTypes>() {
+        if let Some(field) = types.field(message, field) {
+            return field.description.clone();
+        }
+        return field_documentation(types.fallback()?, message, field);
+    }
+    let field = proto_message(registry, message)?.get_field_by_name(field)?;
+
+    let mut sections = vec![format!("```proto\n{}\n```", field_declaration(&field))];
+    sections.extend(comments(&field.parent_file(), field.path()));
+    let constraints = field_constraints(&field);
+    if !constraints.is_empty() {
+        let items: Vec<String> = constraints
+            .iter()
+            .map(|constraint| format!("- `{}`", constraint))
+            .collect();
+        sections.push(format!("*Constraints:*\n{}", items.join("\n")));
+    }
+    Some(sections.join("\n\n"))
+}
+
+/// The comments of a protobuf message type.
+pub fn message_documentation(registry: &dyn ProtoRegistry, message: &str) -> Option<String> {
+    let descriptor = proto_message(registry, message)?;
+    comments(&descriptor.parent_file(), descriptor.path())
+}
+
+/// Whether a protobuf field is declared with `[deprecated = true]`.
+pub fn is_deprecated_field(registry: &dyn ProtoRegistry, message: &str, field: &str) -> bool {
+    proto_message(registry, message)
+        .and_then(|message| message.get_field_by_name(field))
+        .is_some_and(|field| is_deprecated(&field.options()))
+}
+
+/// Whether a protobuf enum value is declared with `[deprecated = true]`.
+pub fn is_deprecated_enum_value(
+    registry: &dyn ProtoRegistry,
+    enum_name: &str,
+    value: &str,
+) -> bool {
+    proto_enum(registry, enum_name)
+        .and_then(|enumeration| enumeration.get_value_by_name(value))
+        .is_some_and(|value| is_deprecated(&value.options()))
+}
+
+/// Whether descriptor options set `deprecated`.
+fn is_deprecated(options: &DynamicMessage) -> bool {
+    options
+        .get_field_by_name("deprecated")
+        .is_some_and(|value| value.as_bool() == Some(true))
+}
+
+/// The protobuf registry behind `registry`, looking through synthetic types.
+fn prost_registry(registry: &dyn ProtoRegistry) -> Option<&ProstProtoRegistry> {
+    let any = registry.as_any();
+    match any.downcast_ref::<ProstProtoRegistry>() {
+        Some(registry) => Some(registry),
+        None => prost_registry(any.downcast_ref::<SyntheticTypes>()?.fallback()?),
+    }
+}
+
+/// The descriptor of a protobuf message.
+fn proto_message(registry: &dyn ProtoRegistry, message: &str) -> Option<MessageDescriptor> {
+    prost_registry(registry)?.get_message(message)
+}
+
+/// The descriptor of a protobuf enum.
+fn proto_enum(registry: &dyn ProtoRegistry, enum_name: &str) -> Option<EnumDescriptor> {
+    prost_registry(registry)?.get_enum(enum_name)
+}
+
+/// The leading and trailing comments of the element at `path`, separated by
+/// a blank line.
+fn comments(file: &FileDescriptor, path: &[i32]) -> Option<String> {
+    let info = file.file_descriptor_proto().source_code_info.as_ref()?;
+    let location = info.location.iter().find(|l| l.path == path)?;
+    let text: Vec<String> = [&location.leading_comments, &location.trailing_comments]
+        .into_iter()
+        .flatten()
+        .map(|comment| {
+            let lines: Vec<&str> = comment.lines().map(str::trim).collect();
+            lines.join("\n").trim().to_string()
+        })
+        .filter(|comment| !comment.is_empty())
+        .collect();
+    (!text.is_empty()).then(|| text.join("\n\n"))
+}
+
+/// The field as declared in a `.proto` file, e.g. `repeated string tags = 2`.
+fn field_declaration(field: &FieldDescriptor) -> String {
+    let proto = field.field_descriptor_proto();
+    let label = if field.is_map() {
+        ""
+    } else {
+        match proto.label() {
+            Label::Repeated => "repeated ",
+            Label::Required => "required ",
+            Label::Optional
+                if proto.proto3_optional() || field.parent_file().syntax() == Syntax::Proto2 =>
+            {
+                "optional "
+            }
+            Label::Optional => "",
+        }
+    };
+    let options = if is_deprecated(&field.options()) {
+        " [deprecated = true]"
+    } else {
+        ""
+    };
+    format!(
+        "{}{} {} = {}{}",
+        label,
+        type_name(field),
+        field.name(),
+        field.number(),
+        options
+    )
+}
+
+/// The proto type of a field, e.g. `int32`, `acme.User` or `map<string, int64>`.
+fn type_name(field: &FieldDescriptor) -> String {
+    if field.is_map() {
+        if let Some(entry) = field.kind().as_message() {
+            return format!(
+                "map<{}, {}>",
+                type_name(&entry.map_entry_key_field()),
+                type_name(&entry.map_entry_value_field())
+            );
+        }
+    }
+    let proto = field.field_descriptor_proto();
+    match proto.r#type() {
+        Type::Message | Type::Enum | Type::Group => {
+            proto.type_name().trim_start_matches('.').to_string()
+        }
+        scalar => scalar
+            .as_str_name()
+            .trim_start_matches("TYPE_")
+            .to_lowercase(),
+    }
+}
+
+/// The protovalidate standard constraints of a field, as assignments such as
+/// `string.max_len = 64`. Custom `cel` rules are left out.
+fn field_constraints(field: &FieldDescriptor) -> Vec<String> {
+    let Some(extension) = field.parent_pool().get_extension_by_name(VALIDATE_FIELD) else {
+        return Vec::new();
+    };
+    let options = field.options();
+    if !options.has_extension(&extension) {
+        return Vec::new();
+    }
+    let mut constraints = Vec::new();
+    if let Some(rules) = options.get_extension(&extension).as_message() {
+        for (rule, value) in rules.fields() {
+            if !rule.name().starts_with("cel") {
+                flatten(rule.name(), &rule, value, &mut constraints);
+            }
+        }
+    }
+    constraints
+}
+
+/// Append `value` as `path = value` assignments, one per scalar field.
+fn flatten(path: &str, field: &FieldDescriptor, value: &Value, out: &mut Vec<String>) {
+    match value {
+        Value::Message(message) => {
+            for (inner, value) in message.fields() {
+                flatten(&format!("{}.{}", path, inner.name()), &inner, value, out);
+            }
+        }
+        Value::List(values) => {
+            let values: Vec<String> = values.iter().map(|v| scalar(field, v)).collect();
+            out.push(format!("{} = [{}]", path, values.join(", ")));
+        }
+        value => out.push(format!("{} = {}", path, scalar(field, value))),
+    }
+}
+
+/// A scalar as written in a `.proto` file, with enum values by name.
+fn scalar(field: &FieldDescriptor, value: &Value) -> String {
+    match (value, field.kind().as_enum()) {
+        (Value::EnumNumber(number), Some(enumeration)) => enumeration
+            .get_value(*number)
+            .map_or_else(|| number.to_string(), |v| v.name().to_string()),
+        _ => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{Buffers, ProtoInputs};
+    use crate::protovalidate::{validate_proto, ProtovalidateVersion, VALIDATE_PROTO};
+    use cel_core::CelType;
+    use std::sync::Arc;
+
+    const USER_PROTO: &str = r#"syntax = "proto3";
+package acme;
+
+import "buf/validate/validate.proto";
+
+// A registered user.
+message User {
+    // The display name.
+    string name = 1 [(buf.validate.field).string = { min_len: 1, max_len: 64 }];
+    optional string email = 2 [(buf.validate.field).string.email = true]; // Verified.
+    repeated string tags = 3 [(buf.validate.field).cel = {
+        id: "tags"
+        expression: "this.size() < 5"
+    }];
+    map<string, int64> scores = 4;
+    string nickname = 5 [deprecated = true];
+
+    enum Role {
+        ROLE_UNSPECIFIED = 0;
+        ROLE_GUEST = 1 [deprecated = true];
+    }
+}
+"#;
+
+    fn registry() -> ProstProtoRegistry {
+        let inputs = ProtoInputs {
+            bundled: vec![
+                (
+                    VALIDATE_PROTO.to_string(),
+                    validate_proto(ProtovalidateVersion::LATEST).to_string(),
+                ),
+                ("acme/user.proto".to_string(), USER_PROTO.to_string()),
+            ],
+            ..Default::default()
+        };
+        let (pool, errors) = inputs.build(&Buffers::new());
+        assert!(errors.is_empty(), "{:?}", errors);
+        ProstProtoRegistry::from_pool(pool)
+    }
+
+    #[test]
+    fn documents_fields_from_descriptors() {
+        let registry = registry();
+        assert_eq!(
+            field_documentation(&registry, "acme.User", "name").unwrap(),
+            "```proto\nstring name = 1\n```\n\nThe display name.\n\n\
+             *Constraints:*\n- `string.min_len = 1`\n- `string.max_len = 64`"
+        );
+        assert_eq!(
+            field_documentation(&registry, "acme.User", "email").unwrap(),
+            "```proto\noptional string email = 2\n```\n\nVerified.\n\n\
+             *Constraints:*\n- `string.email = true`"
+        );
+        assert_eq!(
+            field_documentation(&registry, "acme.User", "tags").unwrap(),
+            "```proto\nrepeated string tags = 3\n```"
+        );
+        assert_eq!(
+            field_documentation(&registry, "acme.User", "scores").unwrap(),
+            "```proto\nmap<string, int64> scores = 4\n```"
+        );
+        assert_eq!(
+            message_documentation(&registry, "acme.User").as_deref(),
+            Some("A registered user.")
+        );
+    }
+
+    #[test]
+    fn finds_deprecated_fields_and_enum_values() {
+        let registry = registry();
+        assert!(is_deprecated_field(&registry, "acme.User", "nickname"));
+        assert!(!is_deprecated_field(&registry, "acme.User", "name"));
+        assert!(is_deprecated_enum_value(
+            &registry,
+            "acme.User.Role",
+            "ROLE_GUEST"
+        ));
+        assert!(!is_deprecated_enum_value(
+            &registry,
+            "acme.User.Role",
+            "ROLE_UNSPECIFIED"
+        ));
+        assert_eq!(
+            field_documentation(&registry, "acme.User", "nickname").unwrap(),
+            "```proto\nstring nickname = 5 [deprecated = true]\n```"
+        );
+    }
+
+    #[test]
+    fn looks_through_synthetic_types() {
+        let mut types = SyntheticTypes::new();
+        types.add_field(
+            "Widget",
+            "size",
+            CelType::Int,
+            Some("Size in pixels.".to_string()),
+        );
+        let types = types.with_fallback(Arc::new(registry()));
+        assert_eq!(
+            field_documentation(&types, "Widget", "size").as_deref(),
+            Some("Size in pixels.")
+        );
+        assert_eq!(
+            message_documentation(&types, "acme.User").as_deref(),
+            Some("A registered user.")
+        );
+    }
+}
@@ -0,0 +1,294 @@
+//! Message types declared outside of protobuf descriptors.
+//!
+//! `SyntheticTypes` implements cel-core's `ProtoRegistry` so that object types
+//! described by other schema languages (such as the OpenAPI schemas of
+//! Kubernetes CRDs) get field-level type checking, hover and completion just
+//! like protobuf messages. Fields may have any CEL type, including lists, maps,
+//! timestamps and other synthetic messages.
+//!
+//! The registry is only used for type checking; evaluation is not supported.
+//! A protobuf registry can be layered underneath with `with_fallback`, so
+//! synthetic types and descriptor sets can be used together.
+
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+use cel_core::types::ResolvedProtoType;
+use cel_core::{CelType, MessageValue, ProtoRegistry, ProtoTypeResolver, StructFieldValue, Value};
+
+/// A field of a synthetic message.
+#[derive(Debug, Clone)]
+pub struct SyntheticField {
+    /// The CEL type of the field.
+    pub cel_type: CelType,
+    /// Documentation shown on hover and completion.
+    pub description: Option<String>,
+}
+
+/// A synthetic message type: an ordered set of named, typed fields.
+#[derive(Debug, Clone, Default)]
+pub struct SyntheticMessage {
+    /// Fields by name.
+    pub fields: BTreeMap<String, SyntheticField>,
+}
+
+/// A registry of synthetic message types.
+#[derive(Clone, Default)]
+pub struct SyntheticTypes {
+    messages: BTreeMap<String, SyntheticMessage>,
+    /// Registry consulted for types that are not declared here.
+    fallback: Option<Arc<dyn ProtoRegistry>>,
+}
+
+impl fmt::Debug for SyntheticTypes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SyntheticTypes")
+            .field("messages", &self.messages)
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
+}
+
+impl SyntheticTypes {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declare a message type, returning it for adding fields.
+    ///
+    /// Declaring an existing message returns the existing declaration.
+    pub fn add_message(&mut self, name: &str) -> &mut SyntheticMessage {
+        self.messages.entry(name.to_string()).or_default()
+    }
+
+    /// Add a field to a message, declaring the message if needed.
+    pub fn add_field(
+        &mut self,
+        message: &str,
+        field: &str,
+        cel_type: CelType,
+        description: Option<String>,
+    ) {
+        self.add_message(message).fields.insert(
+            field.to_string(),
+            SyntheticField {
+                cel_type,
+                description,
+            },
+        );
+    }
+
+    /// Look up a field of a message type.
+    pub fn field(&self, message: &str, field: &str) -> Option<&SyntheticField> {
+        self.messages.get(message)?.fields.get(field)
+    }
+
+    /// Check whether no message types have been declared.
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// Resolve types that are not declared here through another registry.
+    pub fn with_fallback(mut self, fallback: Arc<dyn ProtoRegistry>) -> Self {
+        self.fallback = Some(fallback);
+        self
+    }
+
+    pub(super) fn fallback(&self) -> Option<&dyn ProtoRegistry> {
+        self.fallback.as_deref()
+    }
+}
+
+impl ProtoTypeResolver for SyntheticTypes {
+    fn get_field_type(&self, message: &str, field: &str) -> Option<CelType> {
+        if self.messages.contains_key(message) {
+            return self.field(message, field).map(|f| f.cel_type.clone());
+        }
+        self.fallback()?.get_field_type(message, field)
+    }
+
+    fn has_message(&self, message: &str) -> bool {
+        self.messages.contains_key(message)
+            || self.fallback().is_some_and(|f| f.has_message(message))
+    }
+
+    fn is_extension(&self, message: &str, ext_name: &str) -> bool {
+        self.fallback()
+            .is_some_and(|f| f.is_extension(message, ext_name))
+    }
+
+    fn get_enum_value(&self, enum_name: &str, value_name: &str) -> Option<i32> {
+        self.fallback()?.get_enum_value(enum_name, value_name)
+    }
+
+    fn resolve_qualified(&self, parts: &[&str], container: &str) -> Option<ResolvedProtoType> {
+        if let Some(name) = self.resolve_synthetic_name(&parts.join("."), container) {
+            return Some(ResolvedProtoType::Message {
+                cel_type: CelType::message(&name),
+                name,
+            });
+        }
+        self.fallback()?.resolve_qualified(parts, container)
+    }
+
+    fn resolve_message_name(&self, name: &str, container: &str) -> Option<String> {
+        self.resolve_synthetic_name(name, container)
+            .or_else(|| self.fallback()?.resolve_message_name(name, container))
+    }
+
+    fn message_field_names(&self, message: &str) -> Option<Vec<String>> {
+        match self.messages.get(message) {
+            Some(m) => Some(m.fields.keys().cloned().collect()),
+            None => self.fallback()?.message_field_names(message),
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl SyntheticTypes {
+    /// Resolve a declared message name, following C++ namespace rules.
+    fn resolve_synthetic_name(&self, name: &str, container: &str) -> Option<String> {
+        // Try the most qualified candidate first
+        let mut scope = container;
+        loop {
+            let candidate = if scope.is_empty() {
+                name.to_string()
+            } else {
+                format!("{}.{}", scope, name)
+            };
+            if self.messages.contains_key(&candidate) {
+                return Some(candidate);
+            }
+            if scope.is_empty() {
+                return None;
+            }
+            scope = scope
+                .rsplit_once('.')
+                .map(|(parent, _)| parent)
+                .unwrap_or("");
+        }
+    }
+}
+
+impl ProtoRegistry for SyntheticTypes {
+    fn construct_message(
+        &self,
+        type_name: &str,
+        fields: &[StructFieldValue],
+        strong_enums: bool,
+    ) -> Value {
+        match self.fallback() {
+            Some(f) if !self.messages.contains_key(type_name) => {
+                f.construct_message(type_name, fields, strong_enums)
+            }
+            _ => Value::error(format!(
+                "cannot construct synthetic type '{}' at runtime",
+                type_name
+            )),
+        }
+    }
+
+    fn message_field_access(
+        &self,
+        msg: &dyn MessageValue,
+        field: &str,
+        optional: bool,
+        strong_enums: bool,
+    ) -> Value {
+        match self.fallback() {
+            Some(f) => f.message_field_access(msg, field, optional, strong_enums),
+            None => Value::error(format!(
+                "cannot access field '{}' of a synthetic type at runtime",
+                field
+            )),
+        }
+    }
+
+    fn message_has_field(&self, msg: &dyn MessageValue, field: &str) -> Value {
+        match self.fallback() {
+            Some(f) => f.message_has_field(msg, field),
+            None => Value::error(format!(
+                "cannot test field '{}' of a synthetic type at runtime",
+                field
+            )),
+        }
+    }
+
+    fn get_extension_value(
+        &self,
+        msg: &dyn MessageValue,
+        ext_name: &str,
+        optional: bool,
+        strong_enums: bool,
+    ) -> Option<Value> {
+        self.fallback()?
+            .get_extension_value(msg, ext_name, optional, strong_enums)
+    }
+
+    fn has_extension(&self, msg: &dyn MessageValue, ext_name: &str) -> Option<bool> {
+        self.fallback()?.has_extension(msg, ext_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cel_core::{parse, Env};
+    use std::sync::Arc;
+
+    fn types() -> SyntheticTypes {
+        let mut types = SyntheticTypes::new();
+        types.add_field("acme.Widget", "name", CelType::String, None);
+        types.add_field(
+            "acme.Widget",
+            "parts",
+            CelType::list(CelType::message("acme.Part")),
+            None,
+        );
+        types.add_field("acme.Part", "weight", CelType::Double, None);
+        types
+    }
+
+    fn check(source: &str) -> cel_core::CheckResult {
+        let env = Env::with_standard_library()
+            .with_proto_registry(Arc::new(types()))
+            .with_variable("w", CelType::message("acme.Widget"));
+        env.check(&parse(source).ast.unwrap())
+    }
+
+    #[test]
+    fn fields_are_typed() {
+        let result = check("w.parts.all(p, p.weight > 0.5) && w.name != ''");
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+    }
+
+    #[test]
+    fn unknown_fields_are_reported() {
+        let result = check("w.color == 'red'");
+        assert_eq!(result.errors.len(), 1);
+    }
+
+    #[test]
+    fn resolves_names_in_container() {
+        let types = types();
+        assert_eq!(
+            types.resolve_message_name("Part", "acme"),
+            Some("acme.Part".to_string())
+        );
+        assert_eq!(
+            types.resolve_message_name("acme.Part", "other"),
+            Some("acme.Part".to_string())
+        );
+        assert_eq!(types.resolve_message_name("Gear", "acme"), None);
+        assert_eq!(
+            types.message_field_names("acme.Widget"),
+            Some(vec!["name".to_string(), "parts".to_string()])
+        );
+    }
+}
@@ -0,0 +1,47 @@
+//! Discovery of CEL and proto source files on disk.
+
+use std::path::{Path, PathBuf};
+
+/// Directory names skipped while walking directories for source files.
+const SKIPPED_DIRS: &[&str] = &["target", "node_modules"];
+
+/// Recursively collect `.cel`, `.proto` and YAML files under `dir`, sorted by path.
+///
+/// Hidden directories and build output directories are skipped.
+pub fn source_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    walk_dir(dir, &mut files);
+    files.sort();
+    files
+}
+
+fn walk_dir(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+
+        if file_type.is_dir() {
+            if !name.starts_with('.') && !SKIPPED_DIRS.contains(&name.as_ref()) {
+                walk_dir(&path, files);
+            }
+        } else if is_source_file(&path) {
+            files.push(path);
+        }
+    }
+}
+
+/// Check if a path has a `.cel`, `.proto`, `.yaml` or `.yml` extension.
+pub fn is_source_file(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|e| e.to_str()),
+        Some("cel") | Some("proto") | Some("yaml") | Some("yml")
+    )
+}
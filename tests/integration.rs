@@ -4,8 +4,8 @@ use std::sync::Arc;
 use cel_core::Env;
 use celsp::{build_env_with_protos, discover_settings, load_proto_registry, load_settings};
 use celsp::{
-    completion_at_position_proto, proto_to_diagnostics, to_diagnostics, DocumentState, LineIndex,
-    ProtoDocumentState,
+    completion_at_position_proto, proto_to_diagnostics, to_diagnostics, DocumentState,
+    EmbeddedDocumentState, KubernetesVersion, LineIndex, ProtovalidateVersion,
 };
 use expect_test::expect;
 use tower_lsp::lsp_types::{CompletionResponse, Diagnostic, Position};
@@ -70,7 +70,7 @@ fn check_cel(fixture_dir: &str, source: &str) -> String {
 
     let state = DocumentState::with_env(source.to_string(), 0, Arc::new(env));
     let line_index = LineIndex::new(source.to_string());
-    let diagnostics = to_diagnostics(&state.errors, state.check_errors(), &line_index);
+    let diagnostics = to_diagnostics(&state.errors, state.check_errors(), &line_index, &state.env);
 
     format_diagnostics(&diagnostics)
 }
@@ -80,7 +80,7 @@ fn check_cel_default(source: &str) -> String {
     let env = Env::with_standard_library().with_all_extensions();
     let state = DocumentState::with_env(source.to_string(), 0, Arc::new(env));
     let line_index = LineIndex::new(source.to_string());
-    let diagnostics = to_diagnostics(&state.errors, state.check_errors(), &line_index);
+    let diagnostics = to_diagnostics(&state.errors, state.check_errors(), &line_index, &state.env);
 
     format_diagnostics(&diagnostics)
 }
@@ -174,6 +174,106 @@ fn math_extension() {
     expected.assert_eq(&actual);
 }
 
+#[test]
+fn kubernetes_preset() {
+    let actual = check_cel(
+        "kubernetes",
+        "quantity(limit).isLessThan(quantity('2Gi')) && cidr('10.0.0.0/8').containsIP(addr) && hosts.isSorted()",
+    );
+    let expected = expect![[r#"OK (no diagnostics)"#]];
+    expected.assert_eq(&actual);
+}
+
+#[test]
+fn kubernetes_preset_opaque_types() {
+    let actual = check_cel("kubernetes", "url(endpoint).getHost() + quantity(limit)");
+    let expected = expect![[
+        r#"0:0-0:41 error [no-matching-overload]: no matching overload for '_+_' with argument types (string, kubernetes.Quantity)"#
+    ]];
+    expected.assert_eq(&actual);
+}
+
+#[test]
+fn envoy_preset() {
+    let actual = check_cel(
+        "envoy",
+        "request.headers['x-user'] == user.name && source.port < 1024 && connection.mtls",
+    );
+    let expected = expect![[r#"OK (no diagnostics)"#]];
+    expected.assert_eq(&actual);
+}
+
+#[test]
+fn envoy_preset_attribute_types() {
+    let actual = check_cel("envoy", "request.size + 'large' || upstream.hostname == ''");
+    let expected = expect![[r#"
+        0:0-0:22 error [no-matching-overload]: no matching overload for '_+_' with argument types (int, string)
+        0:26-0:43 error [undefined-field]: undefined field 'hostname' on type 'envoy.attributes.Upstream'"#]];
+    expected.assert_eq(&actual);
+}
+
+#[test]
+fn iam_conditions_preset() {
+    let actual = check_cel(
+        "iam",
+        "request.time < timestamp('2025-01-01T00:00:00Z') && resource.name.startsWith('projects/_/buckets/logs/')",
+    );
+    let expected = expect![[r#"OK (no diagnostics)"#]];
+    expected.assert_eq(&actual);
+}
+
+#[test]
+fn iam_conditions_disallowed_functions() {
+    let actual = check_cel(
+        "iam",
+        "resource.name.matches('^projects/') || size(request.auth.access_levels) > 0",
+    );
+    let expected = expect![[r#"
+        0:0-0:35 error [disallowed-function]: 'matches' is not available in this environment
+        0:39-0:71 error [disallowed-function]: 'size' is not available in this environment"#]];
+    expected.assert_eq(&actual);
+}
+
+#[test]
+fn declared_types() {
+    let actual = check_cel(
+        "types",
+        "user.address.city == 'Oslo' && user.roles['admin'] && orders.all(o, o.total > 10.0)",
+    );
+    let expected = expect![[r#"OK (no diagnostics)"#]];
+    expected.assert_eq(&actual);
+}
+
+#[test]
+fn declared_types_undefined_field() {
+    let actual = check_cel(
+        "types",
+        "user.address.zip == '0150' || orders[0].placed > 1",
+    );
+    let expected = expect![[r#"
+        0:0-0:16 error [undefined-field]: undefined field 'zip' on type 'Address'
+        0:30-0:50 error [no-matching-overload]: no matching overload for '_>_' with argument types (timestamp, int)"#]];
+    expected.assert_eq(&actual);
+}
+
+#[test]
+fn openapi_schema_types() {
+    let actual = check_cel(
+        "openapi",
+        "user.created < timestamp('2024-01-01T00:00:00Z') && user.labels['team'] == user.address.city && orders.exists(o, o.items.exists(i, i.sku == 'x'))",
+    );
+    let expected = expect![[r#"OK (no diagnostics)"#]];
+    expected.assert_eq(&actual);
+}
+
+#[test]
+fn openapi_schema_undefined_field() {
+    let actual = check_cel("openapi", "user.address.zip == '' || orders[0].total > 1.0");
+    let expected =
+        expect![[r#"0:0-0:16 error [undefined-field]: undefined field 'zip' on type 'Address'"#]];
+    expected.assert_eq(&actual);
+}
+
 // ---------------------------------------------------------------------------
 // Tests — proto types
 // ---------------------------------------------------------------------------
@@ -201,12 +301,51 @@ fn proto_nested_access() {
     expected.assert_eq(&actual);
 }
 
+#[test]
+fn proto_sources_field_access() {
+    let actual = check_cel(
+        "proto-sources",
+        "user.address.city == 'Berlin' && user.roles['ops'] == User.Role.ROLE_ADMIN && user.created < timestamp('2024-01-01T00:00:00Z')",
+    );
+    let expected = expect![[r#"OK (no diagnostics)"#]];
+    expected.assert_eq(&actual);
+}
+
+#[test]
+fn proto_sources_undefined_field() {
+    let actual = check_cel("proto-sources", "user.address.zip");
+    let expected = expect![[
+        r#"0:0-0:16 error [undefined-field]: undefined field 'zip' on type 'acme.v1.Address'"#
+    ]];
+    expected.assert_eq(&actual);
+}
+
+#[test]
+fn proto_buf_modules() {
+    let actual = check_cel(
+        "proto-buf",
+        "order.items.all(i, i.quantity > 0) && order.id != ''",
+    );
+    let expected = expect![[r#"OK (no diagnostics)"#]];
+    expected.assert_eq(&actual);
+}
+
+#[test]
+fn proto_bundled_protovalidate_types() {
+    let actual = check_cel(
+        "proto-buf",
+        "buf.validate.StringRules{min_len: 1u}.min_len == 1u && buf.validate.FieldRules{}.string.max_len > 0u",
+    );
+    let expected = expect![[r#"OK (no diagnostics)"#]];
+    expected.assert_eq(&actual);
+}
+
 // ---------------------------------------------------------------------------
 // Tests — protovalidate `this` type resolution
 // ---------------------------------------------------------------------------
 
 /// Build a proto source with a message-level protovalidate CEL expression,
-/// parse it as a ProtoDocumentState with the proto fixture registry,
+/// parse it as a EmbeddedDocumentState with the proto fixture registry,
 /// and return formatted diagnostics.
 fn check_protovalidate_message(message_name: &str, cel_expr: &str) -> String {
     let fixture_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/proto");
@@ -225,7 +364,12 @@ message {} {{
         message_name, cel_expr
     );
 
-    let state = ProtoDocumentState::new(proto_source, 0, registry.as_ref());
+    let state = EmbeddedDocumentState::new(
+        proto_source,
+        0,
+        registry.as_ref(),
+        ProtovalidateVersion::LATEST,
+    );
     let diagnostics = proto_to_diagnostics(&state);
     format_diagnostics(&diagnostics)
 }
@@ -267,7 +411,7 @@ fn protovalidate_has_undefined_field() {
 // ---------------------------------------------------------------------------
 
 /// Build a proto source with a field-level protovalidate CEL expression,
-/// parse it as a ProtoDocumentState with the proto fixture registry,
+/// parse it as a EmbeddedDocumentState with the proto fixture registry,
 /// and return formatted diagnostics.
 fn check_protovalidate_field(field_type: &str, field_name: &str, cel_expr: &str) -> String {
     let fixture_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/proto");
@@ -285,7 +429,12 @@ message TestMessage {{
 }}"#
     );
 
-    let state = ProtoDocumentState::new(proto_source, 0, registry.as_ref());
+    let state = EmbeddedDocumentState::new(
+        proto_source,
+        0,
+        registry.as_ref(),
+        ProtovalidateVersion::LATEST,
+    );
     let diagnostics = proto_to_diagnostics(&state);
     format_diagnostics(&diagnostics)
 }
@@ -338,7 +487,12 @@ message TestMessage {{
 }}"#
     );
 
-    let state = ProtoDocumentState::new(proto_source, 0, registry.as_ref());
+    let state = EmbeddedDocumentState::new(
+        proto_source,
+        0,
+        registry.as_ref(),
+        ProtovalidateVersion::LATEST,
+    );
 
     // The expression string is on line 5 (0-indexed), starting after `expression: "`
     // Line 5 is: `        expression: "<cel_expr>"`
@@ -375,7 +529,12 @@ message {message_name} {{
 }}"#
     );
 
-    let state = ProtoDocumentState::new(proto_source, 0, registry.as_ref());
+    let state = EmbeddedDocumentState::new(
+        proto_source,
+        0,
+        registry.as_ref(),
+        ProtovalidateVersion::LATEST,
+    );
 
     let cel_start_col = 21u32;
     let position = Position::new(5, cel_start_col + cursor_col_within_cel);
@@ -450,6 +609,74 @@ fn proto_completion_int_field_no_string_methods() {
     );
 }
 
+// ---------------------------------------------------------------------------
+// Tests — CEL embedded in Kubernetes YAML
+// ---------------------------------------------------------------------------
+
+fn check_kubernetes_yaml(source: &str) -> String {
+    let state = EmbeddedDocumentState::from_kubernetes_yaml(
+        source.to_string(),
+        0,
+        KubernetesVersion::LATEST,
+    );
+    format_diagnostics(&proto_to_diagnostics(&state))
+}
+
+#[test]
+fn kubernetes_crd_rules_map_to_yaml() {
+    let actual = check_kubernetes_yaml(
+        r#"apiVersion: apiextensions.k8s.io/v1
+kind: CustomResourceDefinition
+spec:
+  names:
+    kind: Widget
+  versions:
+  - name: v1
+    schema:
+      openAPIV3Schema:
+        type: object
+        properties:
+          spec:
+            type: object
+            x-kubernetes-validations:
+            - rule: "self.replicas >= 0 && self.name == \"a\" && self.colour"
+            - rule: >
+                self.replicas <=
+                self.maxReplicas
+            properties:
+              replicas:
+                type: integer
+              name:
+                type: string
+"#,
+    );
+    let expected = expect![[r#"
+        14:65-14:76 error [undefined-field]: undefined field 'colour' on type 'Widget.spec'
+        17:16-17:32 error [undefined-field]: undefined field 'maxReplicas' on type 'Widget.spec'"#]];
+    expected.assert_eq(&actual);
+}
+
+#[test]
+fn kubernetes_admission_policy_environment() {
+    let actual = check_kubernetes_yaml(
+        r#"apiVersion: admissionregistration.k8s.io/v1
+kind: ValidatingAdmissionPolicy
+spec:
+  variables:
+  - name: owner
+    expression: "object.metadata.labels['owner']"
+  validations:
+  - expression: |
+      variables.owner == request.userInfo.username &&
+        !request.dryRun && request.userInfo.email != ''
+"#,
+    );
+    let expected = expect![[
+        r#"9:27-9:49 error [undefined-field]: undefined field 'email' on type 'AdmissionRequest.UserInfo'"#
+    ]];
+    expected.assert_eq(&actual);
+}
+
 // ---------------------------------------------------------------------------
 // Tests — settings discovery
 // ---------------------------------------------------------------------------
@@ -472,7 +699,7 @@ fn discover_settings_applies_to_cel_files() {
     // CEL expression using variables declared in basic/settings.toml should work
     let state = DocumentState::with_env("x > 10 && name.startsWith('test')".to_string(), 0, env);
     let line_index = LineIndex::new(state.source.clone());
-    let diagnostics = to_diagnostics(&state.errors, state.check_errors(), &line_index);
+    let diagnostics = to_diagnostics(&state.errors, state.check_errors(), &line_index, &state.env);
     let actual = format_diagnostics(&diagnostics);
 
     let expected = expect![[r#"OK (no diagnostics)"#]];